@@ -1,3 +1,5 @@
+use std::ops::RangeInclusive;
+
 use chrono::{Duration, NaiveDateTime, TimeDelta};
 use openwhoop_codec::{Activity, ParsedHistoryReading};
 
@@ -5,6 +7,43 @@ const ACTIVITY_CHANGE_THRESHOLD: Duration = Duration::minutes(15);
 const MIN_SLEEP_DURATION: Duration = Duration::minutes(60);
 pub const MAX_SLEEP_PAUSE: Duration = Duration::minutes(60);
 const MAX_PAUSE: Duration = Duration::minutes(10);
+const MARKER_TOLERANCE: Duration = Duration::minutes(10);
+
+/// How readings are split into periods. Periods shorter than the minimum durations are
+/// merged into their neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivityConfig {
+    /// Minimum duration of sleep, inactive and other periods
+    pub min_duration: Duration,
+    /// Minimum duration of `Activity::Active` periods, lower it to keep short workouts
+    pub min_active_duration: Duration,
+    /// Consecutive readings of a new state needed to switch to it, shorter runs stay part
+    /// of the current period. 1 switches on any change.
+    pub hysteresis: usize,
+    /// How far a marker may be from the start or end of an active period to move it there,
+    /// see [`ActivityPeriod::bound_by_markers`]
+    pub marker_tolerance: Duration,
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        Self {
+            min_duration: ACTIVITY_CHANGE_THRESHOLD,
+            min_active_duration: ACTIVITY_CHANGE_THRESHOLD,
+            hysteresis: 1,
+            marker_tolerance: MARKER_TOLERANCE,
+        }
+    }
+}
+
+impl ActivityConfig {
+    fn min_duration_for(&self, activity: Activity) -> Duration {
+        match activity {
+            Activity::Active => self.min_active_duration,
+            _ => self.min_duration,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct ActivityPeriod {
@@ -22,11 +61,19 @@ struct TempActivity {
 }
 
 impl ActivityPeriod {
+    /// Detects periods using the default [`ActivityConfig`].
     pub fn detect(history: &mut [ParsedHistoryReading]) -> Vec<ActivityPeriod> {
+        Self::detect_with_config(history, &ActivityConfig::default())
+    }
+
+    pub fn detect_with_config(
+        history: &mut [ParsedHistoryReading],
+        config: &ActivityConfig,
+    ) -> Vec<ActivityPeriod> {
         Self::smooth_spikes(history);
-        let changes = Self::detect_changes(history);
+        let changes = Self::detect_changes(history, config.hysteresis);
 
-        Self::filter_merge(changes)
+        Self::filter_merge(changes, config)
             .into_iter()
             .map(|a| ActivityPeriod {
                 activity: a.activity,
@@ -41,6 +88,69 @@ impl ActivityPeriod {
         matches!(self.activity, Activity::Active)
     }
 
+    /// Moves the start and end of active periods to the nearest marker within `tolerance`,
+    /// so tapping the band when a workout starts and ends sets its exact boundaries.
+    ///
+    /// Bounds stay within `range`, the span the periods were detected in, and don't move past
+    /// the start of the period before or the end of the one after. A neighbour the period
+    /// grows into is shortened, so periods never overlap.
+    pub fn bound_by_markers(
+        periods: &mut [ActivityPeriod],
+        markers: &[NaiveDateTime],
+        tolerance: Duration,
+        range: RangeInclusive<NaiveDateTime>,
+    ) {
+        let nearest = |time: NaiveDateTime| {
+            markers
+                .iter()
+                .copied()
+                .filter(|marker| (*marker - time).abs() <= tolerance)
+                .min_by_key(|marker| (*marker - time).abs())
+        };
+
+        for i in 0..periods.len() {
+            if !periods[i].is_active() {
+                continue;
+            }
+
+            let earliest = match i.checked_sub(1) {
+                Some(previous) => periods[previous].start.max(*range.start()),
+                None => *range.start(),
+            };
+            let latest = periods
+                .get(i + 1)
+                .map_or(*range.end(), |next| next.end.min(*range.end()));
+
+            let period = periods[i];
+            let start = nearest(period.start)
+                .unwrap_or(period.start)
+                .clamp(earliest, latest);
+            let end = nearest(period.end)
+                .unwrap_or(period.end)
+                .clamp(earliest, latest);
+            // A single marker near both ends of a short period bounds neither
+            if start >= end {
+                continue;
+            }
+            periods[i].set_bounds(start, end);
+
+            if i > 0 && periods[i - 1].end > start {
+                let previous = periods[i - 1];
+                periods[i - 1].set_bounds(previous.start, start);
+            }
+            if let Some(next) = periods.get_mut(i + 1).filter(|next| next.start < end) {
+                let next_end = next.end;
+                next.set_bounds(end, next_end);
+            }
+        }
+    }
+
+    fn set_bounds(&mut self, start: NaiveDateTime, end: NaiveDateTime) {
+        self.start = start;
+        self.end = end;
+        self.duration = end - start;
+    }
+
     pub fn find_sleep(events: &mut Vec<ActivityPeriod>) -> Option<ActivityPeriod> {
         let mut next = || {
             if events.is_empty() {
@@ -79,7 +189,10 @@ impl ActivityPeriod {
         }
     }
 
-    fn filter_merge(mut activities: Vec<TempActivity>) -> Vec<TempActivity> {
+    fn filter_merge(
+        mut activities: Vec<TempActivity>,
+        config: &ActivityConfig,
+    ) -> Vec<TempActivity> {
         if activities.is_empty() {
             return Vec::new();
         }
@@ -91,7 +204,7 @@ impl ActivityPeriod {
             let current = &activities[i];
             let duration = current.end - current.start;
 
-            if duration < ACTIVITY_CHANGE_THRESHOLD {
+            if duration < config.min_duration_for(current.activity) {
                 if i > 0
                     && i + 1 < activities.len()
                     && activities[i - 1].activity == activities[i + 1].activity
@@ -130,7 +243,10 @@ impl ActivityPeriod {
 
         merged
     }
-    fn detect_changes(history: &[ParsedHistoryReading]) -> Vec<TempActivity> {
+
+    /// Splits `history` where the state changes for at least `hysteresis` readings, or
+    /// where readings pause for longer than [`MAX_PAUSE`].
+    fn detect_changes(history: &[ParsedHistoryReading], hysteresis: usize) -> Vec<TempActivity> {
         let mut periods = Vec::new();
         let mut iter = history.iter();
 
@@ -138,9 +254,12 @@ impl ActivityPeriod {
             let mut current_activity = first.activity;
             let mut start_time = first.time;
             let mut last_time = first.time;
+            // State that differs from the current one: activity, its first reading, how many
+            // readings in a row and the last reading before it
+            let mut pending: Option<(Activity, NaiveDateTime, usize, NaiveDateTime)> = None;
 
             for model in iter {
-                if model.activity != current_activity || (model.time - last_time > MAX_PAUSE) {
+                if model.time - last_time > MAX_PAUSE {
                     periods.push(TempActivity {
                         activity: current_activity,
                         start: start_time,
@@ -149,6 +268,30 @@ impl ActivityPeriod {
 
                     current_activity = model.activity;
                     start_time = model.time;
+                    pending = None;
+                } else if model.activity == current_activity {
+                    pending = None;
+                } else {
+                    let (activity, start, count, end) = match pending {
+                        Some((activity, start, count, end)) if activity == model.activity => {
+                            (activity, start, count + 1, end)
+                        }
+                        _ => (model.activity, model.time, 1, last_time),
+                    };
+
+                    if count >= hysteresis {
+                        periods.push(TempActivity {
+                            activity: current_activity,
+                            start: start_time,
+                            end,
+                        });
+
+                        current_activity = activity;
+                        start_time = start;
+                        pending = None;
+                    } else {
+                        pending = Some((activity, start, count, end));
+                    }
                 }
                 last_time = model.time;
             }
@@ -197,9 +340,8 @@ mod tests {
 
     #[test]
     fn detect_single_activity_type() {
-        let mut history = make_readings(
-            &(0..30).map(|m| (m, Activity::Active)).collect::<Vec<_>>(),
-        );
+        let mut history =
+            make_readings(&(0..30).map(|m| (m, Activity::Active)).collect::<Vec<_>>());
         let periods = ActivityPeriod::detect(&mut history);
         assert_eq!(periods.len(), 1);
         assert!(matches!(periods[0].activity, Activity::Active));
@@ -231,6 +373,121 @@ mod tests {
         assert!(!periods.is_empty());
     }
 
+    #[test]
+    fn short_active_block_survives_lowered_threshold() {
+        // 30 min inactive, 10 min workout, 30 min inactive
+        let mut specs: Vec<(i64, Activity)> = (0..30).map(|m| (m, Activity::Inactive)).collect();
+        specs.extend((30..41).map(|m| (m, Activity::Active)));
+        specs.extend((41..71).map(|m| (m, Activity::Inactive)));
+
+        let periods = ActivityPeriod::detect(&mut make_readings(&specs));
+        assert!(!periods.iter().any(ActivityPeriod::is_active));
+
+        let config = ActivityConfig {
+            min_active_duration: Duration::minutes(5),
+            ..Default::default()
+        };
+        let periods = ActivityPeriod::detect_with_config(&mut make_readings(&specs), &config);
+        let active = periods.iter().filter(|p| p.is_active()).collect::<Vec<_>>();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].duration, Duration::minutes(10));
+    }
+
+    #[test]
+    fn hysteresis_ignores_short_wake_blip() {
+        // An hour of sleep with two readings awake in the middle
+        let specs = (0..60)
+            .map(|m| match m {
+                30 | 31 => (m, Activity::Awake),
+                _ => (m, Activity::Sleep),
+            })
+            .collect::<Vec<_>>();
+        let history = make_readings(&specs);
+
+        let changes = ActivityPeriod::detect_changes(&history, 1);
+        assert_eq!(changes.len(), 3);
+
+        let changes = ActivityPeriod::detect_changes(&history, 3);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(changes[0].activity, Activity::Sleep));
+        assert_eq!(changes[0].end - changes[0].start, Duration::minutes(59));
+
+        // A longer wake still switches, from its first reading
+        let mut specs = (0..30).map(|m| (m, Activity::Sleep)).collect::<Vec<_>>();
+        specs.extend((30..40).map(|m| (m, Activity::Awake)));
+        let changes = ActivityPeriod::detect_changes(&make_readings(&specs), 3);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].end, make_reading(29, Activity::Sleep).time);
+        assert_eq!(changes[1].start, make_reading(30, Activity::Awake).time);
+    }
+
+    #[test]
+    fn markers_bound_active_periods() {
+        let time = |minutes| make_reading(minutes, Activity::Active).time;
+        let period = |activity, from, to| ActivityPeriod {
+            activity,
+            start: time(from),
+            end: time(to),
+            duration: time(to) - time(from),
+        };
+        let mut periods = vec![
+            period(Activity::Active, 30, 90),
+            period(Activity::Sleep, 120, 200),
+        ];
+
+        // Start tapped a bit early, end a bit late, another tap too far from either
+        let markers = [time(27), time(60), time(95), time(125)];
+        ActivityPeriod::bound_by_markers(
+            &mut periods,
+            &markers,
+            Duration::minutes(10),
+            time(0)..=time(240),
+        );
+
+        assert_eq!((periods[0].start, periods[0].end), (time(27), time(95)));
+        assert_eq!(periods[0].duration, Duration::minutes(68));
+        assert_eq!((periods[1].start, periods[1].end), (time(120), time(200)));
+    }
+
+    #[test]
+    fn markers_dont_move_bounds_past_neighbours_or_range() {
+        let time = |minutes| make_reading(minutes, Activity::Active).time;
+        let period = |activity, from, to| ActivityPeriod {
+            activity,
+            start: time(from),
+            end: time(to),
+            duration: time(to) - time(from),
+        };
+        let mut periods = vec![
+            period(Activity::Active, 10, 30),
+            period(Activity::Inactive, 31, 40),
+            period(Activity::Active, 41, 60),
+        ];
+
+        // Before the detected range, past the start of the inactive period, and after the
+        // end of the range
+        let markers = [time(2), time(29), time(68)];
+        ActivityPeriod::bound_by_markers(
+            &mut periods,
+            &markers,
+            Duration::minutes(15),
+            time(5)..=time(65),
+        );
+
+        let bounds = periods
+            .iter()
+            .map(|p| (p.start, p.end, p.duration))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bounds,
+            vec![
+                (time(5), time(29), Duration::minutes(24)),
+                (time(31), time(31), Duration::zero()),
+                (time(31), time(65), Duration::minutes(34)),
+            ]
+        );
+    }
+
     #[test]
     fn smooth_spikes_removes_single_point_spike() {
         let mut history = make_readings(&[
@@ -0,0 +1,332 @@
+use chrono::TimeDelta;
+use openwhoop_codec::{ImuSample, ParsedHistoryReading};
+use openwhoop_types::activities::ActivityType;
+
+use crate::helpers::time_math::mean;
+
+/// Seconds per window the cadence is estimated over
+const CADENCE_WINDOW: usize = 10;
+/// Cadences searched for a periodic signal, in steps (or repetitions) per minute
+const MIN_CADENCE: f64 = 40.0;
+const MAX_CADENCE: f64 = 240.0;
+
+/// Guessed type of an activity and how sure the guess is, 0 to 1
+#[derive(Debug, Clone, Copy)]
+pub struct ActivityGuess {
+    pub activity: ActivityType,
+    pub confidence: f64,
+}
+
+/// Thresholds for telling running, cycling and weightlifting apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityClassifierConfig {
+    /// Guesses below this confidence are dropped, leaving the activity generic
+    pub min_confidence: f64,
+    /// Mean heart rate at or below which an activity doesn't count as exercise at all
+    pub calm_bpm: u8,
+    /// Mean heart rate at which an activity counts as fully cardiovascular
+    pub cardio_bpm: u8,
+    /// Step cadence range of running, in steps per minute
+    pub running_cadence: (f64, f64),
+    /// Standard deviation of the acceleration magnitude (g) below which a second counts as still
+    pub still_motion: f64,
+}
+
+impl Default for ActivityClassifierConfig {
+    fn default() -> Self {
+        Self {
+            min_confidence: 0.6,
+            calm_bpm: 70,
+            cardio_bpm: 120,
+            running_cadence: (140.0, 220.0),
+            still_motion: 0.05,
+        }
+    }
+}
+
+/// Guesses the [`ActivityType`] of a detected activity from its IMU and heart rate.
+///
+/// Running shows up as a strong periodic acceleration at step cadence, cycling as a still wrist
+/// with an elevated heart rate, and weightlifting as bursts of movement between rests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ActivityClassifier {
+    config: ActivityClassifierConfig,
+}
+
+/// Signal features of one activity
+#[derive(Debug, Clone, Copy)]
+struct Features {
+    /// Dominant cadence of the acceleration magnitude, per minute
+    cadence: f64,
+    /// Autocorrelation at the dominant cadence, 0 for no rhythm to 1 for a perfect one
+    periodicity: f64,
+    /// Share of seconds with a moving wrist
+    moving: f64,
+    mean_bpm: f64,
+}
+
+impl ActivityClassifier {
+    pub fn with_config(config: ActivityClassifierConfig) -> Self {
+        Self { config }
+    }
+
+    /// Classifies using the default [`ActivityClassifierConfig`].
+    pub fn classify_activity(history: &[ParsedHistoryReading]) -> Option<ActivityGuess> {
+        Self::default().classify(history)
+    }
+
+    /// Best guess for the readings of one activity, `None` without IMU data or when no guess
+    /// reaches [`ActivityClassifierConfig::min_confidence`].
+    pub fn classify(&self, history: &[ParsedHistoryReading]) -> Option<ActivityGuess> {
+        let features = self.features(history)?;
+        let calm = f64::from(self.config.calm_bpm);
+        let cardio = f64::from(self.config.cardio_bpm);
+        let heart = ((features.mean_bpm - calm) / (cardio - calm)).clamp(0.0, 1.0);
+
+        let (min_cadence, max_cadence) = self.config.running_cadence;
+        let running = if (min_cadence..=max_cadence).contains(&features.cadence) {
+            features.periodicity * heart
+        } else {
+            0.0
+        };
+        let cycling = (1.0 - features.moving) * heart;
+        // Peaks when half of the time is spent moving, and fades with a steady rhythm
+        let weightlifting =
+            4.0 * features.moving * (1.0 - features.moving) * (1.0 - features.periodicity) * heart;
+
+        [
+            (ActivityType::Running, running),
+            (ActivityType::Cycling, cycling),
+            (ActivityType::Weightlifting, weightlifting),
+        ]
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|(_, confidence)| *confidence >= self.config.min_confidence)
+        .map(|(activity, confidence)| ActivityGuess {
+            activity,
+            confidence,
+        })
+    }
+
+    fn features(&self, history: &[ParsedHistoryReading]) -> Option<Features> {
+        let runs = imu_runs(history);
+        let seconds = runs
+            .iter()
+            .flat_map(|run| run.samples.chunks_exact(run.rate))
+            .collect::<Vec<_>>();
+
+        if seconds.is_empty() {
+            return None;
+        }
+
+        let moving = seconds
+            .iter()
+            .filter(|second| std_dev(second) >= self.config.still_motion)
+            .count() as f64
+            / seconds.len() as f64;
+
+        let windows = runs
+            .iter()
+            .flat_map(|run| {
+                run.samples
+                    .chunks_exact(run.rate * CADENCE_WINDOW)
+                    .filter(|window| std_dev(window) >= self.config.still_motion)
+                    .filter_map(|window| dominant_cadence(window, run.rate as f64))
+            })
+            .collect::<Vec<_>>();
+
+        let (cadence, periodicity) = if windows.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let mut cadences = windows.iter().map(|(c, _)| *c).collect::<Vec<_>>();
+            cadences.sort_unstable_by(f64::total_cmp);
+            let strengths = windows.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+            (cadences[cadences.len() / 2], mean(&strengths).max(0.0))
+        };
+
+        let bpm = history.iter().map(|r| f64::from(r.bpm)).collect::<Vec<_>>();
+
+        Some(Features {
+            cadence,
+            periodicity,
+            moving,
+            mean_bpm: mean(&bpm),
+        })
+    }
+}
+
+/// Acceleration magnitudes of consecutive readings sharing a sample rate
+#[derive(Debug, PartialEq)]
+struct ImuRun {
+    /// Samples per second, which depends on how the readings were downsampled when stored
+    rate: usize,
+    samples: Vec<f64>,
+}
+
+/// Splits the IMU of `history` wherever a reading is missing or the sample rate changes, so
+/// a cadence window never spans a gap. Every reading carries one second of samples.
+fn imu_runs(history: &[ParsedHistoryReading]) -> Vec<ImuRun> {
+    let mut runs = Vec::<ImuRun>::new();
+    let mut previous = None;
+    for reading in history {
+        let Some(imu) = reading.imu_data.as_deref().filter(|imu| !imu.is_empty()) else {
+            previous = None;
+            continue;
+        };
+
+        let follows = previous.is_some_and(|previous| {
+            reading.time > previous && reading.time - previous <= TimeDelta::seconds(1)
+        });
+        match runs.last_mut() {
+            Some(run) if follows && run.rate == imu.len() => run.samples.extend(magnitudes(imu)),
+            _ => runs.push(ImuRun {
+                rate: imu.len(),
+                samples: magnitudes(imu),
+            }),
+        }
+        previous = Some(reading.time);
+    }
+
+    runs
+}
+
+fn magnitudes(imu: &[ImuSample]) -> Vec<f64> {
+    imu.iter()
+        .map(|s| {
+            let (x, y, z) = (
+                f64::from(s.acc_x_g),
+                f64::from(s.acc_y_g),
+                f64::from(s.acc_z_g),
+            );
+            (x * x + y * y + z * z).sqrt()
+        })
+        .collect()
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    variance.sqrt()
+}
+
+/// Cadence (per minute) with the highest autocorrelation in the window sampled at `rate` per
+/// second, and that correlation
+fn dominant_cadence(window: &[f64], rate: f64) -> Option<(f64, f64)> {
+    let mean = mean(window);
+    let centered = window.iter().map(|v| v - mean).collect::<Vec<_>>();
+    let energy = centered.iter().map(|v| v * v).sum::<f64>();
+    if energy == 0.0 {
+        return None;
+    }
+
+    let min_lag = ((rate * 60.0 / MAX_CADENCE).floor() as usize).max(1);
+    let max_lag = (rate * 60.0 / MIN_CADENCE).ceil() as usize;
+
+    (min_lag..=max_lag.min(centered.len() - 1))
+        .map(|lag| {
+            let correlation = centered
+                .iter()
+                .zip(&centered[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f64>();
+            (lag, correlation / energy)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(lag, correlation)| (rate * 60.0 / lag as f64, correlation))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeDelta};
+    use openwhoop_codec::Activity;
+
+    use super::*;
+
+    /// One reading a second for `minutes`, with `rate` IMU samples produced by `acc_z` from the
+    /// sample time
+    fn trace(
+        minutes: i64,
+        bpm: u8,
+        rate: u32,
+        acc_z: impl Fn(f64) -> f64,
+    ) -> Vec<ParsedHistoryReading> {
+        let start = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(18, 0, 0)
+            .unwrap();
+
+        (0..minutes * 60)
+            .map(|second| ParsedHistoryReading {
+                time: start + TimeDelta::seconds(second),
+                bpm,
+                rr: Vec::new(),
+                activity: Activity::Active,
+                imu_data: Some(
+                    (0..rate)
+                        .map(|i| {
+                            let t = second as f64 + f64::from(i) / f64::from(rate);
+                            ImuSample {
+                                acc_x_g: 0.0,
+                                acc_y_g: 0.0,
+                                acc_z_g: acc_z(t) as f32,
+                                gyr_x_dps: 0.0,
+                                gyr_y_dps: 0.0,
+                                gyr_z_dps: 0.0,
+                            }
+                        })
+                        .collect(),
+                ),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn step_cadence_of_180_is_running() {
+        // Step impacts 3 times a second on top of gravity
+        let steps = |t: f64| 1.0 + 0.8 * (2.0 * std::f64::consts::PI * 3.0 * t).sin();
+
+        // Every sample, and every fourth one as stored with `--imu-downsample 4`
+        for rate in [100, 25] {
+            let history = trace(5, 155, rate, steps);
+            let guess = ActivityClassifier::classify_activity(&history).unwrap();
+            assert!(matches!(guess.activity, ActivityType::Running), "{rate}");
+            assert!(guess.confidence > 0.9, "{rate}: {}", guess.confidence);
+        }
+    }
+
+    #[test]
+    fn imu_is_split_at_gaps_and_rate_changes() {
+        let mut history = trace(1, 150, 100, |_| 1.0);
+        history.truncate(30);
+        // A missing reading
+        history.remove(10);
+        // Downsampled from here on
+        for reading in &mut history[19..] {
+            reading.imu_data.as_mut().unwrap().truncate(25);
+        }
+
+        let runs = imu_runs(&history)
+            .into_iter()
+            .map(|run| (run.rate, run.samples.len() / run.rate))
+            .collect::<Vec<_>>();
+        assert_eq!(runs, vec![(100, 10), (100, 9), (25, 10)]);
+    }
+
+    #[test]
+    fn still_wrist_with_high_heart_rate_is_cycling() {
+        let history = trace(5, 140, 100, |_| 1.0);
+
+        let guess = ActivityClassifier::classify_activity(&history).unwrap();
+        assert!(matches!(guess.activity, ActivityType::Cycling));
+    }
+
+    #[test]
+    fn unclear_activity_stays_generic() {
+        let history = trace(5, 85, 100, |_| 1.0);
+        assert!(ActivityClassifier::classify_activity(&history).is_none());
+
+        let mut without_imu = trace(1, 150, 100, |_| 1.0);
+        without_imu.iter_mut().for_each(|r| r.imu_data = None);
+        assert!(ActivityClassifier::classify_activity(&without_imu).is_none());
+    }
+}
@@ -1,11 +1,12 @@
 use std::fmt::Display;
 
-use chrono::TimeDelta;
+use chrono::{NaiveDate, TimeDelta};
 
 use crate::helpers::{
     format_hm::FormatHM,
     time_math::{mean_deltas, std_dev_delta},
 };
+use openwhoop_codec::ParsedHistoryReading;
 use openwhoop_types::activities::ActivityPeriod;
 
 #[derive(Debug, Default)]
@@ -14,6 +15,8 @@ pub struct ExerciseMetrics {
     pub count: u64,
     pub mean_duration: TimeDelta,
     pub duration_std: TimeDelta,
+    /// Estimated energy expenditure in kcal, `None` without a complete [`CalorieConfig`]
+    pub calories: Option<f64>,
 }
 
 impl ExerciseMetrics {
@@ -35,8 +38,99 @@ impl ExerciseMetrics {
             mean_duration,
             duration_std: std_dev_delta(durations.as_slice(), mean_duration),
             total_duration: durations.into_iter().sum(),
+            calories: None,
         }
     }
+
+    pub fn with_calories(self, calories: Option<f64>) -> Self {
+        Self { calories, ..self }
+    }
+}
+
+/// Gaps between readings longer than this are counted as this long
+const MAX_SAMPLE_GAP: TimeDelta = TimeDelta::minutes(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+/// User parameters for calorie estimates, the estimate is skipped when any of them is missing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CalorieConfig {
+    pub age: Option<u8>,
+    pub weight_kg: Option<f64>,
+    pub sex: Option<Sex>,
+}
+
+impl CalorieConfig {
+    /// Energy expenditure in kcal over `hr`, using the heart rate based prediction equations
+    /// from Keytel et al. (2005).
+    ///
+    /// Each reading counts for the time until the next one. The equations are only valid
+    /// for exercise, at resting heart rates they go negative, which is clamped to zero.
+    pub fn calories(&self, hr: &[ParsedHistoryReading]) -> Option<f64> {
+        let age = f64::from(self.age?);
+        let weight = self.weight_kg?;
+        let sex = self.sex?;
+
+        let kcal_per_minute = |bpm: u8| {
+            let bpm = f64::from(bpm);
+            let kj = match sex {
+                Sex::Male => -55.0969 + 0.6309 * bpm + 0.1988 * weight + 0.2017 * age,
+                Sex::Female => -20.4022 + 0.4472 * bpm - 0.1263 * weight + 0.074 * age,
+            };
+            (kj / 4.184).max(0.0)
+        };
+
+        let kcal = hr
+            .windows(2)
+            .map(|w| {
+                let duration = (w[1].time - w[0].time).clamp(TimeDelta::zero(), MAX_SAMPLE_GAP);
+                kcal_per_minute(w[0].bpm) * duration.num_milliseconds() as f64 / 60_000.0
+            })
+            .sum();
+
+        Some(kcal)
+    }
+}
+
+/// Days averaged for the acute (fatigue) load
+const ACUTE_DAYS: i64 = 7;
+/// Days averaged for the chronic (fitness) load
+const CHRONIC_DAYS: i64 = 28;
+
+/// Acute:chronic workload ratio from daily strain totals.
+///
+/// Averages strain over the last 7 days and divides it by the 28 day average, both ending
+/// at the most recent date. Days without an entry count as zero load. Values above ~1.5
+/// are commonly treated as elevated injury risk. Returns `None` with fewer than 28 days
+/// of history or when there is no chronic load.
+pub fn acwr(daily_strain: &[(NaiveDate, f64)]) -> Option<f64> {
+    let first = daily_strain.iter().map(|(date, _)| *date).min()?;
+    let last = daily_strain.iter().map(|(date, _)| *date).max()?;
+
+    if (last - first).num_days() + 1 < CHRONIC_DAYS {
+        return None;
+    }
+
+    let load = |days: i64| {
+        let start = last - TimeDelta::days(days - 1);
+        daily_strain
+            .iter()
+            .filter(|(date, _)| *date >= start)
+            .map(|(_, strain)| strain)
+            .sum::<f64>()
+            / days as f64
+    };
+
+    let chronic = load(CHRONIC_DAYS);
+    if chronic <= 0.0 {
+        return None;
+    }
+
+    Some(load(ACUTE_DAYS) / chronic)
 }
 
 impl Display for ExerciseMetrics {
@@ -47,7 +141,13 @@ impl Display for ExerciseMetrics {
             self.count,
             self.mean_duration.format_hm(),
             self.duration_std.format_hm()
-        ))
+        ))?;
+
+        if let Some(calories) = self.calories {
+            f.write_fmt(format_args!("\nCalories: {:.0} kcal", calories))?;
+        }
+
+        Ok(())
     }
 }
 
@@ -55,7 +155,7 @@ impl Display for ExerciseMetrics {
 mod tests {
     use chrono::TimeDelta;
 
-    use super::ExerciseMetrics;
+    use super::{CalorieConfig, ExerciseMetrics, Sex, acwr};
 
     #[test]
     fn test_metrics_empty() {
@@ -82,12 +182,14 @@ mod tests {
                 from: base,
                 to: base + TimeDelta::hours(1),
                 activity: ActivityType::Running,
+                confidence: None,
             },
             ActivityPeriod {
                 period_id: base.date(),
                 from: base + TimeDelta::hours(4),
                 to: base + TimeDelta::hours(5),
                 activity: ActivityType::Cycling,
+                confidence: None,
             },
         ];
 
@@ -97,4 +199,87 @@ mod tests {
         assert_eq!(metrics.mean_duration, TimeDelta::hours(1));
         assert_eq!(metrics.duration_std, TimeDelta::seconds(0)); // identical durations
     }
+
+    fn steady_hr(bpm: u8, seconds: i64) -> Vec<openwhoop_codec::ParsedHistoryReading> {
+        let start = chrono::NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        (0..=seconds)
+            .map(|i| openwhoop_codec::ParsedHistoryReading {
+                time: start + TimeDelta::seconds(i),
+                bpm,
+                rr: vec![],
+                activity: openwhoop_codec::Activity::Active,
+                imu_data: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calories_for_an_hour_at_150_bpm() {
+        let config = CalorieConfig {
+            age: Some(30),
+            weight_kg: Some(75.0),
+            sex: Some(Sex::Male),
+        };
+        // (-55.0969 + 0.6309 * 150 + 0.1988 * 75 + 0.2017 * 30) / 4.184 = 14.46 kcal/min
+        let kcal = config.calories(&steady_hr(150, 3600)).unwrap();
+        assert!((kcal - 867.6).abs() < 1.0, "kcal: {kcal}");
+
+        let female = CalorieConfig {
+            sex: Some(Sex::Female),
+            ..config
+        };
+        let kcal = female.calories(&steady_hr(150, 3600)).unwrap();
+        assert!((kcal - 565.4).abs() < 1.0, "kcal: {kcal}");
+    }
+
+    #[test]
+    fn calories_need_every_user_param() {
+        let config = CalorieConfig {
+            age: Some(30),
+            weight_kg: None,
+            sex: Some(Sex::Male),
+        };
+        assert_eq!(config.calories(&steady_hr(150, 60)), None);
+        assert_eq!(CalorieConfig::default().calories(&steady_hr(150, 60)), None);
+    }
+
+    fn daily_strain(strain: impl IntoIterator<Item = f64>) -> Vec<(chrono::NaiveDate, f64)> {
+        let start = chrono::NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        strain
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| (start + TimeDelta::days(i as i64), s))
+            .collect()
+    }
+
+    #[test]
+    fn acwr_requires_chronic_window() {
+        assert_eq!(acwr(&[]), None);
+        assert_eq!(acwr(&daily_strain(vec![10.0; 27])), None);
+    }
+
+    #[test]
+    fn acwr_steady_load_near_one() {
+        let ratio = acwr(&daily_strain(vec![10.0; 35])).unwrap();
+        assert!((ratio - 1.0).abs() < 1e-9, "steady load ratio: {ratio}");
+    }
+
+    #[test]
+    fn acwr_spike_exceeds_danger_line() {
+        let strain = std::iter::repeat_n(5.0, 21).chain(std::iter::repeat_n(15.0, 7));
+        let ratio = acwr(&daily_strain(strain)).unwrap();
+        assert!(ratio > 1.5, "spike ratio: {ratio}");
+    }
+
+    #[test]
+    fn acwr_counts_missing_days_as_rest() {
+        let mut strain = daily_strain(vec![10.0; 28]);
+        // Drop the last week entirely, keeping the final day so the window still ends there
+        strain.drain(21..27);
+        let ratio = acwr(&strain).unwrap();
+        assert!(ratio < 1.0, "rest week ratio: {ratio}");
+    }
 }
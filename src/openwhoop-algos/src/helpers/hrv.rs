@@ -0,0 +1,95 @@
+use crate::{HrvConfig, SleepCycle};
+
+/// SDNN (standard deviation of NN intervals) in ms, using the default [`HrvConfig`].
+pub fn sdnn(rr: &[u16]) -> Option<f64> {
+    sdnn_with_config(rr, &HrvConfig::default())
+}
+
+/// SDNN in ms after the same artifact rejection sleep RMSSD uses, so both metrics are
+/// computed on the same NN intervals.
+///
+/// Missing intervals (0) are skipped, `None` if fewer than 2 intervals remain.
+pub fn sdnn_with_config(rr: &[u16], config: &HrvConfig) -> Option<f64> {
+    sdnn_of_nn(&nn_intervals(rr, config))
+}
+
+/// pNN50 (percentage of successive NN intervals differing by more than 50 ms), using the
+/// default [`HrvConfig`].
+pub fn pnn50(rr: &[u16]) -> Option<f64> {
+    pnn50_with_config(rr, &HrvConfig::default())
+}
+
+/// pNN50 after the same artifact rejection as [`sdnn_with_config`].
+///
+/// `None` if fewer than 2 intervals remain.
+pub fn pnn50_with_config(rr: &[u16], config: &HrvConfig) -> Option<f64> {
+    pnn50_of_nn(&nn_intervals(rr, config))
+}
+
+/// Intervals left after dropping missing ones (0) and artifacts
+fn nn_intervals(rr: &[u16], config: &HrvConfig) -> Vec<u64> {
+    let rr = rr
+        .iter()
+        .filter(|&&v| v > 0)
+        .map(|&v| u64::from(v))
+        .collect();
+
+    SleepCycle::reject_artifacts(rr, config)
+}
+
+/// Sample standard deviation of intervals that were already cleaned of artifacts
+pub(crate) fn sdnn_of_nn(nn: &[u64]) -> Option<f64> {
+    if nn.len() < 2 {
+        return None;
+    }
+
+    let count = nn.len() as f64;
+    let mean = nn.iter().map(|&v| v as f64).sum::<f64>() / count;
+    let variance = nn.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / (count - 1.0);
+
+    Some(variance.sqrt())
+}
+
+/// Percentage of successive differences over 50 ms, of intervals already cleaned of artifacts
+pub(crate) fn pnn50_of_nn(nn: &[u64]) -> Option<f64> {
+    if nn.len() < 2 {
+        return None;
+    }
+
+    let diffs = nn.len() - 1;
+    let over = nn.windows(2).filter(|w| w[0].abs_diff(w[1]) > 50).count();
+
+    Some(over as f64 / diffs as f64 * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sdnn_of_known_series() {
+        // Mean 800, squared deviations 100 + 0 + 100 over n - 1 = 2 -> variance 100
+        assert_eq!(sdnn(&[790, 800, 810]), Some(10.0));
+    }
+
+    #[test]
+    fn sdnn_skips_missing_and_artifacts() {
+        // 0 is a missing interval, 2500 is out of range and 1500 deviates from its neighbours
+        assert_eq!(sdnn(&[790, 0, 800, 2500, 1500, 810]), Some(10.0));
+    }
+
+    #[test]
+    fn pnn50_of_known_differences() {
+        // Successive differences 60, 40, 51, 50: two of four are over 50 ms
+        assert_eq!(pnn50(&[800, 860, 820, 871, 821]), Some(50.0));
+        assert_eq!(pnn50(&[800, 810, 800]), Some(0.0));
+        assert_eq!(pnn50(&[800]), None);
+    }
+
+    #[test]
+    fn sdnn_needs_two_intervals() {
+        assert_eq!(sdnn(&[]), None);
+        assert_eq!(sdnn(&[800]), None);
+        assert_eq!(sdnn(&[800, 0]), None);
+    }
+}
@@ -0,0 +1,103 @@
+use chrono::TimeDelta;
+use openwhoop_codec::ParsedHistoryReading;
+
+/// Reading returned by [`interpolate_gaps`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilledReading {
+    pub reading: ParsedHistoryReading,
+    /// Filled in between two stored readings. These have no RR intervals, so they never
+    /// count toward HRV.
+    pub interpolated: bool,
+}
+
+/// Fills every missing second between consecutive readings at most `max_gap` apart, with
+/// BPM interpolated linearly between them.
+///
+/// Longer gaps are left alone, the strap was likely off the wrist. Filled readings take
+/// the activity of the reading before the gap.
+pub fn interpolate_gaps(
+    readings: &[ParsedHistoryReading],
+    max_gap: TimeDelta,
+) -> Vec<FilledReading> {
+    let mut filled = Vec::with_capacity(readings.len());
+
+    for (i, reading) in readings.iter().enumerate() {
+        if let Some(previous) = i.checked_sub(1).map(|i| &readings[i]) {
+            let gap = reading.time - previous.time;
+            if gap <= max_gap {
+                let seconds = gap.num_seconds();
+                let from = f64::from(previous.bpm);
+                let step = (f64::from(reading.bpm) - from) / seconds as f64;
+
+                filled.extend((1..seconds).map(|s| FilledReading {
+                    reading: ParsedHistoryReading {
+                        time: previous.time + TimeDelta::seconds(s),
+                        bpm: (from + step * s as f64).round() as u8,
+                        rr: Vec::new(),
+                        activity: previous.activity,
+                        imu_data: None,
+                    },
+                    interpolated: true,
+                }));
+            }
+        }
+
+        filled.push(FilledReading {
+            reading: reading.clone(),
+            interpolated: false,
+        });
+    }
+
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use openwhoop_codec::Activity;
+
+    fn reading(second: i64, bpm: u8) -> ParsedHistoryReading {
+        ParsedHistoryReading {
+            time: NaiveDate::from_ymd_opt(2025, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                + TimeDelta::seconds(second),
+            bpm,
+            rr: vec![1000],
+            activity: Activity::Active,
+            imu_data: None,
+        }
+    }
+
+    #[test]
+    fn short_gaps_are_filled_long_ones_left_alone() {
+        // A 3 second hole after the second reading, a minute long one after the third
+        let readings = [
+            reading(0, 60),
+            reading(1, 60),
+            reading(5, 80),
+            reading(65, 70),
+        ];
+        let filled = interpolate_gaps(&readings, TimeDelta::seconds(5));
+
+        let seconds: Vec<_> = filled
+            .iter()
+            .map(|f| (f.reading.time - readings[0].time).num_seconds())
+            .collect();
+        assert_eq!(seconds, [0, 1, 2, 3, 4, 5, 65]);
+
+        let bpm: Vec<_> = filled.iter().map(|f| f.reading.bpm).collect();
+        assert_eq!(bpm, [60, 60, 65, 70, 75, 80, 70]);
+
+        let interpolated: Vec<_> = filled.iter().map(|f| f.interpolated).collect();
+        assert_eq!(interpolated, [false, false, true, true, true, false, false]);
+        assert!(
+            filled
+                .iter()
+                .filter(|f| f.interpolated)
+                .all(|f| f.reading.rr.is_empty() && f.reading.activity == Activity::Active)
+        );
+    }
+}
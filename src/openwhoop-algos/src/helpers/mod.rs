@@ -1,2 +1,6 @@
 pub mod format_hm;
+pub mod hrv;
+pub mod interpolation;
+pub mod smoothing;
 pub mod time_math;
+pub mod units;
@@ -0,0 +1,120 @@
+use openwhoop_codec::ParsedHistoryReading;
+
+/// Window used when smoothing heart rate before sleep and strain statistics
+pub const BPM_SMOOTHING_WINDOW: usize = 5;
+
+/// Replaces every BPM with the median of the `window` readings centered on it.
+///
+/// Removes short motion artifact spikes while keeping elevations that last longer than
+/// half the window. Windows are truncated at the edges, `window` below 3 is a no-op and
+/// even windows are widened by one so they stay centered.
+pub fn smooth_bpm(history: &mut [ParsedHistoryReading], window: usize) {
+    if window < 3 || history.len() < 3 {
+        return;
+    }
+
+    let half = window / 2;
+    let original = history.iter().map(|r| r.bpm).collect::<Vec<_>>();
+
+    for (i, reading) in history.iter_mut().enumerate() {
+        let start = i.saturating_sub(half);
+        let end = (i + half + 1).min(original.len());
+
+        let mut values = original[start..end].to_vec();
+        values.sort_unstable();
+        reading.bpm = values[values.len() / 2];
+    }
+}
+
+/// Exponential moving average over a span of values, weighting each new value by
+/// `2 / (span + 1)`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    /// Continues from `last`, the previous average, or starts at the first value when `None`.
+    pub fn new(span: usize, last: Option<f64>) -> Self {
+        Self {
+            alpha: 2.0 / (span.max(1) as f64 + 1.0),
+            value: last,
+        }
+    }
+
+    pub fn update(&mut self, value: f64) -> f64 {
+        let average = match self.value {
+            Some(last) => last + self.alpha * (value - last),
+            None => value,
+        };
+        self.value = Some(average);
+        average
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeDelta};
+    use openwhoop_codec::Activity;
+
+    fn history(bpm: &[u8]) -> Vec<ParsedHistoryReading> {
+        let start = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        bpm.iter()
+            .enumerate()
+            .map(|(i, &bpm)| ParsedHistoryReading {
+                time: start + TimeDelta::seconds(i as i64),
+                bpm,
+                rr: vec![],
+                activity: Activity::Sleep,
+                imu_data: None,
+            })
+            .collect()
+    }
+
+    fn bpm(history: &[ParsedHistoryReading]) -> Vec<u8> {
+        history.iter().map(|r| r.bpm).collect()
+    }
+
+    #[test]
+    fn lone_spike_removed() {
+        let mut h = history(&[55, 55, 56, 55, 199, 55, 56, 55, 55]);
+        smooth_bpm(&mut h, BPM_SMOOTHING_WINDOW);
+        assert!(bpm(&h).into_iter().all(|b| b <= 56));
+    }
+
+    #[test]
+    fn sustained_elevation_survives() {
+        let mut h = history(&[55, 55, 55, 55, 90, 92, 91, 93, 90, 55, 55, 55, 55]);
+        smooth_bpm(&mut h, BPM_SMOOTHING_WINDOW);
+        assert_eq!(&bpm(&h)[5..8], &[91, 91, 91]);
+        assert!(bpm(&h).into_iter().max().unwrap() >= 90);
+    }
+
+    #[test]
+    fn ema_lags_a_step() {
+        let mut ema = Ema::new(9, None);
+        let smoothed = [1.0, 1.0, 1.0, 8.0, 8.0, 8.0, 8.0, 8.0].map(|value| ema.update(value));
+
+        assert_eq!(&smoothed[..3], &[1.0, 1.0, 1.0]);
+        assert!((smoothed[3] - 2.4).abs() < 1e-9, "{}", smoothed[3]);
+        assert!(smoothed.windows(2).all(|w| w[0] <= w[1]));
+        assert!(smoothed[7] < 8.0);
+
+        // A span of 1 follows the values
+        let mut ema = Ema::new(1, Some(1.0));
+        assert_eq!(ema.update(8.0), 8.0);
+    }
+
+    #[test]
+    fn small_window_is_noop() {
+        let mut h = history(&[55, 199, 55]);
+        smooth_bpm(&mut h, 1);
+        assert_eq!(bpm(&h), vec![55, 199, 55]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::f64::consts::TAU;
+
 use chrono::{NaiveTime, TimeDelta, Timelike as _};
 
 pub fn map_time(time: &NaiveTime) -> i64 {
@@ -46,6 +48,46 @@ pub fn mean_time(times: &[NaiveTime]) -> NaiveTime {
     }
 }
 
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Mean resultant vector of clock times mapped onto the 24h circle, as (angle, length).
+fn resultant(times: &[NaiveTime]) -> (f64, f64) {
+    let (sin, cos) = times
+        .iter()
+        .map(|t| f64::from(t.num_seconds_from_midnight()) / SECONDS_PER_DAY * TAU)
+        .fold((0.0, 0.0), |(sin, cos), angle| {
+            (sin + angle.sin(), cos + angle.cos())
+        });
+
+    let n = times.len() as f64;
+    let (sin, cos) = (sin / n, cos / n);
+    (sin.atan2(cos), sin.hypot(cos))
+}
+
+/// Circular mean of clock times, so 23:50 and 00:10 average to midnight.
+pub fn circular_mean_time(times: &[NaiveTime]) -> NaiveTime {
+    if times.is_empty() {
+        return NaiveTime::default();
+    }
+
+    let (angle, _) = resultant(times);
+    let seconds = (angle / TAU * SECONDS_PER_DAY)
+        .rem_euclid(SECONDS_PER_DAY)
+        .round() as u32;
+    NaiveTime::from_num_seconds_from_midnight_opt(seconds % 86400, 0).expect("Invalid time")
+}
+
+/// Circular standard deviation of clock times, `sqrt(-2 ln R)` scaled back to a duration.
+pub fn circular_std_time(times: &[NaiveTime]) -> TimeDelta {
+    if times.is_empty() {
+        return TimeDelta::default();
+    }
+
+    let (_, length) = resultant(times);
+    let std = (-2.0 * length.min(1.0).ln()).sqrt();
+    TimeDelta::seconds((std / TAU * SECONDS_PER_DAY).round() as i64)
+}
+
 pub fn mean_deltas(durations: &[TimeDelta]) -> TimeDelta {
     if durations.is_empty() {
         TimeDelta::default()
@@ -179,8 +221,34 @@ mod tests {
 
     #[test]
     fn round_float_basic() {
-        assert_eq!(round_float(3.14159), 3.14);
+        assert_eq!(round_float(1.23456), 1.23);
         assert_eq!(round_float(1.999), 2.0);
         assert_eq!(round_float(0.0), 0.0);
     }
+
+    #[test]
+    fn circular_mean_across_midnight() {
+        let times = vec![
+            NaiveTime::from_hms_opt(23, 50, 0).unwrap(),
+            NaiveTime::from_hms_opt(0, 10, 0).unwrap(),
+        ];
+        assert_eq!(
+            circular_mean_time(&times),
+            NaiveTime::from_hms_opt(0, 0, 0).unwrap()
+        );
+        assert!(circular_std_time(&times) < TimeDelta::minutes(11));
+    }
+
+    #[test]
+    fn circular_std_identical_values() {
+        let t = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+        assert_eq!(circular_mean_time(&[t, t]), t);
+        assert_eq!(circular_std_time(&[t, t]), TimeDelta::zero());
+    }
+
+    #[test]
+    fn circular_empty() {
+        assert_eq!(circular_mean_time(&[]), NaiveTime::default());
+        assert_eq!(circular_std_time(&[]), TimeDelta::default());
+    }
 }
@@ -0,0 +1,43 @@
+/// Unit system derived values are printed in. Values are always stored metric.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    /// `celsius` as e.g. `34.0 °C`, or `93.2 °F` in imperial units
+    pub fn format_temperature(self, celsius: f64) -> String {
+        match self {
+            Self::Metric => format!("{:.1} °C", celsius),
+            Self::Imperial => format!("{:.1} °F", celsius * 9.0 / 5.0 + 32.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SkinTempCalculator;
+    use chrono::NaiveDate;
+    use openwhoop_codec::SensorData;
+
+    #[test]
+    fn skin_temperature_prints_in_each_unit_system() {
+        let time = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(3, 0, 0)
+            .unwrap();
+        let sensor_data = SensorData {
+            skin_temp_raw: 850,
+            ..Default::default()
+        };
+        let celsius = SkinTempCalculator::convert(time, sensor_data.skin_temp_raw)
+            .unwrap()
+            .temp_celsius;
+
+        assert_eq!(UnitSystem::Metric.format_temperature(celsius), "34.0 °C");
+        assert_eq!(UnitSystem::Imperial.format_temperature(celsius), "93.2 °F");
+    }
+}
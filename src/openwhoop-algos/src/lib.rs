@@ -1,25 +1,37 @@
 pub(crate) mod activity;
-pub use activity::{ActivityPeriod, MAX_SLEEP_PAUSE};
+pub use activity::{ActivityConfig, ActivityPeriod, MAX_SLEEP_PAUSE};
+
+pub(crate) mod activity_type;
+pub use activity_type::{ActivityClassifier, ActivityClassifierConfig, ActivityGuess};
 
 pub(crate) mod sleep;
-pub use sleep::SleepCycle;
+pub use sleep::{HrvConfig, SleepCycle, SleepScoreConfig};
 
 pub(crate) mod sleep_consistency;
-pub use sleep_consistency::SleepConsistencyAnalyzer;
+pub use sleep_consistency::{CircularTimeMetric, SleepConsistencyAnalyzer};
 
 pub(crate) mod stress;
-pub use stress::{StressCalculator, StressScore};
+pub use stress::{StressCalculator, StressConfig, StressLevel, StressScore};
 
 pub(crate) mod exercise;
-pub use exercise::ExerciseMetrics;
+pub use exercise::{CalorieConfig, ExerciseMetrics, Sex, acwr};
 
 pub(crate) mod strain;
-pub use strain::{StrainCalculator, StrainScore};
+pub use strain::{StrainCalculator, StrainScore, ZoneModel};
 
 pub(crate) mod spo2;
 pub use spo2::{SpO2Calculator, SpO2Reading, SpO2Score};
 
+pub(crate) mod recovery;
+pub use recovery::RecoveryCalculator;
+
 pub(crate) mod temperature;
 pub use temperature::{SkinTempCalculator, SkinTempScore};
 
+pub(crate) mod trends;
+pub use trends::{Trend, WeekSummary, WeekTrends, WeeklyTrends, resting_hr_trend};
+
+pub(crate) mod smart_alarm;
+pub use smart_alarm::smart_wake_time;
+
 pub mod helpers;
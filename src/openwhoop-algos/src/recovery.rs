@@ -0,0 +1,183 @@
+use chrono::TimeDelta;
+
+use crate::helpers::time_math::{mean, round_float};
+
+use super::SleepCycle;
+
+/// Recovery of a night, 0-100, from its resting heart rate and HRV relative to the
+/// user's own baseline: the nights of the `baseline_days` before it. HRV is taken from
+/// deep sleep when the night and every baseline night have it, see [`SleepCycle::deep_hrv`],
+/// and from the whole night otherwise so both sides use the same measure.
+///
+/// Each part scores 0.5 at baseline. HRV reaches full marks `1 / hrv_sensitivity / 2` above
+/// it (40% by default) and resting heart rate `1 / rhr_sensitivity / 2` below it (10%).
+/// The defaults follow how far each usually moves between nights: nightly HRV swings by
+/// tens of percent, resting heart rate by a few beats. HRV is weighted more heavily
+/// (`hrv_weight`, 0.7) as it reacts sooner to strain and illness, resting heart rate keeps
+/// a single noisy HRV night from swinging the score on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecoveryCalculator {
+    pub baseline_days: u32,
+    /// Nights the baseline needs before a night is scored
+    pub min_baseline_nights: usize,
+    pub hrv_sensitivity: f64,
+    pub rhr_sensitivity: f64,
+    /// Share of HRV in the score, the rest is resting heart rate
+    pub hrv_weight: f64,
+}
+
+impl Default for RecoveryCalculator {
+    fn default() -> Self {
+        Self {
+            baseline_days: 30,
+            min_baseline_nights: 4,
+            hrv_sensitivity: 1.25,
+            rhr_sensitivity: 5.0,
+            hrv_weight: 0.7,
+        }
+    }
+}
+
+impl RecoveryCalculator {
+    /// Scores `night` against the nights of `sleeps` within its baseline, `None` if there
+    /// are too few of them or the night has no HRV (no RR intervals were recorded).
+    pub fn score(&self, night: &SleepCycle, sleeps: &[SleepCycle]) -> Option<f64> {
+        if night.avg_hrv == 0 || night.min_bpm == 0 {
+            return None;
+        }
+
+        let from = night.id - TimeDelta::days(i64::from(self.baseline_days));
+        let baseline = sleeps
+            .iter()
+            .filter(|s| s.id >= from && s.id < night.id)
+            .filter(|s| s.avg_hrv > 0 && s.min_bpm > 0)
+            .collect::<Vec<_>>();
+        if baseline.len() < self.min_baseline_nights.max(1) {
+            return None;
+        }
+
+        // Nights stored before deep sleep HRV was computed don't have it
+        let has_deep = |s: &SleepCycle| s.deep_hrv.is_some_and(|hrv| hrv > 0);
+        let deep = has_deep(night) && baseline.iter().all(|s| has_deep(s));
+        let hrv = |s: &SleepCycle| match s.deep_hrv {
+            Some(deep_hrv) if deep => deep_hrv,
+            _ => s.avg_hrv,
+        };
+
+        let baseline_hrv = mean(
+            &baseline
+                .iter()
+                .map(|s| f64::from(hrv(s)))
+                .collect::<Vec<_>>(),
+        );
+        let baseline_rhr = mean(
+            &baseline
+                .iter()
+                .map(|s| f64::from(s.min_bpm))
+                .collect::<Vec<_>>(),
+        );
+
+        let hrv_change = f64::from(hrv(night)) / baseline_hrv - 1.0;
+        let rhr_change = 1.0 - f64::from(night.min_bpm) / baseline_rhr;
+        let hrv = (0.5 + hrv_change * self.hrv_sensitivity).clamp(0.0, 1.0);
+        let rhr = (0.5 + rhr_change * self.rhr_sensitivity).clamp(0.0, 1.0);
+
+        let hrv_weight = self.hrv_weight.clamp(0.0, 1.0);
+        Some(round_float(
+            (hrv * hrv_weight + rhr * (1.0 - hrv_weight)) * 100.0,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn night(offset: i64, min_bpm: u8, avg_hrv: u16) -> SleepCycle {
+        let end = (NaiveDate::from_ymd_opt(2025, 1, 1).unwrap() + TimeDelta::days(offset))
+            .and_hms_opt(7, 0, 0)
+            .unwrap();
+        SleepCycle {
+            min_bpm,
+            avg_hrv,
+            ..SleepCycle::fixture(end - TimeDelta::hours(8), end)
+        }
+    }
+
+    fn baseline() -> Vec<SleepCycle> {
+        (0..7).map(|i| night(i, 50, 60)).collect()
+    }
+
+    #[test]
+    fn night_at_baseline_scores_half() {
+        let calculator = RecoveryCalculator::default();
+        assert_eq!(calculator.score(&night(7, 50, 60), &baseline()), Some(50.0));
+    }
+
+    #[test]
+    fn higher_hrv_and_lower_resting_hr_recover_better() {
+        let calculator = RecoveryCalculator::default();
+        let sleeps = baseline();
+
+        let rested = calculator.score(&night(7, 47, 78), &sleeps).unwrap();
+        let strained = calculator.score(&night(7, 55, 45), &sleeps).unwrap();
+        assert!(rested > 50.0, "{rested}");
+        assert!(strained < 50.0, "{strained}");
+
+        // Far off the baseline clamps to the ends of the scale
+        assert_eq!(calculator.score(&night(7, 40, 200), &sleeps), Some(100.0));
+        assert_eq!(calculator.score(&night(7, 70, 10), &sleeps), Some(0.0));
+    }
+
+    #[test]
+    fn needs_a_baseline() {
+        let calculator = RecoveryCalculator::default();
+        let sleeps = baseline();
+
+        assert_eq!(calculator.score(&night(7, 50, 60), &sleeps[..3]), None);
+        // Nights after the one scored aren't its baseline
+        assert_eq!(calculator.score(&night(0, 50, 60), &sleeps), None);
+        // Nor are nights older than the baseline period
+        assert_eq!(calculator.score(&night(60, 50, 60), &sleeps), None);
+        // A night without RR intervals has no HRV to compare
+        assert_eq!(calculator.score(&night(7, 50, 0), &sleeps), None);
+    }
+
+    #[test]
+    fn deep_sleep_hrv_is_used_when_known() {
+        let calculator = RecoveryCalculator::default();
+        let sleeps = baseline();
+
+        // Same whole-night HRV as the baseline, but higher during deep sleep
+        let rested = SleepCycle {
+            deep_hrv: Some(78),
+            ..night(7, 50, 60)
+        };
+
+        // Baseline nights without deep sleep HRV compare whole-night HRV on both sides
+        assert_eq!(calculator.score(&rested, &sleeps), Some(50.0));
+        let mut mixed = sleeps.clone();
+        mixed[6].deep_hrv = Some(60);
+        assert_eq!(calculator.score(&rested, &mixed), Some(50.0));
+
+        // Once every night has it, deep sleep HRV is compared
+        let sleeps = sleeps
+            .into_iter()
+            .map(|s| SleepCycle {
+                deep_hrv: Some(60),
+                ..s
+            })
+            .collect::<Vec<_>>();
+        assert!(calculator.score(&rested, &sleeps).unwrap() > 50.0);
+
+        let sleeps = sleeps
+            .into_iter()
+            .map(|s| SleepCycle {
+                deep_hrv: Some(78),
+                ..s
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(calculator.score(&rested, &sleeps), Some(50.0));
+    }
+}
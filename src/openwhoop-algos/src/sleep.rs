@@ -1,7 +1,108 @@
 use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
-use openwhoop_codec::ParsedHistoryReading;
+use openwhoop_codec::{Activity, ParsedHistoryReading};
 
 use super::ActivityPeriod;
+use crate::helpers::hrv::{pnn50_of_nn, sdnn_of_nn};
+
+/// Beats on each side of an RR interval used for its local median
+const ARTIFACT_NEIGHBOURS: usize = 5;
+
+/// Heart rate, relative to the night's sleeping average, at or below which sleep is treated
+/// as deep
+const DEEP_SLEEP_HR_RATIO: f64 = 0.95;
+
+/// Strictness of the RR artifact rejection applied before HRV is aggregated.
+///
+/// Intervals outside `min_rr..=max_rr` are dropped, as are intervals deviating from the
+/// median of their neighbours by more than `max_deviation` (a fraction of that median).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HrvConfig {
+    /// Shortest plausible RR interval in ms (200 bpm)
+    pub min_rr: u16,
+    /// Longest plausible RR interval in ms (30 bpm)
+    pub max_rr: u16,
+    /// Allowed deviation from the local median, lower values reject more beats
+    pub max_deviation: f64,
+}
+
+impl Default for HrvConfig {
+    fn default() -> Self {
+        Self {
+            min_rr: 300,
+            max_rr: 2000,
+            max_deviation: 0.2,
+        }
+    }
+}
+
+/// Weighting of [`SleepCycle::score`]. Each part scores 0 to 1 and the score is their
+/// weighted mean, scaled to 0-100.
+///
+/// The default scores duration alone, in whole multiples of `ideal_duration`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SleepScoreConfig {
+    /// Time asleep that scores full marks for duration
+    pub ideal_duration: TimeDelta,
+    pub duration_weight: f64,
+    /// Weight of [`SleepCycle::efficiency`], left out for nights that weren't staged
+    pub efficiency_weight: f64,
+    /// Average RMSSD in ms that scores full marks for HRV
+    pub ideal_hrv: u16,
+    pub hrv_weight: f64,
+    /// Wake-ups during the night that score nothing for disturbances
+    pub max_disturbances: usize,
+    /// Weight of the wake-ups, left out for nights that weren't staged
+    pub disturbance_weight: f64,
+}
+
+impl Default for SleepScoreConfig {
+    fn default() -> Self {
+        Self {
+            ideal_duration: TimeDelta::hours(8),
+            duration_weight: 1.0,
+            efficiency_weight: 0.0,
+            ideal_hrv: 80,
+            hrv_weight: 0.0,
+            max_disturbances: 10,
+            disturbance_weight: 0.0,
+        }
+    }
+}
+
+impl SleepScoreConfig {
+    pub fn score(
+        &self,
+        duration: TimeDelta,
+        efficiency: Option<f64>,
+        avg_hrv: u16,
+        disturbances: Option<usize>,
+    ) -> f64 {
+        let ideal = self.ideal_duration.num_seconds().max(1);
+        let duration = (duration.num_seconds() / ideal) as f64;
+        let hrv = f64::from(avg_hrv) / f64::from(self.ideal_hrv.max(1));
+        let calm = disturbances.map(|d| 1.0 - d as f64 / self.max_disturbances.max(1) as f64);
+
+        let parts = [
+            Some((self.duration_weight, duration)),
+            efficiency.map(|e| (self.efficiency_weight, e / 100.0)),
+            Some((self.hrv_weight, hrv)),
+            calm.map(|c| (self.disturbance_weight, c)),
+        ];
+
+        let mut weighted = 0.0;
+        let mut weights = 0.0;
+        for (weight, part) in parts.into_iter().flatten() {
+            weighted += weight * part.clamp(0.0, 1.0);
+            weights += weight;
+        }
+
+        if weights <= 0.0 {
+            return 0.0;
+        }
+
+        (weighted / weights * 100.0).clamp(0.0, 100.0)
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SleepCycle {
@@ -13,35 +114,75 @@ pub struct SleepCycle {
     pub avg_bpm: u8,
     pub min_hrv: u16,
     pub max_hrv: u16,
+    /// Mean RMSSD over rolling windows, in ms
     pub avg_hrv: u16,
+    /// SDNN over the whole night in ms, `None` for nights stored before it was computed
+    pub sdnn: Option<f64>,
+    /// Percentage of successive RR differences over 50 ms, `None` like [`Self::sdnn`]
+    pub pnn50: Option<f64>,
+    /// Mean RMSSD over deep sleep only in ms, see [`Self::deep_sleep_hrv`]
+    pub deep_hrv: Option<u16>,
     pub score: f64,
+    /// Percentage of the time in bed spent asleep, `None` if the night wasn't staged
+    pub efficiency: Option<f64>,
 }
 
 impl SleepCycle {
+    /// Computes the sleep cycle using the default [`HrvConfig`].
     pub fn from_event(event: ActivityPeriod, history: &[ParsedHistoryReading]) -> SleepCycle {
+        Self::from_event_with_config(
+            event,
+            history,
+            &HrvConfig::default(),
+            &SleepScoreConfig::default(),
+        )
+    }
+
+    pub fn from_event_with_config(
+        event: ActivityPeriod,
+        history: &[ParsedHistoryReading],
+        config: &HrvConfig,
+        score_config: &SleepScoreConfig,
+    ) -> SleepCycle {
         let (heart_rate, rr): (Vec<u64>, Vec<Vec<_>>) = history
             .iter()
             .filter(|h| h.time >= event.start && h.time <= event.end)
             .map(|h| (h.bpm as u64, h.rr.clone()))
             .unzip();
 
-        let rr = Self::clean_rr(rr);
+        let rr = Self::reject_artifacts(Self::clean_rr(rr), config);
+        let sdnn = sdnn_of_nn(&rr);
+        let pnn50 = pnn50_of_nn(&rr);
         let rolling_hrv = Self::rolling_hrv(rr);
 
         let min_hrv = rolling_hrv.iter().min().copied().unwrap_or_default() as u16;
         let max_hrv = rolling_hrv.iter().max().copied().unwrap_or_default() as u16;
 
         let hrv_count = rolling_hrv.len() as u64;
-        let hrv = rolling_hrv.into_iter().sum::<u64>() / hrv_count;
+        // No window survives artifact rejection on a noisy night, or one without RR data
+        let hrv = rolling_hrv
+            .into_iter()
+            .sum::<u64>()
+            .checked_div(hrv_count)
+            .unwrap_or_default();
         let avg_hrv = hrv as u16;
 
         let min_bpm = heart_rate.iter().min().copied().unwrap_or_default() as u8;
         let max_bpm = heart_rate.iter().max().copied().unwrap_or_default() as u8;
 
         let heart_rate_count = heart_rate.len() as u64;
-        let bpm = heart_rate.into_iter().sum::<u64>() / heart_rate_count;
+        let bpm = heart_rate
+            .into_iter()
+            .sum::<u64>()
+            .checked_div(heart_rate_count)
+            .unwrap_or_default();
         let avg_bpm = bpm as u8;
 
+        let deep_hrv = Self::deep_sleep_hrv(event, history, config);
+        let efficiency = Self::efficiency(event, history);
+        let disturbances = Self::disturbances(event, history);
+        let score = score_config.score(event.end - event.start, efficiency, avg_hrv, disturbances);
+
         let id = event.end.date();
 
         Self {
@@ -54,8 +195,99 @@ impl SleepCycle {
             min_hrv,
             max_hrv,
             avg_hrv,
-            score: Self::sleep_score(event.start, event.end),
+            sdnn,
+            pnn50,
+            deep_hrv,
+            score,
+            efficiency,
+        }
+    }
+
+    /// Asleep time over time in bed, using the strap's per reading activity as sleep epochs.
+    ///
+    /// Readings without a sleep classification anywhere in the night (e.g. from a strap that
+    /// reported no activity) mean staging is unavailable, so this returns `None`.
+    pub fn efficiency(event: ActivityPeriod, history: &[ParsedHistoryReading]) -> Option<f64> {
+        let epochs = history
+            .iter()
+            .filter(|h| h.time >= event.start && h.time <= event.end)
+            .map(|h| h.activity)
+            .filter(|a| *a != Activity::Unknown)
+            .collect::<Vec<_>>();
+
+        let asleep = epochs.iter().filter(|a| **a == Activity::Sleep).count();
+        if asleep == 0 {
+            return None;
         }
+
+        Some(asleep as f64 / epochs.len() as f64 * 100.0)
+    }
+
+    /// Times the night went from asleep to awake, `None` like [`Self::efficiency`]
+    pub fn disturbances(event: ActivityPeriod, history: &[ParsedHistoryReading]) -> Option<usize> {
+        let epochs = history
+            .iter()
+            .filter(|h| h.time >= event.start && h.time <= event.end)
+            .map(|h| h.activity)
+            .filter(|a| *a != Activity::Unknown)
+            .collect::<Vec<_>>();
+
+        if !epochs.contains(&Activity::Sleep) {
+            return None;
+        }
+
+        let wake_ups = epochs
+            .windows(2)
+            .filter(|w| w[0] == Activity::Sleep && w[1] != Activity::Sleep)
+            .count();
+        Some(wake_ups)
+    }
+
+    /// Mean RMSSD over deep sleep, where HRV isn't disturbed by movement and arousals. This
+    /// is the HRV recovery should be based on, whole-night HRV averages in the awake time.
+    ///
+    /// There is no sleep stage classifier yet, so deep sleep is approximated from what the
+    /// strap reports: readings it classifies as sleep with a heart rate at or below the
+    /// night's sleeping average by [`DEEP_SLEEP_HR_RATIO`]. RMSSD windows are taken within
+    /// uninterrupted stretches of deep sleep only.
+    ///
+    /// Returns `None` if no stretch held enough beats for a window.
+    pub fn deep_sleep_hrv(
+        event: ActivityPeriod,
+        history: &[ParsedHistoryReading],
+        config: &HrvConfig,
+    ) -> Option<u16> {
+        let night = history
+            .iter()
+            .filter(|h| h.time >= event.start && h.time <= event.end)
+            .collect::<Vec<_>>();
+
+        let sleeping = night
+            .iter()
+            .filter(|h| h.activity == Activity::Sleep)
+            .map(|h| f64::from(h.bpm))
+            .collect::<Vec<_>>();
+        if sleeping.is_empty() {
+            return None;
+        }
+        let threshold = sleeping.iter().sum::<f64>() / sleeping.len() as f64 * DEEP_SLEEP_HR_RATIO;
+        let deep = |h: &ParsedHistoryReading| {
+            h.activity == Activity::Sleep && f64::from(h.bpm) <= threshold
+        };
+
+        let rolling_hrv = night
+            .chunk_by(|a, b| deep(a) == deep(b))
+            .filter(|stretch| deep(stretch[0]))
+            .flat_map(|stretch| {
+                let rr = stretch.iter().map(|h| h.rr.clone()).collect();
+                Self::rolling_hrv(Self::reject_artifacts(Self::clean_rr(rr), config))
+            })
+            .collect::<Vec<_>>();
+        if rolling_hrv.is_empty() {
+            return None;
+        }
+
+        Some((rolling_hrv.iter().sum::<u64>() / rolling_hrv.len() as u64) as u16)
     }
 
     pub fn duration(&self) -> TimeDelta {
@@ -70,6 +302,29 @@ impl SleepCycle {
             .collect()
     }
 
+    /// Drops ectopic beats and missed or doubled detections, a single one of them is
+    /// enough to push a window's RMSSD to an impossible value.
+    pub(crate) fn reject_artifacts(rr: Vec<u64>, config: &HrvConfig) -> Vec<u64> {
+        let range = u64::from(config.min_rr)..=u64::from(config.max_rr);
+        let rr = rr
+            .into_iter()
+            .filter(|v| range.contains(v))
+            .collect::<Vec<_>>();
+
+        (0..rr.len())
+            .filter(|&i| {
+                let start = i.saturating_sub(ARTIFACT_NEIGHBOURS);
+                let end = (i + ARTIFACT_NEIGHBOURS + 1).min(rr.len());
+                let mut neighbours = rr[start..end].to_vec();
+                neighbours.sort_unstable();
+                let median = neighbours[neighbours.len() / 2] as f64;
+
+                (rr[i] as f64 - median).abs() <= median * config.max_deviation
+            })
+            .map(|i| rr[i])
+            .collect()
+    }
+
     fn rolling_hrv(rr: Vec<u64>) -> Vec<u64> {
         rr.windows(300).filter_map(Self::calculate_rmssd).collect()
     }
@@ -88,13 +343,32 @@ impl SleepCycle {
         Some((rr_diff.into_iter().sum::<f64>() / rr_count).sqrt() as u64)
     }
 
+    /// Score of a night from its duration alone, as [`SleepScoreConfig::default`] scores it
     pub fn sleep_score(start: NaiveDateTime, end: NaiveDateTime) -> f64 {
-        let duration = (end - start).num_seconds();
-        const IDEAL_DURATION: i64 = 60 * 60 * 8;
-
-        let score = (duration / IDEAL_DURATION) as f64;
+        SleepScoreConfig::default().score(end - start, None, 0, None)
+    }
+}
 
-        (score * 100.0).clamp(0.0, 100.0)
+#[cfg(any(test, feature = "test-utils"))]
+impl SleepCycle {
+    /// Night between `start` and `end` with plausible vitals, for tests to override
+    pub fn fixture(start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        Self {
+            id: end.date(),
+            start,
+            end,
+            min_bpm: 50,
+            max_bpm: 70,
+            avg_bpm: 60,
+            min_hrv: 30,
+            max_hrv: 80,
+            avg_hrv: 55,
+            sdnn: None,
+            pnn50: None,
+            deep_hrv: None,
+            score: 100.0,
+            efficiency: None,
+        }
     }
 }
 
@@ -133,15 +407,7 @@ mod tests {
     fn duration_returns_difference() {
         let cycle = SleepCycle {
             id: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
-            start: dt(22, 0),
-            end: dt(22, 0) + TimeDelta::hours(8),
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
+            ..SleepCycle::fixture(dt(22, 0), dt(22, 0) + TimeDelta::hours(8))
         };
         assert_eq!(cycle.duration(), TimeDelta::hours(8));
     }
@@ -178,6 +444,51 @@ mod tests {
         assert!(SleepCycle::calculate_rmssd(&[800]).is_none());
     }
 
+    #[test]
+    fn reject_artifacts_drops_outliers() {
+        let rr = vec![1000, 1010, 990, 250, 1000, 2500, 1020, 1600, 1000, 980];
+        let result = SleepCycle::reject_artifacts(rr, &HrvConfig::default());
+        assert_eq!(result, vec![1000, 1010, 990, 1000, 1020, 1000, 980]);
+
+        let lenient = HrvConfig {
+            max_deviation: 1.0,
+            ..HrvConfig::default()
+        };
+        let rr = vec![1000, 1600, 1000];
+        assert_eq!(SleepCycle::reject_artifacts(rr.clone(), &lenient), rr);
+    }
+
+    #[test]
+    fn artifacts_do_not_skew_hrv_bounds() {
+        let base = dt(22, 0);
+        let event = ActivityPeriod {
+            activity: Activity::Sleep,
+            start: base,
+            end: base + TimeDelta::hours(8),
+            duration: TimeDelta::hours(8),
+        };
+        // Alternating 1000/1050 ms has an RMSSD of 50, every 40th beat is an artifact
+        let history: Vec<ParsedHistoryReading> = (0..1000)
+            .map(|i| ParsedHistoryReading {
+                time: base + TimeDelta::seconds(i * 20),
+                bpm: 60,
+                rr: match i % 40 {
+                    13 => vec![1000, 400],
+                    27 => vec![1900],
+                    39 => vec![5000],
+                    _ if i % 2 == 0 => vec![1000],
+                    _ => vec![1050],
+                },
+                activity: Activity::Sleep,
+                imu_data: None,
+            })
+            .collect();
+
+        let cycle = SleepCycle::from_event(event, &history);
+        assert!(cycle.min_hrv >= 40, "min_hrv {}", cycle.min_hrv);
+        assert!(cycle.max_hrv <= 60, "max_hrv {}", cycle.max_hrv);
+    }
+
     #[test]
     fn rolling_hrv_needs_300_samples() {
         // Less than 300 samples -> no windows -> empty result
@@ -216,5 +527,147 @@ mod tests {
         assert_eq!(cycle.max_bpm, 60);
         assert_eq!(cycle.avg_bpm, 60);
         assert_eq!(cycle.score, 100.0);
+        assert_eq!(cycle.efficiency, Some(100.0));
+    }
+
+    #[test]
+    fn deep_sleep_hrv_leaves_out_light_sleep_and_awake_time() {
+        let base = dt(22, 0);
+        // One beat per second, each stage with its own heart rate and RMSSD
+        let stages = [
+            (300, Activity::Awake, 75, (780, 880)),
+            (600, Activity::Sleep, 62, (950, 970)),
+            (600, Activity::Sleep, 52, (1130, 1190)),
+            (600, Activity::Sleep, 62, (950, 970)),
+            (300, Activity::Awake, 75, (780, 880)),
+        ];
+        let history = stages
+            .iter()
+            .flat_map(|&(seconds, activity, bpm, (even, odd))| {
+                (0..seconds).map(move |i| (activity, bpm, if i % 2 == 0 { even } else { odd }))
+            })
+            .enumerate()
+            .map(|(i, (activity, bpm, rr))| ParsedHistoryReading {
+                time: base + TimeDelta::seconds(i as i64),
+                bpm,
+                rr: vec![rr],
+                activity,
+                imu_data: None,
+            })
+            .collect::<Vec<_>>();
+        let end = history.last().unwrap().time;
+        let event = ActivityPeriod {
+            activity: Activity::Sleep,
+            start: base,
+            end,
+            duration: end - base,
+        };
+
+        let cycle = SleepCycle::from_event(event, &history);
+        assert_eq!(cycle.deep_hrv, Some(60));
+        assert_ne!(cycle.avg_hrv, 60);
+    }
+
+    fn night(stages: &[Activity]) -> (ActivityPeriod, Vec<ParsedHistoryReading>) {
+        let base = dt(22, 0);
+        let history = stages
+            .iter()
+            .enumerate()
+            .map(|(i, &activity)| ParsedHistoryReading {
+                time: base + TimeDelta::minutes(i as i64),
+                bpm: 55,
+                rr: vec![],
+                activity,
+                imu_data: None,
+            })
+            .collect::<Vec<_>>();
+
+        let end = base + TimeDelta::minutes(stages.len() as i64 - 1);
+        let event = ActivityPeriod {
+            activity: Activity::Sleep,
+            start: base,
+            end,
+            duration: end - base,
+        };
+        (event, history)
+    }
+
+    #[test]
+    fn efficiency_counts_awake_time() {
+        // 8h in bed, 20 minutes to fall asleep and 40 minutes awake during the night
+        let mut stages = vec![Activity::Sleep; 480];
+        stages[..20].fill(Activity::Awake);
+        stages[200..240].fill(Activity::Awake);
+
+        let (event, history) = night(&stages);
+        assert_eq!(SleepCycle::efficiency(event, &history), Some(87.5));
+    }
+
+    #[test]
+    fn efficiency_ignores_unknown_epochs() {
+        let mut stages = vec![Activity::Sleep; 100];
+        stages[..10].fill(Activity::Unknown);
+        stages[10..28].fill(Activity::Inactive);
+
+        let (event, history) = night(&stages);
+        assert_eq!(SleepCycle::efficiency(event, &history), Some(80.0));
+    }
+
+    #[test]
+    fn score_weights_change_the_score() {
+        // 8h in bed with three wake-ups
+        let mut stages = vec![Activity::Sleep; 481];
+        stages[100..110].fill(Activity::Awake);
+        stages[200..230].fill(Activity::Awake);
+        stages[300..310].fill(Activity::Awake);
+
+        let (event, mut history) = night(&stages);
+        // Steady RR intervals, HRV is averaged over them
+        for reading in &mut history {
+            reading.rr = vec![1090];
+        }
+        assert_eq!(SleepCycle::disturbances(event, &history), Some(3));
+
+        let default = SleepCycle::from_event(event, &history);
+        assert_eq!(default.score, 100.0);
+
+        let balanced = SleepScoreConfig {
+            efficiency_weight: 1.0,
+            disturbance_weight: 1.0,
+            ..Default::default()
+        };
+        let cycle =
+            SleepCycle::from_event_with_config(event, &history, &HrvConfig::default(), &balanced);
+        assert!(cycle.score < default.score, "{}", cycle.score);
+        // Duration is full marks, efficiency (431 of 481) and disturbances (7 of 10) aren't
+        let expected = (1.0 + 431.0 / 481.0 + 0.7) / 3.0 * 100.0;
+        assert!((cycle.score - expected).abs() < 1e-9, "{}", cycle.score);
+    }
+
+    #[test]
+    fn nights_without_rr_or_readings_dont_panic() {
+        let (event, history) = night(&[Activity::Sleep; 60]);
+        let cycle = SleepCycle::from_event(event, &history);
+        assert_eq!((cycle.min_hrv, cycle.max_hrv, cycle.avg_hrv), (0, 0, 0));
+        assert_eq!(cycle.avg_bpm, 55);
+
+        // Every interval rejected as an artifact
+        let (event, mut history) = night(&[Activity::Sleep; 60]);
+        for reading in &mut history {
+            reading.rr = vec![100];
+        }
+        assert_eq!(SleepCycle::from_event(event, &history).avg_hrv, 0);
+
+        let cycle = SleepCycle::from_event(event, &[]);
+        assert_eq!((cycle.min_bpm, cycle.avg_bpm), (0, 0));
+    }
+
+    #[test]
+    fn efficiency_without_staging_is_none() {
+        let (event, history) = night(&[Activity::Inactive; 60]);
+        assert_eq!(SleepCycle::efficiency(event, &history), None);
+
+        let (event, _) = night(&[Activity::Sleep; 60]);
+        assert_eq!(SleepCycle::efficiency(event, &[]), None);
     }
 }
@@ -1,20 +1,29 @@
 use std::fmt::{Debug, Display};
 
-use chrono::{NaiveTime, TimeDelta, Timelike};
+use chrono::{NaiveDate, NaiveTime, TimeDelta, Timelike};
 
 use crate::helpers::{
     format_hm::FormatHM,
-    time_math::{mean, mean_deltas, mean_time, round_float, std_dev_delta, std_time},
+    time_math::{
+        circular_mean_time, circular_std_time, mean, mean_deltas, mean_time, round_float,
+        std_dev_delta, std_time,
+    },
 };
 
 use super::SleepCycle;
 
 #[derive(Default)]
 pub struct SleepConsistencyAnalyzer {
+    nights: Vec<NaiveDate>,
     durations: Vec<TimeDelta>,
     start_times: Vec<NaiveTime>,
     end_times: Vec<NaiveTime>,
     midpoints: Vec<NaiveTime>,
+    efficiencies: Vec<Option<f64>>,
+    rmssd: Vec<Option<f64>>,
+    sdnn: Vec<Option<f64>>,
+    pnn50: Vec<Option<f64>>,
+    deep_rmssd: Vec<Option<f64>>,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -23,7 +32,35 @@ pub struct SleepMetrics {
     pub start_time: DurationMetric<NaiveTime>,
     pub end_time: DurationMetric<NaiveTime>,
     pub midpoint: DurationMetric<NaiveTime>,
+    pub bedtime: CircularTimeMetric,
+    pub wake_time: CircularTimeMetric,
     pub score: ConsistencyScore,
+    /// Mean sleep efficiency of the nights that have one
+    pub efficiency: Option<f64>,
+    /// Mean of the nightly average RMSSD in ms
+    pub rmssd: Option<f64>,
+    /// Mean nightly SDNN in ms, of the nights that have one
+    pub sdnn: Option<f64>,
+    /// Mean nightly pNN50 in %, of the nights that have one
+    pub pnn50: Option<f64>,
+    /// Mean nightly RMSSD during deep sleep in ms, of the nights that have one
+    pub deep_rmssd: Option<f64>,
+}
+
+/// Clock time statistics on the 24h circle, so times around midnight stay close together.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CircularTimeMetric {
+    pub mean: NaiveTime,
+    pub std: TimeDelta,
+}
+
+impl CircularTimeMetric {
+    fn new(times: &[NaiveTime]) -> Self {
+        Self {
+            mean: circular_mean_time(times),
+            std: circular_std_time(times),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
@@ -52,13 +89,45 @@ impl SleepConsistencyAnalyzer {
             let start = cycle.start;
             let end = cycle.end;
 
+            self.nights.push(end.date());
             self.durations.push(end - start);
             self.start_times.push(start.time());
             self.end_times.push(end.time());
             self.midpoints.push((start + ((end - start) / 2)).time());
+            self.efficiencies.push(cycle.efficiency);
+            self.rmssd.push(Some(f64::from(cycle.avg_hrv)));
+            self.sdnn.push(cycle.sdnn);
+            self.pnn50.push(cycle.pnn50);
+            self.deep_rmssd.push(cycle.deep_hrv.map(f64::from));
         }
     }
 
+    /// Restricts the analyzer to the last `days` nights, counted back from the most recent one.
+    pub fn window(&self, days: u32) -> Self {
+        let Some(latest) = self.nights.iter().max() else {
+            return Self::default();
+        };
+        let cutoff = *latest - TimeDelta::days(i64::from(days)) + TimeDelta::days(1);
+
+        let mut analyzer = Self::default();
+        for (i, night) in self.nights.iter().enumerate() {
+            if *night >= cutoff {
+                analyzer.nights.push(*night);
+                analyzer.durations.push(self.durations[i]);
+                analyzer.start_times.push(self.start_times[i]);
+                analyzer.end_times.push(self.end_times[i]);
+                analyzer.midpoints.push(self.midpoints[i]);
+                analyzer.efficiencies.push(self.efficiencies[i]);
+                analyzer.rmssd.push(self.rmssd[i]);
+                analyzer.sdnn.push(self.sdnn[i]);
+                analyzer.pnn50.push(self.pnn50[i]);
+                analyzer.deep_rmssd.push(self.deep_rmssd[i]);
+            }
+        }
+
+        analyzer
+    }
+
     pub fn calculate_consistency_metrics(&self) -> SleepMetrics {
         if self.durations.is_empty() {
             return SleepMetrics::default();
@@ -103,7 +172,14 @@ impl SleepConsistencyAnalyzer {
             start_time,
             end_time,
             midpoint,
+            bedtime: CircularTimeMetric::new(&self.start_times),
+            wake_time: CircularTimeMetric::new(&self.end_times),
             score,
+            efficiency: mean_of_present(&self.efficiencies),
+            rmssd: mean_of_present(&self.rmssd),
+            sdnn: mean_of_present(&self.sdnn),
+            pnn50: mean_of_present(&self.pnn50),
+            deep_rmssd: mean_of_present(&self.deep_rmssd),
         }
     }
 
@@ -156,23 +232,67 @@ where
     }
 }
 
+impl Display for CircularTimeMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "STD: {}, Mean: {}",
+            self.std.format_hm(),
+            self.mean.format_hm()
+        ))
+    }
+}
+
 impl Display for SleepMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
-            "Duration: {}\nStart time: {}\nEnd time: {}\nMidpoint: {}\nScores:\n",
+            "Duration: {}\nStart time: {}\nEnd time: {}\nMidpoint: {}\n",
             self.duration, self.start_time, self.end_time, self.midpoint,
         ))?;
         f.write_fmt(format_args!(
-            "\tDuration score: {}\n\tTiming score: {}\n\tOverall score: {}",
+            "Bedtime: {}\nWake time: {}\n",
+            self.bedtime, self.wake_time,
+        ))?;
+        match self.efficiency {
+            Some(efficiency) => f.write_fmt(format_args!("Efficiency: {}%\n", efficiency))?,
+            None => f.write_str("Efficiency: n/a\n")?,
+        }
+        match self.rmssd {
+            Some(rmssd) => f.write_fmt(format_args!("HRV (RMSSD): {} ms\n", rmssd))?,
+            None => f.write_str("HRV (RMSSD): n/a\n")?,
+        }
+        match self.sdnn {
+            Some(sdnn) => f.write_fmt(format_args!("HRV (SDNN): {} ms\n", sdnn))?,
+            None => f.write_str("HRV (SDNN): n/a\n")?,
+        }
+        match self.pnn50 {
+            Some(pnn50) => f.write_fmt(format_args!("HRV (pNN50): {}%\n", pnn50))?,
+            None => f.write_str("HRV (pNN50): n/a\n")?,
+        }
+        match self.deep_rmssd {
+            Some(rmssd) => f.write_fmt(format_args!("HRV (deep sleep): {} ms\n", rmssd))?,
+            None => f.write_str("HRV (deep sleep): n/a\n")?,
+        }
+        f.write_fmt(format_args!(
+            "Scores:\n\tDuration score: {}\n\tTiming score: {}\n\tOverall score: {}",
             self.score.duration_score, self.score.timing_score, self.score.total_score,
         ))?;
         Ok(())
     }
 }
 
+/// Rounded mean of the values that are set, `None` if none are
+fn mean_of_present(values: &[Option<f64>]) -> Option<f64> {
+    let values = values.iter().flatten().copied().collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(round_float(mean(&values)))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::sleep_consistency::{ConsistencyScore, DurationMetric};
+    use crate::sleep_consistency::{ConsistencyScore, DurationMetric, SleepMetrics};
 
     use super::SleepConsistencyAnalyzer;
 
@@ -200,18 +320,7 @@ mod tests {
                     .and_hms_opt(22, 0, 0)
                     .unwrap();
                 let end = start + TimeDelta::hours(8);
-                SleepCycle {
-                    id: end.date(),
-                    start,
-                    end,
-                    min_bpm: 50,
-                    max_bpm: 70,
-                    avg_bpm: 60,
-                    min_hrv: 30,
-                    max_hrv: 80,
-                    avg_hrv: 55,
-                    score: 100.0,
-                }
+                SleepCycle::fixture(start, end)
             })
             .collect();
 
@@ -237,22 +346,112 @@ mod tests {
             .unwrap()
             .and_hms_opt(6, 0, 0)
             .unwrap();
-        let records = vec![SleepCycle {
-            id: end.date(),
-            start,
-            end,
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
-        }];
+        let records = vec![SleepCycle::fixture(start, end)];
 
         let analyzer = SleepConsistencyAnalyzer::new(records);
         let metrics = analyzer.calculate_consistency_metrics();
 
         assert_eq!(metrics.score.duration_score, 100.0);
     }
+
+    #[test]
+    fn window_uses_most_recent_nights() {
+        use crate::SleepCycle;
+        use chrono::{NaiveDate, TimeDelta};
+
+        // 23 irregular nights followed by a perfectly regular week
+        let records: Vec<SleepCycle> = (0..30)
+            .map(|day| {
+                let hour = if day < 23 { 20 + day % 4 } else { 22 };
+                let start = NaiveDate::from_ymd_opt(2025, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(hour, 0, 0)
+                    .unwrap()
+                    + TimeDelta::days(i64::from(day));
+                let end = start + TimeDelta::hours(i64::from(6 + hour % 3));
+                SleepCycle::fixture(start, end)
+            })
+            .collect();
+
+        let analyzer = SleepConsistencyAnalyzer::new(records);
+        let all_time = analyzer.calculate_consistency_metrics();
+        let week = analyzer.window(7).calculate_consistency_metrics();
+        let month = analyzer.window(30).calculate_consistency_metrics();
+
+        assert_eq!(week.score.total_score, 100.0);
+        assert!(all_time.score.total_score < week.score.total_score);
+        assert_eq!(month.score, all_time.score);
+        assert_eq!(analyzer.window(7).durations.len(), 7);
+        assert!(analyzer.window(0).durations.is_empty());
+    }
+
+    #[test]
+    fn bedtime_straddling_midnight_has_small_circular_std() {
+        use crate::SleepCycle;
+        use chrono::{NaiveDate, TimeDelta};
+
+        // Alternate between 23:50 and 00:10 bedtimes
+        let records: Vec<SleepCycle> = (0..10)
+            .map(|day| {
+                let start = NaiveDate::from_ymd_opt(2025, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(23, 50, 0)
+                    .unwrap()
+                    + TimeDelta::days(day)
+                    + TimeDelta::minutes(if day % 2 == 0 { 0 } else { 20 });
+                let end = start + TimeDelta::hours(8);
+                SleepCycle::fixture(start, end)
+            })
+            .collect();
+
+        let metrics = SleepConsistencyAnalyzer::new(records).calculate_consistency_metrics();
+
+        assert_eq!(
+            metrics.bedtime.mean,
+            chrono::NaiveTime::from_hms_opt(0, 0, 0).unwrap()
+        );
+        assert!(metrics.bedtime.std < TimeDelta::minutes(11));
+        assert!(metrics.wake_time.std < TimeDelta::minutes(11));
+    }
+
+    #[test]
+    fn efficiency_and_hrv_average_nights_that_have_them() {
+        use crate::SleepCycle;
+        use chrono::{NaiveDate, TimeDelta};
+
+        let efficiencies = [Some(80.0), None, Some(90.0)];
+        let records: Vec<SleepCycle> = efficiencies
+            .iter()
+            .enumerate()
+            .map(|(day, &efficiency)| {
+                let start = NaiveDate::from_ymd_opt(2025, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(22, 0, 0)
+                    .unwrap()
+                    + TimeDelta::days(day as i64);
+                let end = start + TimeDelta::hours(8);
+                SleepCycle {
+                    sdnn: efficiency.map(|e| e / 2.0),
+                    pnn50: efficiency.map(|e| e / 4.0),
+                    deep_hrv: efficiency.map(|_| 60),
+                    efficiency,
+                    ..SleepCycle::fixture(start, end)
+                }
+            })
+            .collect();
+
+        let analyzer = SleepConsistencyAnalyzer::new(records);
+        let all_time = analyzer.calculate_consistency_metrics();
+        let last_night = analyzer.window(1).calculate_consistency_metrics();
+
+        assert_eq!(all_time.efficiency, Some(85.0));
+        assert_eq!(last_night.efficiency, Some(90.0));
+        assert_eq!(SleepMetrics::default().efficiency, None);
+
+        assert_eq!(all_time.rmssd, Some(55.0));
+        assert_eq!(all_time.sdnn, Some(42.5));
+        assert_eq!(last_night.sdnn, Some(45.0));
+        assert_eq!(all_time.pnn50, Some(21.25));
+        assert_eq!(all_time.deep_rmssd, Some(60.0));
+    }
 }
@@ -0,0 +1,109 @@
+use chrono::NaiveDateTime;
+use openwhoop_codec::{Activity, ParsedHistoryReading};
+
+/// Heart rate, relative to the night's sleeping average, above which sleep is treated as light
+const LIGHT_SLEEP_HR_RATIO: f64 = 1.1;
+
+/// Picks the moment to wake up inside the `earliest..=latest` window.
+///
+/// There is no sleep stage classifier yet, so light sleep is approximated from what the
+/// strap reports: the first reading in the window that the strap no longer classifies as
+/// sleep, or whose heart rate rises above the night's sleeping average by
+/// [`LIGHT_SLEEP_HR_RATIO`]. The average is taken from sleep readings before the window.
+///
+/// Returns `None` if no such moment was found, in which case the alarm should fire at `latest`.
+pub fn smart_wake_time(
+    readings: &[ParsedHistoryReading],
+    earliest: NaiveDateTime,
+    latest: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    let sleeping = readings
+        .iter()
+        .filter(|r| r.time < earliest && r.activity == Activity::Sleep)
+        .map(|r| f64::from(r.bpm))
+        .collect::<Vec<_>>();
+
+    let baseline = if sleeping.is_empty() {
+        None
+    } else {
+        Some(sleeping.iter().sum::<f64>() / sleeping.len() as f64)
+    };
+
+    readings
+        .iter()
+        .filter(|r| r.time >= earliest && r.time <= latest)
+        .find(|r| {
+            r.activity != Activity::Sleep
+                || baseline.is_some_and(|b| f64::from(r.bpm) >= b * LIGHT_SLEEP_HR_RATIO)
+        })
+        .map(|r| r.time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeDelta};
+
+    fn base() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 1, 2)
+            .unwrap()
+            .and_hms_opt(5, 0, 0)
+            .unwrap()
+    }
+
+    /// One reading per minute from 05:00, each entry is `(bpm, activity)`
+    fn night(stages: &[(u8, Activity)]) -> Vec<ParsedHistoryReading> {
+        stages
+            .iter()
+            .enumerate()
+            .map(|(i, &(bpm, activity))| ParsedHistoryReading {
+                time: base() + TimeDelta::minutes(i as i64),
+                bpm,
+                rr: vec![],
+                activity,
+                imu_data: None,
+            })
+            .collect()
+    }
+
+    fn window() -> (NaiveDateTime, NaiveDateTime) {
+        (
+            base() + TimeDelta::minutes(90),
+            base() + TimeDelta::minutes(120),
+        )
+    }
+
+    #[test]
+    fn wakes_at_first_arousal_in_window() {
+        let mut stages = vec![(50, Activity::Sleep); 150];
+        // Arousal before the window is ignored
+        stages[60] = (50, Activity::Inactive);
+        stages[100] = (52, Activity::Inactive);
+        stages[110] = (52, Activity::Inactive);
+
+        let (earliest, latest) = window();
+        let wake = smart_wake_time(&night(&stages), earliest, latest);
+        assert_eq!(wake, Some(base() + TimeDelta::minutes(100)));
+    }
+
+    #[test]
+    fn wakes_when_heart_rate_rises() {
+        let mut stages = vec![(50, Activity::Sleep); 150];
+        stages[95] = (54, Activity::Sleep);
+        stages[105] = (56, Activity::Sleep);
+
+        let (earliest, latest) = window();
+        let wake = smart_wake_time(&night(&stages), earliest, latest);
+        assert_eq!(wake, Some(base() + TimeDelta::minutes(105)));
+    }
+
+    #[test]
+    fn deep_sleep_falls_back_to_latest() {
+        let mut stages = vec![(50, Activity::Sleep); 150];
+        // Light sleep after the window does not count
+        stages[130] = (70, Activity::Inactive);
+
+        let (earliest, latest) = window();
+        assert_eq!(smart_wake_time(&night(&stages), earliest, latest), None);
+    }
+}
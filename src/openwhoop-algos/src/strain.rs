@@ -1,8 +1,43 @@
-use openwhoop_codec::ParsedHistoryReading;
+use openwhoop_codec::{Activity, ParsedHistoryReading};
 
 pub struct StrainCalculator {
     pub max_hr: u8,
     pub resting_hr: u8,
+    pub zones: ZoneModel,
+}
+
+/// Where the five Edwards zones start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZoneModel {
+    /// Textbook zones at 50, 60, 70, 80 and 90% of heart rate reserve
+    #[default]
+    Hrr,
+    /// Lowest bpm of zones 1-5, taken from the user's own heart rate, see [`ZoneModel::personal`]
+    Personal([u8; 5]),
+}
+
+impl ZoneModel {
+    /// Percentiles of active heart rate where personal zones 1-5 start
+    const PERSONAL_PERCENTILES: [f64; 5] = [0.2, 0.4, 0.6, 0.8, 0.95];
+
+    /// Zones from the distribution of `Activity::Active` readings in `history`, so they follow
+    /// the user's fitness instead of textbook %HRR. `None` without active readings.
+    pub fn personal(history: &[ParsedHistoryReading]) -> Option<Self> {
+        let mut bpm = history
+            .iter()
+            .filter(|r| r.activity == Activity::Active)
+            .map(|r| r.bpm)
+            .collect::<Vec<_>>();
+        if bpm.is_empty() {
+            return None;
+        }
+        bpm.sort_unstable();
+
+        let last = (bpm.len() - 1) as f64;
+        Some(Self::Personal(
+            Self::PERSONAL_PERCENTILES.map(|p| bpm[(last * p).round() as usize]),
+        ))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -28,7 +63,15 @@ impl StrainCalculator {
     const LN_7201: f64 = 8.882_643_961_783_384;
 
     pub fn new(max_hr: u8, resting_hr: u8) -> Self {
-        Self { max_hr, resting_hr }
+        Self {
+            max_hr,
+            resting_hr,
+            zones: ZoneModel::Hrr,
+        }
+    }
+
+    pub fn with_zones(self, zones: ZoneModel) -> Self {
+        Self { zones, ..self }
     }
 
     pub fn calculate(&self, hr: &[ParsedHistoryReading]) -> Option<StrainScore> {
@@ -37,8 +80,7 @@ impl StrainCalculator {
         }
 
         let sample_duration_min = Self::sample_duration_minutes(hr);
-        let hr_reserve = f64::from(self.max_hr) - f64::from(self.resting_hr);
-        let trimp = Self::edwards_trimp(hr, self.resting_hr, hr_reserve, sample_duration_min);
+        let trimp = self.edwards_trimp(hr, sample_duration_min);
 
         Some(StrainScore(Self::trimp_to_strain(trimp)))
     }
@@ -57,6 +99,19 @@ impl StrainCalculator {
         }
     }
 
+    /// Edwards zone weight (1-5) of `bpm` in the configured [`ZoneModel`], 0 below zone 1
+    fn zone(&self, bpm: u8) -> u8 {
+        match self.zones {
+            ZoneModel::Hrr => {
+                let hr_reserve = f64::from(self.max_hr) - f64::from(self.resting_hr);
+                Self::zone_weight(bpm, self.resting_hr, hr_reserve)
+            }
+            ZoneModel::Personal(starts) => {
+                starts.iter().filter(|&&start| bpm >= start).count() as u8
+            }
+        }
+    }
+
     /// Returns the Edwards zone weight (1-5) based on %HRR, or 0 if below zone 1.
     /// Zones use Heart Rate Reserve: %HRR = (bpm - resting_hr) / hr_reserve x 100
     fn zone_weight(bpm: u8, resting_hr: u8, hr_reserve: f64) -> u8 {
@@ -76,18 +131,10 @@ impl StrainCalculator {
         }
     }
 
-    /// Edwards' TRIMP with HRR zones: sum(duration_min x zone_weight)
-    fn edwards_trimp(
-        hr: &[ParsedHistoryReading],
-        resting_hr: u8,
-        hr_reserve: f64,
-        sample_duration_min: f64,
-    ) -> f64 {
+    /// Edwards' TRIMP: sum(duration_min x zone_weight)
+    fn edwards_trimp(&self, hr: &[ParsedHistoryReading], sample_duration_min: f64) -> f64 {
         hr.iter()
-            .map(|r| {
-                sample_duration_min
-                    * f64::from(Self::zone_weight(r.bpm, resting_hr, hr_reserve))
-            })
+            .map(|r| sample_duration_min * f64::from(self.zone(r.bpm)))
             .sum()
     }
 
@@ -237,4 +284,35 @@ mod tests {
         assert_eq!(StrainCalculator::zone_weight(185, resting_hr, hr_reserve), 5);
         assert_eq!(StrainCalculator::zone_weight(200, resting_hr, hr_reserve), 5);
     }
+
+    #[test]
+    fn personal_zones_follow_own_distribution() {
+        // A trained athlete spending their workouts evenly between 130 and 175 bpm
+        let mut readings = make_constant_readings(0, 1840);
+        for (i, reading) in readings.iter_mut().enumerate() {
+            reading.bpm = 130 + (i % 46) as u8;
+        }
+
+        let zones = ZoneModel::personal(&readings).unwrap();
+        assert_eq!(zones, ZoneModel::Personal([139, 148, 157, 166, 173]));
+
+        let hrr = StrainCalculator::new(190, 45);
+        let personal = StrainCalculator::new(190, 45).with_zones(zones);
+        // 150 bpm is 72% of HRR, but only in the middle of this athlete's range
+        assert_eq!(hrr.zone(150), 3);
+        assert_eq!(personal.zone(150), 2);
+        assert_eq!(hrr.zone(135), 2);
+        assert_eq!(personal.zone(135), 0);
+
+        let hrr_strain = hrr.calculate(&readings).unwrap().0;
+        let personal_strain = personal.calculate(&readings).unwrap().0;
+        assert!(
+            personal_strain < hrr_strain,
+            "{} vs {}",
+            personal_strain,
+            hrr_strain
+        );
+
+        assert_eq!(ZoneModel::personal(&[]), None);
+    }
 }
@@ -1,8 +1,11 @@
 use chrono::NaiveDateTime;
-use std::collections::BTreeMap;
-use openwhoop_codec::ParsedHistoryReading;
+use std::{collections::BTreeMap, num::NonZeroU16};
+use openwhoop_codec::{Activity, ParsedHistoryReading};
 
-pub struct StressCalculator;
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StressCalculator {
+    config: StressConfig,
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct StressScore {
@@ -10,10 +13,58 @@ pub struct StressScore {
     pub score: f64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StressLevel {
+    Low,
+    Medium,
+    High,
+}
+
+/// Tunable constants for Baevsky's Stress Index and the resulting stress buckets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressConfig {
+    /// Histogram bin width in ms (50ms is Baevsky's standard)
+    pub bin_width: NonZeroU16,
+    /// RR variability range (seconds) below which stress is reported as maximal
+    pub min_variability: f64,
+    /// Raw stress index at which the 0-10 score saturates
+    pub max_index: f64,
+    /// Scores at or above this are `StressLevel::Medium`
+    pub medium_threshold: f64,
+    /// Scores at or above this are `StressLevel::High`
+    pub high_threshold: f64,
+    /// Weight of `Activity::Active` readings in a window's score, exercise raises heart
+    /// rate and flattens RR variability without being stress. 0 ignores them, 1 scores
+    /// them like rest.
+    pub active_weight: f64,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            bin_width: NonZeroU16::new(50).unwrap(),
+            min_variability: 0.0001,
+            max_index: 1000.0,
+            medium_threshold: 1.5,
+            high_threshold: 5.0,
+            active_weight: 0.0,
+        }
+    }
+}
+
 impl StressCalculator {
     pub const MIN_READING_PERIOD: usize = 120;
 
+    pub fn with_config(config: StressConfig) -> Self {
+        Self { config }
+    }
+
+    /// Calculates stress using the default [`StressConfig`].
     pub fn calculate_stress(hr: &[ParsedHistoryReading]) -> Option<StressScore> {
+        Self::default().calculate(hr)
+    }
+
+    pub fn calculate(&self, hr: &[ParsedHistoryReading]) -> Option<StressScore> {
         if hr.len() < Self::MIN_READING_PERIOD {
             return None;
         }
@@ -32,9 +83,27 @@ impl StressCalculator {
                 .collect()
         };
 
-        let score = StressCalcParams::new(rr).stress_score();
+        let score = StressCalcParams::new(rr, self.config.bin_width).stress_score(&self.config);
+        let score = score * self.motion_weight(hr);
         Some(StressScore { time, score })
     }
+
+    /// Scales a window's score down by the share of its readings taken while active
+    fn motion_weight(&self, hr: &[ParsedHistoryReading]) -> f64 {
+        let active = hr.iter().filter(|r| r.activity == Activity::Active).count();
+        let active = active as f64 / hr.len() as f64;
+        1.0 - active * (1.0 - self.config.active_weight.clamp(0.0, 1.0))
+    }
+
+    pub fn level(&self, score: f64) -> StressLevel {
+        if score >= self.config.high_threshold {
+            StressLevel::High
+        } else if score >= self.config.medium_threshold {
+            StressLevel::Medium
+        } else {
+            StressLevel::Low
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -47,19 +116,17 @@ struct StressCalcParams {
 }
 
 impl StressCalcParams {
-    /// Standard 50ms bin width for Baevsky's Stress Index histogram.
-    const BIN_WIDTH: u16 = 50;
-
-    fn new(rr: Vec<u16>) -> Self {
+    fn new(rr: Vec<u16>, bin_width: NonZeroU16) -> Self {
+        let bin_width = bin_width.get();
         let count = rr.len() as u16;
 
         let min = rr.iter().min().copied().unwrap_or_default();
         let max = rr.iter().max().copied().unwrap_or_default();
 
-        // Build histogram with 50ms bins per Baevsky's standard
+        // Build histogram with fixed-width bins per Baevsky's standard
         let mut bins = BTreeMap::new();
         for &val in &rr {
-            let bin = val / Self::BIN_WIDTH;
+            let bin = val / bin_width;
             *bins.entry(bin).or_insert(0_u16) += 1;
         }
 
@@ -69,7 +136,7 @@ impl StressCalcParams {
             .unwrap_or_default();
 
         // Mode is the center of the most frequent bin
-        let mode = mode_bin * Self::BIN_WIDTH + Self::BIN_WIDTH / 2;
+        let mode = mode_bin * bin_width + bin_width / 2;
 
         Self {
             min,
@@ -80,28 +147,34 @@ impl StressCalcParams {
         }
     }
 
-    fn stress_score(self) -> f64 {
+    fn stress_score(self, config: &StressConfig) -> f64 {
         let vr = f64::from(self.max - self.min) / 1000_f64;
 
         // Near-zero variability means the histogram is maximally narrow/tall,
         // indicating high sympathetic dominance - maximum stress.
-        if vr < 0.0001 {
-            return 10_f64;
+        if vr < config.min_variability {
+            return config.max_index / 100_f64;
         }
 
         let a_mode = f64::from(self.mode_freq) / f64::from(self.count) * 100_f64;
         (a_mode / (2_f64 * vr * f64::from(self.mode) / 1000_f64))
             .round()
-            .min(1000_f64)
+            .min(config.max_index)
             / 100_f64
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::stress::StressCalcParams;
+    use std::num::NonZeroU16;
+
+    use crate::stress::{StressCalcParams, StressConfig, StressLevel};
     use crate::StressCalculator;
 
+    fn default_bin() -> NonZeroU16 {
+        StressConfig::default().bin_width
+    }
+
     #[test]
     fn test_stress_calc_moderate_variability() {
         // RR intervals with moderate variability (530-690ms range, ~87-113 bpm equivalent)
@@ -116,7 +189,7 @@ mod tests {
             612,
         ]
         .to_vec();
-        let score = StressCalcParams::new(rr).stress_score();
+        let score = StressCalcParams::new(rr, default_bin()).stress_score(&StressConfig::default());
         assert!(score > 0.0, "moderate variability should have some stress: {score}");
         assert!(score <= 10.0, "moderate variability stress should be <= 10: {score}");
     }
@@ -135,7 +208,7 @@ mod tests {
             952, 952, 952,
         ]
         .to_vec();
-        let score = StressCalcParams::new(rr).stress_score();
+        let score = StressCalcParams::new(rr, default_bin()).stress_score(&StressConfig::default());
         assert!(score > 0.0, "low variability RR should produce a stress score: {score}");
     }
 
@@ -143,7 +216,7 @@ mod tests {
     fn test_stress_constant_rr_returns_max() {
         // All identical RR -> zero variability -> maximum stress
         let rr = vec![750_u16; 120];
-        let score = StressCalcParams::new(rr).stress_score();
+        let score = StressCalcParams::new(rr, default_bin()).stress_score(&StressConfig::default());
         assert_eq!(score, 10.0);
     }
 
@@ -190,4 +263,95 @@ mod tests {
         assert!(result.is_some());
         assert!(result.unwrap().score >= 0.0);
     }
+
+    fn borderline_readings() -> Vec<openwhoop_codec::ParsedHistoryReading> {
+        use chrono::NaiveDate;
+        use openwhoop_codec::{Activity, ParsedHistoryReading};
+
+        let base = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        (0..120)
+            .map(|i| ParsedHistoryReading {
+                time: base + chrono::TimeDelta::seconds(i),
+                bpm: 70 + (i % 10) as u8,
+                rr: vec![],
+                activity: Activity::Inactive,
+                imu_data: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bin_width_changes_the_score() {
+        let readings = borderline_readings();
+        let default = StressCalculator::calculate_stress(&readings).unwrap().score;
+
+        // Wider bins gather more beats into the mode, which reads as a more rigid rhythm
+        let wide = StressCalculator::with_config(StressConfig {
+            bin_width: NonZeroU16::new(100).unwrap(),
+            ..Default::default()
+        })
+        .calculate(&readings)
+        .unwrap()
+        .score;
+        assert!(wide > default, "{} vs {}", wide, default);
+    }
+
+    #[test]
+    fn tighter_threshold_raises_stress_level() {
+        let readings = borderline_readings();
+        let default = StressCalculator::default();
+        let score = default.calculate(&readings).unwrap().score;
+        assert_eq!(default.level(score), StressLevel::Medium);
+
+        let tight = StressCalculator::with_config(StressConfig {
+            high_threshold: score,
+            ..Default::default()
+        });
+        assert_eq!(tight.level(score), StressLevel::High);
+    }
+
+    #[test]
+    fn max_index_caps_score() {
+        let rr = vec![750_u16; 120];
+        let config = StressConfig {
+            max_index: 500.0,
+            ..Default::default()
+        };
+        let score = StressCalcParams::new(rr, config.bin_width).stress_score(&config);
+        assert_eq!(score, 5.0);
+    }
+
+    #[test]
+    fn active_readings_are_suppressed() {
+        use openwhoop_codec::{Activity, ParsedHistoryReading};
+
+        let sedentary = borderline_readings();
+        let active = sedentary
+            .iter()
+            .cloned()
+            .map(|r| ParsedHistoryReading {
+                activity: Activity::Active,
+                ..r
+            })
+            .collect::<Vec<_>>();
+
+        let calculator = StressCalculator::default();
+        let rest = calculator.calculate(&sedentary).unwrap().score;
+        assert!(rest > 0.0);
+        assert_eq!(calculator.calculate(&active).unwrap().score, 0.0);
+
+        // Half the window active scores half
+        let mixed = [&active[..60], &sedentary[60..]].concat();
+        let half = calculator.calculate(&mixed).unwrap().score;
+        assert!((half - rest / 2.0).abs() < 1e-9, "{} vs {}", half, rest);
+
+        let unsuppressed = StressCalculator::with_config(StressConfig {
+            active_weight: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(unsuppressed.calculate(&active).unwrap().score, rest);
+    }
 }
@@ -0,0 +1,279 @@
+use std::fmt::Display;
+
+use chrono::{NaiveDate, TimeDelta};
+
+use crate::helpers::{
+    format_hm::FormatHM,
+    time_math::{mean, mean_deltas, round_float},
+};
+
+use super::{RecoveryCalculator, SleepCycle};
+
+/// Direction of a metric compared to the previous week
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Up,
+    Down,
+    Flat,
+}
+
+impl Trend {
+    fn between<T: PartialOrd>(previous: Option<T>, current: Option<T>) -> Option<Self> {
+        let (previous, current) = (previous?, current?);
+        Some(if current > previous {
+            Self::Up
+        } else if current < previous {
+            Self::Down
+        } else {
+            Self::Flat
+        })
+    }
+}
+
+impl Display for Trend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Up => "↑",
+            Self::Down => "↓",
+            Self::Flat => "→",
+        })
+    }
+}
+
+/// Averages for one week of nights and exercise.
+///
+/// Resting heart rate is the mean of each night's lowest sleeping heart rate, HRV the mean
+/// of the nightly averages and recovery the mean of the nights [`RecoveryCalculator`] could
+/// score. Strain is the week's total, days without exercise add nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeekSummary {
+    pub start: NaiveDate,
+    pub resting_hr: Option<f64>,
+    pub hrv: Option<f64>,
+    pub sleep_duration: Option<TimeDelta>,
+    pub strain: f64,
+    pub recovery: Option<f64>,
+}
+
+/// Week over week changes between two [`WeekSummary`]s, `None` if either week lacks data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeekTrends {
+    pub resting_hr: Option<Trend>,
+    pub hrv: Option<Trend>,
+    pub sleep_duration: Option<Trend>,
+    pub strain: Option<Trend>,
+    pub recovery: Option<Trend>,
+}
+
+impl WeekSummary {
+    pub fn trends_since(&self, previous: &WeekSummary) -> WeekTrends {
+        WeekTrends {
+            resting_hr: Trend::between(previous.resting_hr, self.resting_hr),
+            hrv: Trend::between(previous.hrv, self.hrv),
+            sleep_duration: Trend::between(previous.sleep_duration, self.sleep_duration),
+            strain: Trend::between(Some(previous.strain), Some(self.strain)),
+            recovery: Trend::between(previous.recovery, self.recovery),
+        }
+    }
+}
+
+/// The last few weeks, oldest first, each ending on the same weekday as `last_day`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeeklyTrends {
+    pub weeks: Vec<WeekSummary>,
+}
+
+impl WeeklyTrends {
+    pub fn new(
+        sleeps: &[SleepCycle],
+        daily_strain: &[(NaiveDate, f64)],
+        weeks: u32,
+        last_day: NaiveDate,
+    ) -> Self {
+        let recovery = RecoveryCalculator::default();
+        let weeks = (0..i64::from(weeks))
+            .rev()
+            .map(|week| {
+                let end = last_day - TimeDelta::weeks(week);
+                let start = end - TimeDelta::days(6);
+                let in_week = |date: NaiveDate| date >= start && date <= end;
+
+                let nights = sleeps
+                    .iter()
+                    .filter(|s| in_week(s.id))
+                    .copied()
+                    .collect::<Vec<_>>();
+
+                let average = |value: fn(&SleepCycle) -> f64| {
+                    let values = nights.iter().map(value).collect::<Vec<_>>();
+                    (!values.is_empty()).then(|| round_float(mean(&values)))
+                };
+
+                let durations = nights.iter().map(|s| s.duration()).collect::<Vec<_>>();
+                let recoveries = nights
+                    .iter()
+                    .filter_map(|night| recovery.score(night, sleeps))
+                    .collect::<Vec<_>>();
+
+                WeekSummary {
+                    start,
+                    resting_hr: average(|s| f64::from(s.min_bpm)),
+                    hrv: average(|s| f64::from(s.avg_hrv)),
+                    sleep_duration: (!durations.is_empty()).then(|| mean_deltas(&durations)),
+                    strain: round_float(
+                        daily_strain
+                            .iter()
+                            .filter(|(date, _)| in_week(*date))
+                            .map(|(_, strain)| strain)
+                            .sum(),
+                    ),
+                    recovery: (!recoveries.is_empty()).then(|| round_float(mean(&recoveries))),
+                }
+            })
+            .collect();
+
+        Self { weeks }
+    }
+}
+
+/// Each night's resting heart rate (its lowest sleeping heart rate, as in [`WeekSummary`])
+/// averaged over the trailing `window_days` days, oldest first.
+///
+/// Days without a night are skipped, so the average covers the nights within the window.
+pub fn resting_hr_trend(sleeps: &[SleepCycle], window_days: u32) -> Vec<(NaiveDate, u8)> {
+    let window = TimeDelta::days(i64::from(window_days.max(1)));
+    let mut nights = sleeps.iter().map(|s| (s.id, s.min_bpm)).collect::<Vec<_>>();
+    nights.sort_unstable_by_key(|(date, _)| *date);
+
+    nights
+        .iter()
+        .map(|&(date, _)| {
+            let values = nights
+                .iter()
+                .filter(|(night, _)| *night <= date && *night > date - window)
+                .map(|(_, bpm)| f64::from(*bpm))
+                .collect::<Vec<_>>();
+            (date, mean(&values).round() as u8)
+        })
+        .collect()
+}
+
+fn format_metric<T: Display>(
+    f: &mut std::fmt::Formatter<'_>,
+    name: &str,
+    value: Option<T>,
+    trend: Option<Trend>,
+) -> std::fmt::Result {
+    match value {
+        Some(value) => f.write_fmt(format_args!("\t{}: {}", name, value))?,
+        None => f.write_fmt(format_args!("\t{}: n/a", name))?,
+    }
+    match trend {
+        Some(trend) => f.write_fmt(format_args!(" {}\n", trend)),
+        None => f.write_str("\n"),
+    }
+}
+
+impl Display for WeeklyTrends {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut previous: Option<&WeekSummary> = None;
+
+        for week in &self.weeks {
+            let trends = previous.map(|p| week.trends_since(p));
+            let trend = |pick: fn(&WeekTrends) -> Option<Trend>| trends.as_ref().and_then(pick);
+
+            f.write_fmt(format_args!("Week of {}:\n", week.start))?;
+            format_metric(f, "Resting HR", week.resting_hr, trend(|t| t.resting_hr))?;
+            format_metric(f, "HRV", week.hrv, trend(|t| t.hrv))?;
+            format_metric(
+                f,
+                "Sleep duration",
+                week.sleep_duration.map(|d| d.format_hm()),
+                trend(|t| t.sleep_duration),
+            )?;
+            format_metric(f, "Strain", Some(week.strain), trend(|t| t.strain))?;
+            format_metric(f, "Recovery", week.recovery, trend(|t| t.recovery))?;
+
+            previous = Some(week);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(offset: i64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, 1).unwrap() + TimeDelta::days(offset)
+    }
+
+    fn night(offset: i64, min_bpm: u8, avg_hrv: u16, hours: i64) -> SleepCycle {
+        let end = day(offset).and_hms_opt(7, 0, 0).unwrap();
+        SleepCycle {
+            min_bpm,
+            avg_hrv,
+            ..SleepCycle::fixture(end - TimeDelta::hours(hours), end)
+        }
+    }
+
+    #[test]
+    fn reports_direction_of_each_metric() {
+        // First week: restless nights and lots of training, second week: rested and easy
+        let sleeps = (0..14)
+            .map(|i| {
+                if i < 7 {
+                    night(i, 58, 40, 6)
+                } else {
+                    night(i, 52, 65, 8)
+                }
+            })
+            .collect::<Vec<_>>();
+        let strain = (0..14)
+            .map(|i| (day(i), if i < 7 { 12.0 } else { 4.0 }))
+            .collect::<Vec<_>>();
+
+        let trends = WeeklyTrends::new(&sleeps, &strain, 2, day(13));
+        assert_eq!(trends.weeks.len(), 2);
+        assert_eq!(trends.weeks[0].start, day(0));
+        assert_eq!(trends.weeks[1].start, day(7));
+        assert_eq!(trends.weeks[1].resting_hr, Some(52.0));
+        assert_eq!(trends.weeks[1].sleep_duration, Some(TimeDelta::hours(8)));
+        assert_eq!(trends.weeks[1].strain, 28.0);
+        // The first nights have no baseline yet, the rest of the week matches it
+        assert_eq!(trends.weeks[0].recovery, Some(50.0));
+
+        let change = trends.weeks[1].trends_since(&trends.weeks[0]);
+        assert_eq!(
+            change,
+            WeekTrends {
+                resting_hr: Some(Trend::Down),
+                hrv: Some(Trend::Up),
+                sleep_duration: Some(Trend::Up),
+                strain: Some(Trend::Down),
+                recovery: Some(Trend::Up),
+            }
+        );
+    }
+
+    #[test]
+    fn missing_week_has_no_trend() {
+        let sleeps = (7..14).map(|i| night(i, 55, 50, 7)).collect::<Vec<_>>();
+
+        let trends = WeeklyTrends::new(&sleeps, &[], 2, day(13));
+        assert_eq!(trends.weeks[0].resting_hr, None);
+        assert_eq!(trends.weeks[0].strain, 0.0);
+
+        let change = trends.weeks[1].trends_since(&trends.weeks[0]);
+        assert_eq!(change.resting_hr, None);
+        assert_eq!(change.sleep_duration, None);
+        assert_eq!(change.strain, Some(Trend::Flat));
+        assert_eq!(change.recovery, None);
+
+        let report = trends.to_string();
+        assert!(report.contains("Resting HR: n/a\n"));
+        assert!(report.contains("Resting HR: 55\n"));
+        assert!(report.contains("Recovery: 50\n"));
+    }
+}
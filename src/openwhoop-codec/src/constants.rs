@@ -26,7 +26,7 @@ pub enum PacketType {
 }
 
 // MetadataType enum
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[repr(u8)]
 pub enum MetadataType {
     HistoryStart = 1,
@@ -98,7 +98,7 @@ pub enum EventNumber {
 }
 
 // CommandNumber enum - truncated for brevity, add more variants as needed
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[repr(u8)]
 pub enum CommandNumber {
     LinkValid = 1,
@@ -287,6 +287,24 @@ impl CommandNumber {
     pub fn as_u8(self) -> u8 {
         self as u8
     }
+
+    /// Commands that erase data, touch firmware or recalibrate the strap
+    pub fn is_dangerous(self) -> bool {
+        matches!(
+            self,
+            Self::ForceTrim
+                | Self::StartFirmwareLoad
+                | Self::LoadFirmwareData
+                | Self::ProcessFirmwareImage
+                | Self::StartFirmwareLoadNew
+                | Self::LoadFirmwareDataNew
+                | Self::ProcessFirmwareImageNew
+                | Self::VerifyFirmwareImage
+                | Self::EnterBleDfu
+                | Self::ResetFuelGauge
+                | Self::CalibrateCapsense
+        )
+    }
 }
 
 impl MetadataType {
@@ -374,4 +392,12 @@ mod tests {
         assert!(CommandNumber::from_u8(0).is_none());
         assert!(CommandNumber::from_u8(200).is_none());
     }
+
+    #[test]
+    fn dangerous_commands() {
+        assert!(CommandNumber::ForceTrim.is_dangerous());
+        assert!(CommandNumber::StartFirmwareLoadNew.is_dangerous());
+        assert!(!CommandNumber::GetBodyLocationAndStatus.is_dangerous());
+        assert!(!CommandNumber::ReportVersionInfo.is_dangerous());
+    }
 }
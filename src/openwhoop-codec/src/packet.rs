@@ -2,7 +2,7 @@ use std::fmt;
 
 use crate::{constants::PacketType, error::WhoopError, helpers::BufferReader};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WhoopPacket {
     pub packet_type: PacketType,
     pub seq: u8,
@@ -30,7 +30,17 @@ impl WhoopPacket {
         }
     }
 
-    pub fn from_data(mut data: Vec<u8>) -> Result<Self, WhoopError> {
+    pub fn from_data(data: Vec<u8>) -> Result<Self, WhoopError> {
+        Self::parse_frame(data, false).map(|(packet, _)| packet)
+    }
+
+    /// Like [`Self::from_data`], but a frame failing its data CRC32 is still parsed, as some
+    /// BLE adapters flip the odd bit. Returns whether the CRC32 matched along with the packet.
+    pub fn from_data_lenient(data: Vec<u8>) -> Result<(Self, bool), WhoopError> {
+        Self::parse_frame(data, true)
+    }
+
+    fn parse_frame(mut data: Vec<u8>, lenient: bool) -> Result<(Self, bool), WhoopError> {
         if data.len() < 8 {
             return Err(WhoopError::PacketTooShort);
         }
@@ -43,7 +53,7 @@ impl WhoopPacket {
         // Verify header CRC8
         let length_buffer = data.read::<2>()?;
         let expected_crc8 = data.pop_front()?;
-        let calculated_crc8 = Self::crc8(&length_buffer);
+        let calculated_crc8 = Self::header_crc8(&length_buffer);
 
         if calculated_crc8 != expected_crc8 {
             return Err(WhoopError::InvalidHeaderCrc8);
@@ -56,15 +66,16 @@ impl WhoopPacket {
         }
 
         // Verify data CRC32
+        let mut crc_ok = true;
         if !partial {
             let expected_crc32 = u32::from_le_bytes(data.read_end()?);
-            let calculated_crc32 = Self::crc32(&data);
-            if calculated_crc32 != expected_crc32 {
+            crc_ok = Self::data_crc32(&data) == expected_crc32;
+            if !crc_ok && !lenient {
                 return Err(WhoopError::InvalidDataCrc32);
             }
         }
 
-        Ok(Self {
+        let packet = Self {
             packet_type: {
                 let packet_type = data.pop_front()?;
                 PacketType::from_u8(packet_type)
@@ -75,7 +86,8 @@ impl WhoopPacket {
             data,
             partial,
             size: length,
-        })
+        };
+        Ok((packet, crc_ok))
     }
 
     fn create_packet(&self) -> Vec<u8> {
@@ -87,7 +99,15 @@ impl WhoopPacket {
         packet
     }
 
-    fn crc8(data: &[u8]) -> u8 {
+    /// CRC8 (poly `0x07`) over the 2 length bytes of a frame header.
+    ///
+    /// ```
+    /// use openwhoop_codec::WhoopPacket;
+    ///
+    /// // Header of `aa 1c00 ab ...`: length 0x001c, CRC8 0xab
+    /// assert_eq!(WhoopPacket::header_crc8(&[0x1c, 0x00]), 0xab);
+    /// ```
+    pub fn header_crc8(data: &[u8]) -> u8 {
         let mut crc: u8 = 0;
         for &byte in data {
             crc ^= byte;
@@ -102,7 +122,14 @@ impl WhoopPacket {
         crc
     }
 
-    fn crc32(data: &[u8]) -> u32 {
+    /// Standard CRC32 over the packet type, seq, cmd and payload of a frame.
+    ///
+    /// ```
+    /// use openwhoop_codec::WhoopPacket;
+    ///
+    /// assert_eq!(WhoopPacket::data_crc32(b"123456789"), 0xCBF43926);
+    /// ```
+    pub fn data_crc32(data: &[u8]) -> u32 {
         let mut crc: u32 = 0xFFFFFFFF;
         for &byte in data {
             crc ^= u32::from(byte);
@@ -117,13 +144,16 @@ impl WhoopPacket {
         !crc
     }
 
+    /// Frame ready to be written to the strap:
+    /// `SOF | length (u16 LE) | CRC8(length) | type | seq | cmd | data | CRC32 (u32 LE)`,
+    /// where length counts everything after the header CRC8, including the CRC32.
     pub fn framed_packet(&self) -> Vec<u8> {
         let pkt = self.create_packet();
         let length = pkt.len() as u16 + 4;
         let length_buffer = length.to_le_bytes();
-        let crc8_value = Self::crc8(&length_buffer);
+        let crc8_value = Self::header_crc8(&length_buffer);
 
-        let crc32_value = Self::crc32(&pkt);
+        let crc32_value = Self::data_crc32(&pkt);
         let crc32_buffer = crc32_value.to_le_bytes();
 
         let mut framed_packet = vec![Self::SOF];
@@ -196,6 +226,24 @@ mod tests {
         assert!(matches!(result, Err(WhoopError::InvalidHeaderCrc8)));
     }
 
+    #[test]
+    fn flipped_bit_parses_only_when_lenient() {
+        let original = WhoopPacket::new(PacketType::HistoricalData, 3, 5, vec![0x10; 16]);
+        let mut framed = original.framed_packet();
+        framed[10] ^= 0x04;
+
+        let result = WhoopPacket::from_data(framed.clone());
+        assert!(matches!(result, Err(WhoopError::InvalidDataCrc32)));
+
+        let (parsed, crc_ok) = WhoopPacket::from_data_lenient(framed).unwrap();
+        assert!(!crc_ok);
+        assert_eq!(parsed.packet_type, original.packet_type);
+        assert_eq!(parsed.data[3], 0x14);
+
+        let (_, crc_ok) = WhoopPacket::from_data_lenient(original.framed_packet()).unwrap();
+        assert!(crc_ok);
+    }
+
     #[test]
     fn with_seq_changes_seq() {
         let packet = WhoopPacket::new(PacketType::Command, 0, 1, vec![]);
@@ -239,4 +287,30 @@ mod tests {
         assert_eq!(framed[0], WhoopPacket::SOF);
         assert_eq!(framed.len(), 11);
     }
+
+    #[test]
+    fn crc_helpers_match_fixture() {
+        let frame = hex::decode("aa1c00ab31370268ae7667702d32000000c7b6000010000000000000e01eba47")
+            .expect("Invalid hex data");
+        let crc32 = u32::from_le_bytes(frame[frame.len() - 4..].try_into().unwrap());
+
+        assert_eq!(WhoopPacket::header_crc8(&frame[1..3]), frame[3]);
+        assert_eq!(WhoopPacket::data_crc32(&frame[4..frame.len() - 4]), crc32);
+    }
+
+    #[test]
+    fn hand_built_frame_parses() {
+        let body = [PacketType::Command.as_u8(), 1, 5, 0xAB];
+        let length = (body.len() as u16 + 4).to_le_bytes();
+
+        let mut frame = vec![0xAA];
+        frame.extend_from_slice(&length);
+        frame.push(WhoopPacket::header_crc8(&length));
+        frame.extend_from_slice(&body);
+        frame.extend_from_slice(&WhoopPacket::data_crc32(&body).to_le_bytes());
+
+        let parsed = WhoopPacket::from_data(frame).unwrap();
+        assert_eq!(parsed.cmd, 5);
+        assert_eq!(parsed.data, vec![0xAB]);
+    }
 }
@@ -1,11 +1,25 @@
 use chrono::Utc;
 
 use crate::{
-    WhoopPacket,
+    WhoopPacket, Wrist,
     constants::{CommandNumber, PacketType},
 };
 
 impl WhoopPacket {
+    /// Raw command with an arbitrary payload, for commands without a dedicated constructor.
+    ///
+    /// ```
+    /// use openwhoop_codec::WhoopPacket;
+    ///
+    /// let frame = WhoopPacket::command(0x4C, vec![0x00]).framed_packet();
+    /// let packet = WhoopPacket::from_data(frame).unwrap();
+    /// assert_eq!(packet.cmd, 0x4C);
+    /// assert_eq!(packet.data, vec![0x00]);
+    /// ```
+    pub fn command(cmd: u8, data: Vec<u8>) -> WhoopPacket {
+        WhoopPacket::new(PacketType::Command, 0, cmd, data)
+    }
+
     pub fn enter_high_freq_sync() -> WhoopPacket {
         WhoopPacket::new(
             PacketType::Command,
@@ -77,6 +91,29 @@ impl WhoopPacket {
         )
     }
 
+    pub fn get_data_range() -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::GetDataRange.as_u8(),
+            vec![0x00],
+        )
+    }
+
+    /// Moves the strap's history read pointer, same layout as [`WhoopPacket::history_end`]
+    pub fn set_read_pointer(pointer: u32) -> WhoopPacket {
+        let mut data = vec![0x01];
+        data.extend_from_slice(&pointer.to_le_bytes());
+        data.append(&mut vec![0, 0, 0, 0]); // padding
+
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::SetReadPointer.as_u8(),
+            data,
+        )
+    }
+
     pub fn alarm_time(unix: u32) -> WhoopPacket {
         let mut data = vec![0x01];
         data.extend_from_slice(&unix.to_le_bytes());
@@ -89,6 +126,24 @@ impl WhoopPacket {
         )
     }
 
+    pub fn get_alarm_time() -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::GetAlarmTime.as_u8(),
+            vec![0x00],
+        )
+    }
+
+    pub fn disable_alarm() -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::DisableAlarm.as_u8(),
+            vec![0x00],
+        )
+    }
+
     pub fn toggle_imu_mode(value: bool) -> WhoopPacket {
         WhoopPacket::new(
             PacketType::Command,
@@ -116,6 +171,25 @@ impl WhoopPacket {
         )
     }
 
+    /// Starts streaming [`crate::WhoopData::RawData`] packets
+    pub fn start_raw_data() -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::StartRawData.as_u8(),
+            vec![0x00],
+        )
+    }
+
+    pub fn stop_raw_data() -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::StopRawData.as_u8(),
+            vec![0x00],
+        )
+    }
+
     pub fn restart() -> WhoopPacket {
         WhoopPacket::new(
             PacketType::Command,
@@ -143,6 +217,24 @@ impl WhoopPacket {
         )
     }
 
+    pub fn get_body_location_status() -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::GetBodyLocationAndStatus.as_u8(),
+            vec![0x00],
+        )
+    }
+
+    pub fn select_wrist(wrist: Wrist) -> WhoopPacket {
+        WhoopPacket::new(
+            PacketType::Command,
+            0,
+            CommandNumber::SelectWrist.as_u8(),
+            vec![0x01, wrist.as_u8()],
+        )
+    }
+
     pub fn enable_optical_data(enable: bool) -> WhoopPacket {
         WhoopPacket::new(
             PacketType::Command,
@@ -179,6 +271,21 @@ mod tests {
         assert_eq!(parsed.data, packet.data);
     }
 
+    #[test]
+    fn raw_command_roundtrip() {
+        let p = WhoopPacket::command(0xFE, vec![0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(p.packet_type, PacketType::Command);
+        assert_eq!(p.cmd, 0xFE);
+        assert_roundtrip(&p);
+
+        // The strap never answers with an empty payload, so such frames are rejected when
+        // parsed, but sending one is framed as usual
+        let empty = WhoopPacket::command(0x10, vec![]).framed_packet();
+        assert_eq!(empty.len(), 11);
+        assert_eq!(&empty[1..3], &[7, 0]);
+        assert_eq!(&empty[4..7], &[PacketType::Command.as_u8(), 0, 0x10]);
+    }
+
     #[test]
     fn enter_high_freq_sync_packet() {
         let p = WhoopPacket::enter_high_freq_sync();
@@ -201,6 +308,14 @@ mod tests {
         assert_roundtrip(&p);
     }
 
+    #[test]
+    fn set_read_pointer_packet() {
+        let p = WhoopPacket::set_read_pointer(0x0102_0304);
+        assert_command_packet(&p, CommandNumber::SetReadPointer);
+        assert_eq!(p.data, vec![0x01, 0x04, 0x03, 0x02, 0x01, 0, 0, 0, 0]);
+        assert_roundtrip(&p);
+    }
+
     #[test]
     fn hello_harvard_packet() {
         let p = WhoopPacket::hello_harvard();
@@ -215,6 +330,43 @@ mod tests {
         assert_roundtrip(&p);
     }
 
+    #[test]
+    fn get_body_location_status_packet() {
+        let p = WhoopPacket::get_body_location_status();
+        assert_command_packet(&p, CommandNumber::GetBodyLocationAndStatus);
+        assert_eq!(p.data, vec![0x00]);
+        assert_roundtrip(&p);
+    }
+
+    #[test]
+    fn select_wrist_left_right() {
+        let left = WhoopPacket::select_wrist(Wrist::Left);
+        assert_command_packet(&left, CommandNumber::SelectWrist);
+        assert_eq!(left.data, vec![0x01, 0x00]);
+        assert_roundtrip(&left);
+
+        let right = WhoopPacket::select_wrist(Wrist::Right);
+        assert_command_packet(&right, CommandNumber::SelectWrist);
+        assert_eq!(right.data, vec![0x01, 0x01]);
+        assert_roundtrip(&right);
+    }
+
+    #[test]
+    fn get_alarm_time_packet() {
+        let p = WhoopPacket::get_alarm_time();
+        assert_command_packet(&p, CommandNumber::GetAlarmTime);
+        assert_eq!(p.data, vec![0x00]);
+        assert_roundtrip(&p);
+    }
+
+    #[test]
+    fn disable_alarm_packet() {
+        let p = WhoopPacket::disable_alarm();
+        assert_command_packet(&p, CommandNumber::DisableAlarm);
+        assert_eq!(p.data, vec![0x00]);
+        assert_roundtrip(&p);
+    }
+
     #[test]
     fn toggle_imu_mode_on_off() {
         let on = WhoopPacket::toggle_imu_mode(true);
@@ -226,6 +378,17 @@ mod tests {
         assert_roundtrip(&off);
     }
 
+    #[test]
+    fn raw_data_start_stop_packets() {
+        let start = WhoopPacket::start_raw_data();
+        assert_command_packet(&start, CommandNumber::StartRawData);
+        assert_roundtrip(&start);
+
+        let stop = WhoopPacket::stop_raw_data();
+        assert_command_packet(&stop, CommandNumber::StopRawData);
+        assert_roundtrip(&stop);
+    }
+
     #[test]
     fn history_end_encodes_data() {
         let p = WhoopPacket::history_end(0x12345678);
@@ -1,13 +1,17 @@
 use crate::{
     WhoopError, WhoopPacket,
-    constants::{CommandNumber, MetadataType, PacketType},
+    constants::{CommandNumber, EventNumber, MetadataType, PacketType},
     helpers::BufferReader,
 };
 
 mod history;
 pub use history::{Activity, HistoryReading, ImuSample, ParsedHistoryReading, SensorData};
 
-#[derive(Debug, PartialEq)]
+mod firmware;
+pub use firmware::{FirmwareVersion, ParseContext, V12Layout};
+
+/// Serializes to the JSON `replay` compares against expected fixtures
+#[derive(Debug, PartialEq, Serialize)]
 pub enum WhoopData {
     HistoryReading(HistoryReading),
     HistoryMetadata {
@@ -30,16 +34,112 @@ pub enum WhoopData {
         unix: u32,
         event: u8,
     },
+    /// The strap prompting for, entering or leaving high frequency sync
+    HighFreqSync {
+        unix: u32,
+        state: HighFreqSyncState,
+    },
+    /// The band was tapped twice, WHOOP uses it to mark a moment
+    DoubleTap {
+        unix: u32,
+    },
+    /// The band was put on or taken off its charger
+    Charging {
+        unix: u32,
+        charging: bool,
+    },
     VersionInfo {
         harvard: String,
         boylston: String,
     },
+    BodyLocation {
+        wrist: Wrist,
+        on_body: bool,
+    },
+    AlarmTime {
+        unix: u32,
+        enabled: bool,
+    },
+    /// Samples from the dedicated IMU stream, separate from the IMU embedded in history readings.
+    /// The layout is inferred, not confirmed by a capture.
+    ImuStream {
+        unix: u32,
+        samples: Vec<ImuSample>,
+    },
+    /// Oldest and newest history pointers the strap still holds.
+    /// Experimental: the layout is inferred, not confirmed by a capture.
+    DataRange {
+        start: u32,
+        end: u32,
+    },
+    /// Optical and accelerometer samples streamed after [`WhoopPacket::start_raw_data`].
+    ///
+    /// Not decoded: no raw data packet has been captured yet, so neither the meaning of
+    /// `cmd` nor the layout of `data` is known. Both are kept as received for research
+    /// captures, decoding them needs a capture to verify the fields against.
+    RawData {
+        cmd: u8,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[repr(u8)]
+pub enum Wrist {
+    Left = 0,
+    Right = 1,
+}
+
+impl Wrist {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Left),
+            1 => Some(Self::Right),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum HighFreqSyncState {
+    Prompt,
+    Enabled,
+    Disabled,
+}
+
+impl HighFreqSyncState {
+    pub fn from_event(event: u8) -> Option<Self> {
+        match event {
+            e if e == EventNumber::HighFreqSyncPrompt as u8 => Some(Self::Prompt),
+            e if e == EventNumber::HighFreqSyncEnabled as u8 => Some(Self::Enabled),
+            e if e == EventNumber::HighFreqSyncDisabled as u8 => Some(Self::Disabled),
+            _ => None,
+        }
+    }
 }
 
 impl WhoopData {
     pub fn from_packet(packet: WhoopPacket) -> Result<Self, WhoopError> {
+        Self::from_packet_with(packet, &ParseContext::default())
+    }
+
+    /// Like [`WhoopData::from_packet`], with field offsets chosen for the strap's firmware
+    pub fn from_packet_with(
+        packet: WhoopPacket,
+        context: &ParseContext,
+    ) -> Result<Self, WhoopError> {
         match packet.packet_type {
-            PacketType::HistoricalData => Self::parse_historical_packet(packet.seq, packet.data),
+            PacketType::HistoricalData => {
+                Self::parse_historical_packet(packet.seq, packet.data, context)
+            }
+            PacketType::RealtimeImuDataStream | PacketType::HistoricalImuDataStream => {
+                Self::parse_imu_stream(packet.data)
+            }
+            PacketType::RealtimeRawData => Self::parse_raw_data(packet),
             PacketType::Metadata => Self::parse_metadata(packet),
             PacketType::ConsoleLogs => Self::parse_console_log(packet.data),
             PacketType::Event => Self::parse_event(packet),
@@ -51,6 +151,11 @@ impl WhoopData {
                     CommandNumber::ReportVersionInfo => {
                         Self::parse_report_version_info(packet.data)
                     }
+                    CommandNumber::GetBodyLocationAndStatus => {
+                        Self::parse_body_location(packet.data)
+                    }
+                    CommandNumber::GetAlarmTime => Self::parse_alarm_time(packet.data),
+                    CommandNumber::GetDataRange => Self::parse_data_range(packet.data),
                     _ => Err(WhoopError::Unimplemented),
                 }
             }
@@ -64,6 +169,26 @@ impl WhoopData {
         let _ = packet.data.pop_front()?;
         let unix = packet.data.read_u32_le()?;
 
+        // These share their numbers with commands, so they are told apart before those
+        if let Some(state) = HighFreqSyncState::from_event(packet.cmd) {
+            return Ok(Self::HighFreqSync { unix, state });
+        }
+        if packet.cmd == EventNumber::DoubleTap as u8 {
+            return Ok(Self::DoubleTap { unix });
+        }
+        if packet.cmd == EventNumber::ChargingOn as u8 {
+            return Ok(Self::Charging {
+                unix,
+                charging: true,
+            });
+        }
+        if packet.cmd == EventNumber::ChargingOff as u8 {
+            return Ok(Self::Charging {
+                unix,
+                charging: false,
+            });
+        }
+
         match command {
             Ok(CommandNumber::RunAlarm) => Ok(Self::RunAlarm { unix }),
             Ok(CommandNumber::SendR10R11Realtime)
@@ -71,7 +196,8 @@ impl WhoopData {
             | Ok(CommandNumber::GetClock)
             | Ok(CommandNumber::RebootStrap)
             | Ok(CommandNumber::ToggleR7DataCollection)
-            | Ok(CommandNumber::ToggleGenericHrProfile) => Ok(Self::Event {
+            | Ok(CommandNumber::ToggleImuMode)
+            | Ok(CommandNumber::ToggleImuModeHistorical) => Ok(Self::Event {
                 unix,
                 event: command.expect("We check above that it is `Ok`"),
             }),
@@ -124,7 +250,11 @@ impl WhoopData {
         Ok(Self::HistoryMetadata { unix, data, cmd })
     }
 
-    fn parse_historical_packet(version: u8, packet: Vec<u8>) -> Result<Self, WhoopError> {
+    fn parse_historical_packet(
+        version: u8,
+        packet: Vec<u8>,
+        context: &ParseContext,
+    ) -> Result<Self, WhoopError> {
         const MIN_PACKET_LEN_FOR_IMU: usize = 1188;
 
         if packet.len() >= MIN_PACKET_LEN_FOR_IMU {
@@ -132,8 +262,9 @@ impl WhoopData {
         }
 
         // V12/V24: packets with DSP sensor fields (SpO2, skin temp, PPG, etc.)
-        if matches!(version, 12 | 24) && packet.len() >= 77 {
-            return Self::parse_historical_packet_v12(packet);
+        let layout = context.v12_layout();
+        if matches!(version, 12 | 24) && packet.len() >= layout.min_len() {
+            return Self::parse_historical_packet_v12(packet, layout);
         }
 
         Self::parse_historical_packet_generic(packet)
@@ -141,21 +272,21 @@ impl WhoopData {
 
     /// Generic historical packet parser (V7, V9, V18, etc. - no DSP fields).
     fn parse_historical_packet_generic(mut packet: Vec<u8>) -> Result<Self, WhoopError> {
+        const MAX_RR_SLOTS: usize = 4;
+
         let _sequence = packet.read::<4>();
         let unix = u64::from(packet.read_u32_le()?) * 1000;
         let _sub_flags_sensors = packet.read::<6>();
         let bpm = packet.pop_front()?;
-        let rr_count = usize::from(packet.pop_front()?);
-        let mut rr = Vec::new();
-        for _ in 0..4 {
-            let rr_ = packet.read_u16_le()?;
-            if rr_ == 0 {
-                continue;
-            }
-            rr.push(rr_);
-        }
+        let rr_count = usize::from(packet.pop_front()?).min(MAX_RR_SLOTS);
+
+        // The packet always carries 4 RR slots, only the first `rr_count` hold intervals
+        let slots = (0..MAX_RR_SLOTS)
+            .map(|_| packet.read_u16_le())
+            .collect::<Result<Vec<_>, _>>()?;
+        let rr = slots[..rr_count].to_vec();
 
-        if rr.len() != rr_count {
+        if rr.contains(&0) {
             return Err(WhoopError::InvalidRRCount);
         }
 
@@ -173,7 +304,8 @@ impl WhoopData {
 
     /// V12/V24 historical packet parser with DSP sensor fields.
     ///
-    /// Layout (offsets into data = inner[3:]):
+    /// Default layout (offsets into data = inner[3:]), firmware revisions that move fields
+    /// supply their own [`V12Layout`] through [`ParseContext`]:
     ///   [0:4]   sequence (u32 LE)
     ///   [4:8]   unix timestamp (u32 LE, seconds)
     ///   [8:10]  subseconds (u16 LE)
@@ -196,26 +328,25 @@ impl WhoopData {
     ///   [71:73] led_drive_2 (u16 LE)
     ///   [73:75] resp_rate_raw (u16 LE)
     ///   [75:77] signal_quality (u16 LE)
-    fn parse_historical_packet_v12(data: Vec<u8>) -> Result<Self, WhoopError> {
-        if data.len() < 77 {
+    fn parse_historical_packet_v12(data: Vec<u8>, layout: V12Layout) -> Result<Self, WhoopError> {
+        if data.len() < layout.min_len() {
             return Err(WhoopError::InvalidData);
         }
 
         let d = &data[..];
 
         let unix = u64::from(u32::from_le_bytes(
-            d[4..8].try_into().map_err(|_| WhoopError::InvalidData)?,
+            d[layout.unix..layout.unix + 4]
+                .try_into()
+                .map_err(|_| WhoopError::InvalidData)?,
         )) * 1000;
 
-        let bpm = d[14];
+        let bpm = d[layout.bpm];
 
-        let rr_count = d[15] as usize;
+        let rr_count = d[layout.rr_count] as usize;
         let mut rr = Vec::new();
         for i in 0..rr_count.min(4) {
-            let off = 16 + i * 2;
-            if off + 2 > d.len() {
-                break;
-            }
+            let off = layout.rr + i * 2;
             let val = u16::from_le_bytes(
                 d[off..off + 2]
                     .try_into()
@@ -226,17 +357,15 @@ impl WhoopData {
             }
         }
 
-        // Read gravity vector at data[33:45]
+        // Read gravity vector, 3 x f32
         let mut gravity = [0.0f32; 3];
-        if d.len() >= 45 {
-            for (i, g) in gravity.iter_mut().enumerate() {
-                let off = 33 + i * 4;
-                *g = f32::from_le_bytes(
-                    d[off..off + 4]
-                        .try_into()
-                        .map_err(|_| WhoopError::InvalidData)?,
-                );
-            }
+        for (i, g) in gravity.iter_mut().enumerate() {
+            let off = layout.accel_gravity + i * 4;
+            *g = f32::from_le_bytes(
+                d[off..off + 4]
+                    .try_into()
+                    .map_err(|_| WhoopError::InvalidData)?,
+            );
         }
 
         let read_u16 = |off: usize| -> u16 {
@@ -244,17 +373,17 @@ impl WhoopData {
         };
 
         let sensor_data = SensorData {
-            ppg_green: read_u16(26),
-            ppg_red_ir: read_u16(28),
-            spo2_red: read_u16(61),
-            spo2_ir: read_u16(63),
-            skin_temp_raw: read_u16(65),
-            ambient_light: read_u16(67),
-            led_drive_1: read_u16(69),
-            led_drive_2: read_u16(71),
-            resp_rate_raw: read_u16(73),
-            signal_quality: read_u16(75),
-            skin_contact: d[48],
+            ppg_green: read_u16(layout.ppg_green),
+            ppg_red_ir: read_u16(layout.ppg_red_ir),
+            spo2_red: read_u16(layout.spo2_red),
+            spo2_ir: read_u16(layout.spo2_ir),
+            skin_temp_raw: read_u16(layout.skin_temp_raw),
+            ambient_light: read_u16(layout.ambient_light),
+            led_drive_1: read_u16(layout.led_drive_1),
+            led_drive_2: read_u16(layout.led_drive_2),
+            resp_rate_raw: read_u16(layout.resp_rate_raw),
+            signal_quality: read_u16(layout.signal_quality),
+            skin_contact: d[layout.skin_contact],
             accel_gravity: gravity,
         };
 
@@ -262,7 +391,8 @@ impl WhoopData {
             unix,
             bpm,
             rr,
-            activity: 0, // V12/V24 don't have an activity field at the same offset
+            // V12/V24 carry no activity field, see `HistoryReading::derive_missing_activity`
+            activity: 0,
             imu_data: Vec::new(),
             sensor_data: Some(sensor_data),
         }))
@@ -277,8 +407,8 @@ impl WhoopData {
         const GYR_Y_OFFSET: usize = 888;
         const GYR_Z_OFFSET: usize = 1088;
         const N_SAMPLES_IMU: usize = 100;
-        const ACC_SENS: f32 = 1875.0;
-        const GYR_SENS: f32 = 15.0;
+        const ACC_SENS: f32 = ImuSample::ACC_SENS;
+        const GYR_SENS: f32 = ImuSample::GYR_SENS;
 
         // baseline offset before evaluating the rr data
         let mut header_offset = 20;
@@ -355,6 +485,61 @@ impl WhoopData {
         }))
     }
 
+    /// IMU stream packet, shared by the realtime and historical streams:
+    ///   [0:4]   sequence (u32 LE)
+    ///   [4:8]   unix timestamp (u32 LE, seconds)
+    ///   [8:10]  subseconds (u16 LE)
+    ///   [10:14] unknown
+    ///   [14..]  six equally sized axis blocks of i16 BE samples,
+    ///           in the order acc x/y/z, gyr x/y/z
+    ///
+    /// No stream packet has been captured yet. The layout is inferred from the history
+    /// packets that embed IMU data (see `parse_historical_packet_with_imu`): the same header,
+    /// then per axis blocks of big endian samples, assumed here to be back to back without
+    /// the few bytes that separate the accelerometer and gyroscope blocks there.
+    fn parse_imu_stream(mut packet: Vec<u8>) -> Result<Self, WhoopError> {
+        const AXES: usize = 6;
+
+        let _sequence = packet.read::<4>()?;
+        let unix = packet.read_u32_le()?;
+        let _sub_second = packet.read_u16_le()?;
+        let _unknown = packet.read::<4>()?;
+
+        if packet.is_empty() || packet.len() % (AXES * 2) != 0 {
+            return Err(WhoopError::InvalidData);
+        }
+
+        let n_samples = packet.len() / (AXES * 2);
+        let axis = |axis: usize, i: usize| {
+            let start = (axis * n_samples + i) * 2;
+            f32::from(i16::from_be_bytes([packet[start], packet[start + 1]]))
+        };
+
+        let samples = (0..n_samples)
+            .map(|i| ImuSample {
+                acc_x_g: axis(0, i) / ImuSample::ACC_SENS,
+                acc_y_g: axis(1, i) / ImuSample::ACC_SENS,
+                acc_z_g: axis(2, i) / ImuSample::ACC_SENS,
+                gyr_x_dps: axis(3, i) / ImuSample::GYR_SENS,
+                gyr_y_dps: axis(4, i) / ImuSample::GYR_SENS,
+                gyr_z_dps: axis(5, i) / ImuSample::GYR_SENS,
+            })
+            .collect();
+
+        Ok(Self::ImuStream { unix, samples })
+    }
+
+    fn parse_raw_data(packet: WhoopPacket) -> Result<Self, WhoopError> {
+        if packet.data.is_empty() {
+            return Err(WhoopError::InvalidData);
+        }
+
+        Ok(Self::RawData {
+            cmd: packet.cmd,
+            data: packet.data,
+        })
+    }
+
     fn parse_report_version_info(mut data: Vec<u8>) -> Result<Self, WhoopError> {
         let _ = data.read::<3>();
         let h_major = data.read_u32_le()?;
@@ -370,16 +555,61 @@ impl WhoopData {
             boylston: format!("{}.{}.{}.{}", b_major, b_minor, b_patch, b_build),
         })
     }
+
+    /// Layout (after the same 3 byte response header as version info):
+    ///   [0] wrist (0 = left, 1 = right)
+    ///   [1] on body (0 = off, 1 = on)
+    ///
+    /// Unverified: no `GetBodyLocationAndStatus` response has been captured. The wrist byte
+    /// mirrors what [`WhoopPacket::select_wrist`] sends, the on body flag is a guess.
+    fn parse_body_location(mut data: Vec<u8>) -> Result<Self, WhoopError> {
+        let _ = data.read::<3>()?;
+        let wrist = Wrist::from_u8(data.pop_front()?).ok_or(WhoopError::InvalidData)?;
+        let on_body = data.pop_front()? != 0;
+
+        Ok(Self::BodyLocation { wrist, on_body })
+    }
+
+    /// Layout (after the 3 byte response header), mirrors [`WhoopPacket::alarm_time`]:
+    ///   [0] enabled (0 = off, 1 = on)
+    ///   [1..5] alarm unix time, LE
+    ///
+    /// Unverified: no `GetAlarmTime` response has been captured, the layout assumes the
+    /// strap echoes back what it was sent.
+    fn parse_alarm_time(mut data: Vec<u8>) -> Result<Self, WhoopError> {
+        let _ = data.read::<3>()?;
+        let enabled = data.pop_front()? != 0;
+        let unix = data.read_u32_le()?;
+
+        Ok(Self::AlarmTime { unix, enabled })
+    }
+
+    /// Layout (after the 3 byte response header), pointers as used by
+    /// [`WhoopPacket::history_end`] and [`WhoopPacket::set_read_pointer`]:
+    ///   [0..4] oldest stored pointer, LE
+    ///   [4..8] newest stored pointer, LE
+    ///
+    /// Unverified: no response to `GetDataRange` has been captured yet. The layout is
+    /// inferred from the other command responses, which share the header, and from the
+    /// pointer format the strap uses in `HistoryEnd`. `WhoopDevice::get_data_range` in the
+    /// `openwhoop` crate logs the raw response so it can be checked against a capture.
+    fn parse_data_range(mut data: Vec<u8>) -> Result<Self, WhoopError> {
+        let _ = data.read::<3>()?;
+        let start = data.read_u32_le()?;
+        let end = data.read_u32_le()?;
+
+        Ok(Self::DataRange { start, end })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        WhoopPacket,
-        constants::{MetadataType, PacketType},
+        WhoopError, WhoopPacket,
+        constants::{CommandNumber, EventNumber, MetadataType, PacketType},
         whoop_data::{
-            WhoopData,
-            history::{HistoryReading, ImuSample},
+            FirmwareVersion, HighFreqSyncState, ParseContext, V12Layout, WhoopData, Wrist,
+            history::{Activity, HistoryReading, ImuSample, SensorData},
         },
     };
 
@@ -2095,6 +2325,44 @@ mod tests {
         );
     }
 
+    fn generic_history_packet(rr_count: u8, rr: [u16; 4]) -> WhoopPacket {
+        let mut data = vec![0; 4];
+        data.extend_from_slice(&1735689600_u32.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.push(65);
+        data.push(rr_count);
+        for interval in rr {
+            data.extend_from_slice(&interval.to_le_bytes());
+        }
+        data.extend_from_slice(&600_000_000_u32.to_le_bytes());
+
+        WhoopPacket::new(PacketType::HistoricalData, 7, 0, data)
+    }
+
+    #[test]
+    fn parse_generic_history_with_trailing_empty_rr() {
+        let packet = generic_history_packet(2, [850, 870, 0, 0]);
+        let data = WhoopData::from_packet(packet).expect("Invalid packet");
+        assert_eq!(
+            data,
+            WhoopData::HistoryReading(HistoryReading {
+                unix: 1735689600000,
+                bpm: 65,
+                rr: vec![850, 870],
+                activity: 600_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_generic_history_rr_count_mismatch() {
+        let packet = generic_history_packet(3, [850, 870, 0, 0]);
+        let result = WhoopData::from_packet(packet);
+        assert!(matches!(result, Err(WhoopError::InvalidRRCount)));
+    }
+
     #[test]
     fn parse_console_logs() {
         let packet = WhoopPacket{
@@ -2132,6 +2400,74 @@ mod tests {
         assert_eq!(data, WhoopData::RunAlarm { unix: 1733561527 });
     }
 
+    #[test]
+    fn parse_high_freq_sync_events() {
+        let event = |cmd| WhoopPacket {
+            packet_type: PacketType::Event,
+            seq: 0,
+            cmd,
+            data: hex::decode("00b70c5467000c04000101ff00").expect("Invalid hex data"),
+            size: 0,
+            partial: false,
+        };
+
+        for (cmd, state) in [
+            (96, HighFreqSyncState::Prompt),
+            (97, HighFreqSyncState::Enabled),
+            (98, HighFreqSyncState::Disabled),
+        ] {
+            let data = WhoopData::from_packet(event(cmd)).expect("Invalid data");
+            assert_eq!(
+                data,
+                WhoopData::HighFreqSync {
+                    unix: 1733561527,
+                    state
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn parse_double_tap_event() {
+        let packet = WhoopPacket {
+            packet_type: PacketType::Event,
+            seq: 0,
+            cmd: EventNumber::DoubleTap as u8,
+            data: hex::decode("00b70c5467000c04000101ff00").expect("Invalid hex data"),
+            size: 0,
+            partial: false,
+        };
+
+        let data = WhoopData::from_packet(packet).expect("Invalid data");
+        assert_eq!(data, WhoopData::DoubleTap { unix: 1733561527 });
+    }
+
+    #[test]
+    fn parse_charging_events() {
+        let event = |cmd: EventNumber| WhoopPacket {
+            packet_type: PacketType::Event,
+            seq: 0,
+            cmd: cmd as u8,
+            data: hex::decode("00b70c5467000c04000101ff00").expect("Invalid hex data"),
+            size: 0,
+            partial: false,
+        };
+
+        for (cmd, charging) in [
+            (EventNumber::ChargingOn, true),
+            (EventNumber::ChargingOff, false),
+        ] {
+            let data = WhoopData::from_packet(event(cmd)).expect("Invalid data");
+            assert_eq!(
+                data,
+                WhoopData::Charging {
+                    unix: 1733561527,
+                    charging
+                }
+            );
+        }
+    }
+
     #[test]
     fn parse_metadata() {
         let bytes = hex::decode("aa1c00ab311002a9fc8367205337000000257e00000a0000000000007ac020f8")
@@ -2173,4 +2509,242 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn parse_body_location_response() {
+        let response = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0x78,
+            CommandNumber::GetBodyLocationAndStatus.as_u8(),
+            vec![0x54, 0x01, 0x01, 0x01, 0x01],
+        )
+        .framed_packet();
+        let packet = WhoopPacket::from_data(response).expect("invalid packet");
+        let data = WhoopData::from_packet(packet).expect("invalid packet");
+        assert_eq!(
+            data,
+            WhoopData::BodyLocation {
+                wrist: Wrist::Right,
+                on_body: true
+            }
+        );
+    }
+
+    #[test]
+    fn parse_body_location_rejects_unknown_wrist() {
+        let response = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::GetBodyLocationAndStatus.as_u8(),
+            vec![0x54, 0x01, 0x01, 0x07, 0x00],
+        )
+        .framed_packet();
+        let packet = WhoopPacket::from_data(response).expect("invalid packet");
+        assert!(WhoopData::from_packet(packet).is_err());
+    }
+
+    #[test]
+    fn parse_alarm_time_response() {
+        let mut payload = vec![0x54, 0x01, 0x01, 0x01];
+        payload.extend_from_slice(&1700000000_u32.to_le_bytes());
+        let response = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0x12,
+            CommandNumber::GetAlarmTime.as_u8(),
+            payload,
+        )
+        .framed_packet();
+        let packet = WhoopPacket::from_data(response).expect("invalid packet");
+        let data = WhoopData::from_packet(packet).expect("invalid packet");
+        assert_eq!(
+            data,
+            WhoopData::AlarmTime {
+                unix: 1700000000,
+                enabled: true
+            }
+        );
+    }
+
+    #[test]
+    fn parse_imu_stream_packet() {
+        // Built from the inferred layout, there is no captured stream packet to decode yet.
+        // Two samples: at rest with 1g on z, then moving
+        let mut data = Vec::new();
+        data.extend_from_slice(&7_u32.to_le_bytes());
+        data.extend_from_slice(&1700000000_u32.to_le_bytes());
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        // One block per axis, each holding both samples
+        let axes: [[i16; 2]; 6] = [
+            [0, 1875],
+            [0, -1875],
+            [1875, 0],
+            [0, 150],
+            [0, -15],
+            [0, 30],
+        ];
+        for axis in axes {
+            for raw in axis {
+                data.extend_from_slice(&i16::to_be_bytes(raw));
+            }
+        }
+
+        for packet_type in [
+            PacketType::HistoricalImuDataStream,
+            PacketType::RealtimeImuDataStream,
+        ] {
+            let frame = WhoopPacket::new(packet_type, 0, 0, data.clone()).framed_packet();
+            let packet = WhoopPacket::from_data(frame).expect("invalid packet");
+            let data = WhoopData::from_packet(packet).expect("invalid packet");
+            assert_eq!(
+                data,
+                WhoopData::ImuStream {
+                    unix: 1700000000,
+                    samples: vec![
+                        ImuSample {
+                            acc_x_g: 0.0,
+                            acc_y_g: 0.0,
+                            acc_z_g: 1.0,
+                            gyr_x_dps: 0.0,
+                            gyr_y_dps: 0.0,
+                            gyr_z_dps: 0.0,
+                        },
+                        ImuSample {
+                            acc_x_g: 1.0,
+                            acc_y_g: -1.0,
+                            acc_z_g: 0.0,
+                            gyr_x_dps: 10.0,
+                            gyr_y_dps: -1.0,
+                            gyr_z_dps: 2.0,
+                        },
+                    ],
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn parse_imu_stream_rejects_uneven_axes() {
+        // 14 byte header followed by 13 bytes, which can't split into six axes
+        let data = vec![0; 14 + 13];
+        let frame =
+            WhoopPacket::new(PacketType::HistoricalImuDataStream, 0, 0, data).framed_packet();
+        let packet = WhoopPacket::from_data(frame).expect("invalid packet");
+        assert!(matches!(
+            WhoopData::from_packet(packet),
+            Err(WhoopError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn raw_data_is_kept_undecoded() {
+        // No raw data packet has been captured, this only checks nothing is lost on the way
+        let payload: Vec<u8> = (0..64).collect();
+        let frame =
+            WhoopPacket::new(PacketType::RealtimeRawData, 3, 7, payload.clone()).framed_packet();
+        let packet = WhoopPacket::from_data(frame).expect("invalid packet");
+        let data = WhoopData::from_packet(packet).expect("invalid packet");
+        assert_eq!(
+            data,
+            WhoopData::RawData {
+                cmd: 7,
+                data: payload
+            }
+        );
+
+        // Framing already rejects an empty payload, the parser does as well
+        let packet = WhoopPacket::new(PacketType::RealtimeRawData, 3, 0, vec![]);
+        assert!(matches!(
+            WhoopData::from_packet(packet),
+            Err(WhoopError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn parse_data_range_response() {
+        // Built from the inferred layout, there is no captured response to decode yet
+        let mut payload = vec![0x54, 0x01, 0x01];
+        payload.extend_from_slice(&1200_u32.to_le_bytes());
+        payload.extend_from_slice(&98000_u32.to_le_bytes());
+        let response = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0x13,
+            CommandNumber::GetDataRange.as_u8(),
+            payload,
+        )
+        .framed_packet();
+        let packet = WhoopPacket::from_data(response).expect("invalid packet");
+        let data = WhoopData::from_packet(packet).expect("invalid packet");
+        assert_eq!(
+            data,
+            WhoopData::DataRange {
+                start: 1200,
+                end: 98000
+            }
+        );
+    }
+
+    #[test]
+    fn parse_v12_layout_follows_firmware() {
+        const V12: &str = "aa5c00f02f0c050f0008029e7e2868906380542c01400000000000000000000021436dff904d893dec19fb3e5ccf9b3d0a03773f00000000ec19fb3e5ccf9b3d0a03773fe0015702eb02590239019004010c020c310000000000000115f49cd0";
+
+        // Hypothetical revision that swapped the SpO2 channels
+        let swapped = V12Layout {
+            spo2_red: V12Layout::DEFAULT.spo2_ir,
+            spo2_ir: V12Layout::DEFAULT.spo2_red,
+            ..V12Layout::DEFAULT
+        };
+        let context =
+            ParseContext::default().with_v12_layout(FirmwareVersion::new(50, 0, 0, 0), swapped);
+
+        let parse = |context: &ParseContext| {
+            let packet = WhoopPacket::from_data(hex::decode(V12).unwrap()).unwrap();
+            match WhoopData::from_packet_with(packet, context).unwrap() {
+                WhoopData::HistoryReading(r) => r.sensor_data.expect("V12 should have sensor_data"),
+                _ => panic!("Expected HistoryReading"),
+            }
+        };
+
+        let old = parse(
+            &context
+                .clone()
+                .with_firmware(FirmwareVersion::new(41, 16, 6, 0)),
+        );
+        assert_eq!((old.spo2_red, old.spo2_ir), (480, 599));
+
+        let new = parse(&context.with_firmware(FirmwareVersion::new(50, 1, 0, 0)));
+        assert_eq!((new.spo2_red, new.spo2_ir), (599, 480));
+        assert_eq!(new.skin_temp_raw, old.skin_temp_raw);
+    }
+
+    #[test]
+    fn v12_activity_is_derived_from_wrist_tilt() {
+        const FLAT: &str = "aa5c00f02f0c050f0008029e7e2868906380542c01400000000000000000000021436dff904d893dec19fb3e5ccf9b3d0a03773f00000000ec19fb3e5ccf9b3d0a03773fe0015702eb02590239019004010c020c310000000000000115f49cd0";
+        const TILTED: &str = "aa5c00f02f0c053f940900da106966280080545401360195040000000000000000a34cff0050bf3b144efb3da4a4463f299c0dbf00004c42144efb3da4a4463f299c0dbff40155023b03530255016004010c020c2000000000000002e8c17c8d";
+
+        let parse = |hex_data: &str| {
+            let packet = WhoopPacket::from_data(hex::decode(hex_data).unwrap()).unwrap();
+            match WhoopData::from_packet(packet).unwrap() {
+                WhoopData::HistoryReading(r) => r.sensor_data.expect("V12 should have sensor_data"),
+                _ => panic!("Expected HistoryReading"),
+            }
+        };
+        let (flat, tilted) = (parse(FLAT), parse(TILTED));
+        let activity = |reading: &SensorData, previous: Option<&SensorData>| {
+            Activity::from(i64::from(reading.derived_activity(previous)))
+        };
+
+        assert_eq!(activity(&tilted, None), Activity::Inactive);
+        assert_eq!(activity(&tilted, Some(&tilted)), Activity::Sleep);
+        assert_eq!(activity(&tilted, Some(&flat)), Activity::Active);
+
+        let nudged = SensorData {
+            accel_gravity: [0.12, 0.8, -0.52],
+            ..tilted.clone()
+        };
+        assert_eq!(activity(&nudged, Some(&tilted)), Activity::Inactive);
+
+        // The flat reading was taken off the wrist, where lying still isn't sleep
+        assert_eq!(flat.skin_contact, 0);
+        assert_eq!(activity(&flat, Some(&flat)), Activity::Inactive);
+    }
 }
@@ -0,0 +1,202 @@
+use std::{fmt, str::FromStr};
+
+use crate::WhoopError;
+
+/// Firmware version as reported in [`WhoopData::VersionInfo`](crate::WhoopData::VersionInfo)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub build: u32,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32, build: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            build,
+        }
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = WhoopError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split('.')
+            .map(|p| p.parse::<u32>().map_err(|_| WhoopError::InvalidData))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match parts.as_slice() {
+            &[major, minor, patch, build] => Ok(Self::new(major, minor, patch, build)),
+            _ => Err(WhoopError::InvalidData),
+        }
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.patch, self.build
+        )
+    }
+}
+
+/// Byte offsets of the fields in a V12/V24 historical packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct V12Layout {
+    pub unix: usize,
+    pub bpm: usize,
+    pub rr_count: usize,
+    pub rr: usize,
+    pub ppg_green: usize,
+    pub ppg_red_ir: usize,
+    pub accel_gravity: usize,
+    pub skin_contact: usize,
+    pub spo2_red: usize,
+    pub spo2_ir: usize,
+    pub skin_temp_raw: usize,
+    pub ambient_light: usize,
+    pub led_drive_1: usize,
+    pub led_drive_2: usize,
+    pub resp_rate_raw: usize,
+    pub signal_quality: usize,
+}
+
+impl V12Layout {
+    /// Layout documented on `parse_historical_packet_v12`
+    pub const DEFAULT: Self = Self {
+        unix: 4,
+        bpm: 14,
+        rr_count: 15,
+        rr: 16,
+        ppg_green: 26,
+        ppg_red_ir: 28,
+        accel_gravity: 33,
+        skin_contact: 48,
+        spo2_red: 61,
+        spo2_ir: 63,
+        skin_temp_raw: 65,
+        ambient_light: 67,
+        led_drive_1: 69,
+        led_drive_2: 71,
+        resp_rate_raw: 73,
+        signal_quality: 75,
+    };
+
+    /// Shortest packet that holds every field of this layout
+    pub fn min_len(&self) -> usize {
+        [
+            self.unix + 4,
+            self.rr + 8,
+            self.accel_gravity + 12,
+            self.skin_contact + 1,
+            self.ppg_green + 2,
+            self.ppg_red_ir + 2,
+            self.spo2_red + 2,
+            self.spo2_ir + 2,
+            self.skin_temp_raw + 2,
+            self.ambient_light + 2,
+            self.led_drive_1 + 2,
+            self.led_drive_2 + 2,
+            self.resp_rate_raw + 2,
+            self.signal_quality + 2,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or_default()
+    }
+}
+
+impl Default for V12Layout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Firmware dependent parsing state for [`WhoopData::from_packet_with`](crate::WhoopData)
+///
+/// No firmware with a different V12 layout is registered yet: that needs captured packets
+/// from the affected firmware to find the moved offsets and test them against. Until one
+/// exists every firmware parses with [`V12Layout::DEFAULT`], and apps that know a layout
+/// can register it with [`ParseContext::with_v12_layout`].
+#[derive(Debug, Clone, Default)]
+pub struct ParseContext {
+    pub firmware: Option<FirmwareVersion>,
+    /// Layouts for firmware revisions that moved fields, keyed by the first version using them
+    v12_layouts: Vec<(FirmwareVersion, V12Layout)>,
+}
+
+impl ParseContext {
+    pub fn with_firmware(self, firmware: FirmwareVersion) -> Self {
+        Self {
+            firmware: Some(firmware),
+            ..self
+        }
+    }
+
+    /// Use `layout` for firmware `since` and newer, until a later registered version
+    pub fn with_v12_layout(mut self, since: FirmwareVersion, layout: V12Layout) -> Self {
+        self.v12_layouts.push((since, layout));
+        self.v12_layouts.sort_by_key(|(version, _)| *version);
+        self
+    }
+
+    /// Layout for the current firmware, [`V12Layout::DEFAULT`] if it is unknown
+    pub fn v12_layout(&self) -> V12Layout {
+        let Some(firmware) = self.firmware else {
+            return V12Layout::DEFAULT;
+        };
+
+        self.v12_layouts
+            .iter()
+            .rev()
+            .find(|(since, _)| *since <= firmware)
+            .map_or(V12Layout::DEFAULT, |(_, layout)| *layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn firmware_version_parse() {
+        let version = "41.16.6.0".parse::<FirmwareVersion>().unwrap();
+        assert_eq!(version, FirmwareVersion::new(41, 16, 6, 0));
+        assert_eq!(version.to_string(), "41.16.6.0");
+        assert!("41.16".parse::<FirmwareVersion>().is_err());
+        assert!("a.b.c.d".parse::<FirmwareVersion>().is_err());
+    }
+
+    #[test]
+    fn layout_selected_by_firmware() {
+        let shifted = V12Layout {
+            bpm: 16,
+            ..V12Layout::DEFAULT
+        };
+        let context =
+            ParseContext::default().with_v12_layout(FirmwareVersion::new(42, 0, 0, 0), shifted);
+
+        assert_eq!(context.v12_layout(), V12Layout::DEFAULT);
+
+        let old = context
+            .clone()
+            .with_firmware(FirmwareVersion::new(41, 16, 6, 0));
+        assert_eq!(old.v12_layout(), V12Layout::DEFAULT);
+
+        let new = context.with_firmware(FirmwareVersion::new(42, 1, 0, 0));
+        assert_eq!(new.v12_layout(), shifted);
+    }
+
+    #[test]
+    fn default_layout_min_len() {
+        assert_eq!(V12Layout::DEFAULT.min_len(), 77);
+    }
+}
@@ -1,6 +1,11 @@
 use chrono::NaiveDateTime;
+use serde::de::Error as _;
+use serde_json::{Map, Value};
 
-#[derive(Debug, Clone, PartialEq)]
+use super::Wrist;
+use crate::WhoopError;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct HistoryReading {
     pub unix: u64,
     pub bpm: u8,
@@ -13,7 +18,10 @@ pub struct HistoryReading {
 /// DSP sensor fields from V12/V24 historical data packets.
 /// These are raw ADC values - the WHOOP app uploads them to the server
 /// for server-side digital signal processing (not parsed client-side).
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+///
+/// Stored as JSON through [`SensorData::to_value`] and [`SensorData::from_value`], which
+/// tag and migrate the serialized form as fields change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct SensorData {
     /// PPG green LED photodiode (channel 1)
     pub ppg_green: u16,
@@ -74,6 +82,234 @@ impl HistoryReading {
     pub fn is_valid(&self) -> bool {
         self.bpm > 0
     }
+
+    /// Keeps every `n`th IMU sample, `0` drops IMU data entirely and `1` keeps everything.
+    pub fn downsample_imu(&mut self, n: usize) {
+        match n {
+            0 => self.imu_data.clear(),
+            1 => {}
+            n => {
+                self.imu_data = std::mem::take(&mut self.imu_data)
+                    .into_iter()
+                    .step_by(n)
+                    .collect();
+            }
+        }
+    }
+
+    /// Fills in an `activity` of `0`, which firmware without an activity field reports,
+    /// from the accelerometer.
+    ///
+    /// Uses the spread of the reading's IMU samples when it has them and how far the wrist
+    /// tilted since the `previous` reading otherwise, see [`SensorData::derived_activity`].
+    /// Readings with neither keep their activity.
+    pub fn derive_missing_activity(&mut self, previous: Option<&SensorData>) {
+        if self.activity != 0 {
+            return;
+        }
+
+        if let Some(activity) = imu_activity(&self.imu_data) {
+            self.activity = activity;
+        } else if let Some(sensor_data) = &self.sensor_data {
+            self.activity = sensor_data.derived_activity(previous);
+        }
+    }
+}
+
+/// Variance of the acceleration within a reading below which the wrist counts as still, in g²
+const STILL_ACC_VARIANCE: f32 = 0.0001;
+/// Variance of the acceleration within a reading above which the wrist counts as active, in g²
+const ACTIVE_ACC_VARIANCE: f32 = 0.01;
+
+/// Raw activity from the variance of the acceleration magnitude, `None` without samples
+fn imu_activity(samples: &[ImuSample]) -> Option<u32> {
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let magnitudes = samples
+        .iter()
+        .map(|s| (s.acc_x_g.powi(2) + s.acc_y_g.powi(2) + s.acc_z_g.powi(2)).sqrt())
+        .collect::<Vec<_>>();
+    let count = magnitudes.len() as f32;
+    let mean = magnitudes.iter().sum::<f32>() / count;
+    let variance = magnitudes.iter().map(|m| (m - mean).powi(2)).sum::<f32>() / count;
+
+    let activity = if variance < STILL_ACC_VARIANCE {
+        RAW_SLEEP
+    } else if variance > ACTIVE_ACC_VARIANCE {
+        RAW_ACTIVE
+    } else {
+        RAW_INACTIVE
+    };
+    Some(activity)
+}
+
+/// Raw activity values at the start of their range in `From<i64> for Activity`
+const RAW_INACTIVE: u32 = 0;
+const RAW_ACTIVE: u32 = 500_000_000;
+const RAW_SLEEP: u32 = 1_000_000_000;
+
+impl SensorData {
+    /// Version of the serialized form written by [`SensorData::to_value`]
+    pub const VERSION: u64 = 1;
+    /// Tilt of the wrist between consecutive readings below which it counts as still
+    const STILL_TILT_DEGREES: f32 = 2.0;
+    /// Tilt of the wrist between consecutive readings above which it counts as active
+    const ACTIVE_TILT_DEGREES: f32 = 15.0;
+
+    /// Raw activity for readings of firmware without an activity field (V12/V24).
+    ///
+    /// Estimated from how far the gravity vector tilted since the `previous` reading: a
+    /// still wrist counts as asleep and a large tilt as active. Anything in between, the
+    /// first reading and readings taken off the wrist count as inactive.
+    pub fn derived_activity(&self, previous: Option<&SensorData>) -> u32 {
+        let Some(previous) = previous.filter(|_| self.skin_contact != 0) else {
+            return RAW_INACTIVE;
+        };
+
+        match tilt_degrees(previous.accel_gravity, self.accel_gravity) {
+            Some(tilt) if tilt < Self::STILL_TILT_DEGREES => RAW_SLEEP,
+            Some(tilt) if tilt > Self::ACTIVE_TILT_DEGREES => RAW_ACTIVE,
+            _ => RAW_INACTIVE,
+        }
+    }
+
+    /// Serialized form with a `version` field, so stored rows can be migrated later.
+    pub fn to_value(&self) -> Result<Value, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        if let Value::Object(fields) = &mut value {
+            fields.insert(String::from("version"), Self::VERSION.into());
+        }
+        Ok(value)
+    }
+
+    /// Reads a value written by any version of [`SensorData::to_value`].
+    ///
+    /// Values without a `version` are version 0, written before the tag existed. Fields
+    /// they lack are filled with defaults and fields that were removed since are ignored.
+    pub fn from_value(value: Value) -> Result<Self, serde_json::Error> {
+        let Value::Object(mut fields) = value else {
+            return Err(serde_json::Error::custom("sensor data is not an object"));
+        };
+
+        match fields.remove("version").map(|v| v.as_u64()) {
+            None => Self::from_v0(fields),
+            Some(Some(Self::VERSION)) => serde_json::from_value(Value::Object(fields)),
+            Some(version) => Err(serde_json::Error::custom(format!(
+                "unsupported sensor data version {:?}",
+                version
+            ))),
+        }
+    }
+
+    fn from_v0(fields: Map<String, Value>) -> Result<Self, serde_json::Error> {
+        // A missing skin contact isn't evidence of being off-wrist, count it as worn like
+        // readings without sensor data
+        let defaults = Self {
+            skin_contact: 1,
+            ..Self::default()
+        };
+
+        let mut value = serde_json::to_value(defaults)?;
+        if let Value::Object(merged) = &mut value {
+            merged.extend(fields);
+        }
+        serde_json::from_value(value)
+    }
+}
+
+/// Angle between two gravity vectors, `None` if either one is zero
+fn tilt_degrees(from: [f32; 3], to: [f32; 3]) -> Option<f32> {
+    let norm = |v: [f32; 3]| v.iter().map(|c| c * c).sum::<f32>().sqrt();
+    let (from_norm, to_norm) = (norm(from), norm(to));
+    if from_norm == 0.0 || to_norm == 0.0 {
+        return None;
+    }
+
+    let dot = from.iter().zip(to).map(|(a, b)| a * b).sum::<f32>();
+    let cos = (dot / (from_norm * to_norm)).clamp(-1.0, 1.0);
+    Some(cos.acos().to_degrees())
+}
+
+impl ImuSample {
+    /// Raw accelerometer counts per g
+    pub const ACC_SENS: f32 = 1875.0;
+    /// Raw gyroscope counts per degree/second
+    pub const GYR_SENS: f32 = 15.0;
+    /// Six little-endian i16 axes per encoded sample
+    const ENCODED_SIZE: usize = 12;
+
+    /// Encodes samples as raw sensor counts, 6 little-endian `i16` per sample
+    /// in the order acc x/y/z, gyr x/y/z.
+    pub fn encode(samples: &[ImuSample]) -> Vec<u8> {
+        let to_raw = |value: f32, sens: f32| {
+            (value * sens)
+                .round()
+                .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+        };
+
+        let mut bytes = Vec::with_capacity(samples.len() * Self::ENCODED_SIZE);
+        for sample in samples {
+            for raw in [
+                to_raw(sample.acc_x_g, Self::ACC_SENS),
+                to_raw(sample.acc_y_g, Self::ACC_SENS),
+                to_raw(sample.acc_z_g, Self::ACC_SENS),
+                to_raw(sample.gyr_x_dps, Self::GYR_SENS),
+                to_raw(sample.gyr_y_dps, Self::GYR_SENS),
+                to_raw(sample.gyr_z_dps, Self::GYR_SENS),
+            ] {
+                bytes.extend_from_slice(&raw.to_le_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// Decodes samples written by [`ImuSample::encode`].
+    pub fn decode(bytes: &[u8]) -> Result<Vec<ImuSample>, WhoopError> {
+        if bytes.len() % Self::ENCODED_SIZE != 0 {
+            return Err(WhoopError::InvalidData);
+        }
+
+        let samples = bytes
+            .chunks_exact(Self::ENCODED_SIZE)
+            .map(|chunk| {
+                let axis = |i: usize, sens: f32| {
+                    f32::from(i16::from_le_bytes([chunk[i * 2], chunk[i * 2 + 1]])) / sens
+                };
+
+                ImuSample {
+                    acc_x_g: axis(0, Self::ACC_SENS),
+                    acc_y_g: axis(1, Self::ACC_SENS),
+                    acc_z_g: axis(2, Self::ACC_SENS),
+                    gyr_x_dps: axis(3, Self::GYR_SENS),
+                    gyr_y_dps: axis(4, Self::GYR_SENS),
+                    gyr_z_dps: axis(5, Self::GYR_SENS),
+                }
+            })
+            .collect();
+
+        Ok(samples)
+    }
+
+    /// Maps a sample into the left-wrist reference frame the raw data assumes.
+    ///
+    /// On the right wrist the band is rotated 180 degrees around its z axis, so
+    /// x and y flip sign for both accelerometer and gyroscope while z is unchanged.
+    pub fn reoriented(&self, wrist: Wrist) -> ImuSample {
+        match wrist {
+            Wrist::Left => self.clone(),
+            Wrist::Right => ImuSample {
+                acc_x_g: -self.acc_x_g,
+                acc_y_g: -self.acc_y_g,
+                acc_z_g: self.acc_z_g,
+                gyr_x_dps: -self.gyr_x_dps,
+                gyr_y_dps: -self.gyr_y_dps,
+                gyr_z_dps: self.gyr_z_dps,
+            },
+        }
+    }
 }
 
 impl From<i64> for Activity {
@@ -159,4 +395,180 @@ mod tests {
         };
         assert!(!reading.is_valid());
     }
+
+    fn sensor_data() -> SensorData {
+        SensorData {
+            ppg_green: 1200,
+            ppg_red_ir: 800,
+            spo2_red: 900,
+            spo2_ir: 950,
+            skin_temp_raw: 1500,
+            ambient_light: 10,
+            led_drive_1: 30,
+            led_drive_2: 31,
+            resp_rate_raw: 14,
+            signal_quality: 90,
+            skin_contact: 1,
+            accel_gravity: [0.0, 0.0, 1.0],
+        }
+    }
+
+    #[test]
+    fn sensor_data_v1_roundtrip() {
+        let value = sensor_data().to_value().unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(SensorData::from_value(value).unwrap(), sensor_data());
+
+        let json = r#"{"version":1,"ppg_green":1200,"ppg_red_ir":800,"spo2_red":900,
+            "spo2_ir":950,"skin_temp_raw":1500,"ambient_light":10,"led_drive_1":30,
+            "led_drive_2":31,"resp_rate_raw":14,"signal_quality":90,"skin_contact":1,
+            "accel_gravity":[0.0,0.0,1.0]}"#;
+        let value = serde_json::from_str(json).unwrap();
+        assert_eq!(SensorData::from_value(value).unwrap(), sensor_data());
+    }
+
+    #[test]
+    fn sensor_data_v0_is_migrated() {
+        // Untagged, without skin temperature and contact, with a field that no longer exists
+        let json = r#"{"ppg_green":1200,"ppg_red_ir":800,"spo2_red":900,"spo2_ir":950,
+            "ambient_light":10,"led_drive_1":30,"led_drive_2":31,"resp_rate_raw":14,
+            "signal_quality":90,"accel_gravity":[0.0,0.0,1.0],"dsp_flags":3}"#;
+        let value = serde_json::from_str(json).unwrap();
+
+        let expected = SensorData {
+            skin_temp_raw: 0,
+            ..sensor_data()
+        };
+        assert_eq!(SensorData::from_value(value).unwrap(), expected);
+    }
+
+    #[test]
+    fn sensor_data_unknown_version_fails() {
+        let mut value = sensor_data().to_value().unwrap();
+        value["version"] = 2.into();
+        assert!(SensorData::from_value(value).is_err());
+        assert!(SensorData::from_value(Value::Null).is_err());
+    }
+
+    fn imu_sample() -> ImuSample {
+        ImuSample {
+            acc_x_g: 0.12,
+            acc_y_g: -0.98,
+            acc_z_g: 0.05,
+            gyr_x_dps: 12.5,
+            gyr_y_dps: -3.0,
+            gyr_z_dps: 40.25,
+        }
+    }
+
+    #[test]
+    fn imu_reoriented_left_is_identity() {
+        let sample = imu_sample();
+        assert_eq!(sample.reoriented(Wrist::Left), sample);
+    }
+
+    #[test]
+    fn imu_reoriented_right_flips_x_y() {
+        let reoriented = imu_sample().reoriented(Wrist::Right);
+        assert_eq!(reoriented.acc_x_g, -0.12);
+        assert_eq!(reoriented.acc_y_g, 0.98);
+        assert_eq!(reoriented.acc_z_g, 0.05);
+        assert_eq!(reoriented.gyr_z_dps, 40.25);
+    }
+
+    #[test]
+    fn imu_reoriented_right_twice_is_original() {
+        let sample = imu_sample();
+        let twice = sample.reoriented(Wrist::Right).reoriented(Wrist::Right);
+        assert_eq!(twice, sample);
+    }
+
+    fn reading_with_imu(samples: usize) -> HistoryReading {
+        HistoryReading {
+            unix: 1000,
+            bpm: 70,
+            rr: vec![],
+            activity: 0,
+            imu_data: vec![imu_sample(); samples],
+            sensor_data: None,
+        }
+    }
+
+    #[test]
+    fn downsample_imu_keeps_every_nth() {
+        let mut reading = reading_with_imu(100);
+        reading.downsample_imu(10);
+        assert_eq!(reading.imu_data.len(), 10);
+    }
+
+    #[test]
+    fn downsample_imu_zero_drops_and_one_keeps() {
+        let mut reading = reading_with_imu(100);
+        reading.downsample_imu(1);
+        assert_eq!(reading.imu_data.len(), 100);
+
+        reading.downsample_imu(0);
+        assert!(reading.imu_data.is_empty());
+    }
+
+    #[test]
+    fn missing_activity_follows_imu_variance() {
+        let derived = |mut reading: HistoryReading| {
+            reading.derive_missing_activity(None);
+            Activity::from(i64::from(reading.activity))
+        };
+
+        // Identical samples, the wrist lies still
+        assert_eq!(derived(reading_with_imu(100)), Activity::Sleep);
+
+        // Acceleration swinging between half and one and a half g
+        let mut shaking = reading_with_imu(100);
+        for (i, sample) in shaking.imu_data.iter_mut().enumerate() {
+            sample.acc_y_g *= if i % 2 == 0 { 0.5 } else { 1.5 };
+        }
+        assert_eq!(derived(shaking), Activity::Active);
+
+        // Activity the strap reported is kept, readings without accelerometer data stay unset
+        let reported = HistoryReading {
+            activity: 1_200_000_000,
+            ..reading_with_imu(100)
+        };
+        assert_eq!(derived(reported), Activity::Sleep);
+        let mut bare = reading_with_imu(0);
+        bare.derive_missing_activity(None);
+        assert_eq!(bare.activity, 0);
+    }
+
+    #[test]
+    fn imu_encode_decode_roundtrip() {
+        let samples = vec![
+            ImuSample {
+                acc_x_g: 1.0,
+                acc_y_g: 0.0,
+                acc_z_g: -1.0,
+                gyr_x_dps: 10.0,
+                gyr_y_dps: 20.0,
+                gyr_z_dps: -30.0,
+            },
+            ImuSample {
+                acc_x_g: f32::from(-1234_i16) / ImuSample::ACC_SENS,
+                acc_y_g: f32::from(i16::MAX) / ImuSample::ACC_SENS,
+                acc_z_g: f32::from(i16::MIN) / ImuSample::ACC_SENS,
+                gyr_x_dps: f32::from(7_i16) / ImuSample::GYR_SENS,
+                gyr_y_dps: f32::from(-7_i16) / ImuSample::GYR_SENS,
+                gyr_z_dps: 0.0,
+            },
+        ];
+
+        let bytes = ImuSample::encode(&samples);
+        assert_eq!(bytes.len(), samples.len() * 12);
+        assert_eq!(&bytes[..2], &1875_i16.to_le_bytes());
+        assert_eq!(ImuSample::decode(&bytes).unwrap(), samples);
+    }
+
+    #[test]
+    fn imu_decode_empty_and_truncated() {
+        assert!(ImuSample::decode(&[]).unwrap().is_empty());
+        assert!(ImuSample::decode(&[0x01, 0x02, 0x03]).is_err());
+    }
 }
@@ -1,16 +1,21 @@
-use chrono::NaiveDateTime;
+use chrono::NaiveDate;
+use openwhoop_algos::{SleepCycle, resting_hr_trend};
 use openwhoop_entities::sleep_cycles;
-use openwhoop_algos::SleepCycle;
 use sea_orm::{ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder};
 
-use crate::DatabaseHandler;
+use crate::{DatabaseHandler, DbError};
 
 impl DatabaseHandler {
+    /// Sleep cycles ordered by start, only those that ended on a day from `from` and up to
+    /// `to` (both inclusive) if given.
     pub async fn get_sleep_cycles(
         &self,
-        start: Option<NaiveDateTime>,
-    ) -> anyhow::Result<Vec<SleepCycle>> {
-        let filter = Condition::all().add_option(start.map(|s| sleep_cycles::Column::Start.gte(s)));
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<SleepCycle>, DbError> {
+        let filter = Condition::all()
+            .add_option(from.map(|from| sleep_cycles::Column::SleepId.gte(from)))
+            .add_option(to.map(|to| sleep_cycles::Column::SleepId.lte(to)));
 
         Ok(sleep_cycles::Entity::find()
             .order_by_asc(sleep_cycles::Column::Start)
@@ -21,6 +26,16 @@ impl DatabaseHandler {
             .map(map_sleep_cycle)
             .collect())
     }
+
+    /// Nightly resting heart rate smoothed over the trailing `window_days`, see
+    /// [`resting_hr_trend`].
+    pub async fn resting_hr_trend(
+        &self,
+        window_days: u32,
+    ) -> Result<Vec<(NaiveDate, u8)>, DbError> {
+        let sleeps = self.get_sleep_cycles(None, None).await?;
+        Ok(resting_hr_trend(&sleeps, window_days))
+    }
 }
 
 fn map_sleep_cycle(value: sleep_cycles::Model) -> SleepCycle {
@@ -37,6 +52,10 @@ fn map_sleep_cycle(value: sleep_cycles::Model) -> SleepCycle {
         score: value
             .score
             .unwrap_or(SleepCycle::sleep_score(value.start, value.end)),
+        efficiency: value.efficiency,
+        sdnn: value.sdnn,
+        pnn50: value.pnn50,
+        deep_hrv: value.deep_hrv.map(|hrv| hrv.try_into().unwrap()),
     }
 }
 
@@ -66,12 +85,21 @@ mod tests {
             avg_hrv: 55,
             score: Some(95.0),
             synced: false,
+            efficiency: Some(90.0),
+            sdnn: Some(42.5),
+            pnn50: Some(12.5),
+            deep_hrv: Some(68),
+            imported: false,
         };
 
         let cycle = map_sleep_cycle(model);
         assert_eq!(cycle.min_bpm, 50);
         assert_eq!(cycle.avg_hrv, 55);
         assert_eq!(cycle.score, 95.0);
+        assert_eq!(cycle.efficiency, Some(90.0));
+        assert_eq!(cycle.sdnn, Some(42.5));
+        assert_eq!(cycle.pnn50, Some(12.5));
+        assert_eq!(cycle.deep_hrv, Some(68));
     }
 
     #[test]
@@ -95,17 +123,23 @@ mod tests {
             avg_hrv: 55,
             score: None, // No score stored
             synced: false,
+            efficiency: None,
+            sdnn: None,
+            pnn50: None,
+            deep_hrv: None,
+            imported: false,
         };
 
         let cycle = map_sleep_cycle(model);
         // 8 hours / 8 hours = 1.0 -> 100.0
         assert_eq!(cycle.score, 100.0);
+        assert_eq!(cycle.efficiency, None);
     }
 
     #[tokio::test]
     async fn get_sleep_cycles_empty() {
         let db = DatabaseHandler::new("sqlite::memory:").await;
-        let cycles = db.get_sleep_cycles(None).await.unwrap();
+        let cycles = db.get_sleep_cycles(None, None).await.unwrap();
         assert!(cycles.is_empty());
     }
 
@@ -123,31 +157,24 @@ mod tests {
             .unwrap();
 
         db.create_sleep(SleepCycle {
-            id: end.date(),
-            start,
-            end,
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
+            efficiency: Some(87.5),
+            ..SleepCycle::fixture(start, end)
         })
         .await
         .unwrap();
 
-        let cycles = db.get_sleep_cycles(None).await.unwrap();
+        let cycles = db.get_sleep_cycles(None, None).await.unwrap();
         assert_eq!(cycles.len(), 1);
         assert_eq!(cycles[0].min_bpm, 50);
+        assert_eq!(cycles[0].efficiency, Some(87.5));
     }
 
     #[tokio::test]
-    async fn get_sleep_cycles_with_start_filter() {
+    async fn get_sleep_cycles_in_range() {
         let db = DatabaseHandler::new("sqlite::memory:").await;
 
-        // Insert two sleep cycles
-        for day in [1, 3] {
+        // Sleeps ending on Jan 2, 4 and 6
+        for day in [1, 3, 5] {
             let start = NaiveDate::from_ymd_opt(2025, 1, day)
                 .unwrap()
                 .and_hms_opt(22, 0, 0)
@@ -157,28 +184,56 @@ mod tests {
                 .and_hms_opt(6, 0, 0)
                 .unwrap();
 
+            db.create_sleep(SleepCycle::fixture(start, end))
+                .await
+                .unwrap();
+        }
+
+        let date = |day| NaiveDate::from_ymd_opt(2025, 1, day).unwrap();
+
+        let ids = |cycles: Vec<SleepCycle>| cycles.iter().map(|cycle| cycle.id).collect::<Vec<_>>();
+
+        let cycles = db.get_sleep_cycles(Some(date(3)), Some(date(5))).await;
+        assert_eq!(ids(cycles.unwrap()), [date(4)]);
+
+        // Both ends are included
+        let cycles = db.get_sleep_cycles(Some(date(2)), Some(date(6))).await;
+        assert_eq!(cycles.unwrap().len(), 3);
+
+        // Either end can be left open
+        let cycles = db.get_sleep_cycles(Some(date(4)), None).await;
+        assert_eq!(ids(cycles.unwrap()), [date(4), date(6)]);
+        let cycles = db.get_sleep_cycles(None, Some(date(4))).await;
+        assert_eq!(ids(cycles.unwrap()), [date(2), date(4)]);
+    }
+
+    #[tokio::test]
+    async fn resting_hr_trend_follows_rising_baseline() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        // Resting HR rises by 1 bpm a night, with every other night 3 bpm higher
+        for day in 0..10 {
+            let end = NaiveDate::from_ymd_opt(2025, 1, 2)
+                .unwrap()
+                .and_hms_opt(6, 0, 0)
+                .unwrap()
+                + chrono::TimeDelta::days(day);
+            let noise = if day % 2 == 1 { 3 } else { 0 };
+
             db.create_sleep(SleepCycle {
-                id: end.date(),
-                start,
-                end,
-                min_bpm: 50,
-                max_bpm: 70,
-                avg_bpm: 60,
-                min_hrv: 30,
-                max_hrv: 80,
-                avg_hrv: 55,
-                score: 100.0,
+                min_bpm: 50 + day as u8 + noise,
+                ..SleepCycle::fixture(end - chrono::TimeDelta::hours(8), end)
             })
             .await
             .unwrap();
         }
 
-        let filter_start = NaiveDate::from_ymd_opt(2025, 1, 2)
-            .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-
-        let cycles = db.get_sleep_cycles(Some(filter_start)).await.unwrap();
-        assert_eq!(cycles.len(), 1); // Only the Jan 3 sleep
+        let trend = db.resting_hr_trend(4).await.unwrap();
+        assert_eq!(trend.len(), 10);
+        assert_eq!(trend[0].0, NaiveDate::from_ymd_opt(2025, 1, 2).unwrap());
+        let bpm = trend.iter().map(|(_, bpm)| *bpm).collect::<Vec<_>>();
+        assert!(bpm.windows(2).all(|w| w[1] >= w[0]), "{:?}", bpm);
+        assert_eq!(bpm[0], 50);
+        assert_eq!(bpm[9], 59);
     }
 }
@@ -1,4 +1,5 @@
 use crate::DatabaseHandler;
+use crate::DbError;
 use crate::SearchHistory;
 
 use chrono::NaiveDateTime;
@@ -11,7 +12,7 @@ use sea_orm::{
 };
 
 impl DatabaseHandler {
-    pub async fn last_spo2_time(&self) -> anyhow::Result<Option<NaiveDateTime>> {
+    pub async fn last_spo2_time(&self) -> Result<Option<NaiveDateTime>, DbError> {
         let reading = heart_rate::Entity::find()
             .filter(heart_rate::Column::Spo2.is_not_null())
             .order_by_desc(heart_rate::Column::Time)
@@ -27,33 +28,40 @@ impl DatabaseHandler {
     pub async fn search_sensor_readings(
         &self,
         options: SearchHistory,
-    ) -> anyhow::Result<Vec<SpO2Reading>> {
+    ) -> Result<Vec<SpO2Reading>, DbError> {
         let limit = options.limit;
         let rows = heart_rate::Entity::find()
             .filter(options.conditions())
             .filter(heart_rate::Column::SensorData.is_not_null())
+            .filter(self.not_charging())
             .limit(limit)
             .order_by_asc(heart_rate::Column::Time)
             .all(&self.db)
             .await?;
 
-        let readings = rows
-            .into_iter()
-            .filter_map(|m| {
-                let json = m.sensor_data?;
-                let sd: SensorData = serde_json::from_value(json).ok()?;
-                Some(SpO2Reading {
-                    time: m.time,
-                    spo2_red: sd.spo2_red,
-                    spo2_ir: sd.spo2_ir,
-                })
-            })
-            .collect();
-
-        Ok(readings)
+        Ok(rows.into_iter().filter_map(sensor_reading).collect())
     }
 
-    pub async fn update_spo2_on_reading(&self, score: SpO2Score) -> anyhow::Result<()> {
+    /// The last `count` readings with sensor data taken before `time`, oldest first, so a
+    /// SpO2 window can be completed by the readings that follow them.
+    pub(crate) async fn sensor_readings_before(
+        &self,
+        time: NaiveDateTime,
+        count: u64,
+    ) -> Result<Vec<SpO2Reading>, DbError> {
+        let rows = heart_rate::Entity::find()
+            .filter(heart_rate::Column::Time.lt(time))
+            .filter(heart_rate::Column::SensorData.is_not_null())
+            .filter(self.not_charging())
+            .order_by_desc(heart_rate::Column::Time)
+            .limit(count)
+            .all(&self.db)
+            .await?;
+
+        Ok(rows.into_iter().rev().filter_map(sensor_reading).collect())
+    }
+
+    pub async fn update_spo2_on_reading(&self, score: SpO2Score) -> Result<(), DbError> {
         let model = heart_rate::ActiveModel {
             id: NotSet,
             bpm: NotSet,
@@ -61,11 +69,13 @@ impl DatabaseHandler {
             rr_intervals: NotSet,
             activity: NotSet,
             stress: NotSet,
+            stress_smoothed: NotSet,
             spo2: Set(Some(score.spo2_percentage)),
             skin_temp: NotSet,
             imu_data: NotSet,
             sensor_data: NotSet,
             synced: NotSet,
+            low_confidence: NotSet,
         };
 
         heart_rate::Entity::update_many()
@@ -78,6 +88,15 @@ impl DatabaseHandler {
     }
 }
 
+fn sensor_reading(model: heart_rate::Model) -> Option<SpO2Reading> {
+    let sd = SensorData::from_value(model.sensor_data?).ok()?;
+    Some(SpO2Reading {
+        time: model.time,
+        spo2_red: sd.spo2_red,
+        spo2_ir: sd.spo2_ir,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
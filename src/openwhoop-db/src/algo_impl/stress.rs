@@ -1,15 +1,17 @@
-use crate::DatabaseHandler;
+use crate::{DatabaseHandler, DbError};
 
-use chrono::NaiveDateTime;
-use openwhoop_entities::heart_rate;
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDateTime, TimeDelta};
 use openwhoop_algos::StressScore;
+use openwhoop_entities::heart_rate;
 use sea_orm::{
     ActiveValue::NotSet, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
     SelectColumns, Set, Unchanged,
 };
 
 impl DatabaseHandler {
-    pub async fn last_stress_time(&self) -> anyhow::Result<Option<NaiveDateTime>> {
+    pub async fn last_stress_time(&self) -> Result<Option<NaiveDateTime>, DbError> {
         let reading = heart_rate::Entity::find()
             .filter(heart_rate::Column::Stress.is_not_null())
             .order_by_desc(heart_rate::Column::Time)
@@ -22,7 +24,66 @@ impl DatabaseHandler {
         Ok(reading)
     }
 
-    pub async fn update_stress_on_reading(&self, stress: StressScore) -> anyhow::Result<()> {
+    /// Smoothed stress of the latest scored reading, smoothing new scores continues from it.
+    pub async fn last_smoothed_stress(&self) -> Result<Option<f64>, DbError> {
+        let smoothed: Option<Option<f64>> = heart_rate::Entity::find()
+            .filter(heart_rate::Column::Stress.is_not_null())
+            .order_by_desc(heart_rate::Column::Time)
+            .select_only()
+            .select_column(heart_rate::Column::StressSmoothed)
+            .into_tuple()
+            .one(&self.db)
+            .await?;
+
+        Ok(smoothed.flatten())
+    }
+
+    /// Mean stress per `bucket` between `from` and `to`, keyed by bucket start.
+    ///
+    /// Buckets are aligned to `from`, buckets without stress scores are left out.
+    pub async fn stress_buckets(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        bucket: TimeDelta,
+    ) -> Result<Vec<(NaiveDateTime, f64)>, DbError> {
+        if bucket <= TimeDelta::zero() {
+            return Err(DbError::Invalid("stress bucket must be positive".into()));
+        }
+
+        let scores: Vec<(NaiveDateTime, f64)> = heart_rate::Entity::find()
+            .select_only()
+            .column(heart_rate::Column::Time)
+            .column(heart_rate::Column::Stress)
+            .filter(heart_rate::Column::Stress.is_not_null())
+            .filter(heart_rate::Column::Time.gte(from))
+            .filter(heart_rate::Column::Time.lt(to))
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        let bucket_ms = bucket.num_milliseconds().max(1);
+        let mut buckets = BTreeMap::<NaiveDateTime, (f64, u32)>::new();
+        for (time, stress) in scores {
+            let index = (time - from).num_milliseconds() / bucket_ms;
+            let start = from + TimeDelta::milliseconds(index * bucket_ms);
+            let (sum, count) = buckets.entry(start).or_default();
+            *sum += stress;
+            *count += 1;
+        }
+
+        Ok(buckets
+            .into_iter()
+            .map(|(start, (sum, count))| (start, sum / f64::from(count)))
+            .collect())
+    }
+
+    /// Stores `stress` on its reading, `smoothed` is stored alongside it when stress is smoothed.
+    pub async fn update_stress_on_reading(
+        &self,
+        stress: StressScore,
+        smoothed: Option<f64>,
+    ) -> Result<(), DbError> {
         let model = heart_rate::ActiveModel {
             id: NotSet,
             bpm: NotSet,
@@ -30,11 +91,13 @@ impl DatabaseHandler {
             rr_intervals: NotSet,
             activity: NotSet,
             stress: Set(Some(stress.score)),
+            stress_smoothed: Set(smoothed),
             spo2: NotSet,
             skin_temp: NotSet,
             imu_data: NotSet,
             sensor_data: NotSet,
             synced: NotSet,
+            low_confidence: NotSet,
         };
 
         heart_rate::Entity::update_many()
@@ -78,14 +141,66 @@ mod tests {
             .unwrap();
         let time = history[0].time;
 
-        let stress = StressScore {
-            time,
-            score: 5.5,
-        };
-        db.update_stress_on_reading(stress).await.unwrap();
+        let stress = StressScore { time, score: 5.5 };
+        db.update_stress_on_reading(stress, None).await.unwrap();
 
         let last_stress = db.last_stress_time().await.unwrap();
         assert!(last_stress.is_some());
         assert_eq!(last_stress.unwrap(), time);
     }
+
+    #[tokio::test]
+    async fn stress_buckets_average_each_hour() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        // A reading every 10 minutes for 3 hours
+        let readings = (0..18)
+            .map(|i| openwhoop_codec::HistoryReading {
+                unix: 1735689600000 + i * 600_000,
+                bpm: 72,
+                rr: vec![833],
+                activity: 500_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let history = db
+            .search_history(crate::SearchHistory::default())
+            .await
+            .unwrap();
+        let from = history[0].time;
+        for (i, reading) in history.iter().enumerate() {
+            // Hour 1 alternates 1 and 3, hour 2 is 4, hour 3 alternates 6 and 8
+            let score = match i / 6 {
+                0 => [1.0, 3.0][i % 2],
+                1 => 4.0,
+                _ => [6.0, 8.0][i % 2],
+            };
+            let stress = StressScore {
+                time: reading.time,
+                score,
+            };
+            db.update_stress_on_reading(stress, None).await.unwrap();
+        }
+
+        // The fourth hour has no readings and is left out
+        let to = from + TimeDelta::hours(4);
+        let buckets = db
+            .stress_buckets(from, to, TimeDelta::hours(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            buckets,
+            vec![
+                (from, 2.0),
+                (from + TimeDelta::hours(1), 4.0),
+                (from + TimeDelta::hours(2), 7.0),
+            ]
+        );
+
+        let invalid = db.stress_buckets(from, to, TimeDelta::zero()).await;
+        assert!(invalid.is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{DatabaseHandler, SearchHistory};
+use crate::{DatabaseHandler, DbError, SearchHistory};
 
 use chrono::NaiveDateTime;
 use openwhoop_algos::SkinTempScore;
@@ -15,7 +15,7 @@ pub struct TempReading {
 }
 
 impl DatabaseHandler {
-    pub async fn last_skin_temp_time(&self) -> anyhow::Result<Option<NaiveDateTime>> {
+    pub async fn last_skin_temp_time(&self) -> Result<Option<NaiveDateTime>, DbError> {
         let reading = heart_rate::Entity::find()
             .filter(heart_rate::Column::SkinTemp.is_not_null())
             .order_by_desc(heart_rate::Column::Time)
@@ -28,15 +28,40 @@ impl DatabaseHandler {
         Ok(reading)
     }
 
+    /// Mean skin temperature in °C of the readings between `from` and `to`, `None` when
+    /// none of them has one. Readings taken while charging are left out.
+    pub async fn mean_skin_temp(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Option<f64>, DbError> {
+        let temps: Vec<f64> = heart_rate::Entity::find()
+            .select_only()
+            .column(heart_rate::Column::SkinTemp)
+            .filter(heart_rate::Column::SkinTemp.is_not_null())
+            .filter(heart_rate::Column::Time.gte(from))
+            .filter(heart_rate::Column::Time.lt(to))
+            .filter(self.not_charging())
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        if temps.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(temps.iter().sum::<f64>() / temps.len() as f64))
+    }
+
     pub async fn search_temp_readings(
         &self,
         options: SearchHistory,
-    ) -> anyhow::Result<Vec<TempReading>> {
+    ) -> Result<Vec<TempReading>, DbError> {
         let limit = options.limit;
         let rows = heart_rate::Entity::find()
             .filter(options.conditions())
             .filter(heart_rate::Column::SensorData.is_not_null())
             .filter(heart_rate::Column::SkinTemp.is_null())
+            .filter(self.not_charging())
             .limit(limit)
             .order_by_asc(heart_rate::Column::Time)
             .all(&self.db)
@@ -46,7 +71,7 @@ impl DatabaseHandler {
             .into_iter()
             .filter_map(|m| {
                 let json = m.sensor_data?;
-                let sd: SensorData = serde_json::from_value(json).ok()?;
+                let sd = SensorData::from_value(json).ok()?;
                 Some(TempReading {
                     time: m.time,
                     skin_temp_raw: sd.skin_temp_raw,
@@ -57,7 +82,7 @@ impl DatabaseHandler {
         Ok(readings)
     }
 
-    pub async fn update_skin_temp_on_reading(&self, score: SkinTempScore) -> anyhow::Result<()> {
+    pub async fn update_skin_temp_on_reading(&self, score: SkinTempScore) -> Result<(), DbError> {
         let model = heart_rate::ActiveModel {
             id: NotSet,
             bpm: NotSet,
@@ -65,11 +90,13 @@ impl DatabaseHandler {
             rr_intervals: NotSet,
             activity: NotSet,
             stress: NotSet,
+            stress_smoothed: NotSet,
             spo2: NotSet,
             skin_temp: Set(Some(score.temp_celsius)),
             imu_data: NotSet,
             sensor_data: NotSet,
             synced: NotSet,
+            low_confidence: NotSet,
         };
 
         heart_rate::Entity::update_many()
@@ -86,6 +113,41 @@ impl DatabaseHandler {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn mean_skin_temp_averages_readings_in_range() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let year = |year| {
+            chrono::NaiveDate::from_ymd_opt(year, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let (from, to) = (year(2024), year(2026));
+        assert!(db.mean_skin_temp(from, to).await.unwrap().is_none());
+
+        let readings = [850, 900]
+            .into_iter()
+            .enumerate()
+            .map(|(i, skin_temp_raw)| openwhoop_codec::HistoryReading {
+                unix: 1735689600000 + i as u64 * 1000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: Some(SensorData {
+                    skin_temp_raw,
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let mean = db.mean_skin_temp(from, to).await.unwrap();
+        assert!((mean.unwrap() - 35.0).abs() < 1e-9, "{mean:?}");
+        let before = db.mean_skin_temp(year(2023), from).await.unwrap();
+        assert!(before.is_none());
+    }
+
     #[tokio::test]
     async fn last_skin_temp_time_empty() {
         let db = DatabaseHandler::new("sqlite::memory:").await;
@@ -112,7 +174,8 @@ mod tests {
             ppg_red_ir: 200,
             spo2_red: 3000,
             spo2_ir: 4000,
-            skin_temp_raw: 850,
+            // Too low to convert, so the reading is stored without a skin temp
+            skin_temp_raw: 50,
             ambient_light: 50,
             led_drive_1: 10,
             led_drive_2: 20,
@@ -138,7 +201,7 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(readings.len(), 1);
-        assert_eq!(readings[0].skin_temp_raw, 850);
+        assert_eq!(readings[0].skin_temp_raw, 50);
 
         let time = readings[0].time;
 
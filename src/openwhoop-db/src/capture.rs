@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use uuid::Uuid;
+
+use crate::{DatabaseHandler, DbError};
+
+impl DatabaseHandler {
+    /// Writes stored packets as newline-delimited `<uuid> <hex>` lines, starting at `from_id`.
+    ///
+    /// Packets are fetched page by page so large stores are never fully loaded into memory.
+    pub async fn dump_packets<W: Write>(
+        &self,
+        writer: &mut W,
+        from_id: Option<i32>,
+    ) -> Result<usize, DbError> {
+        let mut id = from_id.map_or(0, |id| id.saturating_sub(1));
+        let mut count = 0;
+
+        loop {
+            let packets = self.get_packets(id).await?;
+            if packets.is_empty() {
+                break;
+            }
+
+            for packet in packets {
+                id = packet.id;
+                writeln!(writer, "{}", format_line(packet.uuid, &packet.bytes))?;
+                count += 1;
+            }
+        }
+
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Writes the captured unknown packets in the same format as [`Self::dump_packets`], so
+    /// they can be shared and replayed once the codec supports them.
+    pub async fn dump_unknown_packets<W: Write>(&self, writer: &mut W) -> Result<usize, DbError> {
+        let packets = self.get_unknown_packets().await?;
+        for packet in &packets {
+            writeln!(writer, "{}", format_line(packet.uuid, &packet.bytes))?;
+        }
+
+        writer.flush()?;
+        Ok(packets.len())
+    }
+}
+
+pub fn format_line(uuid: Uuid, bytes: &[u8]) -> String {
+    format!("{} {}", uuid, hex::encode(bytes))
+}
+
+pub fn parse_line(line: &str) -> Result<(Uuid, Vec<u8>), DbError> {
+    let (uuid, bytes) = line
+        .trim()
+        .split_once(' ')
+        .ok_or_else(|| DbError::Invalid(format!("Invalid capture line: `{}`", line)))?;
+
+    Ok((Uuid::parse_str(uuid)?, hex::decode(bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_roundtrip() {
+        let uuid = Uuid::new_v4();
+        let line = format_line(uuid, &[0xAA, 0x01, 0xFF]);
+        let (parsed_uuid, bytes) = parse_line(&line).unwrap();
+        assert_eq!(parsed_uuid, uuid);
+        assert_eq!(bytes, vec![0xAA, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn parse_line_rejects_garbage() {
+        assert!(parse_line("not-a-capture").is_err());
+    }
+
+    #[tokio::test]
+    async fn dump_packets_roundtrip() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let first = (Uuid::new_v4(), vec![0xAA, 0xBB, 0xCC]);
+        let second = (Uuid::new_v4(), vec![0x01, 0x02]);
+        db.create_packet(first.0, first.1.clone()).await.unwrap();
+        db.create_packet(second.0, second.1.clone()).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("openwhoop-dump-{}.hex", Uuid::new_v4()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        let count = db.dump_packets(&mut file, None).await.unwrap();
+        assert_eq!(count, 2);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let packets = contents
+            .lines()
+            .map(parse_line)
+            .collect::<Result<Vec<_>, DbError>>()
+            .unwrap();
+        assert_eq!(packets, vec![first, second]);
+    }
+
+    #[tokio::test]
+    async fn dump_packets_from_id() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let uuid = Uuid::new_v4();
+        db.create_packet(uuid, vec![0x01]).await.unwrap();
+        let second = db.create_packet(uuid, vec![0x02]).await.unwrap();
+
+        let mut buffer = Vec::new();
+        let count = db.dump_packets(&mut buffer, Some(second.id)).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(String::from_utf8(buffer).unwrap(), format!("{} 02\n", uuid));
+    }
+
+    #[tokio::test]
+    async fn dump_unknown_packets_skips_duplicates() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let uuid = Uuid::new_v4();
+        for bytes in [vec![0xAA, 0x01], vec![0xAA, 0x02], vec![0xAA, 0x01]] {
+            db.create_unknown_packet(uuid, bytes, "InvalidCommandType(200)".to_owned(), None)
+                .await
+                .unwrap();
+        }
+
+        let mut buffer = Vec::new();
+        let count = db.dump_unknown_packets(&mut buffer).await.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!("{0} aa01\n{0} aa02\n", uuid)
+        );
+    }
+}
@@ -1,14 +1,39 @@
-use chrono::{Local, NaiveDateTime, TimeZone};
-use openwhoop_entities::{packets, sleep_cycles};
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{Local, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use openwhoop_entities::{
+    console_logs, imu_stream, markers, packets, quarantined_readings, sleep_cycles, sync_state,
+    unknown_packets,
+};
 use openwhoop_migration::{Migrator, MigratorTrait, OnConflict};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, ConnectOptions, Database,
-    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+    ActiveValue::NotSet,
+    ColumnTrait, ConnectOptions, Database, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, RuntimeErr, Set,
+    sea_query::Expr,
+    sqlx::{
+        error::DatabaseError,
+        sqlite::{SqliteError, SqliteJournalMode},
+    },
 };
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-use openwhoop_algos::SleepCycle;
-use openwhoop_codec::HistoryReading;
+use openwhoop_algos::{SkinTempCalculator, SleepCycle, SpO2Calculator, SpO2Reading};
+use openwhoop_codec::{HistoryReading, ImuSample, SensorData};
+
+use crate::{DbError, sync::HEART_RATE_BATCH};
+
+/// 2015-01-01 UTC, earlier readings come from a strap that lost its clock
+const MIN_READING_UNIX_MS: u64 = 1_420_070_400_000;
+/// How far a reading may be ahead of the host clock before it counts as clock skew
+const MAX_CLOCK_AHEAD: TimeDelta = TimeDelta::days(1);
+
+/// How long an SQLite connection waits for another connection's lock before giving up
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Times a write is retried when SQLite reports the database busy despite the busy timeout,
+/// e.g. when two processes sync different bands into the same file
+const BUSY_RETRIES: u32 = 5;
 
 #[derive(Clone)]
 pub struct DatabaseHandler {
@@ -24,7 +49,24 @@ impl DatabaseHandler {
     where
         C: Into<ConnectOptions>,
     {
-        let db = Database::connect(path)
+        Self::with_busy_timeout(path, DEFAULT_BUSY_TIMEOUT).await
+    }
+
+    /// Same as [`Self::new`], with SQLite waiting up to `busy_timeout` for a lock.
+    ///
+    /// SQLite connections use WAL journaling, so readers and the writer don't block each other
+    /// and a query running during a sync doesn't fail with "database is locked".
+    pub async fn with_busy_timeout<C>(path: C, busy_timeout: Duration) -> Self
+    where
+        C: Into<ConnectOptions>,
+    {
+        let mut options = path.into();
+        options.map_sqlx_sqlite_opts(move |opts| {
+            opts.journal_mode(SqliteJournalMode::Wal)
+                .busy_timeout(busy_timeout)
+        });
+
+        let db = Database::connect(options)
             .await
             .expect("Unable to connect to db");
 
@@ -35,71 +77,113 @@ impl DatabaseHandler {
         Self { db }
     }
 
+    /// Connects to an SQLite database url read-only (`mode=ro`), without running migrations.
+    ///
+    /// Analysis never takes the write lock this way, so it can't block a sync writing to the
+    /// same file. Writes through the handle fail.
+    pub async fn new_read_only(url: &str) -> Result<Self, DbError> {
+        let Some(path) = url.strip_prefix("sqlite:") else {
+            return Err(DbError::Invalid(format!(
+                "read-only connections need an SQLite url, got {}",
+                url
+            )));
+        };
+
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty() && !param.starts_with("mode="))
+            .chain(["mode=ro"])
+            .collect::<Vec<_>>();
+        let mut options = ConnectOptions::new(format!("sqlite:{}?{}", path, params.join("&")));
+        options.map_sqlx_sqlite_opts(|opts| opts.busy_timeout(DEFAULT_BUSY_TIMEOUT));
+        let db = Database::connect(options).await?;
+
+        let pending = Migrator::get_pending_migrations(&db).await?;
+        if !pending.is_empty() {
+            return Err(DbError::Invalid(format!(
+                "{} has {} pending migrations, open it with openwhoop once to migrate it",
+                url,
+                pending.len()
+            )));
+        }
+
+        Ok(Self { db })
+    }
+
     pub async fn create_packet(
         &self,
         char: Uuid,
         data: Vec<u8>,
-    ) -> anyhow::Result<openwhoop_entities::packets::Model> {
+    ) -> Result<openwhoop_entities::packets::Model, DbError> {
+        // Re-downloading history resends identical frames, keep only the first copy. Inserting
+        // and ignoring the conflict keeps this a single statement, so another process storing
+        // the same frame at the same time can't make it fail.
+        let hash = packet_hash(char, &data);
         let packet = openwhoop_entities::packets::ActiveModel {
             id: NotSet,
             uuid: Set(char),
             bytes: Set(data),
+            hash: Set(Some(hash.clone())),
         };
 
-        let packet = packet.insert(&self.db).await?;
+        retry_busy(|| {
+            packets::Entity::insert(packet.clone())
+                .on_conflict(
+                    OnConflict::column(packets::Column::Hash)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .exec_without_returning(&self.db)
+        })
+        .await?;
+
+        let packet = packets::Entity::find()
+            .filter(packets::Column::Hash.eq(hash.as_str()))
+            .one(&self.db)
+            .await?
+            .ok_or_else(|| DbError::NotFound(format!("packet {} was not stored", hash)))?;
         Ok(packet)
     }
 
-    pub async fn create_reading(&self, reading: HistoryReading) -> anyhow::Result<()> {
-        let time = timestamp_to_local(reading.unix);
-
-        let sensor_json = reading
-            .sensor_data
-            .as_ref()
-            .map(|s| serde_json::to_value(s))
-            .transpose()?;
-
-        let packet = openwhoop_entities::heart_rate::ActiveModel {
-            id: NotSet,
-            bpm: Set(reading.bpm as i16),
-            time: Set(time),
-            rr_intervals: Set(rr_to_string(reading.rr)),
-            activity: Set(Some(i64::from(reading.activity))),
-            stress: NotSet,
-            spo2: NotSet,
-            skin_temp: NotSet,
-            imu_data: Set(Some(serde_json::to_value(reading.imu_data)?)),
-            sensor_data: Set(sensor_json),
-            synced: NotSet,
-        };
-
-        let _model = openwhoop_entities::heart_rate::Entity::insert(packet)
-            .on_conflict(
-                OnConflict::column(openwhoop_entities::heart_rate::Column::Time)
-                    .update_column(openwhoop_entities::heart_rate::Column::Bpm)
-                    .update_column(openwhoop_entities::heart_rate::Column::RrIntervals)
-                    .update_column(openwhoop_entities::heart_rate::Column::Activity)
-                    .update_column(openwhoop_entities::heart_rate::Column::SensorData)
-                    .to_owned(),
-            )
-            .exec(&self.db)
-            .await?;
-
-        Ok(())
+    /// Stores a single reading, like [`Self::create_readings`]: skin temperature is derived
+    /// from its sensor data and SpO2 once the readings stored before it complete a window.
+    ///
+    /// Readings with an implausible timestamp are quarantined instead, see
+    /// [`Self::get_quarantined_readings`].
+    pub async fn create_reading(&self, reading: HistoryReading) -> Result<(), DbError> {
+        self.create_readings(vec![reading]).await
     }
 
-    pub async fn create_readings(&self, readings: Vec<HistoryReading>) -> anyhow::Result<()> {
+    pub async fn create_readings(&self, readings: Vec<HistoryReading>) -> Result<(), DbError> {
         if readings.is_empty() {
             return Ok(());
         }
-        let payloads = readings
+
+        let mut timed = Vec::with_capacity(readings.len());
+        for reading in readings {
+            match reading_time(reading.unix) {
+                Ok(time) => timed.push((time, reading)),
+                Err(reason) => self.quarantine_reading(reading, reason).await?,
+            }
+        }
+
+        // Readings already stored complete the SpO2 windows of the first new ones
+        let seed = match timed.iter().map(|(time, _)| *time).min() {
+            Some(first) => {
+                self.sensor_readings_before(first, SpO2Calculator::WINDOW_SIZE as u64 - 1)
+                    .await?
+            }
+            None => Vec::new(),
+        };
+        let spo2 = spo2_scores(seed, &timed);
+        let payloads = timed
             .into_iter()
-            .map(|r| {
-                let time = timestamp_to_local(r.unix);
+            .map(|(time, r)| {
                 let sensor_json = r
                     .sensor_data
                     .as_ref()
-                    .map(|s| serde_json::to_value(s))
+                    .map(SensorData::to_value)
                     .transpose()?;
                 Ok(openwhoop_entities::heart_rate::ActiveModel {
                     id: NotSet,
@@ -108,31 +192,162 @@ impl DatabaseHandler {
                     rr_intervals: Set(rr_to_string(r.rr)),
                     activity: Set(Some(i64::from(r.activity))),
                     stress: NotSet,
-                    spo2: NotSet,
-                    skin_temp: NotSet,
-                    imu_data: Set(Some(serde_json::to_value(r.imu_data)?)),
+                    stress_smoothed: NotSet,
+                    spo2: Set(spo2.get(&time).copied()),
+                    skin_temp: Set(skin_temp(time, r.sensor_data.as_ref())),
+                    imu_data: Set(Some(ImuSample::encode(&r.imu_data))),
                     sensor_data: Set(sensor_json),
                     synced: NotSet,
+                    low_confidence: Set(false),
                 })
             })
-            .collect::<anyhow::Result<Vec<_>>>()?;
-
-        openwhoop_entities::heart_rate::Entity::insert_many(payloads)
-            .on_conflict(
-                OnConflict::column(openwhoop_entities::heart_rate::Column::Time)
-                    .update_column(openwhoop_entities::heart_rate::Column::Bpm)
-                    .update_column(openwhoop_entities::heart_rate::Column::RrIntervals)
-                    .update_column(openwhoop_entities::heart_rate::Column::Activity)
-                    .update_column(openwhoop_entities::heart_rate::Column::SensorData)
-                    .to_owned(),
-            )
-            .exec(&self.db)
+            .collect::<Result<Vec<_>, DbError>>()?;
+
+        // Stay under SQLite's bound variable limit
+        for chunk in payloads.chunks(HEART_RATE_BATCH as usize) {
+            retry_busy(|| {
+                openwhoop_entities::heart_rate::Entity::insert_many(chunk.iter().cloned())
+                    .on_conflict(
+                        OnConflict::column(openwhoop_entities::heart_rate::Column::Time)
+                            .update_column(openwhoop_entities::heart_rate::Column::Bpm)
+                            .update_column(openwhoop_entities::heart_rate::Column::RrIntervals)
+                            .update_column(openwhoop_entities::heart_rate::Column::Activity)
+                            // A reading stored again without a full window keeps its SpO2
+                            .value(
+                                openwhoop_entities::heart_rate::Column::Spo2,
+                                Expr::cust("COALESCE(excluded.spo2, heart_rate.spo2)"),
+                            )
+                            .update_column(openwhoop_entities::heart_rate::Column::SkinTemp)
+                            .update_column(openwhoop_entities::heart_rate::Column::ImuData)
+                            .update_column(openwhoop_entities::heart_rate::Column::SensorData)
+                            .update_column(openwhoop_entities::heart_rate::Column::LowConfidence)
+                            .to_owned(),
+                    )
+                    .exec(&self.db)
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Flags the stored readings taken at `unix` (in ms) as low confidence, e.g. because
+    /// they were parsed from a packet that failed its CRC. Storing a reading again clears it.
+    pub async fn mark_low_confidence(&self, unix: Vec<u64>) -> Result<(), DbError> {
+        let times = unix
+            .into_iter()
+            .filter_map(|unix| reading_time(unix).ok())
+            .collect::<Vec<_>>();
+
+        for chunk in times.chunks(HEART_RATE_BATCH as usize) {
+            retry_busy(|| {
+                openwhoop_entities::heart_rate::Entity::update_many()
+                    .col_expr(
+                        openwhoop_entities::heart_rate::Column::LowConfidence,
+                        Expr::value(true),
+                    )
+                    .filter(openwhoop_entities::heart_rate::Column::Time.is_in(chunk.to_vec()))
+                    .exec(&self.db)
+            })
             .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn quarantine_reading(
+        &self,
+        reading: HistoryReading,
+        reason: DbError,
+    ) -> Result<(), DbError> {
+        let sensor_json = reading
+            .sensor_data
+            .as_ref()
+            .map(SensorData::to_value)
+            .transpose()?;
+
+        let model = quarantined_readings::ActiveModel {
+            id: NotSet,
+            unix: Set(i64::try_from(reading.unix).unwrap_or(i64::MAX)),
+            bpm: Set(i16::from(reading.bpm)),
+            rr_intervals: Set(rr_to_string(reading.rr)),
+            activity: Set(i64::from(reading.activity)),
+            reason: Set(reason.to_string()),
+            received_at: Set(Local::now().naive_local()),
+            imu_data: Set(Some(ImuSample::encode(&reading.imu_data))),
+            sensor_data: Set(sensor_json),
+        };
+
+        // Downloading the same history again keeps the first copy and when it arrived
+        retry_busy(|| {
+            quarantined_readings::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::column(quarantined_readings::Column::Unix)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Readings kept out of `heart_rate` because their timestamp can't be trusted,
+    /// typically because the strap lost its clock.
+    pub async fn get_quarantined_readings(
+        &self,
+    ) -> Result<Vec<quarantined_readings::Model>, DbError> {
+        Ok(quarantined_readings::Entity::find()
+            .order_by_asc(quarantined_readings::Column::Id)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Stores the raw bytes of a packet the codec couldn't recognize, with the parse error.
+    ///
+    /// A packet already captured is skipped, so it keeps the time it was first received.
+    /// `received_at` is `None` for packets captured while re-running stored packets.
+    pub async fn create_unknown_packet(
+        &self,
+        char: Uuid,
+        data: Vec<u8>,
+        error: String,
+        received_at: Option<NaiveDateTime>,
+    ) -> Result<(), DbError> {
+        let model = unknown_packets::ActiveModel {
+            id: NotSet,
+            uuid: Set(char),
+            hash: Set(packet_hash(char, &data)),
+            bytes: Set(data),
+            error: Set(error),
+            received_at: Set(received_at),
+        };
+
+        retry_busy(|| {
+            unknown_packets::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::column(unknown_packets::Column::Hash)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+        })
+        .await?;
 
         Ok(())
     }
 
-    pub async fn get_packets(&self, id: i32) -> anyhow::Result<Vec<packets::Model>> {
+    pub async fn get_unknown_packets(&self) -> Result<Vec<unknown_packets::Model>, DbError> {
+        Ok(unknown_packets::Entity::find()
+            .order_by_asc(unknown_packets::Column::Id)
+            .all(&self.db)
+            .await?)
+    }
+
+    pub async fn get_packets(&self, id: i32) -> Result<Vec<packets::Model>, DbError> {
         let stream = packets::Entity::find()
             .filter(packets::Column::Id.gt(id))
             .order_by_asc(packets::Column::Id)
@@ -143,9 +358,18 @@ impl DatabaseHandler {
         Ok(stream)
     }
 
+    pub async fn last_packet_id(&self) -> Result<Option<i32>, DbError> {
+        let packet = packets::Entity::find()
+            .order_by_desc(packets::Column::Id)
+            .one(&self.db)
+            .await?;
+
+        Ok(packet.map(|p| p.id))
+    }
+
     pub async fn get_latest_sleep(
         &self,
-    ) -> anyhow::Result<Option<openwhoop_entities::sleep_cycles::Model>> {
+    ) -> Result<Option<openwhoop_entities::sleep_cycles::Model>, DbError> {
         let sleep = sleep_cycles::Entity::find()
             .order_by_desc(sleep_cycles::Column::End)
             .one(&self.db)
@@ -154,7 +378,178 @@ impl DatabaseHandler {
         Ok(sleep)
     }
 
-    pub async fn create_sleep(&self, sleep: SleepCycle) -> anyhow::Result<()> {
+    /// Last history pointer acknowledged to `device` with `HistoryEnd`.
+    pub async fn get_sync_pointer(&self, device: &str) -> Result<Option<u32>, DbError> {
+        let state = sync_state::Entity::find_by_id(device).one(&self.db).await?;
+
+        Ok(state.map(|s| s.history_pointer as u32))
+    }
+
+    pub async fn set_sync_pointer(&self, device: &str, pointer: u32) -> Result<(), DbError> {
+        let model = sync_state::ActiveModel {
+            device: Set(device.to_owned()),
+            history_pointer: Set(i64::from(pointer)),
+            updated_at: Set(Local::now().naive_local()),
+        };
+
+        retry_busy(|| {
+            sync_state::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::column(sync_state::Column::Device)
+                        .update_columns([
+                            sync_state::Column::HistoryPointer,
+                            sync_state::Column::UpdatedAt,
+                        ])
+                        .to_owned(),
+                )
+                .exec(&self.db)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the acknowledged pointer, so the next download doesn't resume from it.
+    pub async fn clear_sync_pointer(&self, device: &str) -> Result<(), DbError> {
+        retry_busy(|| sync_state::Entity::delete_by_id(device).exec(&self.db)).await?;
+
+        Ok(())
+    }
+
+    /// Stores a console log line from the strap, skipping lines already stored by an earlier run.
+    ///
+    /// Logs end with a NUL terminator, which is stripped since Postgres rejects it in text.
+    pub async fn create_console_log(&self, unix: u32, log: String) -> Result<(), DbError> {
+        let time = timestamp_to_local(u64::from(unix) * 1000)?;
+        let model = console_logs::ActiveModel {
+            id: NotSet,
+            time: Set(time),
+            log: Set(log.replace('\0', "")),
+        };
+
+        retry_busy(|| {
+            console_logs::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::columns([console_logs::Column::Time, console_logs::Column::Log])
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Console logs in chronological order, optionally only those containing `pattern`.
+    pub async fn get_console_logs(
+        &self,
+        pattern: Option<&str>,
+    ) -> Result<Vec<console_logs::Model>, DbError> {
+        let mut query = console_logs::Entity::find().order_by_asc(console_logs::Column::Time);
+        if let Some(pattern) = pattern {
+            query = query.filter(console_logs::Column::Log.contains(pattern));
+        }
+
+        Ok(query.all(&self.db).await?)
+    }
+
+    /// Stores samples from the strap's dedicated IMU stream, encoded like the IMU data of
+    /// readings. A packet already stored, e.g. when re-running, is skipped.
+    pub async fn create_imu_stream(&self, unix: u32, samples: &[ImuSample]) -> Result<(), DbError> {
+        let time = timestamp_to_local(u64::from(unix) * 1000)?;
+        let model = imu_stream::ActiveModel {
+            id: NotSet,
+            time: Set(time),
+            samples: Set(ImuSample::encode(samples)),
+        };
+
+        retry_busy(|| {
+            imu_stream::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::columns([imu_stream::Column::Time, imu_stream::Column::Samples])
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// IMU stream samples between `from` and `to`, in the order they were received.
+    pub async fn get_imu_stream(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<(NaiveDateTime, Vec<ImuSample>)>, DbError> {
+        let rows = imu_stream::Entity::find()
+            .filter(imu_stream::Column::Time.between(from, to))
+            .order_by_asc(imu_stream::Column::Time)
+            .order_by_asc(imu_stream::Column::Id)
+            .all(&self.db)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| Some((row.time, ImuSample::decode(&row.samples).ok()?)))
+            .collect())
+    }
+
+    /// Stores a double tap on the band as a marker, taps already stored are ignored.
+    pub async fn create_marker(&self, unix: u32) -> Result<(), DbError> {
+        let model = markers::ActiveModel {
+            id: NotSet,
+            time: Set(timestamp_to_local(u64::from(unix) * 1000)?),
+        };
+
+        retry_busy(|| {
+            markers::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::column(markers::Column::Time)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Times of the stored markers in chronological order, optionally only from `since`.
+    pub async fn get_markers(
+        &self,
+        since: Option<NaiveDateTime>,
+    ) -> Result<Vec<NaiveDateTime>, DbError> {
+        let mut query = markers::Entity::find().order_by_asc(markers::Column::Time);
+        if let Some(since) = since {
+            query = query.filter(markers::Column::Time.gte(since));
+        }
+
+        Ok(query
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|marker| marker.time)
+            .collect())
+    }
+
+    pub async fn create_sleep(&self, sleep: SleepCycle) -> Result<(), DbError> {
+        self.upsert_sleep(sleep, false).await
+    }
+
+    /// Stores `sleep`, replacing the night stored for the same day. `imported` marks nights
+    /// from the WHOOP data export, whose heart rate and HRV range weren't measured.
+    pub(crate) async fn upsert_sleep(
+        &self,
+        sleep: SleepCycle,
+        imported: bool,
+    ) -> Result<(), DbError> {
         let model = sleep_cycles::ActiveModel {
             id: Set(Uuid::new_v4()),
             sleep_id: Set(sleep.id),
@@ -168,37 +563,142 @@ impl DatabaseHandler {
             avg_hrv: Set(sleep.avg_hrv.into()),
             score: Set(sleep.score.into()),
             synced: NotSet,
+            efficiency: Set(sleep.efficiency),
+            sdnn: Set(sleep.sdnn),
+            pnn50: Set(sleep.pnn50),
+            deep_hrv: Set(sleep.deep_hrv.map(i32::from)),
+            imported: Set(imported),
         };
 
-        let _r = sleep_cycles::Entity::insert(model)
-            .on_conflict(
-                OnConflict::column(sleep_cycles::Column::SleepId)
-                    .update_columns([
-                        sleep_cycles::Column::Start,
-                        sleep_cycles::Column::End,
-                        sleep_cycles::Column::MinBpm,
-                        sleep_cycles::Column::MaxBpm,
-                        sleep_cycles::Column::AvgBpm,
-                        sleep_cycles::Column::MinHrv,
-                        sleep_cycles::Column::MaxHrv,
-                        sleep_cycles::Column::AvgHrv,
-                    ])
-                    .to_owned(),
-            )
-            .exec(&self.db)
-            .await?;
+        retry_busy(|| {
+            sleep_cycles::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::column(sleep_cycles::Column::SleepId)
+                        .update_columns([
+                            sleep_cycles::Column::Start,
+                            sleep_cycles::Column::End,
+                            sleep_cycles::Column::MinBpm,
+                            sleep_cycles::Column::MaxBpm,
+                            sleep_cycles::Column::AvgBpm,
+                            sleep_cycles::Column::MinHrv,
+                            sleep_cycles::Column::MaxHrv,
+                            sleep_cycles::Column::AvgHrv,
+                            sleep_cycles::Column::Score,
+                            sleep_cycles::Column::Efficiency,
+                            sleep_cycles::Column::Sdnn,
+                            sleep_cycles::Column::Pnn50,
+                            sleep_cycles::Column::DeepHrv,
+                            sleep_cycles::Column::Imported,
+                        ])
+                        .to_owned(),
+                )
+                .exec_without_returning(&self.db)
+        })
+        .await?;
 
         Ok(())
     }
 }
 
-fn timestamp_to_local(unix: u64) -> NaiveDateTime {
-    let dt = Local
-        .timestamp_millis_opt(unix as i64)
-        .single()
-        .expect("I don't know");
+pub(crate) fn timestamp_to_local(unix: u64) -> Result<NaiveDateTime, DbError> {
+    let dt = i64::try_from(unix)
+        .ok()
+        .and_then(|millis| Local.timestamp_millis_opt(millis).single())
+        .ok_or(DbError::InvalidTimestamp {
+            unix,
+            reason: "out of range",
+        })?;
+
+    Ok(dt.naive_local())
+}
+
+/// Local time of a reading, or an error if its timestamp can't be trusted
+fn reading_time(unix: u64) -> Result<NaiveDateTime, DbError> {
+    if unix < MIN_READING_UNIX_MS {
+        return Err(DbError::InvalidTimestamp {
+            unix,
+            reason: "before 2015",
+        });
+    }
+
+    let latest = (Utc::now() + MAX_CLOCK_AHEAD).timestamp_millis() as u64;
+    if unix > latest {
+        return Err(DbError::InvalidTimestamp {
+            unix,
+            reason: "in the future",
+        });
+    }
+
+    timestamp_to_local(unix)
+}
+
+fn packet_hash(char: Uuid, data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(char.as_bytes());
+    hasher.update(data);
+    hex::encode(hasher.finalize())
+}
+
+/// Runs `write` again, backing off, while SQLite reports the database busy or locked.
+///
+/// The busy timeout doesn't cover every case, SQLite gives up at once when waiting could
+/// deadlock. Each call must be a single statement that can be repeated as is.
+pub(crate) async fn retry_busy<T, F, Fut>(mut write: F) -> Result<T, DbError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, DbErr>>,
+{
+    let mut attempt = 0;
+    loop {
+        match write().await {
+            Err(error) if is_busy(&error) && attempt < BUSY_RETRIES => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// `SQLITE_BUSY` or `SQLITE_LOCKED`, including their extended result codes
+fn is_busy(error: &DbErr) -> bool {
+    let (DbErr::Exec(RuntimeErr::SqlxError(sea_orm::sqlx::Error::Database(error)))
+    | DbErr::Query(RuntimeErr::SqlxError(sea_orm::sqlx::Error::Database(error)))) = error
+    else {
+        return false;
+    };
 
-    dt.naive_local()
+    error
+        .try_downcast_ref::<SqliteError>()
+        .and_then(|error| error.code())
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+fn skin_temp(time: NaiveDateTime, sensor_data: Option<&SensorData>) -> Option<f64> {
+    SkinTempCalculator::convert(time, sensor_data?.skin_temp_raw).map(|s| s.temp_celsius)
+}
+
+/// SpO2 for every reading that ends a full window of `seed` followed by `readings`
+fn spo2_scores(
+    seed: Vec<SpO2Reading>,
+    readings: &[(NaiveDateTime, HistoryReading)],
+) -> HashMap<NaiveDateTime, f64> {
+    let mut sensor_readings = seed;
+    sensor_readings.extend(readings.iter().filter_map(|(time, r)| {
+        let sensor_data = r.sensor_data.as_ref()?;
+        Some(SpO2Reading {
+            time: *time,
+            spo2_red: sensor_data.spo2_red,
+            spo2_ir: sensor_data.spo2_ir,
+        })
+    }));
+
+    sensor_readings
+        .windows(SpO2Calculator::WINDOW_SIZE)
+        .filter_map(SpO2Calculator::calculate)
+        .map(|score| (score.time, score.spo2_percentage))
+        .collect()
 }
 
 fn rr_to_string(rr: Vec<u16>) -> String {
@@ -207,8 +707,41 @@ fn rr_to_string(rr: Vec<u16>) -> String {
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        path::PathBuf,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use chrono::Timelike;
+    use openwhoop_types::activities::{ActivityPeriod, ActivityType};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, DbBackend, PaginatorTrait, Statement, TransactionTrait,
+    };
+
     use super::*;
 
+    /// A fresh SQLite file, so separate handlers get their own connections to it
+    fn temp_sqlite(name: &str) -> (PathBuf, String) {
+        let path =
+            std::env::temp_dir().join(format!("openwhoop-{}-{}.sqlite", name, Uuid::new_v4()));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        (path, url)
+    }
+
+    async fn query_plan(db: &DatabaseHandler, sql: &str) -> String {
+        db.db
+            .query_all(Statement::from_string(
+                DbBackend::Sqlite,
+                format!("EXPLAIN QUERY PLAN {}", sql),
+            ))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.try_get::<String>("", "detail").unwrap())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     #[tokio::test]
     async fn create_and_get_packets() {
         let db = DatabaseHandler::new("sqlite::memory:").await;
@@ -224,6 +757,253 @@ mod tests {
         assert_eq!(packets[0].uuid, uuid);
     }
 
+    #[tokio::test]
+    async fn create_packet_deduplicates() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let uuid = Uuid::new_v4();
+        let data = vec![0xAA, 0xBB, 0xCC];
+
+        let first = db.create_packet(uuid, data.clone()).await.unwrap();
+        let second = db.create_packet(uuid, data.clone()).await.unwrap();
+        assert_eq!(first.id, second.id);
+
+        let packets = db.get_packets(0).await.unwrap();
+        assert_eq!(packets.len(), 1);
+
+        // Same bytes on another characteristic are a different packet
+        db.create_packet(Uuid::new_v4(), data).await.unwrap();
+        assert_eq!(db.get_packets(0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn packets_stored_before_hashing_are_backfilled() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let uuid = Uuid::new_v4();
+        let unhashed = |bytes: Vec<u8>| packets::ActiveModel {
+            id: NotSet,
+            uuid: Set(uuid),
+            bytes: Set(bytes),
+            hash: Set(None),
+        };
+        for bytes in [vec![0x01], vec![0x02], vec![0x01]] {
+            packets::Entity::insert(unhashed(bytes))
+                .exec(&db.db)
+                .await
+                .unwrap();
+        }
+
+        db.db
+            .execute_unprepared(
+                "DELETE FROM seaql_migrations WHERE version = 'm20250626_000000_packet_hash_backfill'",
+            )
+            .await
+            .unwrap();
+        Migrator::up(&db.db, None).await.unwrap();
+
+        let hashes = db
+            .get_packets(0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|packet| packet.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hashes,
+            [
+                Some(packet_hash(uuid, &[0x01])),
+                Some(packet_hash(uuid, &[0x02])),
+                None,
+            ]
+        );
+
+        // Downloading an old frame again doesn't store it again
+        let again = db.create_packet(uuid, vec![0x02]).await.unwrap();
+        assert_eq!(again.id, 2);
+        assert_eq!(db.get_packets(0).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn json_imu_data_is_converted_to_blobs() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        let before_blobs = Migrator::migrations()
+            .iter()
+            .position(|m| m.name() == "m20250605_000000_imu_data_blob")
+            .unwrap();
+        Migrator::up(&db, Some(before_blobs as u32)).await.unwrap();
+
+        db.execute_unprepared(
+            r#"INSERT INTO heart_rate (bpm, time, rr_intervals, activity, imu_data) VALUES
+                (60, '2025-01-01 00:00:00', '', 0, '[{"acc_x_g": 0.0, "acc_y_g": -1.0, "acc_z_g": 2.0, "gyr_x_dps": 0.2, "gyr_y_dps": -500.0, "gyr_z_dps": 3000.0}]'),
+                (60, '2025-01-01 00:00:01', '', 0, NULL)"#,
+        )
+        .await
+        .unwrap();
+        Migrator::up(&db, None).await.unwrap();
+
+        let db = DatabaseHandler { db };
+        let history = db
+            .search_history(crate::SearchHistory::default())
+            .await
+            .unwrap();
+        let sample = ImuSample {
+            acc_x_g: 0.0,
+            acc_y_g: -1.0,
+            acc_z_g: 2.0,
+            gyr_x_dps: 0.2,
+            gyr_y_dps: -500.0,
+            // Out of the raw range, clamped to it
+            gyr_z_dps: f32::from(i16::MAX) / ImuSample::GYR_SENS,
+        };
+        assert_eq!(history[0].imu_data, Some(vec![sample]));
+        assert_eq!(history[1].imu_data, None);
+    }
+
+    #[tokio::test]
+    async fn duplicate_unknown_packets_are_dropped_by_migration() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        let before_hash = Migrator::migrations()
+            .iter()
+            .position(|m| m.name() == "m20250628_000000_unknown_packets_hash")
+            .unwrap();
+        Migrator::up(&db, Some(before_hash as u32)).await.unwrap();
+
+        let uuid = Uuid::new_v4();
+        for (bytes, second) in [("aa01", 0), ("aa02", 1), ("aa01", 2)] {
+            db.execute_unprepared(&format!(
+                "INSERT INTO unknown_packets (uuid, bytes, error, received_at) VALUES \
+                 (x'{}', x'{}', 'InvalidCommandType(200)', '2025-01-01 00:00:0{}')",
+                uuid.simple(),
+                bytes,
+                second
+            ))
+            .await
+            .unwrap();
+        }
+        Migrator::up(&db, None).await.unwrap();
+
+        let db = DatabaseHandler { db };
+        let captured = db
+            .get_unknown_packets()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|packet| (packet.bytes, packet.hash, packet.received_at))
+            .collect::<Vec<_>>();
+        let time = |second| {
+            chrono::NaiveDate::from_ymd_opt(2025, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, second)
+        };
+        assert_eq!(
+            captured,
+            [
+                (vec![0xaa, 0x01], packet_hash(uuid, &[0xaa, 0x01]), time(0)),
+                (vec![0xaa, 0x02], packet_hash(uuid, &[0xaa, 0x02]), time(1)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn is_busy_matches_lock_errors_only() {
+        let (path, url) = temp_sqlite("busy");
+        let holder = DatabaseHandler::new(url.as_str()).await;
+        let waiter = DatabaseHandler::with_busy_timeout(url.as_str(), Duration::ZERO).await;
+
+        let lock = holder.db.begin().await.unwrap();
+        lock.execute_unprepared("DELETE FROM markers")
+            .await
+            .unwrap();
+        let error = waiter
+            .db
+            .execute_unprepared("DELETE FROM markers")
+            .await
+            .unwrap_err();
+        assert!(is_busy(&error), "{:?}", error);
+        lock.rollback().await.unwrap();
+
+        let error = waiter.db.execute_unprepared("SELEKT 1").await.unwrap_err();
+        assert!(!is_busy(&error));
+        assert!(!is_busy(&DbErr::RecordNotFound("marker".to_owned())));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retry_busy_waits_out_a_held_lock() {
+        let (path, url) = temp_sqlite("retry");
+        let holder = DatabaseHandler::new(url.as_str()).await;
+        let waiter = DatabaseHandler::with_busy_timeout(url.as_str(), Duration::ZERO).await;
+
+        let lock = holder.db.begin().await.unwrap();
+        lock.execute_unprepared("DELETE FROM markers")
+            .await
+            .unwrap();
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            lock.commit().await.unwrap();
+        });
+
+        let attempts = AtomicU32::new(0);
+        retry_busy(|| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            waiter.db.execute_unprepared("DELETE FROM markers")
+        })
+        .await
+        .unwrap();
+        assert!(attempts.load(Ordering::SeqCst) > 1);
+        release.await.unwrap();
+
+        // Anything but a lock error is returned at once
+        let attempts = AtomicU32::new(0);
+        let result = retry_busy(|| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            waiter.db.execute_unprepared("SELEKT 1")
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_band_syncs_share_a_database() {
+        // Separate handlers like separate processes, an in-memory database has one connection
+        let (path, url) = temp_sqlite("concurrent");
+        let db = DatabaseHandler::new(url.as_str()).await;
+        let other = DatabaseHandler::new(url.as_str()).await;
+
+        // Two bands with interleaved readings, both receiving the same event frame
+        let sync = |db: DatabaseHandler, band: u64| async move {
+            for i in 0..100 {
+                db.create_packet(Uuid::nil(), vec![0xAA, 0x01]).await?;
+                db.create_packet(Uuid::nil(), vec![0xAA, 0x02, band as u8, i as u8])
+                    .await?;
+                db.create_reading(HistoryReading {
+                    unix: 1735689600000 + (i * 2 + band) * 1000,
+                    bpm: 60,
+                    rr: vec![1000],
+                    activity: 0,
+                    imu_data: vec![],
+                    sensor_data: None,
+                })
+                .await?;
+            }
+            Ok::<_, DbError>(())
+        };
+
+        let first = tokio::spawn(sync(db.clone(), 0));
+        let second = tokio::spawn(sync(other, 1));
+        first.await.unwrap().unwrap();
+        second.await.unwrap().unwrap();
+
+        assert_eq!(packets::Entity::find().count(&db.db).await.unwrap(), 201);
+        let history = db
+            .search_history(crate::SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 200);
+        std::fs::remove_file(&path).unwrap();
+    }
+
     #[tokio::test]
     async fn create_reading_and_search_history() {
         let db = DatabaseHandler::new("sqlite::memory:").await;
@@ -285,18 +1065,7 @@ mod tests {
             .and_hms_opt(6, 0, 0)
             .unwrap();
 
-        let sleep = SleepCycle {
-            id: end.date(),
-            start,
-            end,
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
-        };
+        let sleep = SleepCycle::fixture(start, end);
 
         db.create_sleep(sleep).await.unwrap();
 
@@ -307,6 +1076,27 @@ mod tests {
         assert_eq!(latest.avg_bpm, 60);
     }
 
+    #[tokio::test]
+    async fn redetected_sleep_takes_the_new_score() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let end = chrono::NaiveDate::from_ymd_opt(2025, 1, 2)
+            .unwrap()
+            .and_hms_opt(6, 0, 0)
+            .unwrap();
+        let sleep = SleepCycle::fixture(end - chrono::TimeDelta::hours(8), end);
+        db.create_sleep(sleep).await.unwrap();
+        db.create_sleep(SleepCycle {
+            score: 75.0,
+            ..sleep
+        })
+        .await
+        .unwrap();
+
+        let latest = db.get_latest_sleep().await.unwrap().unwrap();
+        assert_eq!(latest.score, Some(75.0));
+    }
+
     #[tokio::test]
     async fn upsert_reading_on_conflict() {
         let db = DatabaseHandler::new("sqlite::memory:").await;
@@ -339,4 +1129,498 @@ mod tests {
         assert_eq!(history.len(), 1);
         assert_eq!(history[0].bpm, 80);
     }
+
+    #[tokio::test]
+    async fn range_queries_use_indexes() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        // Both are searched through the indexes of their unique constraints
+        let plan = query_plan(
+            &db,
+            "SELECT * FROM heart_rate WHERE time > '2025-01-01' AND time < '2025-02-01' ORDER BY time",
+        )
+        .await;
+        assert!(plan.contains("USING INDEX"), "{}", plan);
+
+        let plan = query_plan(
+            &db,
+            "SELECT * FROM activities WHERE start > '2025-01-01' ORDER BY start",
+        )
+        .await;
+        assert!(plan.contains("USING INDEX"), "{}", plan);
+
+        let plan = query_plan(
+            &db,
+            "SELECT * FROM sleep_cycles WHERE \"end\" > '2025-01-01' ORDER BY \"end\" DESC",
+        )
+        .await;
+        assert!(plan.contains("sleep_cycles-end-index"), "{}", plan);
+
+        for table in ["heart_rate", "activities"] {
+            let non_unique = db
+                .db
+                .query_all(Statement::from_string(
+                    DbBackend::Sqlite,
+                    format!(
+                        "SELECT name FROM pragma_index_list('{}') WHERE \"unique\" = 0",
+                        table
+                    ),
+                ))
+                .await
+                .unwrap();
+            assert!(non_unique.is_empty(), "{} has a redundant index", table);
+        }
+    }
+
+    #[tokio::test]
+    async fn create_readings_chunks_large_batches() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let readings = (0..500)
+            .map(|i| HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 70,
+                rr: vec![850],
+                activity: 500_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+
+        db.create_readings(readings).await.unwrap();
+
+        let count = openwhoop_entities::heart_rate::Entity::find()
+            .count(&db.db)
+            .await
+            .unwrap();
+        assert_eq!(count, 500);
+    }
+
+    fn sensor_data(spo2_red: u16, spo2_ir: u16, skin_temp_raw: u16) -> SensorData {
+        SensorData {
+            ppg_green: 0,
+            ppg_red_ir: 0,
+            spo2_red,
+            spo2_ir,
+            skin_temp_raw,
+            ambient_light: 0,
+            led_drive_1: 0,
+            led_drive_2: 0,
+            resp_rate_raw: 0,
+            signal_quality: 0,
+            skin_contact: 1,
+            accel_gravity: [0.0, 0.0, 1.0],
+        }
+    }
+
+    #[tokio::test]
+    async fn create_readings_stores_spo2_and_skin_temp() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let reading = |i: u64| {
+            let swing = if i % 2 == 0 { 10 } else { 0 };
+            HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: Some(sensor_data(1000 + swing, 1000 + swing, 850)),
+            }
+        };
+
+        // Too few readings for a SpO2 window, they only complete those of the next sync
+        let earlier = SpO2Calculator::WINDOW_SIZE as u64 - 1;
+        db.create_readings((0..earlier).map(reading).collect())
+            .await
+            .unwrap();
+        db.create_readings((earlier..earlier + 40).map(reading).collect())
+            .await
+            .unwrap();
+
+        let rows = openwhoop_entities::heart_rate::Entity::find()
+            .order_by_asc(openwhoop_entities::heart_rate::Column::Time)
+            .all(&db.db)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), earlier as usize + 40);
+
+        for (i, row) in rows.iter().enumerate() {
+            let skin_temp = row.skin_temp.expect("skin temp should be stored");
+            assert!((skin_temp - 34.0).abs() < 0.01);
+
+            if i < earlier as usize {
+                assert_eq!(row.spo2, None);
+            } else {
+                let spo2 = row.spo2.expect("spo2 should be stored");
+                assert!((70.0..=100.0).contains(&spo2));
+            }
+        }
+
+        // Storing a reading again without sensor data keeps its SpO2
+        let last = rows.last().unwrap();
+        db.create_readings(vec![HistoryReading {
+            sensor_data: None,
+            ..reading(earlier + 39)
+        }])
+        .await
+        .unwrap();
+        let stored = openwhoop_entities::heart_rate::Entity::find_by_id(last.id)
+            .one(&db.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.spo2, last.spo2);
+    }
+
+    #[tokio::test]
+    async fn create_reading_stores_spo2_like_create_readings() {
+        let reading = |i: u64| {
+            let swing = if i % 2 == 0 { 10 } else { 0 };
+            HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: Some(sensor_data(1000 + swing, 1000 + swing, 850)),
+            }
+        };
+        let count = SpO2Calculator::WINDOW_SIZE as u64 + 5;
+
+        let single = DatabaseHandler::new("sqlite::memory:").await;
+        for i in 0..count {
+            single.create_reading(reading(i)).await.unwrap();
+        }
+        let batch = DatabaseHandler::new("sqlite::memory:").await;
+        batch
+            .create_readings((0..count).map(reading).collect())
+            .await
+            .unwrap();
+
+        let spo2 = async |db: &DatabaseHandler| {
+            openwhoop_entities::heart_rate::Entity::find()
+                .order_by_asc(openwhoop_entities::heart_rate::Column::Time)
+                .all(&db.db)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|row| row.spo2)
+                .collect::<Vec<_>>()
+        };
+        let stored = spo2(&single).await;
+        let last = stored.last().unwrap().expect("spo2 should be stored");
+        assert!((70.0..=100.0).contains(&last));
+        assert_eq!(stored, spo2(&batch).await);
+    }
+
+    #[tokio::test]
+    async fn create_reading_stores_skin_temp() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        db.create_reading(HistoryReading {
+            unix: 1735689600000,
+            bpm: 60,
+            rr: vec![1000],
+            activity: 0,
+            imu_data: vec![],
+            sensor_data: Some(sensor_data(1000, 1000, 850)),
+        })
+        .await
+        .unwrap();
+
+        let row = openwhoop_entities::heart_rate::Entity::find()
+            .one(&db.db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!((row.skin_temp.unwrap() - 34.0).abs() < 0.01);
+        assert_eq!(row.spo2, None);
+    }
+
+    #[tokio::test]
+    async fn grafana_views_return_rows() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let unix = 1735689600000;
+        for minute in 0..3 {
+            db.create_reading(HistoryReading {
+                unix: unix + minute * 60_000,
+                bpm: 55,
+                rr: vec![1000],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: Some(sensor_data(1000, 1000, 850)),
+            })
+            .await
+            .unwrap();
+        }
+
+        let start = timestamp_to_local(unix).unwrap();
+        let end = start + chrono::TimeDelta::minutes(30);
+        db.create_sleep(SleepCycle {
+            efficiency: Some(90.0),
+            ..SleepCycle::fixture(start, end)
+        })
+        .await
+        .unwrap();
+        // Overlapping periods still give one row per reading
+        for (offset, activity) in [(0, ActivityType::Walking), (1, ActivityType::Nap)] {
+            db.create_activity(ActivityPeriod {
+                period_id: end.date(),
+                from: start + chrono::TimeDelta::minutes(offset),
+                to: end,
+                activity,
+                confidence: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let query = |sql: &str| {
+            db.db
+                .query_all(Statement::from_string(DbBackend::Sqlite, sql.to_string()))
+        };
+
+        let readings =
+            query("SELECT day, hour, sleep_id, activity FROM v_daily_readings ORDER BY time")
+                .await
+                .unwrap();
+        assert_eq!(readings.len(), 3);
+        let activities = readings
+            .iter()
+            .map(|r| r.try_get::<String>("", "activity").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(activities, ["Walking", "Nap", "Nap"]);
+        let day = readings[0].try_get::<String>("", "day").unwrap();
+        assert_eq!(day, start.date().to_string());
+        let hour = readings[0].try_get::<i32>("", "hour").unwrap();
+        assert_eq!(hour, start.hour() as i32);
+        assert!(readings.iter().all(|r| {
+            r.try_get::<Option<String>>("", "sleep_id")
+                .unwrap()
+                .is_some()
+        }));
+
+        let sleeps = query("SELECT duration_minutes, avg_skin_temp FROM v_sleep_detail")
+            .await
+            .unwrap();
+        assert_eq!(sleeps.len(), 1);
+        let duration = sleeps[0].try_get::<i64>("", "duration_minutes").unwrap();
+        assert_eq!(duration, 30);
+        let skin_temp = sleeps[0].try_get::<f64>("", "avg_skin_temp").unwrap();
+        assert!((skin_temp - 34.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn console_logs_roundtrip() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        db.create_console_log(1735689660, "battery 87%\0".to_owned())
+            .await
+            .unwrap();
+        db.create_console_log(1735689600, " Trim: 0x00000000".to_owned())
+            .await
+            .unwrap();
+        // Rerunning stored packets sends the same line again
+        db.create_console_log(1735689600, " Trim: 0x00000000".to_owned())
+            .await
+            .unwrap();
+
+        let logs = db.get_console_logs(None).await.unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].log, " Trim: 0x00000000");
+        assert_eq!(logs[0].time, timestamp_to_local(1735689600000).unwrap());
+        assert_eq!(logs[1].log, "battery 87%");
+
+        // The table itself refuses the duplicate, e.g. from two syncs storing it at once
+        let duplicate = console_logs::ActiveModel {
+            id: NotSet,
+            time: Set(logs[0].time),
+            log: Set(logs[0].log.clone()),
+        }
+        .insert(&db.db)
+        .await;
+        assert!(duplicate.is_err());
+
+        let battery = db.get_console_logs(Some("battery")).await.unwrap();
+        assert_eq!(battery.len(), 1);
+        assert_eq!(battery[0].time, timestamp_to_local(1735689660000).unwrap());
+    }
+
+    #[tokio::test]
+    async fn imu_stream_roundtrip() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let sample = |acc_z_g| ImuSample {
+            acc_x_g: 0.0,
+            acc_y_g: 0.0,
+            acc_z_g,
+            gyr_x_dps: 0.0,
+            gyr_y_dps: 0.0,
+            gyr_z_dps: 0.0,
+        };
+        let first = vec![sample(1.0), sample(0.0)];
+        let second = vec![sample(-1.0)];
+
+        db.create_imu_stream(1735689600, &first).await.unwrap();
+        db.create_imu_stream(1735689600, &second).await.unwrap();
+        // Rerunning stored packets sends the same samples again
+        db.create_imu_stream(1735689600, &first).await.unwrap();
+        db.create_imu_stream(1735693200, &second).await.unwrap();
+
+        let time = timestamp_to_local(1735689600000).unwrap();
+        let stream = db
+            .get_imu_stream(time, time + TimeDelta::minutes(1))
+            .await
+            .unwrap();
+        assert_eq!(stream, vec![(time, first), (time, second)]);
+    }
+
+    #[tokio::test]
+    async fn readings_with_bad_timestamps_are_quarantined() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let sample = ImuSample {
+            acc_x_g: 0.0,
+            acc_y_g: 0.0,
+            acc_z_g: 1.0,
+            gyr_x_dps: 0.0,
+            gyr_y_dps: 0.0,
+            gyr_z_dps: 0.0,
+        };
+        let reading = |unix| HistoryReading {
+            unix,
+            bpm: 60,
+            rr: vec![1000],
+            activity: 0,
+            imu_data: vec![sample.clone()],
+            sensor_data: Some(sensor_data(1000, 1000, 850)),
+        };
+
+        // 1970, 2025 and the year 3000
+        let readings = vec![
+            reading(5_000),
+            reading(1735689600000),
+            reading(32503680000000),
+        ];
+        db.create_readings(readings).await.unwrap();
+        // Too large for chrono to represent
+        db.create_reading(reading(u64::MAX)).await.unwrap();
+        // Downloading the history again doesn't quarantine them twice
+        db.create_readings(vec![reading(5_000), reading(32503680000000)])
+            .await
+            .unwrap();
+
+        let stored = db
+            .search_history(crate::SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].time, timestamp_to_local(1735689600000).unwrap());
+
+        let quarantined = db.get_quarantined_readings().await.unwrap();
+        let unix = quarantined.iter().map(|q| q.unix).collect::<Vec<_>>();
+        assert_eq!(unix, vec![5_000, 32503680000000, i64::MAX]);
+        assert!(quarantined[0].reason.contains("before 2015"));
+        assert!(quarantined[1].reason.contains("in the future"));
+
+        let kept = &quarantined[0];
+        let imu = ImuSample::decode(kept.imu_data.as_deref().unwrap()).unwrap();
+        assert_eq!(imu, vec![sample.clone()]);
+        let sensor = SensorData::from_value(kept.sensor_data.clone().unwrap()).unwrap();
+        assert_eq!(Some(sensor), reading(5_000).sensor_data);
+    }
+
+    #[tokio::test]
+    async fn sync_pointer_roundtrip() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        assert_eq!(db.get_sync_pointer("band").await.unwrap(), None);
+
+        db.set_sync_pointer("band", 1234).await.unwrap();
+        assert_eq!(db.get_sync_pointer("band").await.unwrap(), Some(1234));
+
+        db.set_sync_pointer("band", u32::MAX).await.unwrap();
+        assert_eq!(db.get_sync_pointer("band").await.unwrap(), Some(u32::MAX));
+
+        db.clear_sync_pointer("band").await.unwrap();
+        assert_eq!(db.get_sync_pointer("band").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn sync_pointer_is_kept_per_device() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        db.set_sync_pointer("AA:BB:CC:DD:EE:01", 100).await.unwrap();
+        db.set_sync_pointer("AA:BB:CC:DD:EE:02", 200).await.unwrap();
+
+        assert_eq!(
+            db.get_sync_pointer("AA:BB:CC:DD:EE:01").await.unwrap(),
+            Some(100)
+        );
+        assert_eq!(
+            db.get_sync_pointer("AA:BB:CC:DD:EE:02").await.unwrap(),
+            Some(200)
+        );
+
+        db.clear_sync_pointer("AA:BB:CC:DD:EE:01").await.unwrap();
+        assert_eq!(
+            db.get_sync_pointer("AA:BB:CC:DD:EE:01").await.unwrap(),
+            None
+        );
+        assert_eq!(
+            db.get_sync_pointer("AA:BB:CC:DD:EE:02").await.unwrap(),
+            Some(200)
+        );
+    }
+
+    #[tokio::test]
+    async fn read_only_handle_can_query_but_not_write() {
+        let path = std::env::temp_dir().join(format!("openwhoop-ro-{}.sqlite", Uuid::new_v4()));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+
+        let writer = DatabaseHandler::new(url.as_str()).await;
+        writer.set_sync_pointer("band", 1234).await.unwrap();
+
+        let reader = DatabaseHandler::new_read_only(&url).await.unwrap();
+        assert_eq!(reader.get_sync_pointer("band").await.unwrap(), Some(1234));
+        assert!(reader.set_sync_pointer("band", 5678).await.is_err());
+
+        // The writer is unaffected by the open reader
+        writer.set_sync_pointer("band", 5678).await.unwrap();
+        assert_eq!(reader.get_sync_pointer("band").await.unwrap(), Some(5678));
+
+        let postgres = DatabaseHandler::new_read_only("postgres://localhost/whoop").await;
+        assert!(postgres.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_connections_use_wal_and_busy_timeout() {
+        let path = std::env::temp_dir().join(format!("openwhoop-wal-{}.sqlite", Uuid::new_v4()));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+
+        let db =
+            DatabaseHandler::with_busy_timeout(url.as_str(), Duration::from_millis(1234)).await;
+        let pragma =
+            |name: &str| Statement::from_string(DbBackend::Sqlite, format!("PRAGMA {}", name));
+
+        let journal_mode = db
+            .db
+            .query_one(pragma("journal_mode"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            journal_mode.try_get::<String>("", "journal_mode").unwrap(),
+            "wal"
+        );
+
+        let busy_timeout = db
+            .db
+            .query_one(pragma("busy_timeout"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(busy_timeout.try_get::<i32>("", "timeout").unwrap(), 1234);
+
+        drop(db);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+/// Errors returned by [`crate::DatabaseHandler`]
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error(transparent)]
+    Database(#[from] sea_orm::DbErr),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Hex(#[from] hex::FromHexError),
+    #[error(transparent)]
+    Uuid(#[from] uuid::Error),
+    /// The reading's clock can't be trusted, such readings are quarantined
+    #[error("timestamp {unix} ms is {reason}")]
+    InvalidTimestamp { unix: u64, reason: &'static str },
+    /// A row the call refers to doesn't exist
+    #[error("{0}")]
+    NotFound(String),
+    /// Arguments, or a file being read, that can't be used
+    #[error("{0}")]
+    Invalid(String),
+}
@@ -0,0 +1,539 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use openwhoop_entities::{activities, heart_rate, sleep_cycles};
+use sea_orm::{
+    ActiveValue::{NotSet, Set},
+    ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    sea_query::OnConflict,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    DatabaseHandler, DbError,
+    sync::{ACTIVITIES_BATCH, HEART_RATE_BATCH, SLEEP_CYCLES_BATCH},
+};
+
+/// Prefix of the trailing line holding the hex SHA-256 of every line before it
+const CHECKSUM_PREFIX: &str = "sha256 ";
+
+/// Rows fetched per query while exporting
+const EXPORT_PAGE: u64 = 10_000;
+
+/// One exported row, written as a line of JSON.
+///
+/// Database ids and sync flags are left out, rows are matched on their natural keys on import.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "table", rename_all = "snake_case")]
+enum Record {
+    SleepCycle {
+        sleep_id: NaiveDate,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        min_bpm: i16,
+        max_bpm: i16,
+        avg_bpm: i16,
+        min_hrv: i32,
+        max_hrv: i32,
+        avg_hrv: i32,
+        score: Option<f64>,
+        efficiency: Option<f64>,
+        sdnn: Option<f64>,
+        pnn50: Option<f64>,
+        deep_hrv: Option<i32>,
+        #[serde(default)]
+        imported: bool,
+    },
+    Activity {
+        period_id: NaiveDate,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        activity: String,
+        confidence: Option<f64>,
+        #[serde(default)]
+        manual: bool,
+    },
+    HeartRate {
+        time: NaiveDateTime,
+        bpm: i16,
+        rr_intervals: String,
+        activity: Option<i64>,
+        stress: Option<f64>,
+        stress_smoothed: Option<f64>,
+        spo2: Option<f64>,
+        skin_temp: Option<f64>,
+        /// Hex encoded
+        imu_data: Option<String>,
+        sensor_data: Option<serde_json::Value>,
+        #[serde(default)]
+        low_confidence: bool,
+    },
+}
+
+/// Writes lines to the output while hashing them for the checksum trailer
+struct HashingWriter<'w, W: Write> {
+    writer: &'w mut W,
+    hasher: Sha256,
+    lines: usize,
+}
+
+impl<W: Write> HashingWriter<'_, W> {
+    fn record(&mut self, record: &Record) -> Result<(), DbError> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.hasher.update(line.as_bytes());
+        self.writer.write_all(line.as_bytes())?;
+        self.lines += 1;
+        Ok(())
+    }
+}
+
+impl DatabaseHandler {
+    /// Writes sleep cycles, activities and readings as JSON lines, followed by a
+    /// `sha256 <hex>` line that [`Self::import`] verifies before loading anything.
+    pub async fn export<W: Write>(&self, writer: &mut W) -> Result<usize, DbError> {
+        let mut out = HashingWriter {
+            writer,
+            hasher: Sha256::new(),
+            lines: 0,
+        };
+
+        // Sleep cycles first, activities reference them
+        for m in sleep_cycles::Entity::find()
+            .order_by_asc(sleep_cycles::Column::SleepId)
+            .all(&self.db)
+            .await?
+        {
+            out.record(&Record::SleepCycle {
+                sleep_id: m.sleep_id,
+                start: m.start,
+                end: m.end,
+                min_bpm: m.min_bpm,
+                max_bpm: m.max_bpm,
+                avg_bpm: m.avg_bpm,
+                min_hrv: m.min_hrv,
+                max_hrv: m.max_hrv,
+                avg_hrv: m.avg_hrv,
+                score: m.score,
+                efficiency: m.efficiency,
+                sdnn: m.sdnn,
+                pnn50: m.pnn50,
+                deep_hrv: m.deep_hrv,
+                imported: m.imported,
+            })?;
+        }
+
+        for m in activities::Entity::find()
+            .order_by_asc(activities::Column::Start)
+            .all(&self.db)
+            .await?
+        {
+            out.record(&Record::Activity {
+                period_id: m.period_id,
+                start: m.start,
+                end: m.end,
+                activity: m.activity,
+                confidence: m.confidence,
+                manual: m.manual,
+            })?;
+        }
+
+        let mut id = 0;
+        loop {
+            let rows = heart_rate::Entity::find()
+                .filter(heart_rate::Column::Id.gt(id))
+                .order_by_asc(heart_rate::Column::Id)
+                .limit(EXPORT_PAGE)
+                .all(&self.db)
+                .await?;
+
+            let Some(last) = rows.last() else {
+                break;
+            };
+            id = last.id;
+
+            for m in rows {
+                out.record(&Record::HeartRate {
+                    time: m.time,
+                    bpm: m.bpm,
+                    rr_intervals: m.rr_intervals,
+                    activity: m.activity,
+                    stress: m.stress,
+                    stress_smoothed: m.stress_smoothed,
+                    spo2: m.spo2,
+                    skin_temp: m.skin_temp,
+                    imu_data: m.imu_data.map(hex::encode),
+                    sensor_data: m.sensor_data,
+                    low_confidence: m.low_confidence,
+                })?;
+            }
+        }
+
+        let checksum = hex::encode(out.hasher.finalize());
+        writeln!(out.writer, "{}{}", CHECKSUM_PREFIX, checksum)?;
+        out.writer.flush()?;
+        Ok(out.lines)
+    }
+
+    /// Loads a file written by [`Self::export`], refusing it if the checksum doesn't match.
+    ///
+    /// The whole file is verified before any row is written. Rows already present are
+    /// updated from the file.
+    pub async fn import(&self, path: &Path) -> Result<usize, DbError> {
+        verify_checksum(path)?;
+
+        let mut batch = ImportBatch::default();
+        let mut count = 0;
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.starts_with(CHECKSUM_PREFIX) {
+                break;
+            }
+
+            let record = serde_json::from_str::<Record>(&line)?;
+            if !batch.accepts(&record) {
+                batch.flush(self).await?;
+            }
+            batch.push(record)?;
+            count += 1;
+        }
+
+        batch.flush(self).await?;
+        Ok(count)
+    }
+}
+
+/// Checks that the trailing checksum line matches the SHA-256 of every line before it.
+fn verify_checksum(path: &Path) -> Result<(), DbError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut previous: Option<String> = None;
+
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        if let Some(previous) = previous.replace(line) {
+            hasher.update(previous.as_bytes());
+        }
+    }
+
+    let expected = previous
+        .as_deref()
+        .and_then(|line| line.trim_end().strip_prefix(CHECKSUM_PREFIX))
+        .ok_or_else(|| {
+            DbError::Invalid(format!(
+                "{} has no checksum, it may be truncated",
+                path.display()
+            ))
+        })?;
+
+    let actual = hex::encode(hasher.finalize());
+    if actual != expected {
+        return Err(DbError::Invalid(format!(
+            "checksum mismatch for {}: expected {}, got {}",
+            path.display(),
+            expected,
+            actual
+        )));
+    }
+
+    Ok(())
+}
+
+/// Rows of one table waiting to be inserted together
+#[derive(Default)]
+enum ImportBatch {
+    #[default]
+    Empty,
+    SleepCycles(Vec<sleep_cycles::ActiveModel>),
+    Activities(Vec<activities::ActiveModel>),
+    HeartRate(Vec<heart_rate::ActiveModel>),
+}
+
+impl ImportBatch {
+    /// Whether `record` can join this batch without flushing it first
+    fn accepts(&self, record: &Record) -> bool {
+        match (self, record) {
+            (Self::Empty, _) => true,
+            (Self::SleepCycles(rows), Record::SleepCycle { .. }) => {
+                (rows.len() as u64) < SLEEP_CYCLES_BATCH
+            }
+            (Self::Activities(rows), Record::Activity { .. }) => {
+                (rows.len() as u64) < ACTIVITIES_BATCH
+            }
+            (Self::HeartRate(rows), Record::HeartRate { .. }) => {
+                (rows.len() as u64) < HEART_RATE_BATCH
+            }
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, record: Record) -> Result<(), DbError> {
+        match record {
+            Record::SleepCycle {
+                sleep_id,
+                start,
+                end,
+                min_bpm,
+                max_bpm,
+                avg_bpm,
+                min_hrv,
+                max_hrv,
+                avg_hrv,
+                score,
+                efficiency,
+                sdnn,
+                pnn50,
+                deep_hrv,
+                imported,
+            } => {
+                let model = sleep_cycles::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    sleep_id: Set(sleep_id),
+                    start: Set(start),
+                    end: Set(end),
+                    min_bpm: Set(min_bpm),
+                    max_bpm: Set(max_bpm),
+                    avg_bpm: Set(avg_bpm),
+                    min_hrv: Set(min_hrv),
+                    max_hrv: Set(max_hrv),
+                    avg_hrv: Set(avg_hrv),
+                    score: Set(score),
+                    synced: NotSet,
+                    efficiency: Set(efficiency),
+                    sdnn: Set(sdnn),
+                    pnn50: Set(pnn50),
+                    deep_hrv: Set(deep_hrv),
+                    imported: Set(imported),
+                };
+                match self {
+                    Self::SleepCycles(rows) => rows.push(model),
+                    _ => *self = Self::SleepCycles(vec![model]),
+                }
+            }
+            Record::Activity {
+                period_id,
+                start,
+                end,
+                activity,
+                confidence,
+                manual,
+            } => {
+                let model = activities::ActiveModel {
+                    id: NotSet,
+                    period_id: Set(period_id),
+                    start: Set(start),
+                    end: Set(end),
+                    activity: Set(activity),
+                    synced: NotSet,
+                    confidence: Set(confidence),
+                    manual: Set(manual),
+                };
+                match self {
+                    Self::Activities(rows) => rows.push(model),
+                    _ => *self = Self::Activities(vec![model]),
+                }
+            }
+            Record::HeartRate {
+                time,
+                bpm,
+                rr_intervals,
+                activity,
+                stress,
+                stress_smoothed,
+                spo2,
+                skin_temp,
+                imu_data,
+                sensor_data,
+                low_confidence,
+            } => {
+                let model = heart_rate::ActiveModel {
+                    id: NotSet,
+                    bpm: Set(bpm),
+                    time: Set(time),
+                    rr_intervals: Set(rr_intervals),
+                    activity: Set(activity),
+                    stress: Set(stress),
+                    stress_smoothed: Set(stress_smoothed),
+                    spo2: Set(spo2),
+                    skin_temp: Set(skin_temp),
+                    imu_data: Set(imu_data.map(hex::decode).transpose()?),
+                    sensor_data: Set(sensor_data),
+                    synced: NotSet,
+                    low_confidence: Set(low_confidence),
+                };
+                match self {
+                    Self::HeartRate(rows) => rows.push(model),
+                    _ => *self = Self::HeartRate(vec![model]),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self, db: &DatabaseHandler) -> Result<(), DbError> {
+        match std::mem::take(self) {
+            Self::Empty => {}
+            Self::SleepCycles(rows) => {
+                sleep_cycles::Entity::insert_many(rows)
+                    .on_conflict(
+                        OnConflict::column(sleep_cycles::Column::SleepId)
+                            .update_columns([
+                                sleep_cycles::Column::Start,
+                                sleep_cycles::Column::End,
+                                sleep_cycles::Column::MinBpm,
+                                sleep_cycles::Column::MaxBpm,
+                                sleep_cycles::Column::AvgBpm,
+                                sleep_cycles::Column::MinHrv,
+                                sleep_cycles::Column::MaxHrv,
+                                sleep_cycles::Column::AvgHrv,
+                                sleep_cycles::Column::Score,
+                                sleep_cycles::Column::Efficiency,
+                                sleep_cycles::Column::Sdnn,
+                                sleep_cycles::Column::Pnn50,
+                                sleep_cycles::Column::DeepHrv,
+                                sleep_cycles::Column::Imported,
+                            ])
+                            .to_owned(),
+                    )
+                    .exec(&db.db)
+                    .await?;
+            }
+            Self::Activities(rows) => {
+                activities::Entity::insert_many(rows)
+                    .on_conflict(
+                        OnConflict::column(activities::Column::Start)
+                            .update_columns([
+                                activities::Column::End,
+                                activities::Column::Activity,
+                                activities::Column::Confidence,
+                                activities::Column::Manual,
+                                activities::Column::PeriodId,
+                            ])
+                            .to_owned(),
+                    )
+                    .exec(&db.db)
+                    .await?;
+            }
+            Self::HeartRate(rows) => {
+                heart_rate::Entity::insert_many(rows)
+                    .on_conflict(
+                        OnConflict::column(heart_rate::Column::Time)
+                            .update_columns([
+                                heart_rate::Column::Bpm,
+                                heart_rate::Column::RrIntervals,
+                                heart_rate::Column::Activity,
+                                heart_rate::Column::Stress,
+                                heart_rate::Column::Spo2,
+                                heart_rate::Column::SkinTemp,
+                                heart_rate::Column::ImuData,
+                                heart_rate::Column::SensorData,
+                                heart_rate::Column::LowConfidence,
+                            ])
+                            .to_owned(),
+                    )
+                    .exec(&db.db)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openwhoop_algos::SleepCycle;
+    use openwhoop_codec::HistoryReading;
+    use sea_orm::PaginatorTrait;
+
+    use super::*;
+
+    async fn populated() -> DatabaseHandler {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let readings = (0..200)
+            .map(|i| HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 1_200_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let end = NaiveDate::from_ymd_opt(2025, 1, 2)
+            .unwrap()
+            .and_hms_opt(6, 0, 0)
+            .unwrap();
+        db.create_sleep(SleepCycle {
+            sdnn: Some(48.0),
+            pnn50: Some(21.0),
+            deep_hrv: Some(71),
+            efficiency: Some(92.5),
+            ..SleepCycle::fixture(end - chrono::TimeDelta::hours(8), end)
+        })
+        .await
+        .unwrap();
+
+        db
+    }
+
+    fn export_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("openwhoop-export-{}.jsonl", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn export_import_roundtrip() {
+        let source = populated().await;
+        let path = export_path();
+        let mut file = File::create(&path).unwrap();
+        assert_eq!(source.export(&mut file).await.unwrap(), 201);
+
+        let target = DatabaseHandler::new("sqlite::memory:").await;
+        let imported = target.import(&path).await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(imported.unwrap(), 201);
+
+        let readings = heart_rate::Entity::find().count(&target.db).await.unwrap();
+        assert_eq!(readings, 200);
+        let sleeps = target.get_sleep_cycles(None, None).await.unwrap();
+        assert_eq!(sleeps.len(), 1);
+        assert_eq!(sleeps[0].efficiency, Some(92.5));
+        assert_eq!(sleeps[0].sdnn, Some(48.0));
+        assert_eq!(sleeps[0].pnn50, Some(21.0));
+        assert_eq!(sleeps[0].deep_hrv, Some(71));
+    }
+
+    #[tokio::test]
+    async fn import_rejects_tampered_file() {
+        let source = populated().await;
+        let path = export_path();
+        let mut file = File::create(&path).unwrap();
+        source.export(&mut file).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, contents.replacen("\"bpm\":60", "\"bpm\":61", 1)).unwrap();
+
+        let target = DatabaseHandler::new("sqlite::memory:").await;
+        let result = target.import(&path).await;
+        std::fs::remove_file(&path).unwrap();
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("checksum mismatch"), "{error}");
+        let readings = heart_rate::Entity::find().count(&target.db).await.unwrap();
+        assert_eq!(readings, 0);
+    }
+}
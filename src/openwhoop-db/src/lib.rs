@@ -1,9 +1,20 @@
 mod db;
-pub use db::DatabaseHandler;
+pub use db::{DEFAULT_BUSY_TIMEOUT, DatabaseHandler};
+
+mod error;
+pub use error::DbError;
 
 mod algo_impl;
 pub use algo_impl::TempReading;
+pub mod capture;
+mod export;
 pub mod sync;
 mod type_impl;
+mod whoop_import;
 
-pub use type_impl::history::SearchHistory;
+pub use type_impl::counts::RowCounts;
+pub use type_impl::devices::KnownDevice;
+pub use type_impl::firmware::FirmwareRecord;
+pub use type_impl::history::{HistoryBucket, HistoryRow, SearchHistory};
+pub use type_impl::profile::UserProfile;
+pub use whoop_import::WhoopImport;
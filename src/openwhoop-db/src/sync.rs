@@ -5,22 +5,26 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use openwhoop_entities::{activities, heart_rate, sleep_cycles};
 use sea_orm::{
     ActiveValue::{NotSet, Set},
-    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
-    QuerySelect,
+    ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, QuerySelect,
     sea_query::{Expr, OnConflict},
 };
 
+use crate::DbError;
+
 // SQLite limits to 999 SQL variables, so batch sizes must respect:
 // heart_rate: 10 Set columns -> max 99 rows
 // sleep_cycles: 11 Set columns -> max 90 rows
 // activities: 4 Set columns -> max 249 rows
-const HEART_RATE_BATCH: u64 = 90;
-const SLEEP_CYCLES_BATCH: u64 = 80;
-const ACTIVITIES_BATCH: u64 = 160;
+pub(crate) const HEART_RATE_BATCH: u64 = 90;
+pub(crate) const SLEEP_CYCLES_BATCH: u64 = 80;
+pub(crate) const ACTIVITIES_BATCH: u64 = 160;
 
 pub struct DatabaseSync<'a> {
     local: &'a DatabaseConnection,
     remote: &'a DatabaseConnection,
+    /// Only copy remote into local, without touching the remote database
+    merge: bool,
 }
 
 pub struct SyncReport {
@@ -46,38 +50,64 @@ fn bar_style() -> ProgressStyle {
 
 impl<'a> DatabaseSync<'a> {
     pub fn new(local: &'a DatabaseConnection, remote: &'a DatabaseConnection) -> Self {
-        Self { local, remote }
+        Self {
+            local,
+            remote,
+            merge: false,
+        }
+    }
+
+    /// One directional sync that copies every row of `other` into `local`.
+    ///
+    /// `other` is only read from, so it can be opened read-only. Merged rows are left
+    /// unsynced in `local` so a later [`DatabaseSync::new`] run passes them on.
+    pub fn merge(local: &'a DatabaseConnection, other: &'a DatabaseConnection) -> Self {
+        Self {
+            local,
+            remote: other,
+            merge: true,
+        }
     }
 
-    pub async fn run(&self) -> anyhow::Result<SyncReport> {
+    /// Rows of the source still to be copied, all of them when merging
+    fn pending(&self, synced: impl ColumnTrait) -> Condition {
+        Condition::all().add_option((!self.merge).then(|| synced.eq(false)))
+    }
+
+    pub async fn run(&self) -> Result<SyncReport, DbError> {
         let mp = MultiProgress::new();
 
+        let (mut sleep_cycles_synced, mut activities_synced, mut heart_rate_synced) = (0, 0, 0);
+
         // 1. sleep_cycles (no FK dependencies)
-        let sc_lr = self
-            .sync_sleep_cycles(self.local, self.remote, &mp, "sleep_cycles L->R")
-            .await?;
-        let sc_rl = self
+        if !self.merge {
+            sleep_cycles_synced += self
+                .sync_sleep_cycles(self.local, self.remote, &mp, "sleep_cycles L->R")
+                .await?;
+        }
+        sleep_cycles_synced += self
             .sync_sleep_cycles(self.remote, self.local, &mp, "sleep_cycles R->L")
             .await?;
-        let sleep_cycles_synced = sc_lr + sc_rl;
 
         // 2. activities (FK -> sleep_cycles via period_id)
-        let act_lr = self
-            .sync_activities(self.local, self.remote, &mp, "activities L->R")
-            .await?;
-        let act_rl = self
+        if !self.merge {
+            activities_synced += self
+                .sync_activities(self.local, self.remote, &mp, "activities L->R")
+                .await?;
+        }
+        activities_synced += self
             .sync_activities(self.remote, self.local, &mp, "activities R->L")
             .await?;
-        let activities_synced = act_lr + act_rl;
 
         // 3. heart_rate (largest table, no FK)
-        let hr_lr = self
-            .sync_heart_rate(self.local, self.remote, &mp, "heart_rate L->R")
-            .await?;
-        let hr_rl = self
+        if !self.merge {
+            heart_rate_synced += self
+                .sync_heart_rate(self.local, self.remote, &mp, "heart_rate L->R")
+                .await?;
+        }
+        heart_rate_synced += self
             .sync_heart_rate(self.remote, self.local, &mp, "heart_rate R->L")
             .await?;
-        let heart_rate_synced = hr_lr + hr_rl;
 
         let report = SyncReport {
             sleep_cycles_synced,
@@ -94,11 +124,11 @@ impl<'a> DatabaseSync<'a> {
         target: &DatabaseConnection,
         mp: &MultiProgress,
         label: &str,
-    ) -> anyhow::Result<usize> {
-        let unsynced = sleep_cycles::Entity::find()
-            .filter(sleep_cycles::Column::Synced.eq(false));
+    ) -> Result<usize, DbError> {
+        let unsynced =
+            sleep_cycles::Entity::find().filter(self.pending(sleep_cycles::Column::Synced));
 
-        let total = unsynced.clone().count(source).await? as u64;
+        let total = unsynced.clone().count(source).await?;
         let pb = mp.add(ProgressBar::new(total));
         pb.set_style(bar_style());
         pb.set_prefix(label.to_string());
@@ -109,10 +139,14 @@ impl<'a> DatabaseSync<'a> {
         }
 
         let mut synced = 0usize;
+        let mut after: Option<chrono::NaiveDate> = None;
 
         loop {
             let rows = unsynced
                 .clone()
+                .filter(
+                    Condition::all().add_option(after.map(|a| sleep_cycles::Column::SleepId.gt(a))),
+                )
                 .order_by_asc(sleep_cycles::Column::SleepId)
                 .limit(Some(SLEEP_CYCLES_BATCH))
                 .all(source)
@@ -123,6 +157,11 @@ impl<'a> DatabaseSync<'a> {
             }
 
             let batch_len = rows.len() as u64;
+            // Merging leaves the source rows unsynced, so the next batch has to start after
+            // this one. A sync marks them synced, which already drops them from the query.
+            if self.merge {
+                after = rows.last().map(|r| r.sleep_id);
+            }
 
             // Deduplicate by sleep_id
             let mut deduped: HashMap<chrono::NaiveDate, sleep_cycles::Model> = HashMap::new();
@@ -146,7 +185,12 @@ impl<'a> DatabaseSync<'a> {
                     max_hrv: Set(m.max_hrv),
                     avg_hrv: Set(m.avg_hrv),
                     score: Set(m.score),
-                    synced: Set(true),
+                    synced: Set(!self.merge),
+                    efficiency: Set(m.efficiency),
+                    sdnn: Set(m.sdnn),
+                    pnn50: Set(m.pnn50),
+                    deep_hrv: Set(m.deep_hrv),
+                    imported: Set(m.imported),
                 })
                 .collect();
 
@@ -164,11 +208,28 @@ impl<'a> DatabaseSync<'a> {
                             sleep_cycles::Column::MinHrv,
                             sleep_cycles::Column::MaxHrv,
                             sleep_cycles::Column::AvgHrv,
+                            sleep_cycles::Column::Imported,
                         ])
                         .value(
                             sleep_cycles::Column::Score,
                             Expr::cust("COALESCE(excluded.score, sleep_cycles.score)"),
                         )
+                        .value(
+                            sleep_cycles::Column::Efficiency,
+                            Expr::cust("COALESCE(excluded.efficiency, sleep_cycles.efficiency)"),
+                        )
+                        .value(
+                            sleep_cycles::Column::Sdnn,
+                            Expr::cust("COALESCE(excluded.sdnn, sleep_cycles.sdnn)"),
+                        )
+                        .value(
+                            sleep_cycles::Column::Pnn50,
+                            Expr::cust("COALESCE(excluded.pnn50, sleep_cycles.pnn50)"),
+                        )
+                        .value(
+                            sleep_cycles::Column::DeepHrv,
+                            Expr::cust("COALESCE(excluded.deep_hrv, sleep_cycles.deep_hrv)"),
+                        )
                         .update_column(sleep_cycles::Column::Synced)
                         .to_owned(),
                 )
@@ -176,11 +237,13 @@ impl<'a> DatabaseSync<'a> {
                 .await?;
 
             // Mark as synced on source
-            sleep_cycles::Entity::update_many()
-                .col_expr(sleep_cycles::Column::Synced, Expr::value(true))
-                .filter(sleep_cycles::Column::Id.is_in(ids))
-                .exec(source)
-                .await?;
+            if !self.merge {
+                sleep_cycles::Entity::update_many()
+                    .col_expr(sleep_cycles::Column::Synced, Expr::value(true))
+                    .filter(sleep_cycles::Column::Id.is_in(ids))
+                    .exec(source)
+                    .await?;
+            }
 
             synced += count;
             pb.inc(batch_len);
@@ -200,11 +263,10 @@ impl<'a> DatabaseSync<'a> {
         target: &DatabaseConnection,
         mp: &MultiProgress,
         label: &str,
-    ) -> anyhow::Result<usize> {
-        let unsynced = activities::Entity::find()
-            .filter(activities::Column::Synced.eq(false));
+    ) -> Result<usize, DbError> {
+        let unsynced = activities::Entity::find().filter(self.pending(activities::Column::Synced));
 
-        let total = unsynced.clone().count(source).await? as u64;
+        let total = unsynced.clone().count(source).await?;
         let pb = mp.add(ProgressBar::new(total));
         pb.set_style(bar_style());
         pb.set_prefix(label.to_string());
@@ -215,10 +277,12 @@ impl<'a> DatabaseSync<'a> {
         }
 
         let mut synced = 0usize;
+        let mut after: Option<chrono::NaiveDateTime> = None;
 
         loop {
             let rows = unsynced
                 .clone()
+                .filter(Condition::all().add_option(after.map(|a| activities::Column::Start.gt(a))))
                 .order_by_asc(activities::Column::Start)
                 .limit(Some(ACTIVITIES_BATCH))
                 .all(source)
@@ -229,6 +293,9 @@ impl<'a> DatabaseSync<'a> {
             }
 
             let batch_len = rows.len() as u64;
+            if self.merge {
+                after = rows.last().map(|r| r.start);
+            }
 
             // Deduplicate by start
             let mut deduped: HashMap<chrono::NaiveDateTime, activities::Model> = HashMap::new();
@@ -246,7 +313,9 @@ impl<'a> DatabaseSync<'a> {
                     start: Set(m.start),
                     end: Set(m.end),
                     activity: Set(m.activity),
-                    synced: Set(true),
+                    synced: Set(!self.merge),
+                    confidence: Set(m.confidence),
+                    manual: Set(m.manual),
                 })
                 .collect();
 
@@ -258,6 +327,8 @@ impl<'a> DatabaseSync<'a> {
                         .update_columns([
                             activities::Column::End,
                             activities::Column::Activity,
+                            activities::Column::Confidence,
+                            activities::Column::Manual,
                             activities::Column::PeriodId,
                             activities::Column::Synced,
                         ])
@@ -267,11 +338,13 @@ impl<'a> DatabaseSync<'a> {
                 .await?;
 
             // Mark as synced on source
-            activities::Entity::update_many()
-                .col_expr(activities::Column::Synced, Expr::value(true))
-                .filter(activities::Column::Id.is_in(ids))
-                .exec(source)
-                .await?;
+            if !self.merge {
+                activities::Entity::update_many()
+                    .col_expr(activities::Column::Synced, Expr::value(true))
+                    .filter(activities::Column::Id.is_in(ids))
+                    .exec(source)
+                    .await?;
+            }
 
             synced += count;
             pb.inc(batch_len);
@@ -291,11 +364,10 @@ impl<'a> DatabaseSync<'a> {
         target: &DatabaseConnection,
         mp: &MultiProgress,
         label: &str,
-    ) -> anyhow::Result<usize> {
-        let unsynced = heart_rate::Entity::find()
-            .filter(heart_rate::Column::Synced.eq(false));
+    ) -> Result<usize, DbError> {
+        let unsynced = heart_rate::Entity::find().filter(self.pending(heart_rate::Column::Synced));
 
-        let total = unsynced.clone().count(source).await? as u64;
+        let total = unsynced.clone().count(source).await?;
         let pb = mp.add(ProgressBar::new(total));
         pb.set_style(bar_style());
         pb.set_prefix(label.to_string());
@@ -306,10 +378,12 @@ impl<'a> DatabaseSync<'a> {
         }
 
         let mut synced = 0usize;
+        let mut after: Option<chrono::NaiveDateTime> = None;
 
         loop {
             let rows = unsynced
                 .clone()
+                .filter(Condition::all().add_option(after.map(|a| heart_rate::Column::Time.gt(a))))
                 .order_by_asc(heart_rate::Column::Time)
                 .limit(Some(HEART_RATE_BATCH))
                 .all(source)
@@ -320,6 +394,9 @@ impl<'a> DatabaseSync<'a> {
             }
 
             let batch_len = rows.len() as u64;
+            if self.merge {
+                after = rows.last().map(|r| r.time);
+            }
 
             // Deduplicate by time
             let mut deduped: HashMap<chrono::NaiveDateTime, heart_rate::Model> = HashMap::new();
@@ -338,11 +415,13 @@ impl<'a> DatabaseSync<'a> {
                     rr_intervals: Set(m.rr_intervals),
                     activity: Set(m.activity),
                     stress: Set(m.stress),
+                    stress_smoothed: Set(m.stress_smoothed),
                     spo2: Set(m.spo2),
                     skin_temp: Set(m.skin_temp),
                     imu_data: Set(m.imu_data),
                     sensor_data: Set(m.sensor_data),
-                    synced: Set(true),
+                    synced: Set(!self.merge),
+                    low_confidence: Set(m.low_confidence),
                 })
                 .collect();
 
@@ -351,10 +430,7 @@ impl<'a> DatabaseSync<'a> {
             heart_rate::Entity::insert_many(models)
                 .on_conflict(
                     OnConflict::column(heart_rate::Column::Time)
-                        .update_columns([
-                            heart_rate::Column::Bpm,
-                            heart_rate::Column::RrIntervals,
-                        ])
+                        .update_columns([heart_rate::Column::Bpm, heart_rate::Column::RrIntervals])
                         .value(
                             heart_rate::Column::Activity,
                             Expr::cust("COALESCE(excluded.activity, heart_rate.activity)"),
@@ -363,6 +439,12 @@ impl<'a> DatabaseSync<'a> {
                             heart_rate::Column::Stress,
                             Expr::cust("COALESCE(excluded.stress, heart_rate.stress)"),
                         )
+                        .value(
+                            heart_rate::Column::StressSmoothed,
+                            Expr::cust(
+                                "COALESCE(excluded.stress_smoothed, heart_rate.stress_smoothed)",
+                            ),
+                        )
                         .value(
                             heart_rate::Column::Spo2,
                             Expr::cust("COALESCE(excluded.spo2, heart_rate.spo2)"),
@@ -382,11 +464,13 @@ impl<'a> DatabaseSync<'a> {
                 .await?;
 
             // Mark as synced on source
-            heart_rate::Entity::update_many()
-                .col_expr(heart_rate::Column::Synced, Expr::value(true))
-                .filter(heart_rate::Column::Id.is_in(ids))
-                .exec(source)
-                .await?;
+            if !self.merge {
+                heart_rate::Entity::update_many()
+                    .col_expr(heart_rate::Column::Synced, Expr::value(true))
+                    .filter(heart_rate::Column::Id.is_in(ids))
+                    .exec(source)
+                    .await?;
+            }
 
             synced += count;
             pb.inc(batch_len);
@@ -476,20 +560,9 @@ mod tests {
             .and_hms_opt(6, 0, 0)
             .unwrap();
 
-        db1.create_sleep(openwhoop_algos::SleepCycle {
-            id: end.date(),
-            start,
-            end,
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
-        })
-        .await
-        .unwrap();
+        db1.create_sleep(openwhoop_algos::SleepCycle::fixture(start, end))
+            .await
+            .unwrap();
 
         let sync = DatabaseSync::new(db1.connection(), db2.connection());
         let report = sync.run().await.unwrap();
@@ -497,7 +570,7 @@ mod tests {
         assert_eq!(report.sleep_cycles_synced, 1);
 
         // Verify db2 has the sleep cycle
-        let cycles = db2.get_sleep_cycles(None).await.unwrap();
+        let cycles = db2.get_sleep_cycles(None, None).await.unwrap();
         assert_eq!(cycles.len(), 1);
     }
 
@@ -528,4 +601,64 @@ mod tests {
         let report2 = sync.run().await.unwrap();
         assert_eq!(report2.heart_rate_synced, 0);
     }
+
+    #[tokio::test]
+    async fn merge_copies_other_database() {
+        let primary = crate::DatabaseHandler::new("sqlite::memory:").await;
+        let other = crate::DatabaseHandler::new("sqlite::memory:").await;
+
+        let readings = (0..5)
+            .map(|i| openwhoop_codec::HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 70,
+                rr: vec![850],
+                activity: 500_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        other.create_readings(readings).await.unwrap();
+
+        let end = chrono::NaiveDate::from_ymd_opt(2025, 1, 2)
+            .unwrap()
+            .and_hms_opt(6, 0, 0)
+            .unwrap();
+        other
+            .create_sleep(openwhoop_algos::SleepCycle::fixture(
+                end - chrono::TimeDelta::hours(8),
+                end,
+            ))
+            .await
+            .unwrap();
+
+        let report = DatabaseSync::merge(primary.connection(), other.connection())
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(report.heart_rate_synced, 5);
+        assert_eq!(report.sleep_cycles_synced, 1);
+
+        async fn unsynced(db: &crate::DatabaseHandler) -> u64 {
+            heart_rate::Entity::find()
+                .filter(heart_rate::Column::Synced.eq(false))
+                .count(db.connection())
+                .await
+                .unwrap()
+        }
+        assert_eq!(unsynced(&primary).await, 5);
+        // The other database is left untouched
+        assert_eq!(unsynced(&other).await, 5);
+        assert_eq!(primary.get_sleep_cycles(None, None).await.unwrap().len(), 1);
+
+        // Merging twice copies the same rows again without duplicating them
+        DatabaseSync::merge(primary.connection(), other.connection())
+            .run()
+            .await
+            .unwrap();
+        let total = heart_rate::Entity::find()
+            .count(primary.connection())
+            .await
+            .unwrap();
+        assert_eq!(total, 5);
+    }
 }
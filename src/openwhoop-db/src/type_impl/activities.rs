@@ -1,16 +1,21 @@
-use std::str::FromStr;
+use std::{collections::BTreeSet, str::FromStr};
 
+use chrono::NaiveDateTime;
+use openwhoop_algos::ActivityClassifier;
 use openwhoop_entities::activities;
 use openwhoop_types::activities::{ActivityPeriod, ActivityType, SearchActivityPeriods};
 use sea_orm::{
-    ColumnTrait, Condition, EntityTrait, NotSet, QueryFilter, QueryOrder, Set,
-    sea_query::OnConflict,
+    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, NotSet, QueryFilter, QueryOrder, Set,
+    TransactionTrait,
+    sea_query::{Expr, OnConflict},
 };
 
-use crate::DatabaseHandler;
+use crate::{DatabaseHandler, DbError, SearchHistory};
 
 impl DatabaseHandler {
-    pub async fn create_activity(&self, activity: ActivityPeriod) -> anyhow::Result<()> {
+    /// Stores a detected activity, updating the one with the same start unless it was labeled by
+    /// hand with [`Self::relabel_activity`].
+    pub async fn create_activity(&self, activity: ActivityPeriod) -> Result<(), DbError> {
         let model = activities::ActiveModel {
             id: NotSet,
             period_id: Set(activity.period_id),
@@ -18,6 +23,8 @@ impl DatabaseHandler {
             end: Set(activity.to),
             activity: Set(activity.activity.to_string()),
             synced: NotSet,
+            confidence: Set(activity.confidence),
+            manual: NotSet,
         };
 
         activities::Entity::insert(model)
@@ -25,17 +32,52 @@ impl DatabaseHandler {
                 OnConflict::column(activities::Column::Start)
                     .update_column(activities::Column::End)
                     .update_column(activities::Column::Activity)
+                    .update_column(activities::Column::Confidence)
+                    .action_and_where(activities::Column::Manual.eq(false))
                     .to_owned(),
             )
+            // A skipped manual row inserts nothing, which `exec` reports as an error
+            .exec_without_returning(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Changes the type of the activity starting at `start`, marking it as labeled by hand so
+    /// later detection keeps the label.
+    pub async fn relabel_activity(
+        &self,
+        start: NaiveDateTime,
+        activity: ActivityType,
+    ) -> Result<(), DbError> {
+        let result = activities::Entity::update_many()
+            .col_expr(
+                activities::Column::Activity,
+                Expr::value(activity.to_string()),
+            )
+            .col_expr(
+                activities::Column::Confidence,
+                Expr::value(Option::<f64>::None),
+            )
+            .col_expr(activities::Column::Manual, Expr::value(true))
+            .col_expr(activities::Column::Synced, Expr::value(false))
+            .filter(activities::Column::Start.eq(start))
             .exec(&self.db)
             .await?;
 
+        if result.rows_affected == 0 {
+            return Err(DbError::NotFound(format!(
+                "No activity starts at {}",
+                start
+            )));
+        }
+
         Ok(())
     }
     pub async fn search_activities(
         &self,
         options: SearchActivityPeriods,
-    ) -> anyhow::Result<Vec<ActivityPeriod>> {
+    ) -> Result<Vec<ActivityPeriod>, DbError> {
         let activities = activities::Entity::find()
             .filter(search_activity_periods_query(options))
             .all(&self.db)
@@ -47,13 +89,132 @@ impl DatabaseHandler {
         Ok(activities)
     }
 
-    pub async fn get_latest_activity(&self) -> anyhow::Result<Option<ActivityPeriod>> {
+    pub async fn get_latest_activity(&self) -> Result<Option<ActivityPeriod>, DbError> {
         Ok(activities::Entity::find()
             .order_by_desc(activities::Column::End)
             .one(&self.db)
             .await?
             .map(map_activity_period))
     }
+
+    /// Joins the activities with `ids` into one spanning all of them, kept under the earliest
+    /// one's id and period.
+    ///
+    /// Edited activities count as labeled by hand, so detection keeps their boundaries. Their
+    /// type is guessed again from the readings unless it was already labeled by hand.
+    pub async fn merge_activities(&self, ids: &[i32]) -> Result<ActivityPeriod, DbError> {
+        let ids = ids.iter().copied().collect::<BTreeSet<_>>();
+        if ids.len() < 2 {
+            return Err(DbError::Invalid(
+                "Merging needs at least two activities".into(),
+            ));
+        }
+
+        let rows = activities::Entity::find()
+            .filter(activities::Column::Id.is_in(ids.iter().copied()))
+            .order_by_asc(activities::Column::Start)
+            .all(&self.db)
+            .await?;
+        if rows.len() != ids.len() {
+            return Err(DbError::NotFound(format!(
+                "Only {} of {} activities exist",
+                rows.len(),
+                ids.len()
+            )));
+        }
+
+        let first = rows[0].clone();
+        let end = rows.iter().map(|r| r.end).max().unwrap_or(first.end);
+        let (activity, confidence) = self.reclassify(&first, first.start, end).await?;
+
+        let txn = self.db.begin().await?;
+        activities::Entity::delete_many()
+            .filter(activities::Column::Id.is_in(rows[1..].iter().map(|r| r.id)))
+            .exec(&txn)
+            .await?;
+
+        let mut merged: activities::ActiveModel = first.into();
+        merged.end = Set(end);
+        merged.activity = Set(activity);
+        merged.confidence = Set(confidence);
+        merged.manual = Set(true);
+        merged.synced = Set(false);
+        let merged = merged.update(&txn).await?;
+        txn.commit().await?;
+
+        Ok(map_activity_period(merged))
+    }
+
+    /// Splits the activity with `id` into one ending and one starting at `at`, see
+    /// [`Self::merge_activities`] for how both are stored.
+    pub async fn split_activity(
+        &self,
+        id: i32,
+        at: NaiveDateTime,
+    ) -> Result<(ActivityPeriod, ActivityPeriod), DbError> {
+        let Some(row) = activities::Entity::find_by_id(id).one(&self.db).await? else {
+            return Err(DbError::NotFound(format!("No activity with id {}", id)));
+        };
+        if at <= row.start || at >= row.end {
+            return Err(DbError::Invalid(format!(
+                "{} is outside of activity {} ({} to {})",
+                at, id, row.start, row.end
+            )));
+        }
+
+        let (first_activity, first_confidence) = self.reclassify(&row, row.start, at).await?;
+        let (second_activity, second_confidence) = self.reclassify(&row, at, row.end).await?;
+
+        let second = activities::ActiveModel {
+            id: NotSet,
+            period_id: Set(row.period_id),
+            start: Set(at),
+            end: Set(row.end),
+            activity: Set(second_activity),
+            synced: NotSet,
+            confidence: Set(second_confidence),
+            manual: Set(true),
+        };
+
+        let txn = self.db.begin().await?;
+        let mut first: activities::ActiveModel = row.into();
+        first.end = Set(at);
+        first.activity = Set(first_activity);
+        first.confidence = Set(first_confidence);
+        first.manual = Set(true);
+        first.synced = Set(false);
+        let first = first.update(&txn).await?;
+        let second = second.insert(&txn).await?;
+        txn.commit().await?;
+
+        Ok((map_activity_period(first), map_activity_period(second)))
+    }
+
+    /// Type and confidence of `row` after its boundaries changed to `from` - `to`, naps and
+    /// hand labels keep theirs
+    async fn reclassify(
+        &self,
+        row: &activities::Model,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<(String, Option<f64>), DbError> {
+        if row.manual || row.activity == ActivityType::Nap.to_string() {
+            return Ok((row.activity.clone(), row.confidence));
+        }
+
+        let history = self
+            .search_history(SearchHistory {
+                from: Some(from),
+                to: Some(to),
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(match ActivityClassifier::classify_activity(&history) {
+            Some(guess) => (guess.activity.to_string(), Some(guess.confidence)),
+            None => (ActivityType::Activity.to_string(), None),
+        })
+    }
 }
 
 fn map_activity_period(value: activities::Model) -> ActivityPeriod {
@@ -62,6 +223,7 @@ fn map_activity_period(value: activities::Model) -> ActivityPeriod {
         from: value.start,
         to: value.end,
         activity: ActivityType::from_str(value.activity.as_str()).unwrap(),
+        confidence: value.confidence,
     }
 }
 
@@ -88,6 +250,7 @@ mod tests {
             from: base.and_hms_opt(hour, 0, 0).unwrap(),
             to: base.and_hms_opt(hour + 1, 0, 0).unwrap(),
             activity: ActivityType::Running,
+            confidence: Some(0.8),
         }
     }
 
@@ -106,9 +269,12 @@ mod tests {
                 .unwrap(),
             activity: "Running".to_string(),
             synced: false,
+            confidence: Some(0.8),
+            manual: false,
         };
         let period = map_activity_period(model);
         assert!(matches!(period.activity, ActivityType::Running));
+        assert_eq!(period.confidence, Some(0.8));
     }
 
     #[test]
@@ -131,18 +297,13 @@ mod tests {
         let sleep_date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
         let sleep = openwhoop_algos::SleepCycle {
             id: sleep_date,
-            start: sleep_date.and_hms_opt(22, 0, 0).unwrap(),
-            end: NaiveDate::from_ymd_opt(2025, 1, 2)
-                .unwrap()
-                .and_hms_opt(6, 0, 0)
-                .unwrap(),
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
+            ..openwhoop_algos::SleepCycle::fixture(
+                sleep_date.and_hms_opt(22, 0, 0).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 1, 2)
+                    .unwrap()
+                    .and_hms_opt(6, 0, 0)
+                    .unwrap(),
+            )
         };
         db.create_sleep(sleep).await.unwrap();
 
@@ -155,6 +316,7 @@ mod tests {
             .unwrap();
         assert_eq!(results.len(), 1);
         assert!(matches!(results[0].activity, ActivityType::Running));
+        assert_eq!(results[0].confidence, Some(0.8));
     }
 
     #[tokio::test]
@@ -170,18 +332,13 @@ mod tests {
         let sleep_date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
         let sleep = openwhoop_algos::SleepCycle {
             id: sleep_date,
-            start: sleep_date.and_hms_opt(22, 0, 0).unwrap(),
-            end: NaiveDate::from_ymd_opt(2025, 1, 2)
-                .unwrap()
-                .and_hms_opt(6, 0, 0)
-                .unwrap(),
-            min_bpm: 50,
-            max_bpm: 70,
-            avg_bpm: 60,
-            min_hrv: 30,
-            max_hrv: 80,
-            avg_hrv: 55,
-            score: 100.0,
+            ..openwhoop_algos::SleepCycle::fixture(
+                sleep_date.and_hms_opt(22, 0, 0).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 1, 2)
+                    .unwrap()
+                    .and_hms_opt(6, 0, 0)
+                    .unwrap(),
+            )
         };
         db.create_sleep(sleep).await.unwrap();
 
@@ -191,4 +348,81 @@ mod tests {
         let latest = db.get_latest_activity().await.unwrap().unwrap();
         assert_eq!(latest.from.hour(), 14);
     }
+
+    /// Database with the night the activities of [`make_activity`] belong to
+    async fn db_with_sleep() -> DatabaseHandler {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let sleep_date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        db.create_sleep(openwhoop_algos::SleepCycle {
+            id: sleep_date,
+            ..openwhoop_algos::SleepCycle::fixture(
+                sleep_date.and_hms_opt(22, 0, 0).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 1, 2)
+                    .unwrap()
+                    .and_hms_opt(6, 0, 0)
+                    .unwrap(),
+            )
+        })
+        .await
+        .unwrap();
+        db
+    }
+
+    async fn stored(db: &DatabaseHandler) -> Vec<activities::Model> {
+        activities::Entity::find()
+            .order_by_asc(activities::Column::Start)
+            .all(&db.db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn merging_adjacent_activities_spans_both() {
+        let db = db_with_sleep().await;
+        db.create_activity(make_activity(8)).await.unwrap();
+        db.create_activity(make_activity(9)).await.unwrap();
+        let ids = stored(&db).await.iter().map(|a| a.id).collect::<Vec<_>>();
+
+        let merged = db.merge_activities(&ids).await.unwrap();
+        assert_eq!(merged.from.hour(), 8);
+        assert_eq!(merged.to.hour(), 10);
+
+        let rows = stored(&db).await;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, ids[0]);
+        assert_eq!((rows[0].start, rows[0].end), (merged.from, merged.to));
+        assert!(rows[0].manual);
+
+        let error = db.merge_activities(&[ids[0]]).await.unwrap_err();
+        assert!(matches!(error, DbError::Invalid(_)), "{error:?}");
+        let error = db.merge_activities(&[ids[0], 1000]).await.unwrap_err();
+        assert!(matches!(error, DbError::NotFound(_)), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn splitting_an_activity_keeps_both_halves() {
+        let db = db_with_sleep().await;
+        let base = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        db.create_activity(ActivityPeriod {
+            to: base.and_hms_opt(10, 0, 0).unwrap(),
+            ..make_activity(8)
+        })
+        .await
+        .unwrap();
+        let id = stored(&db).await[0].id;
+
+        let at = base.and_hms_opt(9, 15, 0).unwrap();
+        let (first, second) = db.split_activity(id, at).await.unwrap();
+        assert_eq!((first.from.hour(), first.to), (8, at));
+        assert_eq!((second.from, second.to.hour()), (at, 10));
+        assert_eq!(first.period_id, second.period_id);
+
+        let rows = stored(&db).await;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].end, rows[1].start);
+        assert!(rows.iter().all(|r| r.manual));
+
+        assert!(db.split_activity(id, at).await.is_err());
+        assert!(db.split_activity(1000, at).await.is_err());
+    }
 }
@@ -0,0 +1,212 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use openwhoop_entities::charging_periods;
+use sea_orm::{
+    ActiveModelTrait,
+    ActiveValue::NotSet,
+    ColumnTrait, Condition, ConnectionTrait, DbBackend, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, Set,
+    sea_query::{Expr, OnConflict, SimpleExpr},
+};
+
+use crate::{
+    DatabaseHandler, DbError,
+    db::{retry_busy, timestamp_to_local},
+};
+
+/// How long a charging period whose end never arrived excludes readings
+const MAX_OPEN_CHARGING: TimeDelta = TimeDelta::hours(12);
+
+impl DatabaseHandler {
+    /// Opens a charging period at `unix`, periods already stored are ignored.
+    pub async fn start_charging(&self, unix: u32) -> Result<(), DbError> {
+        let model = charging_periods::ActiveModel {
+            id: NotSet,
+            start: Set(timestamp_to_local(u64::from(unix) * 1000)?),
+            end: Set(None),
+        };
+
+        retry_busy(|| {
+            charging_periods::Entity::insert(model.clone())
+                .on_conflict(
+                    OnConflict::column(charging_periods::Column::Start)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Closes the charging period open at `unix`. Without one, e.g. when the band went on
+    /// its charger before the first download, there is nothing to close.
+    pub async fn stop_charging(&self, unix: u32) -> Result<(), DbError> {
+        let end = timestamp_to_local(u64::from(unix) * 1000)?;
+        let period = charging_periods::Entity::find()
+            .filter(charging_periods::Column::Start.lte(end))
+            .order_by_desc(charging_periods::Column::Start)
+            .one(&self.db)
+            .await?;
+
+        if let Some(period) = period.filter(|period| period.end.is_none()) {
+            let mut period = period.into_active_model();
+            period.end = Set(Some(end));
+            retry_busy(|| period.clone().update(&self.db)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Charging periods overlapping `from` to `to` ordered by start. Periods still open end
+    /// [`MAX_OPEN_CHARGING`] after their start, in case the end was never received.
+    pub async fn get_charging_periods(
+        &self,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+    ) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>, DbError> {
+        let filter = Condition::all()
+            .add_option(to.map(|to| charging_periods::Column::Start.lt(to)))
+            .add_option(from.map(|from| {
+                Condition::any()
+                    .add(charging_periods::Column::End.is_null())
+                    .add(charging_periods::Column::End.gt(from))
+            }));
+
+        Ok(charging_periods::Entity::find()
+            .filter(filter)
+            .order_by_asc(charging_periods::Column::Start)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|period| {
+                let end = period.end.unwrap_or(period.start + MAX_OPEN_CHARGING);
+                (period.start, end)
+            })
+            .filter(|(_, end)| from.is_none_or(|from| *end > from))
+            .collect())
+    }
+
+    /// Matches readings that weren't taken while charging, those are meaningless and left
+    /// out of every aggregate. Periods still open end [`MAX_OPEN_CHARGING`] after their
+    /// start, like in [`Self::get_charging_periods`].
+    pub(crate) fn not_charging(&self) -> SimpleExpr {
+        let hours = MAX_OPEN_CHARGING.num_hours();
+        let open_end = match self.db.get_database_backend() {
+            DbBackend::Postgres => format!("charging_periods.start + INTERVAL '{} hours'", hours),
+            _ => format!("datetime(charging_periods.start, '+{} hours')", hours),
+        };
+
+        Expr::cust(format!(
+            "NOT EXISTS (SELECT 1 FROM charging_periods \
+             WHERE charging_periods.start <= heart_rate.time \
+             AND heart_rate.time <= COALESCE(charging_periods.\"end\", {}))",
+            open_end
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+    use openwhoop_codec::{HistoryReading, SensorData};
+
+    use super::*;
+    use crate::SearchHistory;
+
+    #[tokio::test]
+    async fn charging_events_pair_into_periods() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let time = |unix: u32| {
+            Local
+                .timestamp_opt(i64::from(unix), 0)
+                .unwrap()
+                .naive_local()
+        };
+
+        let on = 1735732800; // 2025-01-01 12:00:00 UTC
+        let off = on + 3600;
+        // A stop without a start is ignored, downloading again resends both events
+        db.stop_charging(on - 60).await.unwrap();
+        for _ in 0..2 {
+            db.start_charging(on).await.unwrap();
+            db.stop_charging(off).await.unwrap();
+        }
+        // Charging again, the end hasn't arrived yet
+        db.start_charging(off + 7200).await.unwrap();
+
+        let periods = db.get_charging_periods(None, None).await.unwrap();
+        assert_eq!(
+            periods,
+            [
+                (time(on), time(off)),
+                (time(off + 7200), time(off + 7200) + MAX_OPEN_CHARGING)
+            ]
+        );
+
+        let after_first = db
+            .get_charging_periods(Some(time(off + 60)), None)
+            .await
+            .unwrap();
+        assert_eq!(after_first, periods[1..]);
+    }
+
+    #[tokio::test]
+    async fn readings_while_charging_are_left_out() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let time = |unix: u32| {
+            Local
+                .timestamp_opt(i64::from(unix), 0)
+                .unwrap()
+                .naive_local()
+        };
+
+        let on = 1735732800; // 2025-01-01 12:00:00 UTC
+        let off = on + 3600;
+        let again = off + 7200;
+        db.start_charging(on).await.unwrap();
+        db.stop_charging(off).await.unwrap();
+        // Its end never arrives
+        db.start_charging(again).await.unwrap();
+
+        // Before, on both ends of the first period, between the periods, in the open one
+        // and after it timed out
+        let worn = [on - 60, off + 60, again + 13 * 3600];
+        let charging = [on, off, again + 3600];
+        let readings = worn
+            .iter()
+            .chain(&charging)
+            .map(|unix| HistoryReading {
+                unix: u64::from(*unix) * 1000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: Some(SensorData {
+                    skin_temp_raw: 850,
+                    ..Default::default()
+                }),
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let history = db.search_history(SearchHistory::default()).await.unwrap();
+        let times = history.iter().map(|r| r.time).collect::<Vec<_>>();
+        assert_eq!(times, worn.map(time));
+
+        let sensor = db
+            .search_sensor_readings(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(sensor.len(), worn.len());
+
+        let mean = db.mean_skin_temp(time(on), time(off + 1)).await.unwrap();
+        assert!(mean.is_none());
+        let mean = db
+            .mean_skin_temp(time(on - 60), time(again + 14 * 3600))
+            .await
+            .unwrap();
+        assert!(mean.is_some());
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+
+use openwhoop_entities::{activities, heart_rate, packets, sleep_cycles};
+use sea_orm::{EntityTrait, PaginatorTrait};
+
+use crate::{DatabaseHandler, DbError};
+
+/// Number of rows in the main tables, to check that a sync added data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RowCounts {
+    pub packets: u64,
+    pub readings: u64,
+    pub sleeps: u64,
+    pub activities: u64,
+}
+
+impl fmt::Display for RowCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Packets: {}", self.packets)?;
+        writeln!(f, "Readings: {}", self.readings)?;
+        writeln!(f, "Sleeps: {}", self.sleeps)?;
+        write!(f, "Activities: {}", self.activities)
+    }
+}
+
+impl DatabaseHandler {
+    pub async fn count_rows(&self) -> Result<RowCounts, DbError> {
+        Ok(RowCounts {
+            packets: packets::Entity::find().count(&self.db).await?,
+            readings: heart_rate::Entity::find().count(&self.db).await?,
+            sleeps: sleep_cycles::Entity::find().count(&self.db).await?,
+            activities: activities::Entity::find().count(&self.db).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeDelta};
+    use openwhoop_algos::SleepCycle;
+    use openwhoop_codec::{HistoryReading, constants::DATA_FROM_STRAP};
+    use openwhoop_types::activities::{ActivityPeriod, ActivityType};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_reflect_inserted_rows() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        assert_eq!(db.count_rows().await.unwrap(), RowCounts::default());
+
+        db.create_packet(DATA_FROM_STRAP, vec![0xaa, 1, 2, 3])
+            .await
+            .unwrap();
+        let readings = (0..3)
+            .map(|i| HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let end = NaiveDate::from_ymd_opt(2025, 1, 2)
+            .unwrap()
+            .and_hms_opt(6, 0, 0)
+            .unwrap();
+        db.create_sleep(SleepCycle::fixture(end - TimeDelta::hours(8), end))
+            .await
+            .unwrap();
+        db.create_activity(ActivityPeriod {
+            period_id: end.date(),
+            from: end + TimeDelta::hours(2),
+            to: end + TimeDelta::hours(3),
+            activity: ActivityType::Running,
+            confidence: None,
+        })
+        .await
+        .unwrap();
+
+        let counts = db.count_rows().await.unwrap();
+        assert_eq!(
+            counts,
+            RowCounts {
+                packets: 1,
+                readings: 3,
+                sleeps: 1,
+                activities: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+use openwhoop_entities::devices;
+use sea_orm::{
+    ActiveValue::NotSet, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+    sea_query::OnConflict,
+};
+
+use crate::{DatabaseHandler, DbError};
+
+/// A band saved under a nickname so commands can refer to it without its address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownDevice {
+    pub nickname: String,
+    pub address: String,
+    /// Advertised name, which identifies the band on macOS
+    pub name: Option<String>,
+}
+
+impl KnownDevice {
+    fn from_model(model: devices::Model) -> Self {
+        Self {
+            nickname: model.nickname,
+            address: model.address,
+            name: model.name,
+        }
+    }
+}
+
+impl fmt::Display for KnownDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.nickname, self.address)?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl DatabaseHandler {
+    /// Saves `device` under its nickname, replacing a device already saved under it.
+    pub async fn add_device(&self, device: &KnownDevice) -> Result<(), DbError> {
+        let model = devices::ActiveModel {
+            id: NotSet,
+            nickname: Set(device.nickname.clone()),
+            address: Set(device.address.clone()),
+            name: Set(device.name.clone()),
+        };
+
+        devices::Entity::insert(model)
+            .on_conflict(
+                OnConflict::column(devices::Column::Nickname)
+                    .update_columns([devices::Column::Address, devices::Column::Name])
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Saved devices ordered by nickname.
+    pub async fn get_devices(&self) -> Result<Vec<KnownDevice>, DbError> {
+        Ok(devices::Entity::find()
+            .order_by_asc(devices::Column::Nickname)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(KnownDevice::from_model)
+            .collect())
+    }
+
+    pub async fn get_device(&self, nickname: &str) -> Result<Option<KnownDevice>, DbError> {
+        Ok(devices::Entity::find()
+            .filter(devices::Column::Nickname.eq(nickname))
+            .one(&self.db)
+            .await?
+            .map(KnownDevice::from_model))
+    }
+
+    pub async fn remove_device(&self, nickname: &str) -> Result<(), DbError> {
+        let deleted = devices::Entity::delete_many()
+            .filter(devices::Column::Nickname.eq(nickname))
+            .exec(&self.db)
+            .await?;
+        if deleted.rows_affected == 0 {
+            return Err(DbError::NotFound(format!(
+                "No device with nickname {}",
+                nickname
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(nickname: &str, address: &str) -> KnownDevice {
+        KnownDevice {
+            nickname: nickname.to_owned(),
+            address: address.to_owned(),
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn add_list_and_remove_devices() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        assert!(db.get_devices().await.unwrap().is_empty());
+
+        db.add_device(&device("work", "AA:BB:CC:DD:EE:01"))
+            .await
+            .unwrap();
+        db.add_device(&device("home", "AA:BB:CC:DD:EE:02"))
+            .await
+            .unwrap();
+        let nicknames: Vec<_> = db
+            .get_devices()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|device| device.nickname)
+            .collect();
+        assert_eq!(nicknames, ["home", "work"]);
+
+        // Adding under a saved nickname replaces the band
+        let replacement = KnownDevice {
+            name: Some(String::from("WHOOP 4C0123456")),
+            ..device("work", "AA:BB:CC:DD:EE:03")
+        };
+        db.add_device(&replacement).await.unwrap();
+        assert_eq!(db.get_devices().await.unwrap().len(), 2);
+        assert_eq!(db.get_device("work").await.unwrap(), Some(replacement));
+
+        db.remove_device("home").await.unwrap();
+        assert_eq!(db.get_device("home").await.unwrap(), None);
+        assert_eq!(db.get_devices().await.unwrap().len(), 1);
+
+        let error = db.remove_device("home").await.unwrap_err();
+        assert!(matches!(error, DbError::NotFound(_)), "{error:?}");
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use openwhoop_entities::firmware_history;
+use sea_orm::{ActiveValue::NotSet, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set};
+
+use crate::{DatabaseHandler, DbError, db::retry_busy};
+
+/// Firmware versions the band reported at `seen_at`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareRecord {
+    /// Address of the band, `None` for reports stored before bands were told apart
+    pub device: Option<String>,
+    pub harvard: String,
+    pub boylston: String,
+    pub seen_at: NaiveDateTime,
+}
+
+impl FirmwareRecord {
+    fn from_model(model: firmware_history::Model) -> Self {
+        Self {
+            device: model.device,
+            harvard: model.harvard,
+            boylston: model.boylston,
+            seen_at: model.seen_at,
+        }
+    }
+
+    fn same_version(&self, other: &Self) -> bool {
+        self.harvard == other.harvard && self.boylston == other.boylston
+    }
+}
+
+impl fmt::Display for FirmwareRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.seen_at.format("%Y-%m-%d %H:%M:%S"))?;
+        if let Some(device) = &self.device {
+            write!(f, " {}", device)?;
+        }
+        write!(f, ": Harvard {}, Boylston {}", self.harvard, self.boylston)
+    }
+}
+
+impl DatabaseHandler {
+    /// Stores a version report, returning the previous report of the same band if the
+    /// version changed since.
+    pub async fn record_firmware(
+        &self,
+        record: &FirmwareRecord,
+    ) -> Result<Option<FirmwareRecord>, DbError> {
+        let same_device = match &record.device {
+            Some(device) => firmware_history::Column::Device.eq(device.as_str()),
+            None => firmware_history::Column::Device.is_null(),
+        };
+        let previous = firmware_history::Entity::find()
+            .filter(same_device)
+            .order_by_desc(firmware_history::Column::SeenAt)
+            .one(&self.db)
+            .await?
+            .map(FirmwareRecord::from_model);
+
+        let model = firmware_history::ActiveModel {
+            id: NotSet,
+            device: Set(record.device.clone()),
+            harvard: Set(record.harvard.clone()),
+            boylston: Set(record.boylston.clone()),
+            seen_at: Set(record.seen_at),
+        };
+        retry_busy(|| firmware_history::Entity::insert(model.clone()).exec(&self.db)).await?;
+
+        Ok(previous.filter(|previous| !previous.same_version(record)))
+    }
+
+    /// Every stored version report, oldest first.
+    pub async fn get_firmware_history(&self) -> Result<Vec<FirmwareRecord>, DbError> {
+        Ok(firmware_history::Entity::find()
+            .order_by_asc(firmware_history::Column::SeenAt)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(FirmwareRecord::from_model)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn firmware_change_is_detected() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let record = |day, harvard: &str| FirmwareRecord {
+            device: Some("AA:BB:CC:DD:EE:01".to_owned()),
+            harvard: harvard.to_owned(),
+            boylston: "17.2.2.0".to_owned(),
+            seen_at: NaiveDate::from_ymd_opt(2025, 1, day)
+                .unwrap()
+                .and_hms_opt(8, 0, 0)
+                .unwrap(),
+        };
+
+        let first = record(1, "41.16.6.0");
+        assert_eq!(db.record_firmware(&first).await.unwrap(), None);
+        // Syncing again on the same version
+        let second = record(2, "41.16.6.0");
+        assert_eq!(db.record_firmware(&second).await.unwrap(), None);
+
+        let updated = record(3, "41.17.2.0");
+        assert_eq!(
+            db.record_firmware(&updated).await.unwrap(),
+            Some(second.clone())
+        );
+
+        let history = db.get_firmware_history().await.unwrap();
+        assert_eq!(history, [first, second, updated.clone()]);
+
+        // Another band on older firmware isn't a change of either
+        let other = FirmwareRecord {
+            device: Some("AA:BB:CC:DD:EE:02".to_owned()),
+            ..record(4, "41.16.6.0")
+        };
+        assert_eq!(db.record_firmware(&other).await.unwrap(), None);
+        let again = record(5, "41.17.2.0");
+        assert_eq!(db.record_firmware(&again).await.unwrap(), None);
+    }
+}
@@ -1,9 +1,37 @@
-use chrono::NaiveDateTime;
+use std::collections::HashSet;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use futures::{Stream, StreamExt};
+use openwhoop_codec::{Activity, ImuSample, ParsedHistoryReading, SensorData};
 use openwhoop_entities::heart_rate;
-use sea_orm::{ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
-use openwhoop_codec::{Activity, ParsedHistoryReading};
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DbBackend, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Statement,
+};
+
+use crate::{DatabaseHandler, DbError};
 
-use crate::DatabaseHandler;
+/// Consecutive readings from `from` to `to` that are more than `min_gap` seconds apart,
+/// bound in that order
+const GAPS_SQLITE: &str = r#"
+SELECT previous, time FROM (
+    SELECT LAG(time) OVER (ORDER BY time) AS previous, time
+    FROM heart_rate
+    WHERE time >= ? AND time <= ?
+)
+WHERE previous IS NOT NULL AND (julianday(time) - julianday(previous)) * 86400 > ?
+ORDER BY time
+"#;
+
+const GAPS_POSTGRES: &str = r#"
+SELECT previous, time FROM (
+    SELECT LAG(time) OVER (ORDER BY time) AS previous, time
+    FROM heart_rate
+    WHERE time >= $1 AND time <= $2
+) AS readings
+WHERE previous IS NOT NULL AND EXTRACT(EPOCH FROM time - previous) > $3
+ORDER BY time
+"#;
 
 #[derive(Default, Debug)]
 pub struct SearchHistory {
@@ -12,6 +40,70 @@ pub struct SearchHistory {
     pub limit: Option<u64>,
 }
 
+/// A stored reading with every field, including the ones filled in by later calculations
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRow {
+    pub time: NaiveDateTime,
+    pub bpm: u8,
+    pub rr: Vec<u16>,
+    pub activity: Activity,
+    pub stress: Option<f64>,
+    /// Exponential moving average of `stress`, unset unless stress was calculated with smoothing
+    pub stress_smoothed: Option<f64>,
+    pub spo2: Option<f64>,
+    pub skin_temp: Option<f64>,
+    pub imu_data: Option<Vec<ImuSample>>,
+    pub sensor_data: Option<SensorData>,
+    /// Parsed from a packet that failed its CRC, see [`DatabaseHandler::mark_low_confidence`]
+    pub low_confidence: bool,
+}
+
+/// Readings of one time bucket averaged together, see [`DatabaseHandler::downsampled_history`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryBucket {
+    /// Start of the bucket
+    pub time: NaiveDateTime,
+    pub readings: usize,
+    pub avg_bpm: f64,
+    pub min_bpm: u8,
+    pub max_bpm: u8,
+    /// Means of the readings that have a value, `None` if none of them has one
+    pub stress: Option<f64>,
+    pub spo2: Option<f64>,
+    pub skin_temp: Option<f64>,
+}
+
+/// Running sums of the bucket being filled
+struct BucketSums {
+    index: i64,
+    readings: usize,
+    bpm: f64,
+    min_bpm: u8,
+    max_bpm: u8,
+    stress: Mean,
+    spo2: Mean,
+    skin_temp: Mean,
+}
+
+#[derive(Default)]
+struct Mean {
+    sum: f64,
+    count: usize,
+}
+
+impl Mean {
+    fn add(&mut self, value: Option<f64>) {
+        if let Some(value) = value {
+            self.sum += value;
+            self.count += 1;
+        }
+    }
+
+    fn value(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
 impl SearchHistory {
     pub(crate) fn conditions(self) -> Condition {
         Condition::all()
@@ -21,13 +113,15 @@ impl SearchHistory {
 }
 
 impl DatabaseHandler {
+    /// Readings in `options`' range, except those taken while the band was charging.
     pub async fn search_history(
         &self,
         options: SearchHistory,
-    ) -> anyhow::Result<Vec<ParsedHistoryReading>> {
+    ) -> Result<Vec<ParsedHistoryReading>, DbError> {
         let limit = options.limit;
         let history = heart_rate::Entity::find()
             .filter(options.conditions())
+            .filter(self.not_charging())
             .filter(heart_rate::Column::Activity.is_not_null())
             .limit(limit)
             .order_by_asc(heart_rate::Column::Time)
@@ -40,6 +134,238 @@ impl DatabaseHandler {
         Ok(history)
     }
 
+    /// Like [`Self::search_history`], but with every stored field of each reading, including
+    /// those taken while charging.
+    pub async fn search_history_rows(
+        &self,
+        options: SearchHistory,
+    ) -> Result<Vec<HistoryRow>, DbError> {
+        let limit = options.limit;
+        let rows = heart_rate::Entity::find()
+            .filter(options.conditions())
+            .filter(heart_rate::Column::Activity.is_not_null())
+            .limit(limit)
+            .order_by_asc(heart_rate::Column::Time)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(Self::parse_row)
+            .collect();
+
+        Ok(rows)
+    }
+
+    /// Like [`Self::search_history_rows`], but reads rows lazily as the stream is polled
+    /// instead of loading the whole range at once. The stream holds a database connection
+    /// until it is dropped.
+    pub async fn stream_readings(
+        &self,
+        options: SearchHistory,
+    ) -> Result<impl Stream<Item = Result<HistoryRow, DbError>>, DbError> {
+        let limit = options.limit;
+        let rows = heart_rate::Entity::find()
+            .filter(options.conditions())
+            .filter(heart_rate::Column::Activity.is_not_null())
+            .limit(limit)
+            .order_by_asc(heart_rate::Column::Time)
+            .stream(&self.db)
+            .await?;
+
+        Ok(rows.map(|row| Ok(Self::parse_row(row?))))
+    }
+
+    /// Intervals between `from` and `to` longer than `min_gap` without any reading.
+    ///
+    /// Gaps at either end of the range count as well, so a range without readings is one gap.
+    /// Gaps between readings are found by the database, only they are loaded.
+    pub async fn find_gaps(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        min_gap: TimeDelta,
+    ) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>, DbError> {
+        let backend = self.db.get_database_backend();
+        let sql = match backend {
+            DbBackend::Postgres => GAPS_POSTGRES,
+            _ => GAPS_SQLITE,
+        };
+        let min_gap_seconds = min_gap.num_milliseconds() as f64 / 1000.0;
+        let statement = Statement::from_sql_and_values(
+            backend,
+            sql,
+            [from.into(), to.into(), min_gap_seconds.into()],
+        );
+
+        let mut gaps = Vec::new();
+        for row in self.db.query_all(statement).await? {
+            gaps.push((row.try_get("", "previous")?, row.try_get("", "time")?));
+        }
+
+        let (first, last) = heart_rate::Entity::find()
+            .select_only()
+            .column_as(heart_rate::Column::Time.min(), "first")
+            .column_as(heart_rate::Column::Time.max(), "last")
+            .filter(heart_rate::Column::Time.gte(from))
+            .filter(heart_rate::Column::Time.lte(to))
+            .into_tuple::<(Option<NaiveDateTime>, Option<NaiveDateTime>)>()
+            .one(&self.db)
+            .await?
+            .unwrap_or_default();
+
+        let (Some(first), Some(last)) = (first, last) else {
+            return Ok(if to - from > min_gap {
+                vec![(from, to)]
+            } else {
+                Vec::new()
+            });
+        };
+        if first - from > min_gap {
+            gaps.insert(0, (from, first));
+        }
+        if to - last > min_gap {
+            gaps.push((last, to));
+        }
+
+        Ok(gaps)
+    }
+
+    /// Readings from `from` up to `to` averaged into at most `max_points` equally long
+    /// buckets, for charting ranges too long to plot every reading.
+    ///
+    /// Buckets without readings are left out. Each keeps its lowest and highest heart rate
+    /// for envelope plots.
+    pub async fn downsampled_history(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        max_points: usize,
+    ) -> Result<Vec<HistoryBucket>, DbError> {
+        if max_points == 0 {
+            return Err(DbError::Invalid("max_points must be positive".into()));
+        }
+        let span = (to - from).num_milliseconds();
+        if span <= 0 {
+            return Ok(Vec::new());
+        }
+
+        let readings = heart_rate::Entity::find()
+            .select_only()
+            .column(heart_rate::Column::Time)
+            .column(heart_rate::Column::Bpm)
+            .column(heart_rate::Column::Stress)
+            .column(heart_rate::Column::Spo2)
+            .column(heart_rate::Column::SkinTemp)
+            .filter(heart_rate::Column::Time.gte(from))
+            .filter(heart_rate::Column::Time.lt(to))
+            .filter(heart_rate::Column::Activity.is_not_null())
+            .order_by_asc(heart_rate::Column::Time)
+            .into_tuple::<(NaiveDateTime, i16, Option<f64>, Option<f64>, Option<f64>)>()
+            .all(&self.db)
+            .await?;
+
+        // Rounded up, so the last bucket still reaches `to`
+        let width = (span - 1) / max_points as i64 + 1;
+        let mut buckets = Vec::<BucketSums>::new();
+        for (time, bpm, stress, spo2, skin_temp) in readings {
+            let index = (time - from).num_milliseconds() / width;
+            let bpm = u8::try_from(bpm).unwrap_or(u8::MAX);
+            if buckets.last().is_none_or(|bucket| bucket.index != index) {
+                buckets.push(BucketSums {
+                    index,
+                    readings: 0,
+                    bpm: 0.0,
+                    min_bpm: bpm,
+                    max_bpm: bpm,
+                    stress: Mean::default(),
+                    spo2: Mean::default(),
+                    skin_temp: Mean::default(),
+                });
+            }
+
+            let Some(bucket) = buckets.last_mut() else {
+                continue;
+            };
+            bucket.readings += 1;
+            bucket.bpm += f64::from(bpm);
+            bucket.min_bpm = bucket.min_bpm.min(bpm);
+            bucket.max_bpm = bucket.max_bpm.max(bpm);
+            bucket.stress.add(stress);
+            bucket.spo2.add(spo2);
+            bucket.skin_temp.add(skin_temp);
+        }
+
+        Ok(buckets
+            .into_iter()
+            .map(|bucket| HistoryBucket {
+                time: from + TimeDelta::milliseconds(bucket.index * width),
+                readings: bucket.readings,
+                avg_bpm: bucket.bpm / bucket.readings as f64,
+                min_bpm: bucket.min_bpm,
+                max_bpm: bucket.max_bpm,
+                stress: bucket.stress.value(),
+                spo2: bucket.spo2.value(),
+                skin_temp: bucket.skin_temp.value(),
+            })
+            .collect())
+    }
+
+    /// Fraction of the minutes of `date` in which the strap was worn.
+    ///
+    /// A minute counts when it has a reading with skin contact. Readings without sensor
+    /// data (older firmware) can't tell, so any of them counts as worn. Time on the
+    /// charger never does.
+    pub async fn on_wrist_fraction(&self, date: NaiveDate) -> Result<f64, DbError> {
+        let from = date.and_time(NaiveTime::MIN);
+        let to = from + TimeDelta::days(1);
+
+        let readings = heart_rate::Entity::find()
+            .select_only()
+            .column(heart_rate::Column::Time)
+            .column(heart_rate::Column::SensorData)
+            .filter(heart_rate::Column::Time.gte(from))
+            .filter(heart_rate::Column::Time.lt(to))
+            .filter(self.not_charging())
+            .into_tuple::<(NaiveDateTime, Option<serde_json::Value>)>()
+            .all(&self.db)
+            .await?;
+
+        let worn = readings
+            .into_iter()
+            .filter_map(|(time, sensor_data)| {
+                let sensor_data = sensor_data.and_then(|json| SensorData::from_value(json).ok());
+                let on_wrist = sensor_data.is_none_or(|data| data.skin_contact != 0);
+                on_wrist.then(|| (time - from).num_minutes())
+            })
+            .collect::<HashSet<_>>();
+
+        Ok(worn.len() as f64 / (to - from).num_minutes() as f64)
+    }
+
+    fn parse_row(mut model: heart_rate::Model) -> HistoryRow {
+        let (stress, stress_smoothed) = (model.stress, model.stress_smoothed);
+        let (spo2, skin_temp) = (model.spo2, model.skin_temp);
+        let low_confidence = model.low_confidence;
+        let sensor_data = model
+            .sensor_data
+            .take()
+            .and_then(|json| SensorData::from_value(json).ok());
+
+        let reading = Self::parse_reading(model);
+        HistoryRow {
+            time: reading.time,
+            bpm: reading.bpm,
+            rr: reading.rr,
+            activity: reading.activity,
+            stress,
+            stress_smoothed,
+            spo2,
+            skin_temp,
+            imu_data: reading.imu_data,
+            sensor_data,
+            low_confidence,
+        }
+    }
+
     fn parse_reading(model: heart_rate::Model) -> ParsedHistoryReading {
         ParsedHistoryReading {
             time: model.time,
@@ -50,13 +376,9 @@ impl DatabaseHandler {
                 .filter_map(|rr| rr.parse().ok())
                 .collect(),
             activity: model.activity.map(Activity::from).unwrap(),
-            imu_data: {
-                if let Some(data) = model.imu_data {
-                    serde_json::from_value(data).unwrap()
-                } else {
-                    Default::default()
-                }
-            },
+            imu_data: model
+                .imu_data
+                .and_then(|data| ImuSample::decode(&data).ok()),
         }
     }
 }
@@ -79,11 +401,13 @@ mod tests {
             rr_intervals: "833,850".to_string(),
             activity: Some(500_000_000),
             stress: Some(3.5),
+            stress_smoothed: None,
             spo2: None,
             skin_temp: None,
             imu_data: None,
             sensor_data: None,
             synced: false,
+            low_confidence: false,
         };
 
         let reading = DatabaseHandler::parse_reading(model);
@@ -107,11 +431,13 @@ mod tests {
             rr_intervals: "".to_string(),
             activity: Some(0),
             stress: None,
+            stress_smoothed: None,
             spo2: None,
             skin_temp: None,
             imu_data: None,
             sensor_data: None,
             synced: false,
+            low_confidence: false,
         };
 
         let reading = DatabaseHandler::parse_reading(model);
@@ -122,7 +448,6 @@ mod tests {
 
     #[test]
     fn parse_reading_with_imu_data() {
-        use openwhoop_codec::ImuSample;
         let time = chrono::NaiveDate::from_ymd_opt(2025, 1, 1)
             .unwrap()
             .and_hms_opt(12, 0, 0)
@@ -144,11 +469,13 @@ mod tests {
             rr_intervals: "800".to_string(),
             activity: Some(500_000_000),
             stress: None,
+            stress_smoothed: None,
             spo2: None,
             skin_temp: None,
-            imu_data: Some(serde_json::to_value(&imu_samples).unwrap()),
+            imu_data: Some(ImuSample::encode(&imu_samples)),
             sensor_data: None,
             synced: false,
+            low_confidence: false,
         };
 
         let reading = DatabaseHandler::parse_reading(model);
@@ -176,10 +503,7 @@ mod tests {
             db.create_reading(r).await.unwrap();
         }
 
-        let history = db
-            .search_history(SearchHistory::default())
-            .await
-            .unwrap();
+        let history = db.search_history(SearchHistory::default()).await.unwrap();
         assert_eq!(history.len(), 3);
 
         let history = db
@@ -192,4 +516,347 @@ mod tests {
             .unwrap();
         assert_eq!(history.len(), 2);
     }
+
+    #[tokio::test]
+    async fn stream_readings_matches_search() {
+        use futures::TryStreamExt;
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let first = 1735689600000;
+        let readings = (0..50)
+            .map(|i| openwhoop_codec::HistoryReading {
+                unix: first + i * 1000,
+                bpm: 60 + (i % 20) as u8,
+                rr: vec![900],
+                activity: 500_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let all = db
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        let range = || SearchHistory {
+            from: Some(all[9].time),
+            to: Some(all[40].time),
+            limit: Some(25),
+        };
+
+        let streamed: Vec<_> = db
+            .stream_readings(range())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let searched = db.search_history_rows(range()).await.unwrap();
+        assert_eq!(streamed, searched);
+        assert_eq!(streamed, all[10..35]);
+    }
+
+    #[tokio::test]
+    async fn downsampled_history_buckets_readings() {
+        use chrono::{Local, TimeZone};
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let from = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(8, 0, 0)
+            .unwrap();
+        let start = Local.from_local_datetime(&from).unwrap().timestamp_millis() as u64;
+
+        // 100 readings a second apart, heart rate cycling through 60..=69
+        let readings = (0..100)
+            .map(|i| openwhoop_codec::HistoryReading {
+                unix: start + i * 1000,
+                bpm: 60 + (i % 10) as u8,
+                rr: vec![1000],
+                activity: 500_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let to = from + TimeDelta::seconds(100);
+        for max_points in [1, 3, 7, 10, 150] {
+            let buckets = db.downsampled_history(from, to, max_points).await.unwrap();
+            assert!(
+                buckets.len() <= max_points,
+                "{max_points}: {}",
+                buckets.len()
+            );
+            let readings = buckets.iter().map(|b| b.readings).sum::<usize>();
+            assert_eq!(readings, 100);
+        }
+
+        let buckets = db.downsampled_history(from, to, 10).await.unwrap();
+        assert_eq!(buckets.len(), 10);
+        for (i, bucket) in buckets.iter().enumerate() {
+            assert_eq!(bucket.time, from + TimeDelta::seconds(10 * i as i64));
+            assert_eq!(bucket.readings, 10);
+            assert!((bucket.avg_bpm - 64.5).abs() < 1e-9);
+            assert_eq!((bucket.min_bpm, bucket.max_bpm), (60, 69));
+        }
+
+        assert!(db.downsampled_history(from, to, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn on_wrist_fraction_of_partial_day() {
+        use chrono::{Local, TimeZone};
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let start = Local
+            .from_local_datetime(&date.and_hms_opt(8, 0, 0).unwrap())
+            .unwrap()
+            .timestamp_millis() as u64;
+
+        let sensor = |skin_contact| SensorData {
+            ppg_green: 100,
+            ppg_red_ir: 200,
+            spo2_red: 3000,
+            spo2_ir: 4000,
+            skin_temp_raw: 850,
+            ambient_light: 50,
+            led_drive_1: 10,
+            led_drive_2: 20,
+            resp_rate_raw: 12,
+            signal_quality: 90,
+            skin_contact,
+            accel_gravity: [0.0, 0.0, 1.0],
+        };
+        // Worn from 08:00 to 14:00, then on the nightstand until 16:00, a reading every 30s
+        let readings = (0..960)
+            .map(|i| openwhoop_codec::HistoryReading {
+                unix: start + i * 30_000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 500_000_000,
+                imu_data: vec![],
+                sensor_data: Some(sensor(u8::from(i < 720))),
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let fraction = db.on_wrist_fraction(date).await.unwrap();
+        assert!((fraction - 0.25).abs() < 1e-9, "fraction: {fraction}");
+
+        // An hour of it on the charger
+        let noon = u32::try_from((start + 4 * 3_600_000) / 1000).unwrap();
+        db.start_charging(noon).await.unwrap();
+        db.stop_charging(noon + 3600).await.unwrap();
+        let fraction = db.on_wrist_fraction(date).await.unwrap();
+        assert!((fraction - 5.0 / 24.0).abs() < 1e-2, "fraction: {fraction}");
+
+        let next_day = date.succ_opt().unwrap();
+        assert_eq!(db.on_wrist_fraction(next_day).await.unwrap(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn history_row_includes_sensor_fields() {
+        use openwhoop_algos::{SkinTempScore, SpO2Score, StressScore};
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let sensor = SensorData {
+            ppg_green: 100,
+            ppg_red_ir: 200,
+            spo2_red: 3000,
+            spo2_ir: 4000,
+            skin_temp_raw: 500,
+            ambient_light: 50,
+            led_drive_1: 10,
+            led_drive_2: 20,
+            resp_rate_raw: 12,
+            signal_quality: 90,
+            skin_contact: 1,
+            accel_gravity: [0.0, 0.0, 1.0],
+        };
+        let imu = vec![ImuSample {
+            acc_x_g: 0.0,
+            acc_y_g: 0.0,
+            acc_z_g: 1.0,
+            gyr_x_dps: 0.0,
+            gyr_y_dps: 0.0,
+            gyr_z_dps: 0.0,
+        }];
+        db.create_reading(openwhoop_codec::HistoryReading {
+            unix: 1735689600000,
+            bpm: 72,
+            rr: vec![833, 840],
+            activity: 500_000_000,
+            imu_data: imu.clone(),
+            sensor_data: Some(sensor.clone()),
+        })
+        .await
+        .unwrap();
+
+        let time = db.search_history(SearchHistory::default()).await.unwrap()[0].time;
+        db.update_stress_on_reading(StressScore { time, score: 2.5 }, Some(2.25))
+            .await
+            .unwrap();
+        db.update_spo2_on_reading(SpO2Score {
+            time,
+            spo2_percentage: 97.5,
+        })
+        .await
+        .unwrap();
+        db.update_skin_temp_on_reading(SkinTempScore {
+            time,
+            temp_celsius: 33.25,
+        })
+        .await
+        .unwrap();
+
+        let rows = db
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![HistoryRow {
+                time,
+                bpm: 72,
+                rr: vec![833, 840],
+                activity: Activity::Active,
+                stress: Some(2.5),
+                stress_smoothed: Some(2.25),
+                spo2: Some(97.5),
+                skin_temp: Some(33.25),
+                imu_data: Some(imu),
+                sensor_data: Some(sensor),
+                low_confidence: false,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_gaps_reports_hole() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        // An hour of readings, a 2 hour hole, then another hour
+        let minutes = (0..60).chain(180..240);
+        let readings = minutes
+            .map(|m| openwhoop_codec::HistoryReading {
+                unix: 1735689600000 + m * 60_000,
+                bpm: 60,
+                rr: vec![1000],
+                activity: 1_200_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        db.create_readings(readings).await.unwrap();
+
+        let history = db.search_history(SearchHistory::default()).await.unwrap();
+        let from = history[0].time - TimeDelta::minutes(1);
+        let to = history[history.len() - 1].time;
+
+        let gaps = db
+            .find_gaps(from, to, TimeDelta::minutes(10))
+            .await
+            .unwrap();
+        assert_eq!(gaps, vec![(history[59].time, history[60].time)]);
+        assert_eq!(gaps[0].1 - gaps[0].0, TimeDelta::minutes(121));
+
+        // Time without readings before the first and after the last one
+        let (early, late) = (from - TimeDelta::minutes(30), to + TimeDelta::minutes(30));
+        let gaps = db
+            .find_gaps(early, late, TimeDelta::minutes(10))
+            .await
+            .unwrap();
+        assert_eq!(
+            gaps,
+            vec![
+                (early, history[0].time),
+                (history[59].time, history[60].time),
+                (to, late),
+            ]
+        );
+
+        let later = to + TimeDelta::hours(1);
+        let empty = db
+            .find_gaps(later, later + TimeDelta::hours(2), TimeDelta::minutes(10))
+            .await
+            .unwrap();
+        assert_eq!(empty.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn imu_data_roundtrips_through_db() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let imu_data = vec![
+            ImuSample {
+                acc_x_g: 0.5,
+                acc_y_g: -0.25,
+                acc_z_g: 1.0,
+                gyr_x_dps: -15.0,
+                gyr_y_dps: 2.0,
+                gyr_z_dps: 100.0,
+            };
+            100
+        ];
+
+        db.create_reading(openwhoop_codec::HistoryReading {
+            unix: 1735689600000,
+            bpm: 70,
+            rr: vec![850],
+            activity: 500_000_000,
+            imu_data: imu_data.clone(),
+            sensor_data: None,
+        })
+        .await
+        .unwrap();
+
+        // Stored as raw sensor counts, so only up to the sensors' resolution
+        let expected = ImuSample::decode(&ImuSample::encode(&imu_data)).unwrap();
+        let history = db.search_history(SearchHistory::default()).await.unwrap();
+        assert_eq!(history[0].imu_data, Some(expected));
+    }
+
+    #[tokio::test]
+    async fn storing_a_reading_again_replaces_its_imu_data() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let sample = ImuSample {
+            acc_x_g: 0.0,
+            acc_y_g: 0.0,
+            acc_z_g: 1.0,
+            gyr_x_dps: 0.0,
+            gyr_y_dps: 0.0,
+            gyr_z_dps: 0.0,
+        };
+        let reading = openwhoop_codec::HistoryReading {
+            unix: 1735689600000,
+            bpm: 70,
+            rr: vec![850],
+            activity: 500_000_000,
+            imu_data: vec![sample; 100],
+            sensor_data: None,
+        };
+        let imu_len = async || {
+            let history = db.search_history(SearchHistory::default()).await.unwrap();
+            history[0].imu_data.as_ref().map_or(0, Vec::len)
+        };
+
+        db.create_reading(reading.clone()).await.unwrap();
+        assert_eq!(imu_len().await, 100);
+
+        // Re-downloaded with `--imu-downsample 10`
+        let mut downsampled = reading.clone();
+        downsampled.downsample_imu(10);
+        db.create_readings(vec![downsampled]).await.unwrap();
+        assert_eq!(imu_len().await, 10);
+
+        // And with `--imu-downsample 0`
+        let mut dropped = reading;
+        dropped.downsample_imu(0);
+        db.create_reading(dropped).await.unwrap();
+        assert_eq!(imu_len().await, 0);
+    }
 }
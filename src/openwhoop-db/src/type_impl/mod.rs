@@ -1,2 +1,8 @@
 mod activities;
+pub(crate) mod charging;
+pub(crate) mod counts;
+pub(crate) mod devices;
+pub(crate) mod firmware;
 pub(crate) mod history;
+pub(crate) mod profile;
+mod recoveries;
@@ -0,0 +1,173 @@
+use std::fmt;
+
+use chrono::Local;
+use openwhoop_algos::{CalorieConfig, Sex};
+use openwhoop_entities::user_profile;
+use sea_orm::{EntityTrait, Set, sea_query::OnConflict};
+
+use crate::{DatabaseHandler, DbError};
+
+/// `user_profile` holds a single row
+const PROFILE_ID: i32 = 1;
+
+/// Used for strain zones when neither a max HR nor an age is known
+const DEFAULT_MAX_HR: u8 = 190;
+const DEFAULT_RESTING_HR: u8 = 60;
+
+/// User parameters consumed by calorie, zone and strain calculations
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UserProfile {
+    pub age: Option<u8>,
+    pub weight_kg: Option<f64>,
+    pub sex: Option<Sex>,
+    pub max_hr: Option<u8>,
+    pub resting_hr: Option<u8>,
+}
+
+impl UserProfile {
+    /// Stored max HR, or `220 - age` when only the age is known
+    pub fn max_hr(&self) -> u8 {
+        self.max_hr
+            .or_else(|| self.age.map(|age| 220_u8.saturating_sub(age)))
+            .unwrap_or(DEFAULT_MAX_HR)
+    }
+
+    pub fn resting_hr(&self) -> u8 {
+        self.resting_hr.unwrap_or(DEFAULT_RESTING_HR)
+    }
+
+    /// This profile with every parameter set in `overrides` replaced
+    pub fn with_overrides(self, overrides: UserProfile) -> Self {
+        Self {
+            age: overrides.age.or(self.age),
+            weight_kg: overrides.weight_kg.or(self.weight_kg),
+            sex: overrides.sex.or(self.sex),
+            max_hr: overrides.max_hr.or(self.max_hr),
+            resting_hr: overrides.resting_hr.or(self.resting_hr),
+        }
+    }
+
+    pub fn calorie_config(&self) -> CalorieConfig {
+        CalorieConfig {
+            age: self.age,
+            weight_kg: self.weight_kg,
+            sex: self.sex,
+        }
+    }
+
+    fn from_model(model: user_profile::Model) -> Self {
+        let sex = model.sex.and_then(|sex| match sex.as_str() {
+            "male" => Some(Sex::Male),
+            "female" => Some(Sex::Female),
+            _ => None,
+        });
+
+        Self {
+            age: model.age.and_then(|age| u8::try_from(age).ok()),
+            weight_kg: model.weight_kg,
+            sex,
+            max_hr: model.max_hr.and_then(|hr| u8::try_from(hr).ok()),
+            resting_hr: model.resting_hr.and_then(|hr| u8::try_from(hr).ok()),
+        }
+    }
+}
+
+impl fmt::Display for UserProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unset = || String::from("unset");
+        let weight = self.weight_kg.map(|w| format!("{} kg", w));
+        let sex = self.sex.map(|s| format!("{:?}", s));
+
+        writeln!(f, "Age: {}", self.age.map_or_else(unset, |a| a.to_string()))?;
+        writeln!(f, "Weight: {}", weight.unwrap_or_else(unset))?;
+        writeln!(f, "Sex: {}", sex.unwrap_or_else(unset))?;
+        writeln!(f, "Max HR: {}", self.max_hr())?;
+        write!(f, "Resting HR: {}", self.resting_hr())
+    }
+}
+
+impl DatabaseHandler {
+    /// Stored profile, with every parameter unset if none was stored yet.
+    pub async fn get_profile(&self) -> Result<UserProfile, DbError> {
+        let profile = user_profile::Entity::find_by_id(PROFILE_ID)
+            .one(&self.db)
+            .await?;
+
+        Ok(profile.map(UserProfile::from_model).unwrap_or_default())
+    }
+
+    pub async fn set_profile(&self, profile: UserProfile) -> Result<(), DbError> {
+        let sex = profile.sex.map(|sex| match sex {
+            Sex::Male => String::from("male"),
+            Sex::Female => String::from("female"),
+        });
+
+        let model = user_profile::ActiveModel {
+            id: Set(PROFILE_ID),
+            age: Set(profile.age.map(i16::from)),
+            weight_kg: Set(profile.weight_kg),
+            sex: Set(sex),
+            max_hr: Set(profile.max_hr.map(i16::from)),
+            resting_hr: Set(profile.resting_hr.map(i16::from)),
+            updated_at: Set(Local::now().naive_local()),
+        };
+
+        user_profile::Entity::insert(model)
+            .on_conflict(
+                OnConflict::column(user_profile::Column::Id)
+                    .update_columns([
+                        user_profile::Column::Age,
+                        user_profile::Column::WeightKg,
+                        user_profile::Column::Sex,
+                        user_profile::Column::MaxHr,
+                        user_profile::Column::RestingHr,
+                        user_profile::Column::UpdatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn profile_roundtrip() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+
+        let unset = db.get_profile().await.unwrap();
+        assert_eq!(unset, UserProfile::default());
+        assert_eq!(unset.max_hr(), 190);
+        assert_eq!(unset.resting_hr(), 60);
+        assert_eq!(unset.calorie_config().calories(&[]), None);
+
+        let profile = UserProfile {
+            age: Some(35),
+            weight_kg: Some(72.5),
+            sex: Some(Sex::Female),
+            max_hr: None,
+            resting_hr: Some(52),
+        };
+        db.set_profile(profile).await.unwrap();
+        let stored = db.get_profile().await.unwrap();
+        assert_eq!(stored, profile);
+        // Estimated from the age
+        assert_eq!(stored.max_hr(), 185);
+        assert_eq!(stored.resting_hr(), 52);
+
+        let overrides = UserProfile {
+            max_hr: Some(178),
+            ..Default::default()
+        };
+        let profile = stored.with_overrides(overrides);
+        db.set_profile(profile).await.unwrap();
+        let updated = db.get_profile().await.unwrap();
+        assert_eq!(updated.max_hr(), 178);
+        assert_eq!(updated.sex, Some(Sex::Female));
+    }
+}
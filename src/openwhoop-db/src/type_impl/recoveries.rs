@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+use openwhoop_entities::recoveries;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+
+use crate::{DatabaseHandler, DbError};
+
+impl DatabaseHandler {
+    /// Recovery score of the first cycle imported from the WHOOP app that started between
+    /// `from` and `to`. Cycles start when their night's sleep does, so the range of a night
+    /// finds the score the app gave it.
+    pub async fn imported_recovery(
+        &self,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Option<f64>, DbError> {
+        let score = recoveries::Entity::find()
+            .filter(recoveries::Column::CycleStart.between(from, to))
+            .filter(recoveries::Column::Score.is_not_null())
+            .order_by_asc(recoveries::Column::CycleStart)
+            .select_only()
+            .column(recoveries::Column::Score)
+            .into_tuple::<Option<f64>>()
+            .one(&self.db)
+            .await?;
+
+        Ok(score.flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeDelta};
+    use sea_orm::{ActiveModelTrait, ActiveValue::NotSet, Set};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn imported_recovery_of_a_night() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let onset = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(23, 10, 0)
+            .unwrap();
+
+        for (start, score) in [(onset, Some(71.0)), (onset + TimeDelta::days(1), None)] {
+            recoveries::ActiveModel {
+                id: NotSet,
+                cycle_start: Set(start),
+                cycle_end: Set(None),
+                score: Set(score),
+                resting_hr: Set(None),
+                hrv: Set(None),
+                skin_temp: Set(None),
+                spo2: Set(None),
+                day_strain: Set(None),
+            }
+            .insert(&db.db)
+            .await
+            .unwrap();
+        }
+
+        let night = |start: NaiveDateTime| db.imported_recovery(start, start + TimeDelta::hours(8));
+        assert_eq!(night(onset).await.unwrap(), Some(71.0));
+        // A cycle without a score, and a night without a cycle
+        assert_eq!(night(onset + TimeDelta::days(1)).await.unwrap(), None);
+        assert_eq!(night(onset - TimeDelta::days(1)).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,405 @@
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
+
+use chrono::NaiveDateTime;
+use openwhoop_algos::SleepCycle;
+use openwhoop_entities::{activities, recoveries, sleep_cycles};
+use openwhoop_types::activities::ActivityType;
+use sea_orm::{
+    ActiveValue::{NotSet, Set},
+    ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    sea_query::OnConflict,
+};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{DatabaseHandler, DbError};
+
+/// Files of the official export, as found in the unzipped archive
+const CYCLES_FILE: &str = "physiological_cycles.csv";
+const SLEEPS_FILE: &str = "sleeps.csv";
+const WORKOUTS_FILE: &str = "workouts.csv";
+
+/// Format of every timestamp in the export, in the cycle's local time
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Rows written by [`DatabaseHandler::import_whoop_export`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WhoopImport {
+    pub recoveries: usize,
+    pub sleeps: usize,
+    /// Workouts and naps
+    pub activities: usize,
+    /// Rows missing times, and activities without an earlier sleep to belong to
+    pub skipped: usize,
+}
+
+impl fmt::Display for WhoopImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "recoveries: {}, sleeps: {}, activities: {}, skipped: {}",
+            self.recoveries, self.sleeps, self.activities, self.skipped
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CycleRow {
+    #[serde(rename = "Cycle start time")]
+    cycle_start: String,
+    #[serde(rename = "Cycle end time")]
+    cycle_end: Option<String>,
+    #[serde(rename = "Recovery score %")]
+    recovery: Option<f64>,
+    #[serde(rename = "Resting heart rate (bpm)")]
+    resting_hr: Option<f64>,
+    #[serde(rename = "Heart rate variability (ms)")]
+    hrv: Option<f64>,
+    #[serde(rename = "Skin temp (celsius)")]
+    skin_temp: Option<f64>,
+    #[serde(rename = "Blood oxygen %")]
+    spo2: Option<f64>,
+    #[serde(rename = "Day Strain")]
+    day_strain: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SleepRow {
+    #[serde(rename = "Cycle start time")]
+    cycle_start: String,
+    #[serde(rename = "Sleep onset")]
+    onset: Option<String>,
+    #[serde(rename = "Wake onset")]
+    wake: Option<String>,
+    #[serde(rename = "Sleep performance %")]
+    performance: Option<f64>,
+    #[serde(rename = "Sleep efficiency %")]
+    efficiency: Option<f64>,
+    #[serde(rename = "Nap")]
+    nap: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkoutRow {
+    #[serde(rename = "Workout start time")]
+    start: Option<String>,
+    #[serde(rename = "Workout end time")]
+    end: Option<String>,
+    #[serde(rename = "Activity name")]
+    activity: String,
+}
+
+impl DatabaseHandler {
+    /// Loads sleeps, recoveries and workouts from the unzipped data export of the WHOOP app, so
+    /// history recorded before switching keeps showing up.
+    ///
+    /// The export has no overnight heart rate range, so sleeps keep the resting heart rate of
+    /// their cycle as `min_bpm` and its HRV as `avg_hrv`, and are marked `imported`. The other
+    /// heart rate and HRV columns are left at 0 and `v_sleep_detail` shows them as `NULL`.
+    /// Workout names, or numeric activity ids, map to [`ActivityType`] where they match,
+    /// everything else is stored as a generic [`ActivityType::Activity`]. Workouts are stored
+    /// as labeled by hand so detection doesn't change them. Files missing from `dir` are skipped, rows already present are updated.
+    pub async fn import_whoop_export(&self, dir: &Path) -> Result<WhoopImport, DbError> {
+        let mut import = WhoopImport::default();
+
+        // Resting heart rate and HRV of every cycle, by its start
+        let mut cycles = HashMap::new();
+        for row in read_csv::<CycleRow>(&dir.join(CYCLES_FILE))? {
+            let Ok(cycle_start) = parse_time(&row.cycle_start) else {
+                import.skipped += 1;
+                continue;
+            };
+            cycles.insert(cycle_start, (row.resting_hr, row.hrv));
+
+            let model = recoveries::ActiveModel {
+                id: NotSet,
+                cycle_start: Set(cycle_start),
+                cycle_end: Set(row.cycle_end.as_deref().and_then(|t| parse_time(t).ok())),
+                score: Set(row.recovery),
+                resting_hr: Set(row.resting_hr.map(|bpm| bpm.round() as i16)),
+                hrv: Set(row.hrv),
+                skin_temp: Set(row.skin_temp),
+                spo2: Set(row.spo2),
+                day_strain: Set(row.day_strain),
+            };
+            recoveries::Entity::insert(model)
+                .on_conflict(
+                    OnConflict::column(recoveries::Column::CycleStart)
+                        .update_columns([
+                            recoveries::Column::CycleEnd,
+                            recoveries::Column::Score,
+                            recoveries::Column::RestingHr,
+                            recoveries::Column::Hrv,
+                            recoveries::Column::SkinTemp,
+                            recoveries::Column::Spo2,
+                            recoveries::Column::DayStrain,
+                        ])
+                        .to_owned(),
+                )
+                .exec(&self.db)
+                .await?;
+            import.recoveries += 1;
+        }
+
+        // Nights first, naps and workouts are stored under the sleep before them
+        let mut naps = Vec::new();
+        for row in read_csv::<SleepRow>(&dir.join(SLEEPS_FILE))? {
+            let (Some(start), Some(end)) = (
+                row.onset.as_deref().and_then(|t| parse_time(t).ok()),
+                row.wake.as_deref().and_then(|t| parse_time(t).ok()),
+            ) else {
+                import.skipped += 1;
+                continue;
+            };
+
+            if row.nap.unwrap_or(false) {
+                naps.push((start, end, ActivityType::Nap));
+                continue;
+            }
+
+            let (resting_hr, hrv) = parse_time(&row.cycle_start)
+                .ok()
+                .and_then(|cycle_start| cycles.get(&cycle_start).copied())
+                .unwrap_or_default();
+
+            self.upsert_sleep(
+                SleepCycle {
+                    id: end.date(),
+                    start,
+                    end,
+                    min_bpm: resting_hr.map_or(0, |bpm| bpm.round() as u8),
+                    max_bpm: 0,
+                    avg_bpm: 0,
+                    min_hrv: 0,
+                    max_hrv: 0,
+                    avg_hrv: hrv.map_or(0, |hrv| hrv.round() as u16),
+                    sdnn: None,
+                    pnn50: None,
+                    deep_hrv: None,
+                    score: row
+                        .performance
+                        .unwrap_or_else(|| SleepCycle::sleep_score(start, end)),
+                    efficiency: row.efficiency,
+                },
+                true,
+            )
+            .await?;
+            import.sleeps += 1;
+        }
+
+        for row in read_csv::<WorkoutRow>(&dir.join(WORKOUTS_FILE))? {
+            let (Some(start), Some(end)) = (
+                row.start.as_deref().and_then(|t| parse_time(t).ok()),
+                row.end.as_deref().and_then(|t| parse_time(t).ok()),
+            ) else {
+                import.skipped += 1;
+                continue;
+            };
+            naps.push((start, end, parse_activity(&row.activity)));
+        }
+
+        for (start, end, activity) in naps {
+            if self.import_activity(start, end, activity).await? {
+                import.activities += 1;
+            } else {
+                import.skipped += 1;
+            }
+        }
+
+        Ok(import)
+    }
+
+    /// Stores an imported activity under the last sleep that ended before it, `false` if there
+    /// is no such sleep.
+    async fn import_activity(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        activity: ActivityType,
+    ) -> Result<bool, DbError> {
+        let Some(sleep) = sleep_cycles::Entity::find()
+            .filter(sleep_cycles::Column::End.lte(start))
+            .order_by_desc(sleep_cycles::Column::End)
+            .one(&self.db)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let model = activities::ActiveModel {
+            id: NotSet,
+            period_id: Set(sleep.sleep_id),
+            start: Set(start),
+            end: Set(end),
+            activity: Set(activity.to_string()),
+            synced: NotSet,
+            confidence: Set(None),
+            manual: Set(true),
+        };
+        activities::Entity::insert(model)
+            .on_conflict(
+                OnConflict::column(activities::Column::Start)
+                    .update_columns([
+                        activities::Column::PeriodId,
+                        activities::Column::End,
+                        activities::Column::Activity,
+                        activities::Column::Confidence,
+                        activities::Column::Manual,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&self.db)
+            .await?;
+
+        Ok(true)
+    }
+}
+
+/// Rows of an export file, none if the export doesn't include it
+fn read_csv<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, DbError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    csv::Reader::from_path(path)?
+        .deserialize()
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|e| {
+            DbError::Invalid(format!(
+                "Invalid WHOOP export file {}: {}",
+                path.display(),
+                e
+            ))
+        })
+}
+
+fn parse_activity(activity: &str) -> ActivityType {
+    let activity = activity.trim();
+    ActivityType::from_str(activity)
+        .ok()
+        .or_else(|| activity.parse().ok().and_then(ActivityType::from_repr))
+        .unwrap_or(ActivityType::Activity)
+}
+
+fn parse_time(time: &str) -> chrono::ParseResult<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time.trim(), TIME_FORMAT)
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ConnectionTrait, DbBackend, PaginatorTrait, Statement};
+    use uuid::Uuid;
+
+    use super::*;
+
+    const CYCLES: &str = "\
+Cycle start time,Cycle end time,Cycle timezone,Recovery score %,Resting heart rate (bpm),Heart rate variability (ms),Skin temp (celsius),Blood oxygen %,Day Strain,Energy burned (cal)
+2025-01-01 23:10:00,2025-01-02 22:45:00,UTC+01:00,71,52,64,33.6,96.5,12.4,2310
+2025-01-02 22:45:00,,UTC+01:00,,,,,,3.1,540
+";
+
+    const SLEEPS: &str = "\
+Cycle start time,Cycle end time,Cycle timezone,Sleep onset,Wake onset,Sleep performance %,Asleep duration (min),Sleep efficiency %,Nap
+2025-01-01 23:10:00,2025-01-02 22:45:00,UTC+01:00,2025-01-01 23:10:00,2025-01-02 07:05:00,88,445,94,false
+2025-01-01 23:10:00,2025-01-02 22:45:00,UTC+01:00,2025-01-02 14:00:00,2025-01-02 14:30:00,,28,93,true
+";
+
+    const WORKOUTS: &str = "\
+Cycle start time,Cycle end time,Cycle timezone,Workout start time,Workout end time,Duration (min),Activity name,Activity Strain
+2025-01-01 23:10:00,2025-01-02 22:45:00,UTC+01:00,2025-01-02 18:00:00,2025-01-02 18:45:00,45,Running,11.2
+2025-01-01 23:10:00,2025-01-02 22:45:00,UTC+01:00,2025-01-02 19:30:00,2025-01-02 20:00:00,30,\"Underwater Basket Weaving\",4.0
+2024-12-31 23:00:00,2025-01-01 23:10:00,UTC+01:00,2025-01-01 12:00:00,2025-01-01 13:00:00,60,Cycling,9.0
+";
+
+    fn sample_export() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("openwhoop-whoop-export-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(CYCLES_FILE), CYCLES).unwrap();
+        std::fs::write(dir.join(SLEEPS_FILE), SLEEPS).unwrap();
+        std::fs::write(dir.join(WORKOUTS_FILE), WORKOUTS).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn imports_sample_export() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let dir = sample_export();
+        let import = db.import_whoop_export(&dir).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The cycling workout predates every sleep
+        assert_eq!(
+            import.unwrap(),
+            WhoopImport {
+                recoveries: 2,
+                sleeps: 1,
+                activities: 3,
+                skipped: 1,
+            }
+        );
+
+        assert_eq!(recoveries::Entity::find().count(&db.db).await.unwrap(), 2);
+        assert_eq!(activities::Entity::find().count(&db.db).await.unwrap(), 3);
+
+        let sleeps = db.get_sleep_cycles(None, None).await.unwrap();
+        assert_eq!(sleeps.len(), 1);
+        assert_eq!(sleeps[0].min_bpm, 52);
+        assert_eq!(sleeps[0].avg_hrv, 64);
+        assert_eq!(sleeps[0].score, 88.0);
+
+        // Only the resting heart rate and HRV come from the export
+        assert_eq!((sleeps[0].max_bpm, sleeps[0].avg_bpm), (0, 0));
+        assert_eq!((sleeps[0].min_hrv, sleeps[0].max_hrv), (0, 0));
+        let sleep = sleep_cycles::Entity::find().one(&db.db).await.unwrap();
+        assert!(sleep.unwrap().imported);
+
+        // Grafana doesn't chart the missing range as zeros
+        let detail = db
+            .db
+            .query_one(Statement::from_string(
+                DbBackend::Sqlite,
+                "SELECT min_bpm, max_bpm, avg_bpm, min_hrv, max_hrv, avg_hrv FROM v_sleep_detail",
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(detail.try_get::<i32>("", "min_bpm").unwrap(), 52);
+        assert_eq!(detail.try_get::<i32>("", "avg_hrv").unwrap(), 64);
+        for column in ["max_bpm", "avg_bpm", "min_hrv", "max_hrv"] {
+            assert_eq!(detail.try_get::<Option<i32>>("", column).unwrap(), None);
+        }
+
+        let labels = activities::Entity::find()
+            .order_by_asc(activities::Column::Start)
+            .all(&db.db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.activity, a.manual))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                ("Nap".to_owned(), true),
+                ("Running".to_owned(), true),
+                ("Activity".to_owned(), true),
+            ]
+        );
+
+        // Importing again updates instead of duplicating
+        let dir = sample_export();
+        db.import_whoop_export(&dir).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(recoveries::Entity::find().count(&db.db).await.unwrap(), 2);
+        assert_eq!(activities::Entity::find().count(&db.db).await.unwrap(), 3);
+    }
+
+    #[test]
+    fn activities_parse_by_name_or_id() {
+        assert!(matches!(parse_activity("Running"), ActivityType::Running));
+        assert!(matches!(parse_activity(" 1 "), ActivityType::Cycling));
+        assert!(matches!(
+            parse_activity("Underwater Basket Weaving"),
+            ActivityType::Activity
+        ));
+    }
+}
@@ -2,7 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "activities")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -13,6 +13,9 @@ pub struct Model {
     pub end: DateTime,
     pub activity: String,
     pub synced: bool,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub confidence: Option<f64>,
+    pub manual: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
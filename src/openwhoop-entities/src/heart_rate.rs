@@ -16,12 +16,16 @@ pub struct Model {
     #[sea_orm(column_type = "Double", nullable)]
     pub stress: Option<f64>,
     #[sea_orm(column_type = "Double", nullable)]
+    pub stress_smoothed: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
     pub spo2: Option<f64>,
     #[sea_orm(column_type = "Double", nullable)]
     pub skin_temp: Option<f64>,
-    pub imu_data: Option<Json>,
+    #[sea_orm(column_type = "Blob", nullable)]
+    pub imu_data: Option<Vec<u8>>,
     pub sensor_data: Option<Json>,
     pub synced: bool,
+    pub low_confidence: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
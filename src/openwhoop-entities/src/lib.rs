@@ -3,6 +3,17 @@
 pub mod prelude;
 
 pub mod activities;
+pub mod charging_periods;
+pub mod console_logs;
+pub mod devices;
+pub mod firmware_history;
 pub mod heart_rate;
+pub mod imu_stream;
+pub mod markers;
 pub mod packets;
+pub mod quarantined_readings;
+pub mod recoveries;
 pub mod sleep_cycles;
+pub mod sync_state;
+pub mod unknown_packets;
+pub mod user_profile;
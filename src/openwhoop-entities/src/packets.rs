@@ -10,6 +10,7 @@ pub struct Model {
     pub uuid: Uuid,
     #[sea_orm(column_type = "Binary(1)")]
     pub bytes: Vec<u8>,
+    pub hash: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,6 +1,17 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
 pub use super::activities::Entity as Activities;
+pub use super::charging_periods::Entity as ChargingPeriods;
+pub use super::console_logs::Entity as ConsoleLogs;
+pub use super::devices::Entity as Devices;
+pub use super::firmware_history::Entity as FirmwareHistory;
 pub use super::heart_rate::Entity as HeartRate;
+pub use super::imu_stream::Entity as ImuStream;
+pub use super::markers::Entity as Markers;
 pub use super::packets::Entity as Packets;
+pub use super::quarantined_readings::Entity as QuarantinedReadings;
+pub use super::recoveries::Entity as Recoveries;
 pub use super::sleep_cycles::Entity as SleepCycles;
+pub use super::sync_state::Entity as SyncState;
+pub use super::unknown_packets::Entity as UnknownPackets;
+pub use super::user_profile::Entity as UserProfile;
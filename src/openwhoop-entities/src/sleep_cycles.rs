@@ -20,6 +20,14 @@ pub struct Model {
     #[sea_orm(column_type = "Double", nullable)]
     pub score: Option<f64>,
     pub synced: bool,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub efficiency: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub sdnn: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub pnn50: Option<f64>,
+    pub deep_hrv: Option<i32>,
+    pub imported: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -12,6 +12,35 @@ mod m20250601_000000_add_synced;
 mod m20250602_000000_sensor_data;
 mod m20250602_000001_spo2;
 mod m20250603_000000_skin_temp;
+mod m20250604_000000_packet_hash;
+mod m20250605_000000_imu_data_blob;
+mod m20250606_000000_time_indexes;
+mod m20250607_000000_sync_state;
+mod m20250608_000000_sleep_efficiency;
+mod m20250609_000000_grafana_views;
+mod m20250610_000000_console_logs;
+mod m20250611_000000_quarantined_readings;
+mod m20250612_000000_user_profile;
+mod m20250613_000000_unknown_packets;
+mod m20250614_000000_sleep_sdnn;
+mod m20250615_000000_sleep_pnn50;
+mod m20250616_000000_activity_confidence;
+mod m20250617_000000_activity_manual;
+mod m20250618_000000_recoveries;
+mod m20250619_000000_markers;
+mod m20250620_000000_devices;
+mod m20250621_000000_stress_smoothed;
+mod m20250622_000000_charging_periods;
+mod m20250623_000000_low_confidence;
+mod m20250624_000000_firmware_history;
+mod m20250625_000000_sleep_deep_hrv;
+mod m20250626_000000_packet_hash_backfill;
+mod m20250627_000000_sync_state_device;
+mod m20250628_000000_unknown_packets_hash;
+mod m20250629_000000_quarantined_readings_full;
+mod m20250630_000000_imu_stream;
+mod m20250701_000000_firmware_history_device;
+mod m20250702_000000_sleep_cycles_imported;
 
 pub struct Migrator;
 
@@ -31,6 +60,35 @@ impl MigratorTrait for Migrator {
             Box::new(m20250602_000000_sensor_data::Migration),
             Box::new(m20250602_000001_spo2::Migration),
             Box::new(m20250603_000000_skin_temp::Migration),
+            Box::new(m20250604_000000_packet_hash::Migration),
+            Box::new(m20250605_000000_imu_data_blob::Migration),
+            Box::new(m20250606_000000_time_indexes::Migration),
+            Box::new(m20250607_000000_sync_state::Migration),
+            Box::new(m20250608_000000_sleep_efficiency::Migration),
+            Box::new(m20250609_000000_grafana_views::Migration),
+            Box::new(m20250610_000000_console_logs::Migration),
+            Box::new(m20250611_000000_quarantined_readings::Migration),
+            Box::new(m20250612_000000_user_profile::Migration),
+            Box::new(m20250613_000000_unknown_packets::Migration),
+            Box::new(m20250614_000000_sleep_sdnn::Migration),
+            Box::new(m20250615_000000_sleep_pnn50::Migration),
+            Box::new(m20250616_000000_activity_confidence::Migration),
+            Box::new(m20250617_000000_activity_manual::Migration),
+            Box::new(m20250618_000000_recoveries::Migration),
+            Box::new(m20250619_000000_markers::Migration),
+            Box::new(m20250620_000000_devices::Migration),
+            Box::new(m20250621_000000_stress_smoothed::Migration),
+            Box::new(m20250622_000000_charging_periods::Migration),
+            Box::new(m20250623_000000_low_confidence::Migration),
+            Box::new(m20250624_000000_firmware_history::Migration),
+            Box::new(m20250625_000000_sleep_deep_hrv::Migration),
+            Box::new(m20250626_000000_packet_hash_backfill::Migration),
+            Box::new(m20250627_000000_sync_state_device::Migration),
+            Box::new(m20250628_000000_unknown_packets_hash::Migration),
+            Box::new(m20250629_000000_quarantined_readings_full::Migration),
+            Box::new(m20250630_000000_imu_stream::Migration),
+            Box::new(m20250701_000000_firmware_history_device::Migration),
+            Box::new(m20250702_000000_sleep_cycles_imported::Migration),
         ]
     }
 }
@@ -0,0 +1,219 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{QueryResult, TransactionTrait},
+};
+use serde::{Deserialize, Serialize};
+
+/// Rows converted per query so large databases are never loaded at once
+const BATCH_SIZE: u64 = 500;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // A failed conversion leaves the JSON column as it was
+        let txn = manager.get_connection().begin().await?;
+        let manager = SchemaManager::new(&txn);
+
+        add_column(
+            &manager,
+            ColumnDef::new(HeartRate::ImuDataConverted).blob().null(),
+        )
+        .await?;
+
+        convert_rows(&manager, |row| {
+            let json = row.try_get::<serde_json::Value>("", &HeartRate::ImuData.to_string())?;
+            let samples = serde_json::from_value::<Vec<JsonImuSample>>(json)
+                .map_err(|e| DbErr::Custom(e.to_string()))?;
+            Ok(encode(&samples).into())
+        })
+        .await?;
+
+        replace_column(&manager).await?;
+        txn.commit().await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let txn = manager.get_connection().begin().await?;
+        let manager = SchemaManager::new(&txn);
+
+        add_column(
+            &manager,
+            ColumnDef::new(HeartRate::ImuDataConverted).json().null(),
+        )
+        .await?;
+
+        convert_rows(&manager, |row| {
+            let bytes = row.try_get::<Vec<u8>>("", &HeartRate::ImuData.to_string())?;
+            let samples = decode(&bytes)?;
+            let json = serde_json::to_value(samples).map_err(|e| DbErr::Custom(e.to_string()))?;
+            Ok(json.into())
+        })
+        .await?;
+
+        replace_column(&manager).await?;
+        txn.commit().await
+    }
+}
+
+/// An IMU sample as the JSON column stored it. This and the encoding below are frozen
+/// copies of `openwhoop_codec::ImuSample`, so later changes to the codec can't change
+/// what this migration does.
+#[derive(Serialize, Deserialize)]
+struct JsonImuSample {
+    acc_x_g: f32,
+    acc_y_g: f32,
+    acc_z_g: f32,
+    gyr_x_dps: f32,
+    gyr_y_dps: f32,
+    gyr_z_dps: f32,
+}
+
+/// Raw accelerometer counts per g
+const ACC_SENS: f32 = 1875.0;
+/// Raw gyroscope counts per degree/second
+const GYR_SENS: f32 = 15.0;
+/// Six little-endian i16 axes per encoded sample
+const ENCODED_SIZE: usize = 12;
+
+/// Encodes samples as raw sensor counts, 6 little-endian `i16` per sample in the order
+/// acc x/y/z, gyr x/y/z.
+fn encode(samples: &[JsonImuSample]) -> Vec<u8> {
+    let to_raw = |value: f32, sens: f32| {
+        (value * sens)
+            .round()
+            .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+    };
+
+    let mut bytes = Vec::with_capacity(samples.len() * ENCODED_SIZE);
+    for sample in samples {
+        for raw in [
+            to_raw(sample.acc_x_g, ACC_SENS),
+            to_raw(sample.acc_y_g, ACC_SENS),
+            to_raw(sample.acc_z_g, ACC_SENS),
+            to_raw(sample.gyr_x_dps, GYR_SENS),
+            to_raw(sample.gyr_y_dps, GYR_SENS),
+            to_raw(sample.gyr_z_dps, GYR_SENS),
+        ] {
+            bytes.extend_from_slice(&raw.to_le_bytes());
+        }
+    }
+
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<JsonImuSample>, DbErr> {
+    if bytes.len() % ENCODED_SIZE != 0 {
+        return Err(DbErr::Custom(format!(
+            "IMU data of {} bytes isn't whole samples",
+            bytes.len()
+        )));
+    }
+
+    let samples = bytes
+        .chunks_exact(ENCODED_SIZE)
+        .map(|chunk| {
+            let axis = |i: usize, sens: f32| {
+                f32::from(i16::from_le_bytes([chunk[i * 2], chunk[i * 2 + 1]])) / sens
+            };
+
+            JsonImuSample {
+                acc_x_g: axis(0, ACC_SENS),
+                acc_y_g: axis(1, ACC_SENS),
+                acc_z_g: axis(2, ACC_SENS),
+                gyr_x_dps: axis(3, GYR_SENS),
+                gyr_y_dps: axis(4, GYR_SENS),
+                gyr_z_dps: axis(5, GYR_SENS),
+            }
+        })
+        .collect();
+
+    Ok(samples)
+}
+
+async fn add_column(manager: &SchemaManager<'_>, column: &mut ColumnDef) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(HeartRate::Table)
+                .add_column(column)
+                .to_owned(),
+        )
+        .await
+}
+
+/// Fills `imu_data_converted` from `imu_data` for every row that has IMU data, a batch of
+/// rows per update.
+async fn convert_rows<F>(manager: &SchemaManager<'_>, convert: F) -> Result<(), DbErr>
+where
+    F: Fn(&QueryResult) -> Result<Value, DbErr>,
+{
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+    let mut last_id = 0;
+
+    loop {
+        let select = Query::select()
+            .columns([HeartRate::Id, HeartRate::ImuData])
+            .from(HeartRate::Table)
+            .and_where(Expr::col(HeartRate::ImuData).is_not_null())
+            .and_where(Expr::col(HeartRate::Id).gt(last_id))
+            .order_by(HeartRate::Id, Order::Asc)
+            .limit(BATCH_SIZE)
+            .to_owned();
+
+        let rows = db.query_all(backend.build(&select)).await?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let mut ids = Vec::with_capacity(rows.len());
+        let mut converted = CaseStatement::new();
+        for row in rows {
+            let id = row.try_get::<i32>("", &HeartRate::Id.to_string())?;
+            converted = converted.case(Expr::col(HeartRate::Id).eq(id), convert(&row)?);
+            ids.push(id);
+        }
+        last_id = ids.last().copied().unwrap_or(last_id);
+
+        let update = Query::update()
+            .table(HeartRate::Table)
+            .value(HeartRate::ImuDataConverted, converted)
+            .and_where(Expr::col(HeartRate::Id).is_in(ids))
+            .to_owned();
+        db.execute(backend.build(&update)).await?;
+    }
+
+    Ok(())
+}
+
+/// Drops the old `imu_data` column and moves the converted column into its place.
+async fn replace_column(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    manager
+        .alter_table(
+            Table::alter()
+                .table(HeartRate::Table)
+                .drop_column(HeartRate::ImuData)
+                .to_owned(),
+        )
+        .await?;
+
+    manager
+        .alter_table(
+            Table::alter()
+                .table(HeartRate::Table)
+                .rename_column(HeartRate::ImuDataConverted, HeartRate::ImuData)
+                .to_owned(),
+        )
+        .await
+}
+
+#[derive(Iden)]
+enum HeartRate {
+    Table,
+    Id,
+    ImuData,
+    ImuDataConverted,
+}
@@ -0,0 +1,35 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20250127_195808_sleep_cycles::SleepCycles;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // `heart_rate.time` and `activities.start` are unique, their automatic indexes
+        // already serve range queries
+        manager
+            .create_index(
+                Index::create()
+                    .name("sleep_cycles-end-index")
+                    .table(SleepCycles::Table)
+                    .col(SleepCycles::End)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("sleep_cycles-end-index")
+                    .table(SleepCycles::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,136 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Readings with the local day and hour they fall on and the sleep/activity containing them,
+/// one row per reading. Where periods overlap, e.g. a nap within a longer activity, the one
+/// that started last is taken.
+///
+/// Times are stored as local wall clock time, so the day is just the date part.
+const DAILY_READINGS_SQLITE: &str = r#"
+CREATE VIEW IF NOT EXISTS v_daily_readings AS
+SELECT
+    hr.id,
+    hr.time,
+    date(hr.time) AS day,
+    CAST(strftime('%H', hr.time) AS INTEGER) AS hour,
+    hr.bpm,
+    hr.stress,
+    hr.spo2,
+    hr.skin_temp,
+    (
+        SELECT sc.sleep_id FROM sleep_cycles sc
+        WHERE hr.time BETWEEN sc.start AND sc."end"
+        ORDER BY sc.start DESC
+        LIMIT 1
+    ) AS sleep_id,
+    (
+        SELECT a.activity FROM activities a
+        WHERE hr.time BETWEEN a.start AND a."end"
+        ORDER BY a.start DESC
+        LIMIT 1
+    ) AS activity
+FROM heart_rate hr
+"#;
+
+const DAILY_READINGS_POSTGRES: &str = r#"
+CREATE OR REPLACE VIEW v_daily_readings AS
+SELECT
+    hr.id,
+    hr.time,
+    hr.time::date AS day,
+    CAST(EXTRACT(HOUR FROM hr.time) AS INTEGER) AS hour,
+    hr.bpm,
+    hr.stress,
+    hr.spo2,
+    hr.skin_temp,
+    (
+        SELECT sc.sleep_id FROM sleep_cycles sc
+        WHERE hr.time BETWEEN sc.start AND sc."end"
+        ORDER BY sc.start DESC
+        LIMIT 1
+    ) AS sleep_id,
+    (
+        SELECT a.activity FROM activities a
+        WHERE hr.time BETWEEN a.start AND a."end"
+        ORDER BY a.start DESC
+        LIMIT 1
+    ) AS activity
+FROM heart_rate hr
+"#;
+
+/// One row per night, keyed by the day the sleep ended, with the night's SpO2 and skin
+/// temperature averaged from the readings.
+pub(crate) const SLEEP_DETAIL_SQLITE: &str = r#"
+CREATE VIEW IF NOT EXISTS v_sleep_detail AS
+SELECT
+    sc.sleep_id AS day,
+    sc.start,
+    sc."end",
+    CAST(ROUND((julianday(sc."end") - julianday(sc.start)) * 1440) AS INTEGER) AS duration_minutes,
+    sc.min_bpm,
+    sc.max_bpm,
+    sc.avg_bpm,
+    sc.min_hrv,
+    sc.max_hrv,
+    sc.avg_hrv,
+    sc.score,
+    sc.efficiency,
+    AVG(hr.spo2) AS avg_spo2,
+    AVG(hr.skin_temp) AS avg_skin_temp
+FROM sleep_cycles sc
+LEFT JOIN heart_rate hr ON hr.time BETWEEN sc.start AND sc."end"
+GROUP BY sc.id
+"#;
+
+pub(crate) const SLEEP_DETAIL_POSTGRES: &str = r#"
+CREATE OR REPLACE VIEW v_sleep_detail AS
+SELECT
+    sc.sleep_id AS day,
+    sc.start,
+    sc."end",
+    CAST(ROUND(EXTRACT(EPOCH FROM sc."end" - sc.start) / 60) AS INTEGER) AS duration_minutes,
+    sc.min_bpm,
+    sc.max_bpm,
+    sc.avg_bpm,
+    sc.min_hrv,
+    sc.max_hrv,
+    sc.avg_hrv,
+    sc.score,
+    sc.efficiency,
+    AVG(hr.spo2) AS avg_spo2,
+    AVG(hr.skin_temp) AS avg_skin_temp
+FROM sleep_cycles sc
+LEFT JOIN heart_rate hr ON hr.time BETWEEN sc.start AND sc."end"
+GROUP BY sc.id
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let (daily_readings, sleep_detail) = match manager.get_database_backend() {
+            DbBackend::Sqlite => (DAILY_READINGS_SQLITE, SLEEP_DETAIL_SQLITE),
+            DbBackend::Postgres => (DAILY_READINGS_POSTGRES, SLEEP_DETAIL_POSTGRES),
+            DbBackend::MySql => {
+                return Err(DbErr::Migration(
+                    "Grafana views are only available for SQLite and Postgres".to_string(),
+                ));
+            }
+        };
+
+        let db = manager.get_connection();
+        db.execute_unprepared(daily_readings).await?;
+        db.execute_unprepared(sleep_detail).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared("DROP VIEW IF EXISTS v_sleep_detail")
+            .await?;
+        db.execute_unprepared("DROP VIEW IF EXISTS v_daily_readings")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ConsoleLogs::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ConsoleLogs::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ConsoleLogs::Time).date_time().not_null())
+                    .col(ColumnDef::new(ConsoleLogs::Log).text().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("console_logs-time-log-index")
+                    .table(ConsoleLogs::Table)
+                    .col(ConsoleLogs::Time)
+                    .col(ConsoleLogs::Log)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ConsoleLogs::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ConsoleLogs {
+    Table,
+    Id,
+    Time,
+    Log,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(QuarantinedReadings::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::Unix)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::Bpm)
+                            .small_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::RrIntervals)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::Activity)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::Reason)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(QuarantinedReadings::ReceivedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(QuarantinedReadings::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum QuarantinedReadings {
+    Table,
+    Id,
+    Unix,
+    Bpm,
+    RrIntervals,
+    Activity,
+    Reason,
+    ReceivedAt,
+}
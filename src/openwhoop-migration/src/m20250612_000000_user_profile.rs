@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserProfile::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserProfile::Id)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserProfile::Age).small_integer().null())
+                    .col(ColumnDef::new(UserProfile::WeightKg).double().null())
+                    .col(ColumnDef::new(UserProfile::Sex).text().null())
+                    .col(ColumnDef::new(UserProfile::MaxHr).small_integer().null())
+                    .col(
+                        ColumnDef::new(UserProfile::RestingHr)
+                            .small_integer()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserProfile::UpdatedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserProfile::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserProfile {
+    Table,
+    Id,
+    Age,
+    WeightKg,
+    Sex,
+    MaxHr,
+    RestingHr,
+    UpdatedAt,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UnknownPackets::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UnknownPackets::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UnknownPackets::Uuid).uuid().not_null())
+                    .col(ColumnDef::new(UnknownPackets::Bytes).binary().not_null())
+                    .col(ColumnDef::new(UnknownPackets::Error).text().not_null())
+                    .col(
+                        ColumnDef::new(UnknownPackets::ReceivedAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UnknownPackets::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UnknownPackets {
+    Table,
+    Id,
+    Uuid,
+    Bytes,
+    Error,
+    ReceivedAt,
+}
@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Recoveries::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Recoveries::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Recoveries::CycleStart)
+                            .date_time()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Recoveries::CycleEnd).date_time().null())
+                    .col(ColumnDef::new(Recoveries::Score).double().null())
+                    .col(ColumnDef::new(Recoveries::RestingHr).small_integer().null())
+                    .col(ColumnDef::new(Recoveries::Hrv).double().null())
+                    .col(ColumnDef::new(Recoveries::SkinTemp).double().null())
+                    .col(ColumnDef::new(Recoveries::Spo2).double().null())
+                    .col(ColumnDef::new(Recoveries::DayStrain).double().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Recoveries::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Recoveries {
+    Table,
+    Id,
+    CycleStart,
+    CycleEnd,
+    Score,
+    RestingHr,
+    Hrv,
+    SkinTemp,
+    Spo2,
+    DayStrain,
+}
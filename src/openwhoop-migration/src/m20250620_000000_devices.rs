@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Devices::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Devices::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Devices::Nickname)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Devices::Address).string().not_null())
+                    .col(ColumnDef::new(Devices::Name).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Devices::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Devices {
+    Table,
+    Id,
+    Nickname,
+    Address,
+    Name,
+}
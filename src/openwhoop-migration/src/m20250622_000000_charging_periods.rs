@@ -0,0 +1,46 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChargingPeriods::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChargingPeriods::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ChargingPeriods::Start)
+                            .date_time()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(ChargingPeriods::End).date_time().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChargingPeriods::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ChargingPeriods {
+    Table,
+    Id,
+    Start,
+    End,
+}
@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FirmwareHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FirmwareHistory::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(FirmwareHistory::Harvard).string().not_null())
+                    .col(
+                        ColumnDef::new(FirmwareHistory::Boylston)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(FirmwareHistory::SeenAt)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FirmwareHistory::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum FirmwareHistory {
+    Table,
+    Id,
+    Harvard,
+    Boylston,
+    SeenAt,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{QueryResult, TransactionTrait, prelude::Uuid},
+};
+use sha2::{Digest, Sha256};
+
+/// Rows hashed per query so large databases are never loaded at once
+const BATCH_SIZE: u64 = 500;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Hashes packets stored before `packets.hash` existed, so frames downloaded again are
+    /// recognized as duplicates of them. Packets that already were duplicates keep a `NULL`
+    /// hash, only the first copy takes it.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        let txn = db.begin().await?;
+        let mut last_id = 0;
+
+        loop {
+            let select = Query::select()
+                .columns([Packets::Id, Packets::Uuid, Packets::Bytes])
+                .from(Packets::Table)
+                .and_where(Expr::col(Packets::Hash).is_null())
+                .and_where(Expr::col(Packets::Id).gt(last_id))
+                .order_by(Packets::Id, Order::Asc)
+                .limit(BATCH_SIZE)
+                .to_owned();
+
+            let rows = txn.query_all(backend.build(&select)).await?;
+            let Some(last) = rows.last() else {
+                break;
+            };
+            last_id = last.try_get::<i32>("", &Packets::Id.to_string())?;
+
+            for row in rows {
+                let id = row.try_get::<i32>("", &Packets::Id.to_string())?;
+                let hash = packet_hash(&row)?;
+                let taken = Query::select()
+                    .expr(Expr::val(1))
+                    .from(Packets::Table)
+                    .and_where(Expr::col(Packets::Hash).eq(hash.as_str()))
+                    .to_owned();
+                let update = Query::update()
+                    .table(Packets::Table)
+                    .value(Packets::Hash, hash)
+                    .and_where(Expr::col(Packets::Id).eq(id))
+                    .and_where(Expr::exists(taken).not())
+                    .to_owned();
+
+                txn.execute(backend.build(&update)).await?;
+            }
+        }
+
+        txn.commit().await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // Hashes of older packets are as valid as those of newer ones
+        Ok(())
+    }
+}
+
+/// SHA-256 of the characteristic and the frame, as the database computed it when this
+/// migration was written
+fn packet_hash(row: &QueryResult) -> Result<String, DbErr> {
+    let uuid = row.try_get::<Uuid>("", &Packets::Uuid.to_string())?;
+    let bytes = row.try_get::<Vec<u8>>("", &Packets::Bytes.to_string())?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(uuid.as_bytes());
+    hasher.update(bytes);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[derive(Iden)]
+enum Packets {
+    Table,
+    Id,
+    Uuid,
+    Bytes,
+    Hash,
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Keys the sync pointer by the band it was acknowledged to, so syncing a second band
+    /// doesn't resume from the first one's pointer. The single pointer stored so far can't
+    /// be attributed to a band and is dropped, the next download starts from the strap's
+    /// own read pointer instead.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SyncState::Table).to_owned())
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SyncState::Table)
+                    .col(
+                        ColumnDef::new(SyncState::Device)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SyncState::HistoryPointer)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SyncState::UpdatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SyncState::Table).to_owned())
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SyncState::Table)
+                    .col(
+                        ColumnDef::new(SyncState::Id)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(SyncState::HistoryPointer)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SyncState::UpdatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum SyncState {
+    Table,
+    Id,
+    Device,
+    HistoryPointer,
+    UpdatedAt,
+}
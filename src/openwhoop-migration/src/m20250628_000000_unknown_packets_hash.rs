@@ -0,0 +1,192 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{
+        TransactionTrait,
+        prelude::{DateTime, Uuid},
+    },
+};
+use sha2::{Digest, Sha256};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Makes captured unknown packets unique on their characteristic and bytes, so re-running
+    /// or re-downloading doesn't store them again, and lets `received_at` be `NULL` for
+    /// packets first captured while re-running, when they arrived isn't known.
+    ///
+    /// SQLite can't change a column's nullability, so the table is rebuilt. Duplicates
+    /// stored so far are dropped, keeping the first copy.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        let txn = db.begin().await?;
+
+        let create = Table::create()
+            .table(NewUnknownPackets::Table)
+            .col(
+                ColumnDef::new(UnknownPackets::Id)
+                    .integer()
+                    .not_null()
+                    .auto_increment()
+                    .primary_key(),
+            )
+            .col(ColumnDef::new(UnknownPackets::Uuid).uuid().not_null())
+            .col(ColumnDef::new(UnknownPackets::Bytes).binary().not_null())
+            .col(
+                ColumnDef::new(UnknownPackets::Hash)
+                    .string_len(64)
+                    .not_null()
+                    .unique_key(),
+            )
+            .col(ColumnDef::new(UnknownPackets::Error).text().not_null())
+            .col(
+                ColumnDef::new(UnknownPackets::ReceivedAt)
+                    .date_time()
+                    .null(),
+            )
+            .to_owned();
+        txn.execute(backend.build(&create)).await?;
+
+        let select = Query::select()
+            .columns([
+                UnknownPackets::Uuid,
+                UnknownPackets::Bytes,
+                UnknownPackets::Error,
+                UnknownPackets::ReceivedAt,
+            ])
+            .from(UnknownPackets::Table)
+            .order_by(UnknownPackets::Id, Order::Asc)
+            .to_owned();
+        for row in txn.query_all(backend.build(&select)).await? {
+            let uuid = row.try_get::<Uuid>("", &UnknownPackets::Uuid.to_string())?;
+            let bytes = row.try_get::<Vec<u8>>("", &UnknownPackets::Bytes.to_string())?;
+            let error = row.try_get::<String>("", &UnknownPackets::Error.to_string())?;
+            let received_at =
+                row.try_get::<DateTime>("", &UnknownPackets::ReceivedAt.to_string())?;
+
+            let insert = Query::insert()
+                .into_table(NewUnknownPackets::Table)
+                .columns([
+                    UnknownPackets::Uuid,
+                    UnknownPackets::Bytes,
+                    UnknownPackets::Hash,
+                    UnknownPackets::Error,
+                    UnknownPackets::ReceivedAt,
+                ])
+                .values_panic([
+                    uuid.into(),
+                    bytes.clone().into(),
+                    packet_hash(uuid, &bytes).into(),
+                    error.into(),
+                    received_at.into(),
+                ])
+                .on_conflict(
+                    OnConflict::column(UnknownPackets::Hash)
+                        .do_nothing()
+                        .to_owned(),
+                )
+                .to_owned();
+            txn.execute(backend.build(&insert)).await?;
+        }
+
+        let drop = Table::drop().table(UnknownPackets::Table).to_owned();
+        txn.execute(backend.build(&drop)).await?;
+        let rename = Table::rename()
+            .table(NewUnknownPackets::Table, UnknownPackets::Table)
+            .to_owned();
+        txn.execute(backend.build(&rename)).await?;
+
+        txn.commit().await
+    }
+
+    /// Packets without a receive time get the time of the rollback
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        let txn = db.begin().await?;
+
+        let create = Table::create()
+            .table(NewUnknownPackets::Table)
+            .col(
+                ColumnDef::new(UnknownPackets::Id)
+                    .integer()
+                    .not_null()
+                    .auto_increment()
+                    .primary_key(),
+            )
+            .col(ColumnDef::new(UnknownPackets::Uuid).uuid().not_null())
+            .col(ColumnDef::new(UnknownPackets::Bytes).binary().not_null())
+            .col(ColumnDef::new(UnknownPackets::Error).text().not_null())
+            .col(
+                ColumnDef::new(UnknownPackets::ReceivedAt)
+                    .date_time()
+                    .not_null(),
+            )
+            .to_owned();
+        txn.execute(backend.build(&create)).await?;
+
+        let columns = [
+            UnknownPackets::Id,
+            UnknownPackets::Uuid,
+            UnknownPackets::Bytes,
+            UnknownPackets::Error,
+            UnknownPackets::ReceivedAt,
+        ];
+        let select = Query::select()
+            .columns([
+                UnknownPackets::Id,
+                UnknownPackets::Uuid,
+                UnknownPackets::Bytes,
+                UnknownPackets::Error,
+            ])
+            .expr(Func::coalesce([
+                Expr::col(UnknownPackets::ReceivedAt).into(),
+                Expr::current_timestamp().into(),
+            ]))
+            .from(UnknownPackets::Table)
+            .to_owned();
+        let copy = Query::insert()
+            .into_table(NewUnknownPackets::Table)
+            .columns(columns)
+            .select_from(select)
+            .map_err(|error| DbErr::Migration(error.to_string()))?
+            .to_owned();
+        txn.execute(backend.build(&copy)).await?;
+
+        let drop = Table::drop().table(UnknownPackets::Table).to_owned();
+        txn.execute(backend.build(&drop)).await?;
+        let rename = Table::rename()
+            .table(NewUnknownPackets::Table, UnknownPackets::Table)
+            .to_owned();
+        txn.execute(backend.build(&rename)).await?;
+
+        txn.commit().await
+    }
+}
+
+/// SHA-256 of the characteristic and the bytes, like `packets.hash`
+fn packet_hash(uuid: Uuid, bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(uuid.as_bytes());
+    hasher.update(bytes);
+    hex::encode(hasher.finalize())
+}
+
+#[derive(Iden)]
+enum UnknownPackets {
+    Table,
+    Id,
+    Uuid,
+    Bytes,
+    Hash,
+    Error,
+    ReceivedAt,
+}
+
+#[derive(Iden)]
+enum NewUnknownPackets {
+    #[iden = "unknown_packets_new"]
+    Table,
+}
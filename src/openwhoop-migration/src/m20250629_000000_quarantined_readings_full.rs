@@ -0,0 +1,93 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Keeps the IMU and sensor data of quarantined readings, so nothing is lost if their
+    /// time can be recovered later, and makes them unique on their timestamp so downloading
+    /// the same history again doesn't quarantine a reading twice. Duplicates stored so far
+    /// are dropped, keeping the first copy.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(QuarantinedReadings::Table)
+                    .add_column(ColumnDef::new(QuarantinedReadings::ImuData).blob().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(QuarantinedReadings::Table)
+                    .add_column(
+                        ColumnDef::new(QuarantinedReadings::SensorData)
+                            .json()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let first = Query::select()
+            .expr(Expr::col(QuarantinedReadings::Id).min())
+            .from(QuarantinedReadings::Table)
+            .group_by_col(QuarantinedReadings::Unix)
+            .to_owned();
+        let duplicates = Query::delete()
+            .from_table(QuarantinedReadings::Table)
+            .and_where(Expr::col(QuarantinedReadings::Id).not_in_subquery(first))
+            .to_owned();
+        manager.exec_stmt(duplicates).await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("quarantined_readings-unix-index")
+                    .table(QuarantinedReadings::Table)
+                    .col(QuarantinedReadings::Unix)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("quarantined_readings-unix-index")
+                    .table(QuarantinedReadings::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        for column in [
+            QuarantinedReadings::ImuData,
+            QuarantinedReadings::SensorData,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(QuarantinedReadings::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum QuarantinedReadings {
+    Table,
+    Id,
+    Unix,
+    ImuData,
+    SensorData,
+}
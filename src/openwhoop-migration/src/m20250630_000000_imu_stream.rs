@@ -0,0 +1,54 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImuStream::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImuStream::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImuStream::Time).date_time().not_null())
+                    .col(ColumnDef::new(ImuStream::Samples).blob().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("imu_stream-time-samples-index")
+                    .table(ImuStream::Table)
+                    .col(ImuStream::Time)
+                    .col(ImuStream::Samples)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImuStream::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum ImuStream {
+    Table,
+    Id,
+    Time,
+    Samples,
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Keys firmware reports on the band that sent them, so two bands on different firmware
+    /// don't look like a firmware change on every sync. Reports stored so far stay `NULL`,
+    /// which band sent them isn't known.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FirmwareHistory::Table)
+                    .add_column(ColumnDef::new(FirmwareHistory::Device).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(FirmwareHistory::Table)
+                    .drop_column(FirmwareHistory::Device)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum FirmwareHistory {
+    Table,
+    Device,
+}
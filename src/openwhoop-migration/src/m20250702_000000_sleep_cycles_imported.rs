@@ -0,0 +1,112 @@
+use sea_orm_migration::{prelude::*, sea_orm::DbBackend};
+
+use crate::m20250609_000000_grafana_views::{SLEEP_DETAIL_POSTGRES, SLEEP_DETAIL_SQLITE};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// [`SLEEP_DETAIL_SQLITE`] with the heart rate range and HRV range of imported nights left
+/// out, the WHOOP export only has their resting heart rate and HRV.
+const IMPORTED_SLEEP_DETAIL_SQLITE: &str = r#"
+CREATE VIEW v_sleep_detail AS
+SELECT
+    sc.sleep_id AS day,
+    sc.start,
+    sc."end",
+    CAST(ROUND((julianday(sc."end") - julianday(sc.start)) * 1440) AS INTEGER) AS duration_minutes,
+    sc.min_bpm,
+    CASE WHEN sc.imported THEN NULL ELSE sc.max_bpm END AS max_bpm,
+    CASE WHEN sc.imported THEN NULL ELSE sc.avg_bpm END AS avg_bpm,
+    CASE WHEN sc.imported THEN NULL ELSE sc.min_hrv END AS min_hrv,
+    CASE WHEN sc.imported THEN NULL ELSE sc.max_hrv END AS max_hrv,
+    sc.avg_hrv,
+    sc.score,
+    sc.efficiency,
+    sc.imported,
+    AVG(hr.spo2) AS avg_spo2,
+    AVG(hr.skin_temp) AS avg_skin_temp
+FROM sleep_cycles sc
+LEFT JOIN heart_rate hr ON hr.time BETWEEN sc.start AND sc."end"
+GROUP BY sc.id
+"#;
+
+const IMPORTED_SLEEP_DETAIL_POSTGRES: &str = r#"
+CREATE VIEW v_sleep_detail AS
+SELECT
+    sc.sleep_id AS day,
+    sc.start,
+    sc."end",
+    CAST(ROUND(EXTRACT(EPOCH FROM sc."end" - sc.start) / 60) AS INTEGER) AS duration_minutes,
+    sc.min_bpm,
+    CASE WHEN sc.imported THEN NULL ELSE sc.max_bpm END AS max_bpm,
+    CASE WHEN sc.imported THEN NULL ELSE sc.avg_bpm END AS avg_bpm,
+    CASE WHEN sc.imported THEN NULL ELSE sc.min_hrv END AS min_hrv,
+    CASE WHEN sc.imported THEN NULL ELSE sc.max_hrv END AS max_hrv,
+    sc.avg_hrv,
+    sc.score,
+    sc.efficiency,
+    sc.imported,
+    AVG(hr.spo2) AS avg_spo2,
+    AVG(hr.skin_temp) AS avg_skin_temp
+FROM sleep_cycles sc
+LEFT JOIN heart_rate hr ON hr.time BETWEEN sc.start AND sc."end"
+GROUP BY sc.id
+"#;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    /// Marks sleeps loaded from the WHOOP data export, which only carry a resting heart rate
+    /// and HRV instead of the overnight range measured for detected sleeps, and leaves the
+    /// range of those nights out of `v_sleep_detail`.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SleepCycles::Table)
+                    .add_column(
+                        ColumnDef::new(SleepCycles::Imported)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let sleep_detail = match manager.get_database_backend() {
+            DbBackend::Postgres => IMPORTED_SLEEP_DETAIL_POSTGRES,
+            _ => IMPORTED_SLEEP_DETAIL_SQLITE,
+        };
+        let db = manager.get_connection();
+        db.execute_unprepared("DROP VIEW IF EXISTS v_sleep_detail")
+            .await?;
+        db.execute_unprepared(sleep_detail).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sleep_detail = match manager.get_database_backend() {
+            DbBackend::Postgres => SLEEP_DETAIL_POSTGRES,
+            _ => SLEEP_DETAIL_SQLITE,
+        };
+        let db = manager.get_connection();
+        db.execute_unprepared("DROP VIEW IF EXISTS v_sleep_detail")
+            .await?;
+        db.execute_unprepared(sleep_detail).await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SleepCycles::Table)
+                    .drop_column(SleepCycles::Imported)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum SleepCycles {
+    Table,
+    Imported,
+}
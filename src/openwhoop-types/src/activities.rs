@@ -8,6 +8,8 @@ pub struct ActivityPeriod {
     pub from: NaiveDateTime,
     pub to: NaiveDateTime,
     pub activity: ActivityType,
+    /// How sure an inferred activity type is, 0 to 1, `None` when it wasn't guessed
+    pub confidence: Option<f64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -320,6 +322,164 @@ pub enum ActivityType {
 }
 
 impl ActivityType {
+    /// Numeric code of the activity, as used by WHOOP for activity ids
+    pub fn as_i32(self) -> i32 {
+        self as i32
+    }
+
+    /// Activity with the numeric code `value`, `None` for codes without a variant
+    pub fn from_repr(value: i32) -> Option<Self> {
+        match value {
+            -1 => Some(Self::Activity),
+            0 => Some(Self::Running),
+            1 => Some(Self::Cycling),
+            16 => Some(Self::Baseball),
+            17 => Some(Self::Basketball),
+            18 => Some(Self::Rowing),
+            19 => Some(Self::Fencing),
+            20 => Some(Self::FieldHockey),
+            21 => Some(Self::Football),
+            22 => Some(Self::Golf),
+            24 => Some(Self::IceHockey),
+            25 => Some(Self::Lacrosse),
+            27 => Some(Self::Rugby),
+            28 => Some(Self::Sailing),
+            29 => Some(Self::Skiing),
+            30 => Some(Self::Soccer),
+            31 => Some(Self::Softball),
+            32 => Some(Self::Squash),
+            33 => Some(Self::Swimming),
+            34 => Some(Self::Tennis),
+            35 => Some(Self::TrackField),
+            36 => Some(Self::Volleyball),
+            37 => Some(Self::WaterPolo),
+            38 => Some(Self::Wrestling),
+            39 => Some(Self::Boxing),
+            42 => Some(Self::Dance),
+            43 => Some(Self::Pilates),
+            44 => Some(Self::Yoga),
+            45 => Some(Self::Weightlifting),
+            46 => Some(Self::Canoeing),
+            47 => Some(Self::CrossCountrySkiing),
+            48 => Some(Self::FunctionalFitness),
+            49 => Some(Self::Duathlon),
+            50 => Some(Self::MachineWorkout),
+            51 => Some(Self::Gymnastics),
+            52 => Some(Self::HikingRucking),
+            53 => Some(Self::HorsebackRiding),
+            54 => Some(Self::Jogging),
+            55 => Some(Self::Kayaking),
+            56 => Some(Self::MartialArts),
+            57 => Some(Self::MountainBiking),
+            58 => Some(Self::ObstacleRacing),
+            59 => Some(Self::Powerlifting),
+            60 => Some(Self::RockClimbing),
+            61 => Some(Self::Paddleboarding),
+            62 => Some(Self::Triathlon),
+            63 => Some(Self::Walking),
+            64 => Some(Self::Surfing),
+            65 => Some(Self::Elliptical),
+            66 => Some(Self::Stairmaster),
+            67 => Some(Self::Plyometrics),
+            68 => Some(Self::Spinning),
+            69 => Some(Self::Sex),
+            70 => Some(Self::Meditation),
+            71 => Some(Self::Other),
+            72 => Some(Self::PitPractice),
+            73 => Some(Self::Diving),
+            74 => Some(Self::OperationsTactical),
+            75 => Some(Self::OperationsMedical),
+            76 => Some(Self::OperationsFlying),
+            77 => Some(Self::OperationsWater),
+            82 => Some(Self::Ultimate),
+            83 => Some(Self::Climber),
+            84 => Some(Self::JumpingRope),
+            85 => Some(Self::AustralianRulesFootball),
+            86 => Some(Self::Skateboarding),
+            87 => Some(Self::Coaching),
+            88 => Some(Self::IceBath),
+            89 => Some(Self::Commuting),
+            90 => Some(Self::Gaming),
+            91 => Some(Self::Snowboarding),
+            92 => Some(Self::Motocross),
+            93 => Some(Self::Caddying),
+            94 => Some(Self::ObstacleCourseRacing),
+            95 => Some(Self::MotorRacing),
+            96 => Some(Self::Hiit),
+            97 => Some(Self::Spin),
+            98 => Some(Self::JiuJitsu),
+            99 => Some(Self::ManualLabor),
+            100 => Some(Self::Cricket),
+            101 => Some(Self::Pickleball),
+            102 => Some(Self::InlineSkating),
+            103 => Some(Self::BoxFitness),
+            104 => Some(Self::Spikeball),
+            105 => Some(Self::WheelchairPushing),
+            106 => Some(Self::PaddleTennis),
+            107 => Some(Self::Barre),
+            108 => Some(Self::StagePerformance),
+            109 => Some(Self::HighStressWork),
+            110 => Some(Self::Parkour),
+            111 => Some(Self::GaelicFootball),
+            112 => Some(Self::HurlingCamogie),
+            113 => Some(Self::CircusArts),
+            116 => Some(Self::ResonanceFrequencyBreathing),
+            121 => Some(Self::MassageTherapy),
+            123 => Some(Self::StrengthTrainer),
+            125 => Some(Self::WatchingSports),
+            126 => Some(Self::AssaultBike),
+            127 => Some(Self::Kickboxing),
+            128 => Some(Self::Stretching),
+            131 => Some(Self::OtherRecovery),
+            230 => Some(Self::TableTennisPingPong),
+            231 => Some(Self::Badminton),
+            232 => Some(Self::Netball),
+            233 => Some(Self::Sauna),
+            234 => Some(Self::DiscGolf),
+            235 => Some(Self::YardWorkGardening),
+            236 => Some(Self::AirCompression),
+            237 => Some(Self::PercussiveMassage),
+            238 => Some(Self::Paintball),
+            239 => Some(Self::IceSkating),
+            240 => Some(Self::Handball),
+            241 => Some(Self::PercussiveMassageHypervolt),
+            242 => Some(Self::AirCompressionNormatec),
+            243 => Some(Self::IncreaseRelaxation),
+            244 => Some(Self::IncreaseAlertness),
+            245 => Some(Self::Breathwork),
+            246 => Some(Self::NonSleepDeepRest),
+            247 => Some(Self::SteamRoom),
+            248 => Some(Self::F45Training),
+            249 => Some(Self::Padel),
+            250 => Some(Self::BarryS),
+            251 => Some(Self::DedicatedParenting),
+            252 => Some(Self::StrollerWalking),
+            253 => Some(Self::StrollerJogging),
+            254 => Some(Self::Toddlerwearing),
+            255 => Some(Self::Babywearing),
+            256 => Some(Self::PlayingWithChild),
+            257 => Some(Self::CuddlingWithChild),
+            258 => Some(Self::Barre3),
+            259 => Some(Self::HotYoga),
+            261 => Some(Self::StadiumSteps),
+            262 => Some(Self::Polo),
+            263 => Some(Self::MusicalPerformance),
+            264 => Some(Self::KiteBoarding),
+            265 => Some(Self::RestorativeYoga),
+            266 => Some(Self::DogWalking),
+            267 => Some(Self::WaterSkiing),
+            268 => Some(Self::Wakeboarding),
+            269 => Some(Self::Cooking),
+            270 => Some(Self::Cleaning),
+            271 => Some(Self::WarmBath),
+            272 => Some(Self::PublicSpeaking),
+            274 => Some(Self::RaceWalking),
+            275 => Some(Self::Driving),
+            1000 => Some(Self::Nap),
+            _ => None,
+        }
+    }
+
     pub fn icon_url(&self) -> &'static str {
         match self {
             ActivityType::Activity => {
@@ -1241,3 +1401,33 @@ impl SearchActivityPeriods {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_variant_round_trips_through_its_code() {
+        let variants = (-1..=1000)
+            .filter_map(ActivityType::from_repr)
+            .collect::<Vec<_>>();
+        assert_eq!(variants.len(), 146);
+
+        for activity in variants {
+            let code = activity.as_i32();
+            assert!(matches!(ActivityType::from_repr(code), Some(a) if a.as_i32() == code));
+            let parsed = ActivityType::from_str(&activity.to_string()).unwrap();
+            assert_eq!(parsed.as_i32(), code);
+        }
+
+        assert!(matches!(
+            ActivityType::from_repr(-1),
+            Some(ActivityType::Activity)
+        ));
+        assert!(matches!(
+            ActivityType::from_repr(1000),
+            Some(ActivityType::Nap)
+        ));
+        assert!(ActivityType::from_repr(2).is_none());
+    }
+}
@@ -1,12 +1,16 @@
-use anyhow::anyhow;
-use btleplug::{
-    api::{Central, CharPropFlags, Characteristic, Peripheral as _, WriteType},
-    platform::{Adapter, Peripheral},
+use btleplug::platform::{Adapter, Peripheral};
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use openwhoop_codec::{
+    WhoopData, WhoopPacket,
+    constants::{
+        CMD_FROM_STRAP, CMD_TO_STRAP, CommandNumber, DATA_FROM_STRAP, EVENTS_FROM_STRAP, MEMFAULT,
+    },
 };
 use openwhoop_entities::packets::Model;
-use futures::StreamExt;
 use std::{
-    collections::BTreeSet,
+    fs::File,
+    io::Write,
     sync::{
         Arc,
         atomic::{AtomicBool, Ordering},
@@ -14,21 +18,43 @@ use std::{
     time::Duration,
 };
 use tokio::time::{sleep, timeout};
-use uuid::Uuid;
-use openwhoop_codec::{
-    WhoopData, WhoopPacket,
-    constants::{
-        CMD_FROM_STRAP, CMD_TO_STRAP, DATA_FROM_STRAP, EVENTS_FROM_STRAP, MEMFAULT, WHOOP_SERVICE,
-    },
+
+use crate::{
+    db::DatabaseHandler,
+    error::OpenWhoopError,
+    openwhoop::{HighFreqSync, OpenWhoop, PacketOutcome, SyncOutcome},
+    transport::{BleTransport, NotificationStream, Transport},
 };
 
-use crate::{db::DatabaseHandler, openwhoop::OpenWhoop};
+/// Signal strength in dBm below which syncs tend to be slow and drop
+pub const DEFAULT_MIN_RSSI: i16 = -85;
+
+/// What to do about the signal strength before syncing, see [`signal_quality`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalQuality {
+    Good,
+    /// Below the threshold, sync anyway with a warning
+    Weak,
+    /// Below the threshold and asked not to sync
+    TooWeak,
+}
+
+/// Checks `rssi` against `min_rssi`. An unknown RSSI counts as good, not every adapter
+/// reports one.
+pub fn signal_quality(rssi: Option<i16>, min_rssi: i16, refuse_weak: bool) -> SignalQuality {
+    match rssi {
+        Some(rssi) if rssi < min_rssi && refuse_weak => SignalQuality::TooWeak,
+        Some(rssi) if rssi < min_rssi => SignalQuality::Weak,
+        _ => SignalQuality::Good,
+    }
+}
 
-pub struct WhoopDevice {
-    peripheral: Peripheral,
+pub struct WhoopDevice<T = BleTransport> {
+    transport: T,
     whoop: OpenWhoop,
+    /// Store every packet before it's handled. Otherwise only packets that fail to parse
+    /// are stored, see [`OpenWhoop::keep_unparsed`]
     debug_packets: bool,
-    adapter: Adapter,
 }
 
 impl WhoopDevice {
@@ -38,77 +64,114 @@ impl WhoopDevice {
         db: DatabaseHandler,
         debug_packets: bool,
     ) -> Self {
+        Self::with_transport(BleTransport::new(peripheral, adapter), db, debug_packets)
+    }
+}
+
+impl<T: Transport> WhoopDevice<T> {
+    pub fn with_transport(transport: T, db: DatabaseHandler, debug_packets: bool) -> Self {
         Self {
-            peripheral,
-            whoop: OpenWhoop::new(db),
+            transport,
+            whoop: OpenWhoop::new(db).with_keep_unparsed(!debug_packets),
             debug_packets,
-            adapter,
         }
     }
 
-    pub async fn connect(&mut self) -> anyhow::Result<()> {
-        self.peripheral.connect().await?;
-        let _ = self.adapter.stop_scan().await;
-        self.peripheral.discover_services().await?;
-        self.whoop.packet = None;
-        Ok(())
+    pub fn with_imu_downsample(self, imu_downsample: usize) -> Self {
+        Self {
+            whoop: self.whoop.with_imu_downsample(imu_downsample),
+            ..self
+        }
     }
 
-    pub async fn is_connected(&mut self) -> anyhow::Result<bool> {
-        let is_connected = self.peripheral.is_connected().await?;
-        Ok(is_connected)
+    pub fn with_console_log(self, console_log: File) -> Self {
+        Self {
+            whoop: self.whoop.with_console_log(console_log),
+            ..self
+        }
     }
 
-    fn create_char(characteristic: Uuid) -> Characteristic {
-        Characteristic {
-            uuid: characteristic,
-            service_uuid: WHOOP_SERVICE,
-            properties: CharPropFlags::empty(),
-            descriptors: BTreeSet::new(),
+    pub fn with_capture_unknown(self, capture_unknown: bool) -> Self {
+        Self {
+            whoop: self.whoop.with_capture_unknown(capture_unknown),
+            ..self
         }
     }
 
-    async fn subscribe(&self, char: Uuid) -> anyhow::Result<()> {
-        self.peripheral.subscribe(&Self::create_char(char)).await?;
+    pub fn with_lenient_crc(self, lenient_crc: bool) -> Self {
+        Self {
+            whoop: self.whoop.with_lenient_crc(lenient_crc),
+            ..self
+        }
+    }
+
+    pub fn with_device(self, device: impl Into<String>) -> Self {
+        Self {
+            whoop: self.whoop.with_device(device),
+            ..self
+        }
+    }
+
+    pub async fn connect(&mut self) -> Result<(), OpenWhoopError> {
+        self.transport.connect().await?;
+        self.whoop.packet = None;
         Ok(())
     }
 
-    pub async fn initialize(&mut self) -> anyhow::Result<()> {
-        self.subscribe(DATA_FROM_STRAP).await?;
-        self.subscribe(CMD_FROM_STRAP).await?;
-        self.subscribe(EVENTS_FROM_STRAP).await?;
-        self.subscribe(MEMFAULT).await?;
+    /// Signal strength of the last advertisement in dBm, if the adapter reports it
+    pub async fn rssi(&self) -> Result<Option<i16>, OpenWhoopError> {
+        self.transport.rssi().await
+    }
+
+    pub async fn is_connected(&mut self) -> Result<bool, OpenWhoopError> {
+        self.transport.is_connected().await
+    }
+
+    pub async fn initialize(&mut self) -> Result<(), OpenWhoopError> {
+        self.transport.subscribe(DATA_FROM_STRAP).await?;
+        self.transport.subscribe(CMD_FROM_STRAP).await?;
+        self.transport.subscribe(EVENTS_FROM_STRAP).await?;
+        self.transport.subscribe(MEMFAULT).await?;
 
         self.send_command(WhoopPacket::hello_harvard()).await?;
         self.send_command(WhoopPacket::set_time()).await?;
         self.send_command(WhoopPacket::get_name()).await?;
+        // Selects the history layout and is recorded in the firmware history
+        self.send_command(WhoopPacket::version()).await?;
 
+        self.whoop.high_freq_sync = HighFreqSync::Requested;
         self.send_command(WhoopPacket::enter_high_freq_sync())
             .await?;
         Ok(())
     }
 
-    pub async fn send_command(&mut self, packet: WhoopPacket) -> anyhow::Result<()> {
+    pub async fn send_command(&mut self, packet: WhoopPacket) -> Result<(), OpenWhoopError> {
         let packet = packet.framed_packet();
-        self.peripheral
-            .write(
-                &Self::create_char(CMD_TO_STRAP),
-                &packet,
-                WriteType::WithoutResponse,
-            )
-            .await?;
-        Ok(())
+        self.transport.write(CMD_TO_STRAP, &packet).await
     }
 
-    pub async fn sync_history(&mut self, should_exit: Arc<AtomicBool>) -> anyhow::Result<()> {
-        let mut notifications = self.peripheral.notifications().await?;
+    /// Downloads history until the strap reports it complete, `should_exit` is set or the
+    /// strap can't be reconnected. Warns if the download ended before it was complete.
+    ///
+    /// History is requested once the strap reports high frequency sync enabled, or after
+    /// waiting for that without a notification for a while, for firmware that never does.
+    pub async fn sync_history(
+        &mut self,
+        should_exit: Arc<AtomicBool>,
+    ) -> Result<SyncOutcome, OpenWhoopError> {
+        let mut notifications = self.transport.notifications().await?;
 
-        self.send_command(WhoopPacket::history_start()).await?;
+        self.whoop.sync_outcome = SyncOutcome::default();
+        let mut history_started = false;
 
         'a: loop {
-            if should_exit.load(Ordering::SeqCst) {
+            if should_exit.load(Ordering::SeqCst) || self.whoop.sync_outcome.complete {
                 break;
             }
+            if !history_started && self.whoop.high_freq_sync.is_enabled() {
+                self.start_history().await?;
+                history_started = true;
+            }
             let notification = notifications.next();
             let sleep_ = sleep(Duration::from_secs(10));
 
@@ -119,7 +182,7 @@ impl WhoopDevice {
                         for _ in 0..5{
                             if self.connect().await.is_ok() {
                                 self.initialize().await?;
-                                self.send_command(WhoopPacket::history_start()).await?;
+                                history_started = false;
                                 continue 'a;
                             }
 
@@ -128,46 +191,795 @@ impl WhoopDevice {
 
                         break;
                     }
+
+                    if !history_started {
+                        warn!("High frequency sync not confirmed, requesting history anyway");
+                        self.start_history().await?;
+                        history_started = true;
+                    }
                 },
                 Some(notification) = notification => {
                     let packet = match self.debug_packets {
                         true => self.whoop.store_packet(notification).await?,
-                        false => Model { id: 0, uuid: notification.uuid, bytes: notification.value },
+                        false => Model { id: 0, uuid: notification.uuid, bytes: notification.value, hash: None },
                     };
 
-                    if let Some(packet) = self.whoop.handle_packet(packet).await?{
-                        self.send_command(packet).await?;
+                    match self.whoop.handle_packet(packet).await? {
+                        PacketOutcome::Handled(Some(packet)) => self.send_command(packet).await?,
+                        PacketOutcome::ParseError(error) => warn!("Invalid packet: {}", error),
+                        _ => {}
                     }
                 }
             }
         }
 
+        let outcome = self.whoop.sync_outcome;
+        if !outcome.complete {
+            warn!(
+                "History download ended before the strap reported it complete, last marker: {:?}",
+                outcome.last_unix
+            );
+        }
+
+        Ok(outcome)
+    }
+
+    /// Starts or, after a reconnect, resumes the download from the last acknowledged pointer
+    async fn start_history(&mut self) -> Result<(), OpenWhoopError> {
+        for packet in self.whoop.resume_history_packets().await? {
+            self.send_command(packet).await?;
+        }
+
         Ok(())
     }
 
-    async fn on_sleep(&mut self) -> anyhow::Result<bool> {
-        let is_connected = self.peripheral.is_connected().await?;
+    async fn on_sleep(&mut self) -> Result<bool, OpenWhoopError> {
+        let is_connected = self.transport.is_connected().await?;
         Ok(!is_connected)
     }
 
-    pub async fn get_version(&mut self) -> anyhow::Result<()> {
-        self.subscribe(CMD_FROM_STRAP).await?;
+    /// Firmware versions of the Harvard and Boylston cores, skipping any other packet the
+    /// strap sends in the meantime. The versions are recorded like those reported during a
+    /// sync, see [`OpenWhoop::version_reported`].
+    pub async fn get_version(&mut self) -> Result<(String, String), OpenWhoopError> {
+        self.transport.subscribe(CMD_FROM_STRAP).await?;
 
-        let mut notifications = self.peripheral.notifications().await?;
+        let mut notifications = self.transport.notifications().await?;
         self.send_command(WhoopPacket::version()).await?;
 
         let timeout_duration = Duration::from_secs(5);
-        match timeout(timeout_duration, notifications.next()).await {
-            Ok(Some(notification)) => {
-                let packet = WhoopPacket::from_data(notification.value)?;
-                let data = WhoopData::from_packet(packet)?;
-                if let WhoopData::VersionInfo { harvard, boylston } = data {
-                    info!("version harvard {} boylston {}", harvard, boylston);
+        loop {
+            match timeout(timeout_duration, notifications.next()).await {
+                Ok(Some(notification)) => {
+                    let Ok(packet) = WhoopPacket::from_data(notification.value) else {
+                        continue;
+                    };
+                    if let Ok(WhoopData::VersionInfo { harvard, boylston }) =
+                        WhoopData::from_packet(packet)
+                    {
+                        self.whoop
+                            .version_reported(harvard.clone(), boylston.clone())
+                            .await?;
+                        return Ok((harvard, boylston));
+                    }
                 }
+                Ok(None) => return Err(OpenWhoopError::StreamEnded),
+                Err(_) => return Err(OpenWhoopError::Timeout("version")),
+            }
+        }
+    }
+
+    /// Logs which wrist the strap is set to and whether it's worn.
+    ///
+    /// The layout of the response isn't confirmed by a capture yet, so the raw frame is
+    /// logged before it's parsed.
+    pub async fn get_body_location(&mut self) -> Result<(), OpenWhoopError> {
+        self.transport.subscribe(CMD_FROM_STRAP).await?;
+
+        let mut notifications = self.transport.notifications().await?;
+        self.send_command(WhoopPacket::get_body_location_status())
+            .await?;
+
+        let timeout_duration = Duration::from_secs(5);
+        loop {
+            match timeout(timeout_duration, notifications.next()).await {
+                Ok(Some(notification)) => {
+                    let raw = hex::encode(&notification.value);
+                    let Ok(packet) = WhoopPacket::from_data(notification.value) else {
+                        continue;
+                    };
+                    if packet.cmd != CommandNumber::GetBodyLocationAndStatus.as_u8() {
+                        continue;
+                    }
+
+                    info!("body location response: {}", raw);
+                    if let WhoopData::BodyLocation { wrist, on_body } =
+                        WhoopData::from_packet(packet)?
+                    {
+                        info!("wrist {:?} on body {}", wrist, on_body);
+                    }
+                    return Ok(());
+                }
+                Ok(None) => return Err(OpenWhoopError::StreamEnded),
+                Err(_) => return Err(OpenWhoopError::Timeout("body location")),
+            }
+        }
+    }
+
+    /// Logs the alarm set on the strap.
+    ///
+    /// The layout of the response isn't confirmed by a capture yet, so the raw frame is
+    /// logged before it's parsed.
+    pub async fn get_alarm(&mut self) -> Result<(), OpenWhoopError> {
+        self.transport.subscribe(CMD_FROM_STRAP).await?;
+
+        let mut notifications = self.transport.notifications().await?;
+        self.send_command(WhoopPacket::get_alarm_time()).await?;
+
+        let timeout_duration = Duration::from_secs(5);
+        loop {
+            match timeout(timeout_duration, notifications.next()).await {
+                Ok(Some(notification)) => {
+                    let raw = hex::encode(&notification.value);
+                    let Ok(packet) = WhoopPacket::from_data(notification.value) else {
+                        continue;
+                    };
+                    if packet.cmd != CommandNumber::GetAlarmTime.as_u8() {
+                        continue;
+                    }
+
+                    info!("alarm response: {}", raw);
+                    if let WhoopData::AlarmTime { unix, enabled } = WhoopData::from_packet(packet)?
+                    {
+                        let time = DateTime::from_timestamp(i64::from(unix), 0)
+                            .ok_or_else(|| {
+                                OpenWhoopError::Invalid(format!("invalid alarm time: {}", unix))
+                            })?
+                            .with_timezone(&Local);
+                        info!("alarm at {} enabled {}", time, enabled);
+                    }
+                    return Ok(());
+                }
+                Ok(None) => return Err(OpenWhoopError::StreamEnded),
+                Err(_) => return Err(OpenWhoopError::Timeout("alarm")),
+            }
+        }
+    }
+
+    /// Oldest and newest history pointers the strap still holds.
+    ///
+    /// The layout of the response isn't confirmed by a capture yet, so the raw frame is
+    /// logged before it's parsed.
+    pub async fn get_data_range(&mut self) -> Result<(u32, u32), OpenWhoopError> {
+        self.transport.subscribe(CMD_FROM_STRAP).await?;
+
+        let mut notifications = self.transport.notifications().await?;
+        self.send_command(WhoopPacket::get_data_range()).await?;
+
+        let timeout_duration = Duration::from_secs(5);
+        loop {
+            match timeout(timeout_duration, notifications.next()).await {
+                Ok(Some(notification)) => {
+                    let raw = hex::encode(&notification.value);
+                    let Ok(packet) = WhoopPacket::from_data(notification.value) else {
+                        continue;
+                    };
+                    if packet.cmd != CommandNumber::GetDataRange.as_u8() {
+                        continue;
+                    }
+
+                    info!("data range response: {}", raw);
+                    if let WhoopData::DataRange { start, end } = WhoopData::from_packet(packet)? {
+                        return Ok((start, end));
+                    }
+                }
+                Ok(None) => return Err(OpenWhoopError::StreamEnded),
+                Err(_) => return Err(OpenWhoopError::Timeout("data range")),
+            }
+        }
+    }
+
+    /// Rewinds the strap's read pointer to `start`, the oldest pointer of the range from
+    /// [`WhoopDevice::get_data_range`], so the next [`WhoopDevice::sync_history`] downloads
+    /// everything it still holds.
+    pub async fn rewind_history(&mut self, start: u32, end: u32) -> Result<(), OpenWhoopError> {
+        info!("rewinding history to pointer {} (newest {})", start, end);
+        let packet = self.whoop.rewind_history_packet(start, end).await?;
+        self.send_command(packet).await
+    }
+
+    /// Sends `packet` and waits up to `wait` for the strap to acknowledge it with an event
+    /// for the same command.
+    pub async fn send_acknowledged(
+        &mut self,
+        packet: WhoopPacket,
+        wait: Duration,
+    ) -> Result<(), OpenWhoopError> {
+        self.transport.subscribe(EVENTS_FROM_STRAP).await?;
+
+        let cmd = packet.cmd;
+        let mut notifications = self.transport.notifications().await?;
+        self.send_command(packet).await?;
+
+        let deadline = tokio::time::Instant::now() + wait;
+        while let Ok(notification) = tokio::time::timeout_at(deadline, notifications.next()).await {
+            let Some(notification) = notification else {
+                return Err(OpenWhoopError::StreamEnded);
+            };
+
+            let Ok(packet) = WhoopPacket::from_data(notification.value) else {
+                continue;
+            };
+            if let Ok(WhoopData::Event { event, .. }) = WhoopData::from_packet(packet) {
+                if event.as_u8() == cmd {
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(OpenWhoopError::NotAcknowledged(cmd))
+    }
+
+    /// Sends `packet` and logs every packet the strap sends back within `listen`,
+    /// for exploring commands that aren't parsed yet
+    pub async fn raw_command(
+        &mut self,
+        packet: WhoopPacket,
+        listen: Duration,
+    ) -> Result<(), OpenWhoopError> {
+        self.transport.subscribe(CMD_FROM_STRAP).await?;
+        self.transport.subscribe(EVENTS_FROM_STRAP).await?;
+
+        let mut notifications = self.transport.notifications().await?;
+        self.send_command(packet).await?;
+
+        let deadline = tokio::time::Instant::now() + listen;
+        while let Ok(notification) = tokio::time::timeout_at(deadline, notifications.next()).await {
+            let Some(notification) = notification else {
+                return Err(OpenWhoopError::StreamEnded);
+            };
+
+            match WhoopPacket::from_data(notification.value.clone()) {
+                Ok(packet) => info!("{}", packet),
+                Err(error) => warn!("{:?}: {}", error, hex::encode(&notification.value)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Streams raw optical and accelerometer data for `duration`, writing the command byte
+    /// and payload of every [`WhoopData::RawData`] packet to `writer` as a line of hex.
+    /// Returns the number of packets written.
+    pub async fn capture_raw_data<W: Write>(
+        &mut self,
+        writer: &mut W,
+        duration: Duration,
+    ) -> Result<usize, OpenWhoopError> {
+        self.transport.subscribe(DATA_FROM_STRAP).await?;
+
+        let notifications = self.transport.notifications().await?;
+        self.send_command(WhoopPacket::start_raw_data()).await?;
+
+        // The strap streams until told otherwise, so it's stopped however the capture ended
+        let captured = self.write_raw_data(notifications, writer, duration).await;
+        let stopped = self.send_command(WhoopPacket::stop_raw_data()).await;
+        let count = captured?;
+        stopped?;
+        Ok(count)
+    }
+
+    async fn write_raw_data<W: Write>(
+        &mut self,
+        mut notifications: NotificationStream,
+        writer: &mut W,
+        duration: Duration,
+    ) -> Result<usize, OpenWhoopError> {
+        let mut count = 0;
+        let deadline = tokio::time::Instant::now() + duration;
+        while let Ok(notification) = tokio::time::timeout_at(deadline, notifications.next()).await {
+            let Some(notification) = notification else {
+                return Err(OpenWhoopError::StreamEnded);
+            };
+            if notification.uuid != DATA_FROM_STRAP {
+                continue;
+            }
+
+            // Raw data packets can be larger than the MTU, join them like history
+            let stored = Model {
+                id: 0,
+                uuid: notification.uuid,
+                bytes: notification.value,
+                hash: None,
+            };
+            let Ok(packet) = self.whoop.assemble_packet(stored) else {
+                continue;
+            };
+            if let Ok(WhoopData::RawData { cmd, data }) = WhoopData::from_packet(packet) {
+                writeln!(writer, "{:02x} {}", cmd, hex::encode(data))?;
+                count += 1;
+            }
+        }
+
+        writer.flush()?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use btleplug::api::ValueNotification;
+    use openwhoop_codec::constants::{EventNumber, MetadataType, PacketType};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::db::SearchHistory;
+
+    /// Plays back scripted notifications and records every write
+    struct MockTransport {
+        script: Mutex<Vec<ValueNotification>>,
+        writes: Arc<Mutex<Vec<Vec<u8>>>>,
+        connected: bool,
+    }
+
+    impl MockTransport {
+        fn new(script: Vec<ValueNotification>) -> Self {
+            Self {
+                script: Mutex::new(script),
+                writes: Arc::default(),
+                connected: true,
+            }
+        }
+    }
+
+    impl Transport for MockTransport {
+        async fn connect(&mut self) -> Result<(), OpenWhoopError> {
+            Ok(())
+        }
+
+        async fn is_connected(&self) -> Result<bool, OpenWhoopError> {
+            Ok(self.connected)
+        }
+
+        async fn subscribe(&self, _characteristic: Uuid) -> Result<(), OpenWhoopError> {
+            Ok(())
+        }
+
+        async fn write(&self, characteristic: Uuid, data: &[u8]) -> Result<(), OpenWhoopError> {
+            assert_eq!(characteristic, CMD_TO_STRAP);
+            if !self.connected {
+                return Err(OpenWhoopError::NotConnected);
+            }
+            self.writes.lock().unwrap().push(data.to_vec());
+            Ok(())
+        }
+
+        async fn notifications(&self) -> Result<NotificationStream, OpenWhoopError> {
+            let script = std::mem::take(&mut *self.script.lock().unwrap());
+            Ok(Box::pin(
+                futures::stream::iter(script).chain(futures::stream::pending()),
+            ))
+        }
+
+        async fn rssi(&self) -> Result<Option<i16>, OpenWhoopError> {
+            Ok(Some(-60))
+        }
+    }
+
+    fn notification(uuid: Uuid, packet: WhoopPacket) -> ValueNotification {
+        ValueNotification {
+            uuid,
+            value: packet.framed_packet(),
+        }
+    }
+
+    fn metadata(cmd: MetadataType, unix: u32, data: u32) -> ValueNotification {
+        let mut bytes = unix.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 6]);
+        bytes.extend_from_slice(&data.to_le_bytes());
+        let packet = WhoopPacket::new(PacketType::Metadata, 0, cmd as u8, bytes);
+        notification(DATA_FROM_STRAP, packet)
+    }
+
+    fn reading(unix: u32) -> ValueNotification {
+        let mut data = Vec::new();
+        data.extend_from_slice(&unix.to_le_bytes()); // sequence
+        data.extend_from_slice(&unix.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&[60, 1]);
+        data.extend_from_slice(&[0xe8, 0x03, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        let packet = WhoopPacket::new(PacketType::HistoricalData, 7, 0, data);
+        notification(DATA_FROM_STRAP, packet)
+    }
+
+    #[tokio::test]
+    async fn full_sync_through_mock_transport() {
+        let first = 1735689600;
+        let enabled = WhoopPacket::new(
+            PacketType::Event,
+            0,
+            EventNumber::HighFreqSyncEnabled as u8,
+            vec![0x00, 0, 0, 0, 0],
+        );
+        let mut script = vec![
+            notification(EVENTS_FROM_STRAP, enabled),
+            metadata(MetadataType::HistoryStart, first, 0),
+        ];
+        script.extend((0..5).map(|i| reading(first + i)));
+        script.push(metadata(MetadataType::HistoryEnd, first + 5, 100));
+        script.push(metadata(MetadataType::HistoryComplete, first + 6, 0));
+
+        let transport = MockTransport::new(script);
+        let writes = transport.writes.clone();
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(transport, db, false).with_device("band");
+
+        device.connect().await.unwrap();
+        device.initialize().await.unwrap();
+        let outcome = device
+            .sync_history(Arc::new(AtomicBool::new(false)))
+            .await
+            .unwrap();
+
+        assert!(outcome.complete);
+        assert_eq!(outcome.last_unix, Some(first + 6));
+
+        let readings = device
+            .whoop
+            .database
+            .search_history(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(readings.len(), 5);
+        let pointer = device
+            .whoop
+            .database
+            .get_sync_pointer("band")
+            .await
+            .unwrap();
+        assert_eq!(pointer, Some(100));
+
+        // History is only requested once high frequency sync is on, and the chunk acknowledged
+        let writes = writes.lock().unwrap();
+        let position = |packet: WhoopPacket| {
+            let framed = packet.framed_packet();
+            writes.iter().position(|w| *w == framed)
+        };
+        let enter = position(WhoopPacket::enter_high_freq_sync()).unwrap();
+        let start = position(WhoopPacket::history_start()).unwrap();
+        let ack = position(WhoopPacket::history_end(100)).unwrap();
+        assert!(enter < start && start < ack);
+    }
+
+    fn history_script(first: u32, pointer: u32) -> Vec<ValueNotification> {
+        let enabled = WhoopPacket::new(
+            PacketType::Event,
+            0,
+            EventNumber::HighFreqSyncEnabled as u8,
+            vec![0x00, 0, 0, 0, 0],
+        );
+        let mut script = vec![
+            notification(EVENTS_FROM_STRAP, enabled),
+            metadata(MetadataType::HistoryStart, first, 0),
+        ];
+        script.extend((0..3).map(|i| reading(first + i)));
+        script.push(metadata(MetadataType::HistoryEnd, first + 3, pointer));
+        script.push(metadata(MetadataType::HistoryComplete, first + 4, 0));
+        script
+    }
+
+    #[tokio::test]
+    async fn parsed_packets_are_kept_for_rerun() {
+        let script = history_script(1735689600, 100);
+        let count = script.len();
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(MockTransport::new(script), db, true);
+
+        device.connect().await.unwrap();
+        device.initialize().await.unwrap();
+        device
+            .sync_history(Arc::new(AtomicBool::new(false)))
+            .await
+            .unwrap();
+
+        let database = device.whoop.database.clone();
+        assert_eq!(database.get_packets(0).await.unwrap().len(), count);
+
+        let stats = OpenWhoop::new(database.clone()).rerun(0).await.unwrap();
+        assert_eq!(stats.handled, count);
+        let readings = database
+            .search_history(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(readings.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn unparsed_packets_are_kept_without_debug_packets() {
+        let first = 1735689600;
+        let mut script = history_script(first, 100);
+        let unparsed = WhoopPacket::new(PacketType::RealtimeData, 0, 0, vec![1, 2, 3, 4]);
+        script.insert(3, notification(DATA_FROM_STRAP, unparsed.clone()));
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(MockTransport::new(script), db, false);
+
+        device.connect().await.unwrap();
+        device.initialize().await.unwrap();
+        device
+            .sync_history(Arc::new(AtomicBool::new(false)))
+            .await
+            .unwrap();
+
+        // Parsed packets aren't kept, the one the codec can't parse yet is
+        let packets = device.whoop.database.get_packets(0).await.unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].uuid, DATA_FROM_STRAP);
+        assert_eq!(packets[0].bytes, unparsed.framed_packet());
+
+        let stats = OpenWhoop::new(device.whoop.database.clone())
+            .rerun(0)
+            .await
+            .unwrap();
+        assert_eq!(stats.unimplemented, 1);
+    }
+
+    #[tokio::test]
+    async fn data_range_skips_other_responses() {
+        let version = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::ReportVersionInfo.as_u8(),
+            vec![0; 8],
+        );
+        let mut data = vec![0; 3];
+        data.extend_from_slice(&1200_u32.to_le_bytes());
+        data.extend_from_slice(&98_000_u32.to_le_bytes());
+        let range = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::GetDataRange.as_u8(),
+            data,
+        );
+        let script = vec![
+            notification(CMD_FROM_STRAP, version),
+            notification(CMD_FROM_STRAP, range),
+        ];
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(MockTransport::new(script), db, false);
+        assert_eq!(device.get_data_range().await.unwrap(), (1200, 98_000));
+    }
+
+    #[tokio::test]
+    async fn version_is_recorded_for_the_device() {
+        let mut data = vec![0; 3];
+        for part in [41_u32, 16, 6, 0, 17, 2, 2, 0] {
+            data.extend_from_slice(&part.to_le_bytes());
+        }
+        let version = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::ReportVersionInfo.as_u8(),
+            data,
+        );
+        let script = vec![notification(CMD_FROM_STRAP, version)];
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(MockTransport::new(script), db.clone(), false)
+            .with_device("AA:BB:CC:DD:EE:01");
+        let (harvard, boylston) = device.get_version().await.unwrap();
+        assert_eq!(
+            (harvard.as_str(), boylston.as_str()),
+            ("41.16.6.0", "17.2.2.0")
+        );
+
+        let history = db.get_firmware_history().await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].device.as_deref(), Some("AA:BB:CC:DD:EE:01"));
+        assert_eq!(history[0].harvard, "41.16.6.0");
+    }
+
+    #[tokio::test]
+    async fn body_location_skips_other_responses() {
+        let version = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::ReportVersionInfo.as_u8(),
+            vec![0; 8],
+        );
+        let location = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::GetBodyLocationAndStatus.as_u8(),
+            vec![0, 0, 0, 1, 1],
+        );
+        let script = vec![
+            notification(CMD_FROM_STRAP, version),
+            notification(CMD_FROM_STRAP, location),
+        ];
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(MockTransport::new(script), db, false);
+        device.get_body_location().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn alarm_skips_other_responses() {
+        let version = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::ReportVersionInfo.as_u8(),
+            vec![0; 8],
+        );
+        let mut data = vec![0, 0, 0, 1];
+        data.extend_from_slice(&1735714800_u32.to_le_bytes());
+        let alarm = WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            CommandNumber::GetAlarmTime.as_u8(),
+            data,
+        );
+        let script = vec![
+            notification(CMD_FROM_STRAP, version),
+            notification(CMD_FROM_STRAP, alarm),
+        ];
+
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(MockTransport::new(script), db, false);
+        device.get_alarm().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn two_bands_sync_into_one_database() {
+        let first = 1735689600;
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let band = |first, pointer, device: &str| {
+            let transport = MockTransport::new(history_script(first, pointer));
+            WhoopDevice::with_transport(transport, db.clone(), false).with_device(device)
+        };
+        let mut left = band(first, 100, "AA:BB:CC:DD:EE:01");
+        let mut right = band(first + 60, 200, "AA:BB:CC:DD:EE:02");
+
+        let sync = async |device: &mut WhoopDevice<MockTransport>| {
+            device.connect().await.unwrap();
+            device.initialize().await.unwrap();
+            device
+                .sync_history(Arc::new(AtomicBool::new(false)))
+                .await
+                .unwrap()
+        };
+        let (left_outcome, right_outcome) = tokio::join!(sync(&mut left), sync(&mut right));
+        assert!(left_outcome.complete && right_outcome.complete);
+
+        let readings = db.search_history(SearchHistory::default()).await.unwrap();
+        assert_eq!(readings.len(), 6);
+        let left = db.get_sync_pointer("AA:BB:CC:DD:EE:01").await.unwrap();
+        let right = db.get_sync_pointer("AA:BB:CC:DD:EE:02").await.unwrap();
+        assert_eq!((left, right), (Some(100), Some(200)));
+    }
+
+    #[tokio::test]
+    async fn raw_capture_writes_raw_data_payloads() {
+        let raw = |cmd, data: Vec<u8>| {
+            notification(
+                DATA_FROM_STRAP,
+                WhoopPacket::new(PacketType::RealtimeRawData, 0, cmd, data),
+            )
+        };
+        let script = vec![
+            raw(0, vec![0x01, 0x02, 0x03]),
+            reading(1735689600),
+            raw(0x1a, vec![0xff, 0x00]),
+        ];
+
+        let transport = MockTransport::new(script);
+        let writes = transport.writes.clone();
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(transport, db, false);
+        device.connect().await.unwrap();
+
+        let mut output = Vec::new();
+        let count = device
+            .capture_raw_data(&mut output, Duration::from_millis(50))
+            .await
+            .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(String::from_utf8(output).unwrap(), "00 010203\n1a ff00\n");
+
+        let writes = writes.lock().unwrap();
+        let start = WhoopPacket::start_raw_data().framed_packet();
+        let stop = WhoopPacket::stop_raw_data().framed_packet();
+        assert_eq!(writes.first(), Some(&start));
+        assert_eq!(writes.last(), Some(&stop));
+    }
+
+    #[tokio::test]
+    async fn raw_capture_stops_the_stream_on_error() {
+        struct FullDisk;
+
+        impl Write for FullDisk {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::StorageFull.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
                 Ok(())
             }
-            Ok(None) => Err(anyhow!("stream ended unexpectedly")),
-            Err(_) => Err(anyhow!("timed out waiting for version notification")),
         }
+
+        let packet = WhoopPacket::new(PacketType::RealtimeRawData, 0, 0, vec![0x01]);
+        let transport = MockTransport::new(vec![notification(DATA_FROM_STRAP, packet)]);
+        let writes = transport.writes.clone();
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(transport, db, false);
+        device.connect().await.unwrap();
+
+        let result = device
+            .capture_raw_data(&mut FullDisk, Duration::from_secs(60))
+            .await;
+        assert!(matches!(result, Err(OpenWhoopError::Io(_))), "{:?}", result);
+
+        let stop = WhoopPacket::stop_raw_data().framed_packet();
+        assert_eq!(writes.lock().unwrap().last(), Some(&stop));
+    }
+
+    #[test]
+    fn weak_signal_warns_or_refuses() {
+        let min = DEFAULT_MIN_RSSI;
+        assert_eq!(signal_quality(Some(-60), min, false), SignalQuality::Good);
+        assert_eq!(signal_quality(Some(-85), min, true), SignalQuality::Good);
+        assert_eq!(signal_quality(Some(-92), min, false), SignalQuality::Weak);
+        assert_eq!(signal_quality(Some(-92), min, true), SignalQuality::TooWeak);
+        assert_eq!(signal_quality(None, min, true), SignalQuality::Good);
+    }
+
+    #[tokio::test]
+    async fn version_is_read_from_the_response() {
+        let enabled = WhoopPacket::new(
+            PacketType::Event,
+            0,
+            EventNumber::HighFreqSyncEnabled as u8,
+            vec![0x00, 0, 0, 0, 0],
+        );
+        // Captured from a strap
+        let response = hex::decode("aa50000c2477070a01012900000011000000020000000000000011000000020000000200000000000000030000000400000000000000000000000300000006000000000000000000000008050100000074b95569").unwrap();
+        let transport = MockTransport::new(vec![
+            notification(EVENTS_FROM_STRAP, enabled),
+            ValueNotification {
+                uuid: CMD_FROM_STRAP,
+                value: response,
+            },
+        ]);
+        let writes = transport.writes.clone();
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(transport, db, false);
+
+        let (harvard, boylston) = device.get_version().await.unwrap();
+        assert_eq!(harvard, "41.17.2.0");
+        assert_eq!(boylston, "17.2.2.0");
+        assert_eq!(
+            *writes.lock().unwrap(),
+            vec![WhoopPacket::version().framed_packet()]
+        );
+    }
+
+    #[tokio::test]
+    async fn commands_fail_with_not_connected_after_the_link_drops() {
+        let mut transport = MockTransport::new(Vec::new());
+        transport.connected = false;
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let mut device = WhoopDevice::with_transport(transport, db, false);
+
+        let error = device.initialize().await.unwrap_err();
+        assert!(matches!(error, OpenWhoopError::NotConnected), "{error:?}");
+
+        // btleplug reports the same condition through its own error
+        let error = OpenWhoopError::from(btleplug::Error::NotConnected);
+        assert!(matches!(error, OpenWhoopError::NotConnected));
     }
 }
@@ -0,0 +1,43 @@
+use openwhoop_codec::WhoopError;
+use openwhoop_db::DbError;
+use thiserror::Error;
+
+/// Errors returned by [`crate::WhoopDevice`], [`crate::OpenWhoop`] and transports
+#[derive(Debug, Error)]
+pub enum OpenWhoopError {
+    /// The strap isn't connected, or the link dropped
+    #[error("Whoop is not connected")]
+    NotConnected,
+    #[error(transparent)]
+    Database(#[from] DbError),
+    #[error(transparent)]
+    Bluetooth(btleplug::Error),
+    /// Error of a [`crate::transport::Transport`] other than Bluetooth
+    #[error(transparent)]
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    #[error(transparent)]
+    Packet(#[from] WhoopError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Task(#[from] tokio::task::JoinError),
+    /// The notification stream ended while waiting for a response
+    #[error("stream ended unexpectedly")]
+    StreamEnded,
+    #[error("timed out waiting for {0} notification")]
+    Timeout(&'static str),
+    #[error("strap did not acknowledge command {0}")]
+    NotAcknowledged(u8),
+    /// Arguments, or data from the strap, that can't be used
+    #[error("{0}")]
+    Invalid(String),
+}
+
+impl From<btleplug::Error> for OpenWhoopError {
+    fn from(error: btleplug::Error) -> Self {
+        match error {
+            btleplug::Error::NotConnected => Self::NotConnected,
+            error => Self::Bluetooth(error),
+        }
+    }
+}
@@ -5,11 +5,21 @@ pub mod db {
     pub use openwhoop_db::*;
 }
 
+mod error;
+pub use error::OpenWhoopError;
+
 mod device;
-pub use device::WhoopDevice;
+pub use device::{DEFAULT_MIN_RSSI, SignalQuality, WhoopDevice, signal_quality};
+
+pub mod transport;
 
 mod openwhoop;
-pub use openwhoop::OpenWhoop;
+pub use openwhoop::{
+    DailySummary, HighFreqSync, OpenWhoop, PacketOutcome, PacketStats, SyncOutcome,
+};
+
+mod replay;
+pub use replay::ReplayMismatch;
 
 pub mod api;
 
@@ -2,7 +2,10 @@
 extern crate log;
 
 use std::{
-    io,
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{
         Arc,
@@ -16,20 +19,30 @@ use btleplug::{
     api::{BDAddr, Central, Manager as _, Peripheral as _, ScanFilter},
     platform::{Adapter, Manager, Peripheral},
 };
-use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeDelta, Utc};
-use clap::{CommandFactory, Parser, Subcommand};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
 use clap_complete::{Shell, generate};
-use openwhoop_entities::packets;
+use directories::ProjectDirs;
 use dotenv::dotenv;
+use openwhoop::api;
 use openwhoop::{
-    OpenWhoop, WhoopDevice,
-    algo::{ExerciseMetrics, SleepConsistencyAnalyzer},
-    db::DatabaseHandler,
+    DEFAULT_MIN_RSSI, OpenWhoop, SignalQuality, WhoopDevice,
+    algo::{
+        ActivityConfig, ExerciseMetrics, HrvConfig, Sex, SleepConsistencyAnalyzer,
+        SleepScoreConfig, StrainCalculator, WeeklyTrends, acwr, helpers::units::UnitSystem,
+        smart_wake_time,
+    },
+    db::{DEFAULT_BUSY_TIMEOUT, DatabaseHandler, KnownDevice, SearchHistory, UserProfile},
+    signal_quality,
     types::activities::{ActivityType, SearchActivityPeriods},
 };
+use openwhoop_codec::{
+    WhoopPacket, Wrist,
+    constants::{CommandNumber, WHOOP_SERVICE},
+};
+use openwhoop_entities::packets;
 use tokio::time::sleep;
-use openwhoop::api;
-use openwhoop_codec::{WhoopPacket, constants::WHOOP_SERVICE};
+use uuid::Uuid;
 
 #[cfg(target_os = "linux")]
 pub type DeviceId = BDAddr;
@@ -39,13 +52,32 @@ pub type DeviceId = String;
 
 #[derive(Parser)]
 pub struct OpenWhoopCli {
-    #[arg(env, long)]
+    /// Keep every raw packet, not only those that fail to parse, so `re-run` can
+    /// re-derive everything once the parser improves
+    #[arg(env, long, visible_alias = "keep-packets")]
     pub debug_packets: bool,
+    /// Defaults to `db.sqlite` in the data directory
     #[arg(env, long)]
-    pub database_url: String,
+    pub database_url: Option<String>,
+    /// Directory of the default database, defaults to the platform's data directory
+    #[arg(env, long)]
+    pub data_dir: Option<PathBuf>,
+    /// How long SQLite waits for a lock held by another connection, in milliseconds
+    #[arg(env, long, default_value_t = DEFAULT_BUSY_TIMEOUT.as_millis() as u64)]
+    pub busy_timeout_ms: u64,
     #[cfg(target_os = "linux")]
     #[arg(env, long)]
     pub ble_interface: Option<String>,
+    /// Service UUID to scan for, for bands that don't advertise the usual Whoop service
+    #[arg(env, long)]
+    pub service_uuid: Option<Uuid>,
+    /// Scan every BLE device instead of only those advertising a Whoop service
+    #[arg(env, long, conflicts_with = "service_uuid")]
+    pub scan_all: bool,
+    /// `metric` or `imperial`, for printed temperatures. Exports always stay metric so
+    /// they import anywhere
+    #[arg(env, long, default_value = "metric", value_parser = parse_units)]
+    pub units: UnitSystem,
     #[clap(subcommand)]
     pub subcommand: OpenWhoopCommand,
 }
@@ -55,35 +87,201 @@ pub enum OpenWhoopCommand {
     ///
     /// Scan for Whoop devices
     ///
-    Scan,
+    Scan {
+        /// Stop after this many seconds and list the devices found, strongest signal first
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
     ///
     /// Download history data from whoop devices
     ///
     DownloadHistory {
-        #[arg(long, env)]
-        whoop: DeviceId,
+        #[command(flatten)]
+        whoop: WhoopArg,
+        /// Keep every Nth IMU sample, 0 drops IMU data
+        #[arg(long, default_value_t = 1)]
+        imu_downsample: usize,
+        /// Append decoded console logs from the strap to this file
+        #[arg(long)]
+        log_console: Option<PathBuf>,
+        /// Download all history the strap still holds, overwriting stored readings.
+        /// Experimental: the strap's data range response isn't verified against a capture
+        #[arg(long)]
+        full: bool,
+        /// Store raw bytes of packets with an unknown type or command
+        #[arg(long)]
+        capture_unknown: bool,
+        /// Parse packets failing their CRC32 instead of dropping them, some BLE adapters flip
+        /// the odd bit. Their readings are flagged low confidence
+        #[arg(long)]
+        lenient_crc: bool,
+        /// Signal strength in dBm below which the connection counts as weak
+        #[arg(long, env, default_value_t = DEFAULT_MIN_RSSI, allow_hyphen_values = true)]
+        min_rssi: i16,
+        /// Don't sync over a weak connection instead of warning about it
+        #[arg(long)]
+        refuse_weak_signal: bool,
     },
     ///
     /// Reruns the packet processing on stored packets
     /// This is used after new more of packets get handled
     ///
-    ReRun,
+    ReRun {
+        /// Keep every Nth IMU sample, 0 drops IMU data
+        #[arg(long, default_value_t = 1)]
+        imu_downsample: usize,
+        /// Only re-run packets with this id or later
+        #[arg(long, conflicts_with = "since_date")]
+        since_id: Option<i32>,
+        /// Only re-run packets recorded on this date (YYYY-MM-DD) or later
+        #[arg(long)]
+        since_date: Option<NaiveDate>,
+        /// Threads parsing packets, defaults to the number of CPUs
+        #[arg(long)]
+        workers: Option<usize>,
+        /// Store raw bytes of packets with an unknown type or command
+        #[arg(long)]
+        capture_unknown: bool,
+        /// Parse packets failing their CRC32, flagging their readings low confidence
+        #[arg(long)]
+        lenient_crc: bool,
+    },
     ///
     /// Detects sleeps and exercises
     ///
-    DetectEvents,
+    DetectEvents {
+        /// Shortest workout in minutes kept as its own activity
+        #[arg(long, default_value_t = 15)]
+        min_active_minutes: i64,
+        /// Readings in a row a new state needs before detection switches to it, raise it if
+        /// restless nights split sleep into pieces
+        #[arg(long, default_value_t = 1)]
+        hysteresis: usize,
+        /// Largest deviation of an RR interval from its neighbours' median kept for sleep
+        /// HRV, as a fraction of that median
+        #[arg(long, default_value_t = 0.2)]
+        rr_max_deviation: f64,
+        /// Weight of time asleep in the sleep score
+        #[arg(long, default_value_t = 1.0)]
+        score_duration_weight: f64,
+        /// Weight of sleep efficiency in the sleep score
+        #[arg(long, default_value_t = 0.0)]
+        score_efficiency_weight: f64,
+        /// Weight of whole-night HRV in the sleep score
+        #[arg(long, default_value_t = 0.0)]
+        score_hrv_weight: f64,
+        /// Weight of wake-ups during the night in the sleep score
+        #[arg(long, default_value_t = 0.0)]
+        score_disturbance_weight: f64,
+    },
+    ///
+    /// Correct the type of a detected activity, detection won't overwrite the label
+    ///
+    Label {
+        /// Start of the activity, e.g. `2025-01-01T08:00:00`
+        start: NaiveDateTime,
+        /// Activity type, e.g. `Running` or `Weightlifting`
+        #[arg(value_parser = parse_activity_type)]
+        activity: ActivityType,
+    },
     ///
-    /// Print sleep statistics for all time and last week
+    /// Print sleep statistics for all time and the last few days
     ///
-    SleepStats,
+    SleepStats {
+        /// Number of most recent nights in the windowed view
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+    },
     ///
     /// Print activity statistics for all time and last week
     ///
-    ExerciseStats,
+    ExerciseStats {
+        #[command(flatten)]
+        profile: ProfileArgs,
+        /// Strain zones from percentiles of your own exercise heart rate instead of %HRR
+        #[arg(long)]
+        personal_zones: bool,
+    },
+    ///
+    /// Print week over week trends of resting HR, HRV, sleep and strain
+    ///
+    Trends {
+        /// Number of most recent weeks to compare
+        #[arg(long, default_value_t = 4)]
+        weeks: u32,
+        /// Days the daily resting HR trend is averaged over
+        #[arg(long, default_value_t = 7)]
+        rhr_window: u32,
+        #[command(flatten)]
+        profile: ProfileArgs,
+        /// Strain zones from percentiles of your own exercise heart rate instead of %HRR
+        #[arg(long)]
+        personal_zones: bool,
+    },
+    ///
+    /// Print sleep, recovery, strain, stress and wear time of one day, today by default
+    ///
+    Summary {
+        date: Option<NaiveDate>,
+        #[command(flatten)]
+        profile: ProfileArgs,
+        /// Strain zones from percentiles of your own exercise heart rate instead of %HRR
+        #[arg(long)]
+        personal_zones: bool,
+    },
+    ///
+    /// Store user parameters used by strain and calorie calculations, prints the profile
+    ///
+    Profile {
+        #[command(flatten)]
+        profile: ProfileArgs,
+    },
+    ///
+    /// Print how much of each recent day the strap was worn, flagging days with too little data
+    ///
+    WearTime {
+        /// Number of most recent days to print
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+        /// Days worn for less than this fraction are flagged
+        #[arg(long, default_value_t = 0.8)]
+        min_fraction: f64,
+    },
+    ///
+    /// Print the moments marked by double tapping the band
+    ///
+    Markers {
+        /// Only markers from this time on, e.g. `2025-01-01T08:00:00`
+        #[arg(long)]
+        since: Option<NaiveDateTime>,
+    },
+    ///
+    /// Print when the band was charging, readings from then are left out of every aggregate
+    ///
+    Charging {
+        /// Only periods from this time on, e.g. `2025-01-01T08:00:00`
+        #[arg(long)]
+        since: Option<NaiveDateTime>,
+    },
+    ///
+    /// Print the firmware versions the band reported during syncs
+    ///
+    Firmware,
+    ///
+    /// Manage bands saved under a nickname, usable in place of their device id
+    ///
+    Devices {
+        #[command(subcommand)]
+        action: DevicesCommand,
+    },
     ///
     /// Calculate stress for historical data
     ///
-    CalculateStress,
+    CalculateStress {
+        /// Also store a moving average of stress over this many readings, for smoother charts
+        #[arg(long)]
+        smoothing_span: Option<usize>,
+    },
     ///
     /// Calculate SpO2 from raw sensor data
     ///
@@ -96,31 +294,142 @@ pub enum OpenWhoopCommand {
     /// Set alarm
     ///
     SetAlarm {
-        #[arg(long, env)]
-        whoop: DeviceId,
+        #[command(flatten)]
+        whoop: WhoopArg,
         alarm_time: AlarmTime,
     },
     ///
-    /// Copy packets from one database into another
+    /// Read back the alarm currently set on the strap
+    ///
+    GetAlarm {
+        #[command(flatten)]
+        whoop: WhoopArg,
+    },
+    ///
+    /// Wake up between `earliest` and `latest` (local time), as soon as sleep gets light
+    ///
+    SmartAlarm {
+        #[command(flatten)]
+        whoop: WhoopArg,
+        earliest: NaiveTime,
+        latest: NaiveTime,
+    },
+    ///
+    /// Cancel the alarm currently set on the strap
+    ///
+    DisableAlarm {
+        #[command(flatten)]
+        whoop: WhoopArg,
+    },
+    ///
+    /// Merge another openwhoop database into this one: its packets, sleeps, activities and
+    /// readings. An SQLite database is opened read-only, so it is never modified
     ///
-    Merge { from: String },
+    Merge {
+        /// Database url to copy from
+        from: String,
+    },
+    ///
+    /// Print how many packets, readings, sleeps and activities are stored
+    ///
+    Stats,
+    ///
+    /// Dump stored packets as newline-delimited hex into a capture file
+    ///
+    DumpPackets {
+        output: PathBuf,
+        #[arg(long)]
+        from_id: Option<i32>,
+    },
+    ///
+    /// Dump the packets captured with `--capture-unknown` in the `dump-packets` format
+    ///
+    DumpUnknown { output: PathBuf },
+    ///
+    /// Parse a capture file written by `dump-packets` without storing anything, printing the
+    /// parsed packets as JSON. Redirect the output to build an expected-output fixture.
+    ///
+    Replay {
+        capture: PathBuf,
+        /// Compare the parsed packets to this expected JSON, failing on any mismatch
+        #[arg(long)]
+        validate: Option<PathBuf>,
+    },
+    ///
+    /// Export readings, sleep cycles and activities to a checksummed file
+    ///
+    Export { output: PathBuf },
+    ///
+    /// Import a file written by `export`, refusing it if the checksum doesn't match
+    ///
+    Import { input: PathBuf },
+    ///
+    /// Import sleeps, recoveries and workouts from the unzipped data export of the WHOOP app
+    ///
+    ImportWhoop { path: PathBuf },
     Restart {
-        #[arg(long, env)]
-        whoop: DeviceId,
+        #[command(flatten)]
+        whoop: WhoopArg,
     },
     ///
     /// Erase all history data from the device
     ///
     Erase {
-        #[arg(long, env)]
-        whoop: DeviceId,
+        #[command(flatten)]
+        whoop: WhoopArg,
     },
     ///
     /// Get device firmware version info
     ///
     Version {
-        #[arg(long, env)]
-        whoop: DeviceId,
+        #[command(flatten)]
+        whoop: WhoopArg,
+    },
+    ///
+    /// Get which wrist the strap is on and whether it is being worn
+    ///
+    Status {
+        #[command(flatten)]
+        whoop: WhoopArg,
+    },
+    ///
+    /// Tell the strap which wrist it is worn on (`left` or `right`)
+    ///
+    SetWrist {
+        #[command(flatten)]
+        whoop: WhoopArg,
+        #[arg(value_parser = parse_wrist)]
+        wrist: Wrist,
+    },
+    ///
+    /// Send a raw command to the strap and print the packets it sends back
+    ///
+    Raw {
+        #[command(flatten)]
+        whoop: WhoopArg,
+        /// Command number
+        cmd: u8,
+        /// Payload as hex, e.g. `00` or `01ff`
+        #[arg(default_value = "", value_parser = parse_hex)]
+        data: HexBytes,
+        /// Seconds to listen for responses
+        #[arg(long, default_value_t = 5)]
+        listen: u64,
+        /// Allow commands that erase data or touch firmware, and unknown command numbers
+        #[arg(long)]
+        yes_i_know: bool,
+    },
+    ///
+    /// Stream raw optical and accelerometer data into a file, one packet per line as its
+    /// command byte and payload in hex. The packets aren't decoded yet
+    ///
+    RawCapture {
+        #[command(flatten)]
+        whoop: WhoopArg,
+        output: PathBuf,
+        /// Seconds to capture for
+        #[arg(long, default_value_t = 60)]
+        seconds: u64,
     },
     ///
     /// Generate Shell completions
@@ -130,8 +439,31 @@ pub enum OpenWhoopCommand {
     /// Enable IMU data
     ///
     EnableImu {
-        #[arg(long, env)]
-        whoop: DeviceId,
+        #[command(flatten)]
+        whoop: WhoopArg,
+    },
+    ///
+    /// Switch IMU data collection on or off and wait for the strap to confirm it
+    ///
+    ImuMode {
+        #[command(flatten)]
+        whoop: WhoopArg,
+        /// Turn IMU data on, `--on false` turns it off
+        #[arg(long, action = ArgAction::Set, default_value_t = true)]
+        on: bool,
+        /// Toggle IMU data embedded in history readings instead of the realtime stream
+        #[arg(long)]
+        historical: bool,
+    },
+    ///
+    /// Switch the optical sensors (PPG and SpO2 LEDs) on or off
+    ///
+    OpticalMode {
+        #[command(flatten)]
+        whoop: WhoopArg,
+        /// Turn the optical sensors on, `--on false` turns them off to save battery
+        #[arg(long, action = ArgAction::Set, default_value_t = true)]
+        on: bool,
     },
     ///
     /// Sync data between local and remote database
@@ -159,6 +491,23 @@ pub enum OpenWhoopCommand {
     },
 }
 
+#[derive(Subcommand)]
+pub enum DevicesCommand {
+    /// Save a band under a nickname, replacing the band already saved under it
+    Add {
+        nickname: String,
+        /// Address the band is found by, as printed by `scan`
+        address: String,
+        /// Advertised name, which identifies the band on macOS
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// List the saved bands
+    List,
+    /// Forget a saved band
+    Remove { nickname: String },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     if let Err(error) = dotenv() {
@@ -175,6 +524,43 @@ async fn main() -> anyhow::Result<()> {
     OpenWhoopCli::parse().run().await
 }
 
+/// File name of the database kept in the data directory
+const DATABASE_FILE: &str = "db.sqlite";
+
+/// `~/.local/share/openwhoop` (or under `$XDG_DATA_HOME`) on Linux,
+/// `~/Library/Application Support/openwhoop` on macOS
+fn default_data_dir() -> anyhow::Result<PathBuf> {
+    ProjectDirs::from("", "", "openwhoop")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .ok_or_else(|| anyhow!("No home directory found, set --database-url or --data-dir"))
+}
+
+/// SQLite url of the database in `data_dir`, creating the directory if needed
+fn database_url_in(data_dir: &Path) -> anyhow::Result<String> {
+    std::fs::create_dir_all(data_dir)?;
+    let path = data_dir.join(DATABASE_FILE);
+    Ok(format!("sqlite://{}?mode=rwc", path.display()))
+}
+
+async fn strain_calculator(
+    whoop: &OpenWhoop,
+    profile: &UserProfile,
+    personal_zones: bool,
+) -> anyhow::Result<StrainCalculator> {
+    let calculator = StrainCalculator::new(profile.max_hr(), profile.resting_hr());
+    if !personal_zones {
+        return Ok(calculator);
+    }
+
+    match whoop.personal_zones().await? {
+        Some(zones) => Ok(calculator.with_zones(zones)),
+        None => {
+            log::warn!("No exercise to derive personal zones from, using %HRR zones");
+            Ok(calculator)
+        }
+    }
+}
+
 async fn download_firmware(
     email: &str,
     password: &str,
@@ -193,9 +579,7 @@ async fn download_firmware(
     };
 
     let target_versions: std::collections::HashMap<&str, &str> =
-        [("MAXIM", maxim), ("NORDIC", nordic)]
-            .into_iter()
-            .collect();
+        [("MAXIM", maxim), ("NORDIC", nordic)].into_iter().collect();
 
     let current: Vec<api::ChipFirmware> = chip_names
         .iter()
@@ -227,15 +611,57 @@ async fn download_firmware(
     Ok(())
 }
 
+/// Looks `whoop` up as the nickname of a saved band, falling back to reading it as a device id
+async fn resolve_device(db: &DatabaseHandler, whoop: &str) -> anyhow::Result<DeviceId> {
+    match db.get_device(whoop).await? {
+        Some(device) => saved_device_id(&device),
+        None => parse_device_id(whoop),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn saved_device_id(device: &KnownDevice) -> anyhow::Result<DeviceId> {
+    parse_device_id(&device.address)
+}
+
+/// Bands are found by their advertised name on macOS
+#[cfg(target_os = "macos")]
+fn saved_device_id(device: &KnownDevice) -> anyhow::Result<DeviceId> {
+    let name = device.name.as_ref().unwrap_or(&device.address);
+    Ok(name.clone())
+}
+
+fn parse_device_id(whoop: &str) -> anyhow::Result<DeviceId> {
+    whoop
+        .parse()
+        .map_err(|error| anyhow!("{} is not a saved device or a device id: {}", whoop, error))
+}
+
+/// Service a Whoop is recognized by while scanning, `None` matches every device
+fn scan_service(service_uuid: Option<Uuid>, scan_all: bool) -> Option<Uuid> {
+    if scan_all {
+        None
+    } else {
+        Some(service_uuid.unwrap_or(WHOOP_SERVICE))
+    }
+}
+
+fn scan_filter(service: Option<Uuid>) -> ScanFilter {
+    ScanFilter {
+        services: service.into_iter().collect(),
+    }
+}
+
+fn advertises(services: &[Uuid], service: Option<Uuid>) -> bool {
+    service.is_none_or(|service| services.contains(&service))
+}
+
 async fn scan_command(
     adapter: &Adapter,
     device_id: Option<DeviceId>,
+    service: Option<Uuid>,
 ) -> anyhow::Result<Peripheral> {
-    adapter
-        .start_scan(ScanFilter {
-            services: vec![WHOOP_SERVICE],
-        })
-        .await?;
+    adapter.start_scan(scan_filter(service)).await?;
 
     loop {
         let peripherals = adapter.peripherals().await?;
@@ -245,7 +671,7 @@ async fn scan_command(
                 continue;
             };
 
-            if !properties.services.contains(&WHOOP_SERVICE) {
+            if !advertises(&properties.services, service) {
                 continue;
             }
 
@@ -277,6 +703,287 @@ async fn scan_command(
     }
 }
 
+/// A Whoop seen while scanning
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScannedDevice {
+    address: BDAddr,
+    name: Option<String>,
+    rssi: Option<i16>,
+}
+
+impl fmt::Display for ScannedDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.address)?;
+        match &self.rssi {
+            Some(rssi) => write!(f, "\t{} dBm", rssi)?,
+            None => write!(f, "\t? dBm")?,
+        }
+        if let Some(name) = &self.name {
+            write!(f, "\t{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the Whoops advertising during `timeout`, see [`dedup_devices`]
+async fn scan_devices(
+    adapter: &Adapter,
+    timeout: Duration,
+    service: Option<Uuid>,
+) -> anyhow::Result<Vec<ScannedDevice>> {
+    adapter.start_scan(scan_filter(service)).await?;
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut seen = Vec::new();
+    loop {
+        for peripheral in adapter.peripherals().await? {
+            let Some(properties) = peripheral.properties().await? else {
+                continue;
+            };
+
+            if advertises(&properties.services, service) {
+                let device = ScannedDevice {
+                    address: properties.address,
+                    name: properties.local_name,
+                    rssi: properties.rssi,
+                };
+                seen.push((peripheral.id(), device));
+            }
+        }
+
+        if tokio::time::Instant::now() >= deadline {
+            break;
+        }
+        sleep(Duration::from_secs(1).min(timeout)).await;
+    }
+
+    adapter.stop_scan().await?;
+    Ok(dedup_devices(seen))
+}
+
+/// One entry per peripheral id with its strongest signal and last known name, strongest
+/// first. Devices without a signal reading go last.
+///
+/// Devices are told apart by id rather than address, macOS reports every address as
+/// 00:00:00:00:00:00.
+fn dedup_devices<K: PartialEq>(seen: Vec<(K, ScannedDevice)>) -> Vec<ScannedDevice> {
+    let mut devices: Vec<(K, ScannedDevice)> = Vec::new();
+    for (id, device) in seen {
+        match devices.iter_mut().find(|(known, _)| *known == id) {
+            Some((_, known)) => {
+                known.rssi = known.rssi.max(device.rssi);
+                if device.name.is_some() {
+                    known.name = device.name;
+                }
+            }
+            None => devices.push((id, device)),
+        }
+    }
+
+    let mut devices = devices
+        .into_iter()
+        .map(|(_, device)| device)
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| b.rssi.cmp(&a.rssi).then(a.address.cmp(&b.address)));
+    devices
+}
+
+/// How long history is downloaded between smart alarm checks
+const SMART_ALARM_POLL: Duration = Duration::from_secs(120);
+/// Sleep history before the window used as the heart rate baseline
+const SMART_ALARM_BASELINE: TimeDelta = TimeDelta::hours(3);
+
+async fn smart_alarm(
+    mut whoop: WhoopDevice,
+    db_handler: DatabaseHandler,
+    earliest: NaiveTime,
+    latest: NaiveTime,
+) -> anyhow::Result<()> {
+    let latest = AlarmTime::Time(latest).checked_unix()?;
+    let mut earliest = AlarmTime::Time(earliest).checked_unix()?;
+    if earliest > latest {
+        earliest -= TimeDelta::days(1);
+    }
+
+    let to_local = |t: DateTime<Utc>| t.with_timezone(&Local).naive_local();
+
+    whoop.connect().await?;
+    whoop.initialize().await?;
+
+    // Fallback so the alarm fires at the end of the window no matter what
+    whoop
+        .send_command(WhoopPacket::alarm_time(latest.timestamp() as u32))
+        .await?;
+    println!(
+        "Alarm set for: {}, watching for light sleep from {}",
+        to_local(latest).format("%Y-%m-%d %H:%M:%S"),
+        to_local(earliest).format("%Y-%m-%d %H:%M:%S")
+    );
+
+    if let Ok(wait) = (earliest - Utc::now()).to_std() {
+        sleep(wait).await;
+    }
+
+    while Utc::now() + TimeDelta::minutes(1) < latest {
+        let should_exit = Arc::new(AtomicBool::new(false));
+        let se = should_exit.clone();
+        tokio::spawn(async move {
+            sleep(SMART_ALARM_POLL).await;
+            se.store(true, Ordering::SeqCst);
+        });
+
+        if !whoop.is_connected().await? {
+            whoop.connect().await?;
+            whoop.initialize().await?;
+        }
+        whoop.sync_history(should_exit).await?;
+
+        let options = SearchHistory {
+            from: Some(to_local(earliest - SMART_ALARM_BASELINE)),
+            ..Default::default()
+        };
+        let readings = db_handler.search_history(options).await?;
+        if let Some(time) = smart_wake_time(&readings, to_local(earliest), to_local(latest)) {
+            let alarm = Utc::now() + TimeDelta::minutes(1);
+            whoop
+                .send_command(WhoopPacket::alarm_time(alarm.timestamp() as u32))
+                .await?;
+            println!(
+                "Light sleep at {}, alarm moved to: {}",
+                time.format("%H:%M:%S"),
+                to_local(alarm).format("%Y-%m-%d %H:%M:%S")
+            );
+            return Ok(());
+        }
+    }
+
+    println!("No light sleep found, alarm stays at the end of the window");
+    Ok(())
+}
+
+/// Payload bytes parsed from a hex argument
+#[derive(Clone, Debug, PartialEq)]
+pub struct HexBytes(Vec<u8>);
+
+fn parse_hex(s: &str) -> anyhow::Result<HexBytes> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let s = s.replace([' ', ':'], "");
+    Ok(HexBytes(hex::decode(s)?))
+}
+
+/// Refuses commands that can erase data or brick the strap unless `yes_i_know` is set,
+/// including command numbers openwhoop doesn't know
+fn check_raw_command(cmd: u8, yes_i_know: bool) -> anyhow::Result<()> {
+    if yes_i_know {
+        return Ok(());
+    }
+    match CommandNumber::from_u8(cmd) {
+        Some(command) if command.is_dangerous() => Err(anyhow!(
+            "{:?} can erase data or brick the strap, pass --yes-i-know to send it",
+            command
+        )),
+        Some(_) => Ok(()),
+        None => Err(anyhow!(
+            "Command {} is unknown and might be dangerous, pass --yes-i-know to send it",
+            cmd
+        )),
+    }
+}
+
+fn imu_mode_packet(on: bool, historical: bool) -> WhoopPacket {
+    if historical {
+        WhoopPacket::toggle_imu_mode_historical(on)
+    } else {
+        WhoopPacket::toggle_imu_mode(on)
+    }
+}
+
+/// Optical data collection and the optical mode are switched together
+fn optical_mode_packets(on: bool) -> [WhoopPacket; 2] {
+    [
+        WhoopPacket::enable_optical_data(on),
+        WhoopPacket::toggle_optical_mode(on),
+    ]
+}
+
+fn confirm(question: &str) -> io::Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Band a command talks to
+#[derive(Args)]
+pub struct WhoopArg {
+    /// Device id, or the nickname of a band saved with `devices add`
+    #[arg(long, env, visible_alias = "device")]
+    whoop: String,
+}
+
+/// User parameters, each one overriding the stored profile when given
+#[derive(Args, Clone, Copy)]
+pub struct ProfileArgs {
+    /// Age in years, needed for calorie estimates
+    #[arg(long, env)]
+    age: Option<u8>,
+    /// Weight in kg, needed for calorie estimates
+    #[arg(long, env)]
+    weight_kg: Option<f64>,
+    /// `male` or `female`, needed for calorie estimates
+    #[arg(long, env, value_parser = parse_sex)]
+    sex: Option<Sex>,
+    /// Maximum heart rate used for strain zones, estimated from the age when unset
+    #[arg(long, env)]
+    max_hr: Option<u8>,
+    /// Resting heart rate used for strain zones
+    #[arg(long, env)]
+    resting_hr: Option<u8>,
+}
+
+impl From<ProfileArgs> for UserProfile {
+    fn from(args: ProfileArgs) -> Self {
+        Self {
+            age: args.age,
+            weight_kg: args.weight_kg,
+            sex: args.sex,
+            max_hr: args.max_hr,
+            resting_hr: args.resting_hr,
+        }
+    }
+}
+
+fn parse_sex(s: &str) -> anyhow::Result<Sex> {
+    match s.to_lowercase().as_str() {
+        "male" | "m" => Ok(Sex::Male),
+        "female" | "f" => Ok(Sex::Female),
+        _ => Err(anyhow!("Invalid sex, expected `male` or `female`")),
+    }
+}
+
+fn parse_wrist(s: &str) -> anyhow::Result<Wrist> {
+    match s.to_lowercase().as_str() {
+        "left" | "l" => Ok(Wrist::Left),
+        "right" | "r" => Ok(Wrist::Right),
+        _ => Err(anyhow!("Invalid wrist, expected `left` or `right`")),
+    }
+}
+
+fn parse_units(s: &str) -> anyhow::Result<UnitSystem> {
+    match s.to_lowercase().as_str() {
+        "metric" => Ok(UnitSystem::Metric),
+        "imperial" => Ok(UnitSystem::Imperial),
+        _ => Err(anyhow!("Invalid units, expected `metric` or `imperial`")),
+    }
+}
+
+fn parse_activity_type(s: &str) -> anyhow::Result<ActivityType> {
+    ActivityType::from_str(s).map_err(|_| anyhow!("Unknown activity type `{}`", s))
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum AlarmTime {
     DateTime(NaiveDateTime),
@@ -322,31 +1029,60 @@ impl FromStr for AlarmTime {
 }
 
 impl AlarmTime {
-    pub fn unix(self) -> DateTime<Utc> {
-        let mut now = Utc::now();
-        let timezone_df = Local::now().offset().to_owned();
+    /// Alarm time relative to `now`, with `DateTime` and `Time` read in the `timezone` local time
+    fn unix_at(self, now: DateTime<Utc>, timezone: FixedOffset) -> DateTime<Utc> {
+        now + self.offset(now, timezone)
+    }
 
-        match self {
-            AlarmTime::DateTime(dt) => dt.and_utc() - timezone_df,
-            AlarmTime::Time(t) => {
-                let current_time = now.time();
-                if current_time > t {
-                    now += TimeDelta::days(1);
-                }
+    /// Alarm time from now, rejecting alarms the strap would never fire
+    pub fn checked_unix(self) -> anyhow::Result<DateTime<Utc>> {
+        self.checked_unix_at(Utc::now(), *Local::now().offset())
+    }
 
-                now.with_time(t).unwrap() - timezone_df
-            }
-            _ => {
-                let offset = self.offset();
-                now + offset
-            }
+    fn checked_unix_at(
+        self,
+        now: DateTime<Utc>,
+        timezone: FixedOffset,
+    ) -> anyhow::Result<DateTime<Utc>> {
+        let time = self.unix_at(now, timezone);
+        let format = |t: DateTime<Utc>| {
+            t.with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+
+        if time < now {
+            return Err(anyhow!(
+                "Alarm time {} is in the past, current time: {}",
+                format(time),
+                format(now)
+            ));
+        }
+
+        if matches!(self, AlarmTime::Time(_)) && time - now > TimeDelta::days(1) {
+            return Err(anyhow!(
+                "Alarm time {} is more than 24h away, current time: {}",
+                format(time),
+                format(now)
+            ));
         }
+
+        Ok(time)
     }
 
-    fn offset(self) -> TimeDelta {
+    fn offset(self, now: DateTime<Utc>, timezone: FixedOffset) -> TimeDelta {
+        let local_now = now.with_timezone(&timezone).naive_local();
+
         match self {
-            AlarmTime::DateTime(_) => todo!(),
-            AlarmTime::Time(_) => todo!(),
+            AlarmTime::DateTime(dt) => dt - local_now,
+            AlarmTime::Time(t) => {
+                let mut alarm = local_now.date().and_time(t);
+                if local_now.time() > t {
+                    alarm += TimeDelta::days(1);
+                }
+
+                alarm - local_now
+            }
             AlarmTime::Minute => TimeDelta::minutes(1),
             AlarmTime::Minute5 => TimeDelta::minutes(5),
             AlarmTime::Minute10 => TimeDelta::minutes(10),
@@ -366,6 +1102,32 @@ pub fn sanitize_name(name: &str) -> String {
         .to_string()
 }
 
+impl OpenWhoopCommand {
+    /// Commands that only analyze stored data. They open SQLite read-only so they never
+    /// take the write lock a concurrent sync needs.
+    fn reads_only(&self) -> bool {
+        matches!(
+            self,
+            Self::SleepStats { .. }
+                | Self::ExerciseStats { .. }
+                | Self::Trends { .. }
+                | Self::Summary { .. }
+                | Self::WearTime { .. }
+                | Self::Markers { .. }
+                | Self::Charging { .. }
+                | Self::Firmware
+                | Self::Devices {
+                    action: DevicesCommand::List
+                }
+                | Self::Stats
+                | Self::DumpPackets { .. }
+                | Self::DumpUnknown { .. }
+                | Self::Replay { .. }
+                | Self::Export { .. }
+        )
+    }
+}
+
 impl OpenWhoopCli {
     async fn run(self) -> anyhow::Result<()> {
         if let OpenWhoopCommand::DownloadFirmware {
@@ -377,20 +1139,85 @@ impl OpenWhoopCli {
             output_dir,
         } = &self.subcommand
         {
-            return download_firmware(email, password, device_name, maxim, nordic, output_dir).await;
+            return download_firmware(email, password, device_name, maxim, nordic, output_dir)
+                .await;
         }
 
         let adapter = self.create_ble_adapter().await?;
-        let db_handler = DatabaseHandler::new(self.database_url).await;
-
+        let database_url = match &self.database_url {
+            Some(url) => url.clone(),
+            None => {
+                let data_dir = match &self.data_dir {
+                    Some(dir) => dir.clone(),
+                    None => default_data_dir()?,
+                };
+                database_url_in(&data_dir)?
+            }
+        };
+        let read_only = self.subcommand.reads_only() && database_url.starts_with("sqlite:");
+        let db_handler = if read_only {
+            DatabaseHandler::new_read_only(&database_url).await?
+        } else {
+            let busy_timeout = Duration::from_millis(self.busy_timeout_ms);
+            DatabaseHandler::with_busy_timeout(database_url, busy_timeout).await
+        };
+
+        let service = scan_service(self.service_uuid, self.scan_all);
         match self.subcommand {
-            OpenWhoopCommand::Scan => {
-                scan_command(&adapter, None).await?;
+            OpenWhoopCommand::Scan { timeout: None } => {
+                scan_command(&adapter, None, service).await?;
+            }
+            OpenWhoopCommand::Scan {
+                timeout: Some(timeout),
+            } => {
+                let devices = scan_devices(&adapter, Duration::from_secs(timeout), service).await?;
+                if devices.is_empty() {
+                    println!("No Whoop devices found");
+                }
+                for device in devices {
+                    println!("{}", device);
+                }
             }
-            OpenWhoopCommand::DownloadHistory { whoop } => {
-                let peripheral = scan_command(&adapter, Some(whoop)).await?;
+            OpenWhoopCommand::DownloadHistory {
+                whoop,
+                imu_downsample,
+                log_console,
+                full,
+                capture_unknown,
+                lenient_crc,
+                min_rssi,
+                refuse_weak_signal,
+            } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let device = whoop.to_string();
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
                 let mut whoop =
-                    WhoopDevice::new(peripheral, adapter, db_handler, self.debug_packets);
+                    WhoopDevice::new(peripheral, adapter, db_handler, self.debug_packets)
+                        .with_device(device)
+                        .with_imu_downsample(imu_downsample)
+                        .with_capture_unknown(capture_unknown)
+                        .with_lenient_crc(lenient_crc);
+
+                if let Some(path) = log_console {
+                    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+                    whoop = whoop.with_console_log(file);
+                }
+
+                let rssi = whoop.rssi().await?;
+                match signal_quality(rssi, min_rssi, refuse_weak_signal) {
+                    SignalQuality::Good => {}
+                    SignalQuality::Weak => warn!(
+                        "Weak signal ({} dBm), sync may be slow or drop, try moving closer",
+                        rssi.unwrap_or_default()
+                    ),
+                    SignalQuality::TooWeak => {
+                        return Err(anyhow!(
+                            "Signal too weak ({} dBm, below {} dBm), move closer and try again",
+                            rssi.unwrap_or_default(),
+                            min_rssi
+                        ));
+                    }
+                }
 
                 let should_exit = Arc::new(AtomicBool::new(false));
 
@@ -402,6 +1229,20 @@ impl OpenWhoopCli {
 
                 whoop.connect().await?;
                 whoop.initialize().await?;
+                if full {
+                    // The data range layout isn't confirmed by a capture, so the pointer it
+                    // yields is shown before the strap is rewound to it
+                    let (start, end) = whoop.get_data_range().await?;
+                    let question = format!(
+                        "Strap reports history from pointer {} to {}. Rewind to {} and \
+                         re-download all of it, overwriting stored readings?",
+                        start, end, start
+                    );
+                    if !confirm(&question)? {
+                        return Ok(());
+                    }
+                    whoop.rewind_history(start, end).await?;
+                }
 
                 let result = whoop.sync_history(should_exit).await;
 
@@ -422,60 +1263,114 @@ impl OpenWhoopCli {
                     }
                 }
             }
-            OpenWhoopCommand::ReRun => {
-                let mut whoop = OpenWhoop::new(db_handler.clone());
-                let mut id = 0;
-                loop {
-                    let packets = db_handler.get_packets(id).await?;
-                    if packets.is_empty() {
-                        break;
-                    }
-
-                    for packet in packets {
-                        id = packet.id;
-                        whoop.handle_packet(packet).await?;
+            OpenWhoopCommand::ReRun {
+                imu_downsample,
+                since_id,
+                since_date,
+                workers,
+                capture_unknown,
+                lenient_crc,
+            } => {
+                let mut whoop = OpenWhoop::new(db_handler)
+                    .with_imu_downsample(imu_downsample)
+                    .with_capture_unknown(capture_unknown)
+                    .with_lenient_crc(lenient_crc)
+                    .with_record_firmware(false)
+                    .with_rerunning(true);
+                let since_id = match (since_id, since_date) {
+                    (Some(id), _) => id,
+                    (None, Some(date)) => {
+                        let id = whoop.packet_id_since(date.and_time(NaiveTime::MIN)).await?;
+                        println!("re-running from packet {}", id);
+                        id
                     }
+                    (None, None) => 0,
+                };
 
-                    println!("{}", id);
-                }
+                let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+                let workers = workers.unwrap_or(cpus);
+                let stats = whoop.rerun_parallel(since_id, workers).await?;
+                println!("{}", stats);
             }
-            OpenWhoopCommand::DetectEvents => {
-                let whoop = OpenWhoop::new(db_handler);
+            OpenWhoopCommand::DetectEvents {
+                min_active_minutes,
+                hysteresis,
+                rr_max_deviation,
+                score_duration_weight,
+                score_efficiency_weight,
+                score_hrv_weight,
+                score_disturbance_weight,
+            } => {
+                let config = ActivityConfig {
+                    min_active_duration: TimeDelta::minutes(min_active_minutes),
+                    hysteresis,
+                    ..Default::default()
+                };
+                let hrv_config = HrvConfig {
+                    max_deviation: rr_max_deviation,
+                    ..Default::default()
+                };
+                let score_config = SleepScoreConfig {
+                    duration_weight: score_duration_weight,
+                    efficiency_weight: score_efficiency_weight,
+                    hrv_weight: score_hrv_weight,
+                    disturbance_weight: score_disturbance_weight,
+                    ..Default::default()
+                };
+                let whoop = OpenWhoop::new(db_handler)
+                    .with_activity_config(config)
+                    .with_hrv_config(hrv_config)
+                    .with_sleep_score_config(score_config);
                 whoop.detect_sleeps().await?;
                 whoop.detect_events().await?;
             }
-            OpenWhoopCommand::SleepStats => {
+            OpenWhoopCommand::Label { start, activity } => {
+                db_handler.relabel_activity(start, activity).await?;
+                println!("Labeled activity starting at {} as {}", start, activity);
+            }
+            OpenWhoopCommand::SleepStats { days } => {
                 let whoop = OpenWhoop::new(db_handler);
-                let sleep_records = whoop.database.get_sleep_cycles(None).await?;
+                let sleep_records = whoop.database.get_sleep_cycles(None, None).await?;
 
                 if sleep_records.is_empty() {
                     println!("No sleep records found, exiting now");
                     return Ok(());
                 }
 
-                let mut last_week = sleep_records
-                    .iter()
-                    .rev()
-                    .take(7)
-                    .copied()
-                    .collect::<Vec<_>>();
-
-                last_week.reverse();
-                let analyzer = SleepConsistencyAnalyzer::new(sleep_records);
-                let metrics = analyzer.calculate_consistency_metrics();
+                // Nights are counted back from the most recent one
+                let latest = sleep_records.iter().map(|sleep| sleep.id).max();
+                let metrics =
+                    SleepConsistencyAnalyzer::new(sleep_records).calculate_consistency_metrics();
                 println!("All time: \n{}", metrics);
-                let analyzer = SleepConsistencyAnalyzer::new(last_week);
-                let metrics = analyzer.calculate_consistency_metrics();
-                println!("\nWeek: \n{}", metrics);
+
+                let recent = match latest {
+                    Some(latest) if days > 0 => {
+                        let from = latest - TimeDelta::days(i64::from(days) - 1);
+                        whoop
+                            .database
+                            .get_sleep_cycles(Some(from), Some(latest))
+                            .await?
+                    }
+                    _ => Vec::new(),
+                };
+                let metrics = SleepConsistencyAnalyzer::new(recent).calculate_consistency_metrics();
+                println!("\nLast {} days: \n{}", days, metrics);
             }
-            OpenWhoopCommand::ExerciseStats => {
+            OpenWhoopCommand::ExerciseStats {
+                profile,
+                personal_zones,
+            } => {
                 let whoop = OpenWhoop::new(db_handler);
+                let stored = whoop.database.get_profile().await?;
+                let profile = stored.with_overrides(profile.into());
+                // Detected workouts may carry an inferred type, everything but naps is exercise
                 let exercises = whoop
                     .database
-                    .search_activities(
-                        SearchActivityPeriods::default().with_activity(ActivityType::Activity),
-                    )
-                    .await?;
+                    .search_activities(SearchActivityPeriods::default())
+                    .await?
+                    .into_iter()
+                    .filter(|a| !matches!(a.activity, ActivityType::Nap))
+                    .collect::<Vec<_>>();
 
                 if exercises.is_empty() {
                     println!("No activities found, exiting now");
@@ -490,14 +1385,159 @@ impl OpenWhoopCli {
                     .rev()
                     .collect::<Vec<_>>();
 
-                let metrics = ExerciseMetrics::new(exercises);
-                let last_week = ExerciseMetrics::new(last_week);
+                let calculator = strain_calculator(&whoop, &profile, personal_zones).await?;
+                let daily_strain = whoop.daily_strain(&exercises, &calculator).await?;
+
+                let calorie_config = profile.calorie_config();
+                let calories = whoop.exercise_calories(&exercises, &calorie_config).await?;
+                let last_week_calories =
+                    whoop.exercise_calories(&last_week, &calorie_config).await?;
+
+                let metrics = ExerciseMetrics::new(exercises).with_calories(calories);
+                let last_week = ExerciseMetrics::new(last_week).with_calories(last_week_calories);
 
                 println!("All time: \n{}", metrics);
                 println!("Last week: \n{}", last_week);
+                match acwr(&daily_strain) {
+                    Some(ratio) => println!("ACWR: {:.2}", ratio),
+                    None => println!("ACWR: not enough history (28 days required)"),
+                }
+            }
+            OpenWhoopCommand::Trends {
+                weeks,
+                rhr_window,
+                profile,
+                personal_zones,
+            } => {
+                let whoop = OpenWhoop::new(db_handler);
+                let stored = whoop.database.get_profile().await?;
+                let profile = stored.with_overrides(profile.into());
+                let today = Local::now().date_naive();
+                let since = (today - TimeDelta::weeks(i64::from(weeks))).and_hms_opt(0, 0, 0);
+
+                let sleeps = whoop
+                    .database
+                    .get_sleep_cycles(since.map(|since| since.date()), Some(today))
+                    .await?;
+                let exercises = whoop
+                    .database
+                    .search_activities(SearchActivityPeriods {
+                        from: since,
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_iter()
+                    .filter(|a| !matches!(a.activity, ActivityType::Nap))
+                    .collect::<Vec<_>>();
+
+                let calculator = strain_calculator(&whoop, &profile, personal_zones).await?;
+                let daily_strain = whoop.daily_strain(&exercises, &calculator).await?;
+
+                println!(
+                    "{}",
+                    WeeklyTrends::new(&sleeps, &daily_strain, weeks, today)
+                );
+
+                let trend = whoop.database.resting_hr_trend(rhr_window).await?;
+                let start = today - TimeDelta::weeks(i64::from(weeks));
+                println!("Resting HR trend ({} day average):", rhr_window);
+                for (date, bpm) in trend.into_iter().filter(|(date, _)| *date > start) {
+                    println!("\t{}: {}", date, bpm);
+                }
             }
-            OpenWhoopCommand::CalculateStress => {
+            OpenWhoopCommand::Summary {
+                date,
+                profile,
+                personal_zones,
+            } => {
                 let whoop = OpenWhoop::new(db_handler);
+                let stored = whoop.database.get_profile().await?;
+                let profile = stored.with_overrides(profile.into());
+                let date = date.unwrap_or_else(|| Local::now().date_naive());
+
+                let calculator = strain_calculator(&whoop, &profile, personal_zones).await?;
+                let summary = whoop.daily_summary(date, &calculator).await?;
+                println!("{}", summary.with_units(self.units));
+            }
+            OpenWhoopCommand::Profile { profile } => {
+                let stored = db_handler.get_profile().await?;
+                let profile = stored.with_overrides(profile.into());
+                if profile != stored {
+                    db_handler.set_profile(profile).await?;
+                }
+
+                println!("{}", profile);
+            }
+            OpenWhoopCommand::WearTime { days, min_fraction } => {
+                let today = Local::now().date_naive();
+                for offset in (0..days).rev() {
+                    let date = today - TimeDelta::days(i64::from(offset));
+                    let fraction = db_handler.on_wrist_fraction(date).await?;
+                    let flag = if fraction < min_fraction {
+                        " (low coverage)"
+                    } else {
+                        ""
+                    };
+                    println!("{}: {:.0}%{}", date, fraction * 100.0, flag);
+                }
+            }
+            OpenWhoopCommand::Markers { since } => {
+                for time in db_handler.get_markers(since).await? {
+                    println!("{}", time.format("%Y-%m-%d %H:%M:%S"));
+                }
+            }
+            OpenWhoopCommand::Charging { since } => {
+                for (start, end) in db_handler.get_charging_periods(since, None).await? {
+                    println!(
+                        "{} - {}",
+                        start.format("%Y-%m-%d %H:%M:%S"),
+                        end.format("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+            }
+            OpenWhoopCommand::Firmware => {
+                for record in db_handler.get_firmware_history().await? {
+                    println!("{}", record);
+                }
+            }
+            OpenWhoopCommand::Devices { action } => match action {
+                DevicesCommand::Add {
+                    nickname,
+                    address,
+                    name,
+                } => {
+                    // Bands are found by their address on Linux, so a typo would only
+                    // surface once a command scans for the band
+                    #[cfg(target_os = "linux")]
+                    address.parse::<DeviceId>().map_err(|error| {
+                        anyhow!("{} is not a device address: {}", address, error)
+                    })?;
+                    let device = KnownDevice {
+                        nickname,
+                        address,
+                        name,
+                    };
+                    db_handler.add_device(&device).await?;
+                    println!("Saved {}", device);
+                }
+                DevicesCommand::List => {
+                    let devices = db_handler.get_devices().await?;
+                    if devices.is_empty() {
+                        println!("No saved devices");
+                    }
+                    for device in devices {
+                        println!("{}", device);
+                    }
+                }
+                DevicesCommand::Remove { nickname } => {
+                    db_handler.remove_device(&nickname).await?;
+                }
+            },
+            OpenWhoopCommand::CalculateStress { smoothing_span } => {
+                let mut whoop = OpenWhoop::new(db_handler);
+                if let Some(span) = smoothing_span {
+                    whoop = whoop.with_stress_smoothing(span);
+                }
                 whoop.calculate_stress().await?;
             }
             OpenWhoopCommand::CalculateSpo2 => {
@@ -509,23 +1549,14 @@ impl OpenWhoopCli {
                 whoop.calculate_skin_temp().await?;
             }
             OpenWhoopCommand::SetAlarm { whoop, alarm_time } => {
-                let peripheral = scan_command(&adapter, Some(whoop)).await?;
+                let time = alarm_time.checked_unix()?;
+
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
                 let mut whoop =
                     WhoopDevice::new(peripheral, adapter, db_handler, self.debug_packets);
                 whoop.connect().await?;
 
-                let time = alarm_time.unix();
-                let now = Utc::now();
-
-                if time < now {
-                    error!(
-                        "Time {} is in past, current time: {}",
-                        time.format("%Y-%m-%d %H:%M:%S"),
-                        now.format("%Y-%m-%d %H:%M:%S")
-                    );
-                    return Ok(());
-                }
-
                 let packet = WhoopPacket::alarm_time(time.timestamp() as u32);
                 whoop.send_command(packet).await?;
                 let time = time.with_timezone(&Local);
@@ -533,7 +1564,11 @@ impl OpenWhoopCli {
                 println!("Alarm time set for: {}", time.format("%Y-%m-%d %H:%M:%S"));
             }
             OpenWhoopCommand::Merge { from } => {
-                let from_db = DatabaseHandler::new(from).await;
+                let from_db = if from.starts_with("sqlite:") {
+                    DatabaseHandler::new_read_only(&from).await?
+                } else {
+                    DatabaseHandler::new(from).await
+                };
 
                 let mut id = 0;
                 loop {
@@ -546,6 +1581,7 @@ impl OpenWhoopCli {
                         uuid,
                         bytes,
                         id: c_id,
+                        ..
                     } in packets
                     {
                         id = c_id;
@@ -554,16 +1590,75 @@ impl OpenWhoopCli {
 
                     println!("{}", id);
                 }
+
+                let merge = openwhoop::db::sync::DatabaseSync::merge(
+                    db_handler.connection(),
+                    from_db.connection(),
+                );
+                merge.run().await?;
+            }
+            OpenWhoopCommand::Stats => {
+                println!("{}", db_handler.count_rows().await?);
+            }
+            OpenWhoopCommand::DumpPackets { output, from_id } => {
+                let mut file = io::BufWriter::new(std::fs::File::create(&output)?);
+                let count = db_handler.dump_packets(&mut file, from_id).await?;
+                println!("Dumped {} packets to {}", count, output.display());
+            }
+            OpenWhoopCommand::DumpUnknown { output } => {
+                let mut file = io::BufWriter::new(std::fs::File::create(&output)?);
+                let count = db_handler.dump_unknown_packets(&mut file).await?;
+                println!("Dumped {} unknown packets to {}", count, output.display());
+            }
+            OpenWhoopCommand::Replay { capture, validate } => {
+                let capture = std::fs::read_to_string(&capture)?;
+                let mut whoop = OpenWhoop::new(db_handler);
+                match validate {
+                    Some(expected) => {
+                        let expected: Vec<serde_json::Value> =
+                            serde_json::from_str(&std::fs::read_to_string(&expected)?)?;
+                        let mismatches = whoop.validate_replay(&capture, &expected)?;
+                        for mismatch in &mismatches {
+                            println!("{}", mismatch);
+                        }
+                        if !mismatches.is_empty() {
+                            return Err(anyhow!(
+                                "{} packets don't match the expected output",
+                                mismatches.len()
+                            ));
+                        }
+                        println!("All {} packets match", expected.len());
+                    }
+                    None => {
+                        let parsed = whoop.replay(&capture)?;
+                        println!("{}", serde_json::to_string_pretty(&parsed)?);
+                    }
+                }
+            }
+            OpenWhoopCommand::Export { output } => {
+                let mut file = io::BufWriter::new(std::fs::File::create(&output)?);
+                let count = db_handler.export(&mut file).await?;
+                println!("Exported {} records to {}", count, output.display());
+            }
+            OpenWhoopCommand::Import { input } => {
+                let count = db_handler.import(&input).await?;
+                println!("Imported {} records from {}", count, input.display());
+            }
+            OpenWhoopCommand::ImportWhoop { path } => {
+                let import = db_handler.import_whoop_export(&path).await?;
+                println!("Imported from {}: {}", path.display(), import);
             }
             OpenWhoopCommand::Restart { whoop } => {
-                let peripheral = scan_command(&adapter, Some(whoop)).await?;
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
                 let mut whoop =
                     WhoopDevice::new(peripheral, adapter, db_handler, self.debug_packets);
                 whoop.connect().await?;
                 whoop.send_command(WhoopPacket::restart()).await?;
             }
             OpenWhoopCommand::Erase { whoop } => {
-                let peripheral = scan_command(&adapter, Some(whoop)).await?;
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
                 let mut whoop =
                     WhoopDevice::new(peripheral, adapter, db_handler, self.debug_packets);
                 whoop.connect().await?;
@@ -571,19 +1666,92 @@ impl OpenWhoopCli {
                 info!("Erase command sent - device will trim all stored history data");
             }
             OpenWhoopCommand::Version { whoop } => {
-                let peripheral = scan_command(&adapter, Some(whoop)).await?;
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let device = whoop.to_string();
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop =
+                    WhoopDevice::new(peripheral, adapter, db_handler, false).with_device(device);
+                whoop.connect().await?;
+                let (harvard, boylston) = whoop.get_version().await?;
+                println!("Harvard: {}", harvard);
+                println!("Boylston: {}", boylston);
+            }
+            OpenWhoopCommand::GetAlarm { whoop } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+                whoop.get_alarm().await?;
+            }
+            OpenWhoopCommand::SmartAlarm {
+                whoop,
+                earliest,
+                latest,
+            } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let device = whoop.to_string();
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let whoop =
+                    WhoopDevice::new(peripheral, adapter, db_handler.clone(), self.debug_packets)
+                        .with_device(device);
+                smart_alarm(whoop, db_handler, earliest, latest).await?;
+            }
+            OpenWhoopCommand::DisableAlarm { whoop } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+                whoop.send_command(WhoopPacket::disable_alarm()).await?;
+                info!("Alarm disabled");
+            }
+            OpenWhoopCommand::Status { whoop } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+                whoop.get_body_location().await?;
+            }
+            OpenWhoopCommand::SetWrist { whoop, wrist } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
                 let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
                 whoop.connect().await?;
-                whoop.get_version().await?;
+                whoop.send_command(WhoopPacket::select_wrist(wrist)).await?;
+                info!("Wrist set to {:?}", wrist);
             }
             OpenWhoopCommand::EnableImu { whoop } => {
-                let peripheral = scan_command(&adapter, Some(whoop)).await?;
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
                 let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
                 whoop.connect().await?;
                 whoop
                     .send_command(WhoopPacket::toggle_r7_data_collection())
                     .await?;
             }
+            OpenWhoopCommand::ImuMode {
+                whoop,
+                on,
+                historical,
+            } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+                whoop
+                    .send_acknowledged(imu_mode_packet(on, historical), Duration::from_secs(5))
+                    .await?;
+                println!("IMU mode {}", if on { "enabled" } else { "disabled" });
+            }
+            OpenWhoopCommand::OpticalMode { whoop, on } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+                for packet in optical_mode_packets(on) {
+                    whoop.send_command(packet).await?;
+                }
+                println!("Optical mode {}", if on { "enabled" } else { "disabled" });
+            }
             OpenWhoopCommand::Sync { remote } => {
                 let remote_db = DatabaseHandler::new(remote).await;
                 let sync = openwhoop::db::sync::DatabaseSync::new(
@@ -592,6 +1760,42 @@ impl OpenWhoopCli {
                 );
                 sync.run().await?;
             }
+            OpenWhoopCommand::Raw {
+                whoop,
+                cmd,
+                data,
+                listen,
+                yes_i_know,
+            } => {
+                check_raw_command(cmd, yes_i_know)?;
+
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+                whoop
+                    .raw_command(
+                        WhoopPacket::command(cmd, data.0),
+                        Duration::from_secs(listen),
+                    )
+                    .await?;
+            }
+            OpenWhoopCommand::RawCapture {
+                whoop,
+                output,
+                seconds,
+            } => {
+                let whoop = resolve_device(&db_handler, &whoop.whoop).await?;
+                let peripheral = scan_command(&adapter, Some(whoop), service).await?;
+                let mut whoop = WhoopDevice::new(peripheral, adapter, db_handler, false);
+                whoop.connect().await?;
+
+                let mut file = io::BufWriter::new(std::fs::File::create(&output)?);
+                let count = whoop
+                    .capture_raw_data(&mut file, Duration::from_secs(seconds))
+                    .await?;
+                println!("Captured {} packets to {}", count, output.display());
+            }
             OpenWhoopCommand::Completions { shell } => {
                 let mut command = OpenWhoopCli::command();
                 let bin_name = command.get_name().to_string();
@@ -640,3 +1844,234 @@ impl OpenWhoopCli {
             .ok_or(anyhow!("No BLE adapters found"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2025-01-01T20:00:00Z".parse().unwrap()
+    }
+
+    fn timezone() -> FixedOffset {
+        FixedOffset::east_opt(2 * 3600).unwrap()
+    }
+
+    fn alarm(s: &str) -> DateTime<Utc> {
+        s.parse::<AlarmTime>().unwrap().unix_at(now(), timezone())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn nicknames_resolve_to_saved_devices() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        db.add_device(&KnownDevice {
+            nickname: String::from("work"),
+            address: String::from("AA:BB:CC:DD:EE:01"),
+            name: None,
+        })
+        .await
+        .unwrap();
+
+        let work = resolve_device(&db, "work").await.unwrap();
+        assert_eq!(work.to_string(), "AA:BB:CC:DD:EE:01");
+
+        // Anything that isn't a nickname is read as an address
+        let other = resolve_device(&db, "AA:BB:CC:DD:EE:02").await.unwrap();
+        assert_eq!(other.to_string(), "AA:BB:CC:DD:EE:02");
+
+        assert!(resolve_device(&db, "home").await.is_err());
+    }
+
+    #[test]
+    fn scan_filter_uses_service_override() {
+        let newer = Uuid::parse_str("fd4b0001-cce1-4033-93ce-002d5875f58a").unwrap();
+
+        let service = scan_service(Some(newer), false);
+        assert_eq!(scan_filter(service).services, vec![newer]);
+        assert!(advertises(&[newer], service));
+        assert!(!advertises(&[WHOOP_SERVICE], service));
+
+        let service = scan_service(None, false);
+        assert_eq!(scan_filter(service).services, vec![WHOOP_SERVICE]);
+
+        let service = scan_service(Some(newer), true);
+        assert!(scan_filter(service).services.is_empty());
+        assert!(advertises(&[], service));
+    }
+
+    #[test]
+    fn scanned_devices_are_deduplicated_by_signal() {
+        let device = |last: u8, name: Option<&str>, rssi: Option<i16>| ScannedDevice {
+            address: BDAddr::from([0xC0, 0, 0, 0, 0, last]),
+            name: name.map(str::to_owned),
+            rssi,
+        };
+        let seen =
+            |last: u8, name: Option<&str>, rssi: Option<i16>| (last, device(last, name, rssi));
+
+        let devices = dedup_devices(vec![
+            seen(1, None, Some(-80)),
+            seen(2, Some("WHOOP 4A1"), None),
+            seen(3, Some("WHOOP 4C3"), Some(-60)),
+            seen(1, Some("WHOOP 4B2"), Some(-55)),
+            seen(3, None, Some(-70)),
+        ]);
+
+        assert_eq!(
+            devices,
+            [
+                device(1, Some("WHOOP 4B2"), Some(-55)),
+                device(3, Some("WHOOP 4C3"), Some(-60)),
+                device(2, Some("WHOOP 4A1"), None),
+            ]
+        );
+
+        // Like on macOS, where every address reads as zero
+        let hidden = |id: u8, name: &str| {
+            let device = ScannedDevice {
+                address: BDAddr::from([0; 6]),
+                name: Some(name.to_owned()),
+                rssi: Some(-60),
+            };
+            (id, device)
+        };
+        let devices = dedup_devices(vec![hidden(1, "WHOOP 4A1"), hidden(2, "WHOOP 4B2")]);
+        assert_eq!(devices.len(), 2);
+    }
+
+    #[test]
+    fn database_url_in_creates_data_dir() {
+        let root = std::env::temp_dir().join(format!("openwhoop-data-{}", uuid::Uuid::new_v4()));
+        let data_dir = root.join("nested");
+
+        let url = database_url_in(&data_dir).unwrap();
+        assert!(data_dir.is_dir());
+        let path = data_dir.join("db.sqlite");
+        assert_eq!(url, format!("sqlite://{}?mode=rwc", path.display()));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn default_data_dir_follows_xdg() {
+        let dir = default_data_dir().unwrap();
+        match std::env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+            Some(xdg) if xdg.is_absolute() => assert_eq!(dir, xdg.join("openwhoop")),
+            _ => assert!(dir.ends_with(".local/share/openwhoop"), "{}", dir.display()),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn default_data_dir_is_application_support() {
+        let dir = default_data_dir().unwrap();
+        assert!(
+            dir.ends_with("Library/Application Support/openwhoop"),
+            "{}",
+            dir.display()
+        );
+    }
+
+    #[test]
+    fn imu_mode_picks_command() {
+        let packet = imu_mode_packet(true, false);
+        assert_eq!(packet.cmd, CommandNumber::ToggleImuMode.as_u8());
+        assert_eq!(packet.data, vec![1]);
+
+        let packet = imu_mode_packet(false, true);
+        assert_eq!(packet.cmd, CommandNumber::ToggleImuModeHistorical.as_u8());
+        assert_eq!(packet.data, vec![0]);
+    }
+
+    #[test]
+    fn optical_mode_sends_both_commands() {
+        let [data, mode] = optical_mode_packets(true);
+        assert_eq!(data.cmd, CommandNumber::EnableOpticalData.as_u8());
+        assert_eq!(data.data, vec![0x01, 0x01]);
+        assert_eq!(mode.cmd, CommandNumber::ToggleOpticalMode.as_u8());
+        assert_eq!(mode.data, vec![0x01, 0x01]);
+
+        let [data, mode] = optical_mode_packets(false);
+        assert_eq!(data.cmd, CommandNumber::EnableOpticalData.as_u8());
+        assert_eq!(data.data, vec![0x01, 0x00]);
+        assert_eq!(mode.cmd, CommandNumber::ToggleOpticalMode.as_u8());
+        assert_eq!(mode.data, vec![0x01, 0x00]);
+    }
+
+    #[test]
+    fn alarm_relative_variants() {
+        assert_eq!(alarm("min"), now() + TimeDelta::minutes(1));
+        assert_eq!(alarm("5min"), now() + TimeDelta::minutes(5));
+        assert_eq!(alarm("10min"), now() + TimeDelta::minutes(10));
+        assert_eq!(alarm("15min"), now() + TimeDelta::minutes(15));
+        assert_eq!(alarm("30min"), now() + TimeDelta::minutes(30));
+        assert_eq!(alarm("hour"), now() + TimeDelta::hours(1));
+    }
+
+    #[test]
+    fn alarm_date_time_is_local() {
+        let expected: DateTime<Utc> = "2025-01-02T05:30:00Z".parse().unwrap();
+        assert_eq!(alarm("2025-01-02 07:30:00"), expected);
+        assert_eq!(alarm("2025-01-02T07:30:00"), expected);
+    }
+
+    #[test]
+    fn alarm_time_later_today() {
+        // now is 22:00 local time
+        let expected: DateTime<Utc> = "2025-01-01T21:30:00Z".parse().unwrap();
+        assert_eq!(alarm("23:30:00"), expected);
+        assert_eq!(alarm("22:00:00"), now());
+    }
+
+    #[test]
+    fn alarm_time_rolls_over_to_tomorrow() {
+        let expected: DateTime<Utc> = "2025-01-02T05:30:00Z".parse().unwrap();
+        assert_eq!(alarm("07:30:00"), expected);
+    }
+
+    #[test]
+    fn alarm_rejects_past_date_time() {
+        let alarm = "2024-12-31 07:30:00".parse::<AlarmTime>().unwrap();
+        assert!(alarm.checked_unix_at(now(), timezone()).is_err());
+    }
+
+    #[test]
+    fn alarm_accepts_near_future() {
+        let alarm = "22:30:00".parse::<AlarmTime>().unwrap();
+        let expected: DateTime<Utc> = "2025-01-01T20:30:00Z".parse().unwrap();
+        assert_eq!(alarm.checked_unix_at(now(), timezone()).unwrap(), expected);
+
+        let alarm = "07:30:00".parse::<AlarmTime>().unwrap();
+        assert!(alarm.checked_unix_at(now(), timezone()).is_ok());
+    }
+
+    #[test]
+    fn parse_hex_payload() {
+        assert_eq!(parse_hex("").unwrap(), HexBytes(vec![]));
+        assert_eq!(parse_hex("00").unwrap(), HexBytes(vec![0x00]));
+        assert_eq!(parse_hex("01ff").unwrap(), HexBytes(vec![0x01, 0xff]));
+        assert_eq!(parse_hex("0x01FF").unwrap(), HexBytes(vec![0x01, 0xff]));
+        assert_eq!(
+            parse_hex("01 ff:10").unwrap(),
+            HexBytes(vec![0x01, 0xff, 0x10])
+        );
+        assert!(parse_hex("0").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+
+    #[test]
+    fn raw_commands_that_may_be_dangerous_need_confirmation() {
+        let safe = CommandNumber::GetBodyLocationAndStatus as u8;
+        let dangerous = CommandNumber::ForceTrim as u8;
+        let unknown = 255;
+        assert!(CommandNumber::from_u8(unknown).is_none());
+
+        assert!(check_raw_command(safe, false).is_ok());
+        assert!(check_raw_command(dangerous, false).is_err());
+        assert!(check_raw_command(unknown, false).is_err());
+        assert!(check_raw_command(dangerous, true).is_ok());
+        assert!(check_raw_command(unknown, true).is_ok());
+    }
+}
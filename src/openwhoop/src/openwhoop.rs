@@ -1,25 +1,251 @@
 use btleplug::api::ValueNotification;
-use chrono::{DateTime, Local, TimeDelta};
-use openwhoop_entities::packets;
-use openwhoop_db::{DatabaseHandler, SearchHistory};
+use std::{collections::BTreeMap, fmt, fs::File, io::Write, ops::Range, sync::Arc};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
 use openwhoop_codec::{
-    Activity, HistoryReading, WhoopData, WhoopPacket,
-    constants::{CMD_FROM_STRAP, DATA_FROM_STRAP, MetadataType},
+    Activity, HighFreqSyncState, HistoryReading, ParseContext, WhoopData, WhoopError, WhoopPacket,
+    constants::{CMD_FROM_STRAP, DATA_FROM_STRAP, EVENTS_FROM_STRAP, EventNumber, MetadataType},
 };
+use openwhoop_db::{DatabaseHandler, FirmwareRecord, SearchHistory};
+use openwhoop_entities::packets;
+use uuid::Uuid;
 
 use crate::{
     algo::{
-        ActivityPeriod, MAX_SLEEP_PAUSE, SkinTempCalculator, SleepCycle, SpO2Calculator,
-        StressCalculator, helpers::format_hm::FormatHM,
+        ActivityClassifier, ActivityConfig, ActivityPeriod, CalorieConfig, HrvConfig,
+        MAX_SLEEP_PAUSE, RecoveryCalculator, SkinTempCalculator, SleepCycle, SleepScoreConfig,
+        SpO2Calculator, StrainCalculator, StressCalculator, ZoneModel,
+        helpers::{
+            format_hm::FormatHM,
+            smoothing::{BPM_SMOOTHING_WINDOW, Ema, smooth_bpm},
+            units::UnitSystem,
+        },
     },
+    error::OpenWhoopError,
     types::activities,
 };
 
+/// Result of feeding a single stored packet through [`OpenWhoop::handle_packet`]
+#[derive(Debug)]
+pub enum PacketOutcome {
+    /// Packet was parsed and handled, optionally with a packet to send back to the strap
+    Handled(Option<WhoopPacket>),
+    /// Packet is the start of a multi-part packet and was buffered until the rest arrives
+    Partial,
+    /// Packet was recognized but parsing its contents is not implemented
+    Unimplemented,
+    /// Packet could not be parsed
+    ParseError(WhoopError),
+}
+
+impl PacketOutcome {
+    fn from_error(error: WhoopError) -> Self {
+        match error {
+            WhoopError::Unimplemented => Self::Unimplemented,
+            error => Self::ParseError(error),
+        }
+    }
+}
+
+/// Tally of [`PacketOutcome`]s over a run of packets
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PacketStats {
+    pub handled: usize,
+    pub partial: usize,
+    pub unimplemented: usize,
+    pub parse_errors: usize,
+}
+
+impl PacketStats {
+    pub fn record(&mut self, outcome: &PacketOutcome) {
+        match outcome {
+            PacketOutcome::Handled(_) => self.handled += 1,
+            PacketOutcome::Partial => self.partial += 1,
+            PacketOutcome::Unimplemented => self.unimplemented += 1,
+            PacketOutcome::ParseError(_) => self.parse_errors += 1,
+        }
+    }
+}
+
+impl fmt::Display for PacketStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "handled: {}, partial: {}, unimplemented: {}, parse errors: {}",
+            self.handled, self.partial, self.unimplemented, self.parse_errors
+        )
+    }
+}
+
+/// Progress of a history download, tracked from the strap's `HistoryMetadata` markers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncOutcome {
+    /// The strap reported `HistoryComplete`, so everything it had was downloaded
+    pub complete: bool,
+    /// Timestamp of the last metadata marker received
+    pub last_unix: Option<u32>,
+}
+
+/// Where the strap is in entering high frequency sync, history is only requested once it
+/// is [`HighFreqSync::Enabled`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HighFreqSync {
+    /// Not requested since connecting
+    #[default]
+    Idle,
+    /// Entering was requested, waiting for the strap to confirm
+    Requested,
+    /// The strap asked to enter it again, e.g. after it dropped out
+    Prompted,
+    Enabled,
+    Disabled,
+}
+
+impl HighFreqSync {
+    pub fn on_event(self, state: HighFreqSyncState) -> Self {
+        match state {
+            HighFreqSyncState::Prompt if self == Self::Enabled => self,
+            HighFreqSyncState::Prompt => Self::Prompted,
+            HighFreqSyncState::Enabled => Self::Enabled,
+            HighFreqSyncState::Disabled => Self::Disabled,
+        }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        self == Self::Enabled
+    }
+}
+
+/// Days of exercise that personal heart rate zones are taken from
+const PERSONAL_ZONE_DAYS: i64 = 90;
+
+/// Days worn for less than this fraction of the time are flagged in a [`DailySummary`]
+const LOW_WEAR_FRACTION: f64 = 0.8;
+
+/// Sleep, strain, stress and wear time of one day, see [`OpenWhoop::daily_summary`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailySummary {
+    pub date: NaiveDate,
+    /// The night that ended on `date`
+    pub sleep: Option<SleepCycle>,
+    /// Mean skin temperature during that night in °C
+    pub skin_temp: Option<f64>,
+    /// Recovery of that night, the WHOOP app's score if one was imported for it, otherwise
+    /// see [`RecoveryCalculator`]
+    pub recovery: Option<f64>,
+    /// Strain of the day's exercises
+    pub strain: f64,
+    pub exercises: usize,
+    /// Mean stress score of the day, `None` before stress was calculated
+    pub stress: Option<f64>,
+    /// Fraction of the day the strap was worn
+    pub wear: f64,
+    /// Units the summary is printed in
+    pub units: UnitSystem,
+}
+
+impl DailySummary {
+    pub fn with_units(self, units: UnitSystem) -> Self {
+        Self { units, ..self }
+    }
+}
+
+impl fmt::Display for DailySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Summary of {}:", self.date)?;
+        match self.sleep {
+            Some(sleep) => {
+                writeln!(f, "Sleep: {}", sleep.duration().format_hm())?;
+                writeln!(f, "\tScore: {}", sleep.score)?;
+                match sleep.efficiency {
+                    Some(efficiency) => writeln!(f, "\tEfficiency: {}%", efficiency)?,
+                    None => writeln!(f, "\tEfficiency: n/a")?,
+                }
+                writeln!(f, "\tResting HR: {}", sleep.min_bpm)?;
+                writeln!(f, "\tHRV: {} ms", sleep.avg_hrv)?;
+                match sleep.deep_hrv {
+                    Some(hrv) => writeln!(f, "\tHRV (deep sleep): {} ms", hrv)?,
+                    None => writeln!(f, "\tHRV (deep sleep): n/a")?,
+                }
+                match self.skin_temp {
+                    Some(temp) => {
+                        writeln!(f, "\tSkin temp: {}", self.units.format_temperature(temp))?
+                    }
+                    None => writeln!(f, "\tSkin temp: n/a")?,
+                }
+            }
+            None => writeln!(f, "Sleep: n/a")?,
+        }
+        match self.recovery {
+            Some(recovery) => writeln!(f, "Recovery: {}", recovery)?,
+            None => writeln!(f, "Recovery: n/a")?,
+        }
+        writeln!(
+            f,
+            "Strain: {:.1} ({} exercises)",
+            self.strain, self.exercises
+        )?;
+        match self.stress {
+            Some(stress) => {
+                let level = StressCalculator::default().level(stress);
+                writeln!(f, "Stress: {:.1} ({:?})", stress, level)?
+            }
+            None => writeln!(f, "Stress: n/a")?,
+        }
+        let flag = if self.wear < LOW_WEAR_FRACTION {
+            " (low coverage)"
+        } else {
+            ""
+        };
+        write!(f, "Data quality: {:.0}% worn{}", self.wear * 100.0, flag)
+    }
+}
+
 pub struct OpenWhoop {
     pub database: DatabaseHandler,
     pub packet: Option<WhoopPacket>,
     pub last_history_packet: Option<HistoryReading>,
     pub history_packets: Vec<HistoryReading>,
+    /// Keep every Nth IMU sample of stored readings, see [`HistoryReading::downsample_imu`]
+    pub imu_downsample: usize,
+    /// Firmware dependent field offsets, updated when the strap reports its version
+    pub parse_context: ParseContext,
+    /// File that decoded console logs are appended to
+    pub console_log: Option<File>,
+    /// Markers seen since the current history download started
+    pub sync_outcome: SyncOutcome,
+    /// Updated from the strap's high frequency sync events
+    pub high_freq_sync: HighFreqSync,
+    /// Minimum period durations used by [`OpenWhoop::detect_events`]
+    pub activity_config: ActivityConfig,
+    /// RR artifact rejection applied before sleep HRV is computed
+    pub hrv_config: HrvConfig,
+    /// Weighting of the score of detected sleeps
+    pub sleep_score_config: SleepScoreConfig,
+    /// Store packets with an unknown type or command, see [`DatabaseHandler::get_unknown_packets`]
+    pub capture_unknown: bool,
+    /// Span of the moving average stored alongside each stress score, `None` stores none
+    pub stress_smoothing: Option<usize>,
+    /// Store packets that fail to parse or aren't implemented yet, so `ReRun` can parse
+    /// them once the codec supports them
+    pub keep_unparsed: bool,
+    /// Parse packets that fail their CRC32 instead of dropping them, flagging their readings
+    /// as low confidence
+    pub lenient_crc: bool,
+    /// The last frame assembled failed its CRC32, only possible with `lenient_crc`
+    pub crc_failed: bool,
+    /// Timestamps of the readings in `history_packets` parsed from a packet that failed its CRC32
+    pub low_confidence: Vec<u64>,
+    /// Store reported firmware versions, see [`DatabaseHandler::get_firmware_history`]. Off
+    /// when re-running stored packets, their versions were recorded when received.
+    pub record_firmware: bool,
+    /// Band the packets come from, its acknowledged history pointer and firmware reports are
+    /// stored under it. `None` when re-running stored packets, which mustn't move any band's
+    /// pointer.
+    pub device: Option<String>,
+    /// Packets come from the `packets` table rather than the strap, so when they were
+    /// received isn't known. Captured unknown packets are stored without a receive time.
+    pub rerunning: bool,
 }
 
 impl OpenWhoop {
@@ -29,13 +255,115 @@ impl OpenWhoop {
             packet: None,
             last_history_packet: None,
             history_packets: Vec::new(),
+            imu_downsample: 1,
+            parse_context: ParseContext::default(),
+            console_log: None,
+            sync_outcome: SyncOutcome::default(),
+            high_freq_sync: HighFreqSync::default(),
+            activity_config: ActivityConfig::default(),
+            hrv_config: HrvConfig::default(),
+            sleep_score_config: SleepScoreConfig::default(),
+            capture_unknown: false,
+            stress_smoothing: None,
+            keep_unparsed: false,
+            lenient_crc: false,
+            crc_failed: false,
+            low_confidence: Vec::new(),
+            record_firmware: true,
+            device: None,
+            rerunning: false,
+        }
+    }
+
+    pub fn with_imu_downsample(self, imu_downsample: usize) -> Self {
+        Self {
+            imu_downsample,
+            ..self
+        }
+    }
+
+    pub fn with_parse_context(self, parse_context: ParseContext) -> Self {
+        Self {
+            parse_context,
+            ..self
+        }
+    }
+
+    pub fn with_console_log(self, console_log: File) -> Self {
+        Self {
+            console_log: Some(console_log),
+            ..self
+        }
+    }
+
+    pub fn with_activity_config(self, activity_config: ActivityConfig) -> Self {
+        Self {
+            activity_config,
+            ..self
         }
     }
 
+    pub fn with_hrv_config(self, hrv_config: HrvConfig) -> Self {
+        Self { hrv_config, ..self }
+    }
+
+    pub fn with_sleep_score_config(self, sleep_score_config: SleepScoreConfig) -> Self {
+        Self {
+            sleep_score_config,
+            ..self
+        }
+    }
+
+    pub fn with_capture_unknown(self, capture_unknown: bool) -> Self {
+        Self {
+            capture_unknown,
+            ..self
+        }
+    }
+
+    pub fn with_stress_smoothing(self, span: usize) -> Self {
+        Self {
+            stress_smoothing: Some(span),
+            ..self
+        }
+    }
+
+    pub fn with_keep_unparsed(self, keep_unparsed: bool) -> Self {
+        Self {
+            keep_unparsed,
+            ..self
+        }
+    }
+
+    pub fn with_lenient_crc(self, lenient_crc: bool) -> Self {
+        Self {
+            lenient_crc,
+            ..self
+        }
+    }
+
+    pub fn with_record_firmware(self, record_firmware: bool) -> Self {
+        Self {
+            record_firmware,
+            ..self
+        }
+    }
+
+    pub fn with_device(self, device: impl Into<String>) -> Self {
+        Self {
+            device: Some(device.into()),
+            ..self
+        }
+    }
+
+    pub fn with_rerunning(self, rerunning: bool) -> Self {
+        Self { rerunning, ..self }
+    }
+
     pub async fn store_packet(
         &self,
         notification: ValueNotification,
-    ) -> anyhow::Result<packets::Model> {
+    ) -> Result<packets::Model, OpenWhoopError> {
         let packet = self
             .database
             .create_packet(notification.uuid, notification.value)
@@ -47,68 +375,159 @@ impl OpenWhoop {
     pub async fn handle_packet(
         &mut self,
         packet: packets::Model,
-    ) -> anyhow::Result<Option<WhoopPacket>> {
-        let data = match packet.uuid {
+    ) -> Result<PacketOutcome, OpenWhoopError> {
+        let uuid = packet.uuid;
+        let store_failures = self.capture_unknown || self.keep_unparsed;
+        let raw = store_failures.then(|| packet.bytes.clone());
+        let packet = match self.assemble_packet(packet) {
+            Ok(packet) => packet,
+            Err(outcome) => {
+                if let (Some(raw), PacketOutcome::ParseError(error)) = (raw, &outcome) {
+                    self.store_failed_packet(uuid, raw, error).await?;
+                }
+                return Ok(outcome);
+            }
+        };
+
+        // Multi-part packets only have their last part in `raw`, store the whole frame
+        let framed = store_failures.then(|| packet.framed_packet());
+        let data = match WhoopData::from_packet_with(packet, &self.parse_context) {
+            Ok(data) => data,
+            Err(error) => {
+                if let Some(framed) = framed {
+                    self.store_failed_packet(uuid, framed, &error).await?;
+                }
+                return Ok(PacketOutcome::from_error(error));
+            }
+        };
+        if let (true, WhoopData::HistoryReading(reading)) = (self.crc_failed, &data) {
+            self.low_confidence.push(reading.unix);
+        }
+
+        Ok(PacketOutcome::Handled(self.handle_data(data).await?))
+    }
+
+    /// Frames a stored packet, joining multi-part packets, or returns the outcome for
+    /// packets that can't be parsed (yet).
+    pub(crate) fn assemble_packet(
+        &mut self,
+        packet: packets::Model,
+    ) -> Result<WhoopPacket, PacketOutcome> {
+        match packet.uuid {
             DATA_FROM_STRAP => {
-                let packet = if let Some(mut whoop_packet) = self.packet.take() {
+                if let Some(mut whoop_packet) = self.packet.take() {
                     // TODO: maybe not needed but it would be nice to handle packet length here
                     // so if next packet contains end of one and start of another it is handled
 
                     whoop_packet.data.extend_from_slice(&packet.bytes);
 
                     if whoop_packet.data.len() + 3 >= whoop_packet.size {
-                        whoop_packet
+                        Ok(whoop_packet)
                     } else {
                         self.packet = Some(whoop_packet);
-                        return Ok(None);
+                        Err(PacketOutcome::Partial)
                     }
                 } else {
-                    let packet = WhoopPacket::from_data(packet.bytes)?;
+                    let packet = self.parse_frame(packet.bytes)?;
                     if packet.partial {
                         self.packet = Some(packet);
-                        return Ok(None);
+                        return Err(PacketOutcome::Partial);
                     }
-                    packet
-                };
-
-                let Ok(data) = WhoopData::from_packet(packet) else {
-                    return Ok(None);
-                };
-                data
+                    Ok(packet)
+                }
             }
-            CMD_FROM_STRAP => {
-                let packet = WhoopPacket::from_data(packet.bytes)?;
+            CMD_FROM_STRAP | EVENTS_FROM_STRAP => self.parse_frame(packet.bytes),
+            _ => Err(PacketOutcome::Handled(None)),
+        }
+    }
 
-                let Ok(data) = WhoopData::from_packet(packet) else {
-                    return Ok(None);
-                };
+    /// Parses the start of a frame, tolerating a CRC32 mismatch with `lenient_crc`.
+    fn parse_frame(&mut self, bytes: Vec<u8>) -> Result<WhoopPacket, PacketOutcome> {
+        if !self.lenient_crc {
+            return WhoopPacket::from_data(bytes).map_err(PacketOutcome::ParseError);
+        }
 
-                data
-            }
-            _ => return Ok(None),
-        };
+        let (packet, crc_ok) =
+            WhoopPacket::from_data_lenient(bytes).map_err(PacketOutcome::ParseError)?;
+        if !crc_ok {
+            warn!("Parsing a packet that failed its CRC32, its readings are low confidence");
+        }
+        self.crc_failed = !crc_ok;
+        Ok(packet)
+    }
+
+    /// Stores a packet that failed with `error`, as configured by `capture_unknown` and
+    /// `keep_unparsed`. A whole frame stored in `packets` is parsed in one go on re-run.
+    async fn store_failed_packet(
+        &self,
+        uuid: Uuid,
+        bytes: Vec<u8>,
+        error: &WhoopError,
+    ) -> Result<(), OpenWhoopError> {
+        if self.keep_unparsed {
+            self.database.create_packet(uuid, bytes.clone()).await?;
+        }
+        if self.capture_unknown {
+            self.capture_unknown_packet(uuid, bytes, error).await?;
+        }
 
-        self.handle_data(data).await
+        Ok(())
+    }
+
+    /// Stores `bytes` if `error` means the codec doesn't know the packet type or command,
+    /// so new packets can be reverse engineered later.
+    async fn capture_unknown_packet(
+        &self,
+        uuid: Uuid,
+        bytes: Vec<u8>,
+        error: &WhoopError,
+    ) -> Result<(), OpenWhoopError> {
+        if matches!(
+            error,
+            WhoopError::InvalidPacketType(_) | WhoopError::InvalidCommandType(_)
+        ) {
+            warn!("Capturing unknown packet: {}", error);
+            let received_at = (!self.rerunning).then(|| Local::now().naive_local());
+            self.database
+                .create_unknown_packet(uuid, bytes, error.to_string(), received_at)
+                .await?;
+        }
+
+        Ok(())
     }
 
-    async fn handle_data(&mut self, data: WhoopData) -> anyhow::Result<Option<WhoopPacket>> {
+    async fn handle_data(
+        &mut self,
+        data: WhoopData,
+    ) -> Result<Option<WhoopPacket>, OpenWhoopError> {
         match data {
-            WhoopData::HistoryReading(hr) if hr.is_valid() => {
+            WhoopData::HistoryReading(mut hr) if hr.is_valid() => {
+                let previous = self
+                    .last_history_packet
+                    .as_ref()
+                    .and_then(|last| last.sensor_data.as_ref());
+                hr.derive_missing_activity(previous);
+
                 if let Some(last_packet) = self.last_history_packet.as_mut() {
                     if last_packet.unix == hr.unix && last_packet.bpm == hr.bpm {
                         return Ok(None);
                     } else {
                         last_packet.unix = hr.unix;
                         last_packet.bpm = hr.bpm;
+                        last_packet.sensor_data = hr.sensor_data.clone();
                     }
                 } else {
                     self.last_history_packet = Some(hr.clone());
                 }
 
+                // Straps that lost their clock send timestamps chrono can't represent
                 let ptime = DateTime::from_timestamp_millis(hr.unix as i64)
-                    .unwrap()
-                    .with_timezone(&Local)
-                    .format("%Y-%m-%d %H:%M:%S");
+                    .map(|t| {
+                        t.with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_else(|| hr.unix.to_string());
 
                 if hr.imu_data.is_empty() {
                     info!(target: "HistoryReading", "time: {}", ptime);
@@ -116,27 +535,83 @@ impl OpenWhoop {
                     info!(target: "HistoryReading", "time: {}, (IMU)", ptime);
                 }
 
+                hr.downsample_imu(self.imu_downsample);
                 self.history_packets.push(hr);
             }
-            WhoopData::HistoryMetadata { data, cmd, .. } => match cmd {
-                MetadataType::HistoryComplete => {}
-                MetadataType::HistoryStart => {}
+            WhoopData::HistoryMetadata { unix, data, cmd } => match cmd {
+                MetadataType::HistoryComplete => {
+                    self.sync_outcome.complete = true;
+                    self.sync_outcome.last_unix = Some(unix);
+                }
+                MetadataType::HistoryStart => {
+                    self.sync_outcome.last_unix = Some(unix);
+                }
                 MetadataType::HistoryEnd => {
+                    self.sync_outcome.last_unix = Some(unix);
                     self.database
                         .create_readings(std::mem::take(&mut self.history_packets))
                         .await?;
+                    self.database
+                        .mark_low_confidence(std::mem::take(&mut self.low_confidence))
+                        .await?;
+                    if let Some(device) = &self.device {
+                        self.database.set_sync_pointer(device, data).await?;
+                    }
 
                     let packet = WhoopPacket::history_end(data);
                     return Ok(Some(packet));
                 }
             },
-            WhoopData::ConsoleLog { log, .. } => {
+            WhoopData::ConsoleLog { unix, log } => {
                 trace!(target: "ConsoleLog", "{}", log);
+                // A full disk or a removed file shouldn't stop the download
+                let written = self
+                    .console_log
+                    .as_mut()
+                    .map(|file| write_console_log(file, unix, &log));
+                if let Some(Err(e)) = written {
+                    warn!("Failed to write console log: {}", e);
+                }
+                self.database.create_console_log(unix, log).await?;
             }
             WhoopData::RunAlarm { .. } => {}
             WhoopData::Event { .. } => {}
+            WhoopData::UnknownEvent { unix, event } if event == EventNumber::RtcLost as u8 => {
+                // Readings recorded until the clock is set again would be misdated
+                warn!("Strap lost its clock (event at {}), setting time", unix);
+                return Ok(Some(WhoopPacket::set_time()));
+            }
+            WhoopData::HighFreqSync { unix, state } => {
+                debug!("High frequency sync {:?} (event at {})", state, unix);
+                self.high_freq_sync = self.high_freq_sync.on_event(state);
+                if self.high_freq_sync == HighFreqSync::Prompted {
+                    return Ok(Some(WhoopPacket::enter_high_freq_sync()));
+                }
+            }
             WhoopData::VersionInfo { harvard, boylston } => {
-                info!("version harvard {} boylston {}", harvard, boylston);
+                self.version_reported(harvard, boylston).await?;
+            }
+            WhoopData::BodyLocation { wrist, on_body } => {
+                info!("wrist {:?} on body {}", wrist, on_body);
+            }
+            WhoopData::AlarmTime { unix, enabled } => {
+                info!("alarm unix {} enabled {}", unix, enabled);
+            }
+            WhoopData::ImuStream { unix, samples } => {
+                trace!(target: "ImuStream", "unix: {}, samples: {}", unix, samples.len());
+                self.database.create_imu_stream(unix, &samples).await?;
+            }
+            WhoopData::DoubleTap { unix } => {
+                info!("Double tap at {}", unix);
+                self.database.create_marker(unix).await?;
+            }
+            WhoopData::Charging { unix, charging } => {
+                info!("Charging {} (event at {})", charging, unix);
+                if charging {
+                    self.database.start_charging(unix).await?;
+                } else {
+                    self.database.stop_charging(unix).await?;
+                }
             }
             _ => {}
         }
@@ -144,23 +619,318 @@ impl OpenWhoop {
         Ok(None)
     }
 
-    pub async fn get_latest_sleep(&self) -> anyhow::Result<Option<SleepCycle>> {
+    /// Parses later packets for the reported firmware and records it for [`OpenWhoop::device`],
+    /// warning when it changed since the band last reported.
+    pub async fn version_reported(
+        &mut self,
+        harvard: String,
+        boylston: String,
+    ) -> Result<(), OpenWhoopError> {
+        info!("version harvard {} boylston {}", harvard, boylston);
+        match harvard.parse() {
+            Ok(firmware) => self.parse_context.firmware = Some(firmware),
+            Err(_) => warn!("Unrecognized firmware version: {}", harvard),
+        }
+
+        if !self.record_firmware {
+            return Ok(());
+        }
+
+        let record = FirmwareRecord {
+            device: self.device.clone(),
+            harvard,
+            boylston,
+            seen_at: Local::now().naive_local(),
+        };
+        if let Some(previous) = self.database.record_firmware(&record).await? {
+            warn!(
+                "Firmware changed since {}: harvard {} -> {}, boylston {} -> {}",
+                previous.seen_at.format("%Y-%m-%d %H:%M"),
+                previous.harvard,
+                record.harvard,
+                previous.boylston,
+                record.boylston
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Stores a reading that arrived outside of [`OpenWhoop::handle_packet`], for apps that
+    /// talk to the strap through their own transport.
+    ///
+    /// IMU samples are downsampled like downloaded history and skin temperature and SpO2 are
+    /// derived from the sensor data. Readings without a heart rate are ignored.
+    pub async fn process_reading(&self, mut reading: HistoryReading) -> Result<(), OpenWhoopError> {
+        if !reading.is_valid() {
+            return Ok(());
+        }
+
+        reading.downsample_imu(self.imu_downsample);
+        Ok(self.database.create_reading(reading).await?)
+    }
+
+    /// Feeds stored packets starting at id `since_id` through [`OpenWhoop::handle_packet`]
+    /// again, e.g. after a parsing change.
+    pub async fn rerun(&mut self, since_id: i32) -> Result<PacketStats, OpenWhoopError> {
+        let mut stats = PacketStats::default();
+        let mut id = since_id.saturating_sub(1);
+        loop {
+            let packets = self.database.get_packets(id).await?;
+            if packets.is_empty() {
+                break;
+            }
+
+            for packet in packets {
+                id = packet.id;
+                let outcome = self.handle_packet(packet).await?;
+                stats.record(&outcome);
+            }
+
+            info!("re-ran packets up to {}", id);
+        }
+
+        Ok(stats)
+    }
+
+    /// Same as [`OpenWhoop::rerun`], with packets parsed on `workers` threads.
+    ///
+    /// Joining multi-part packets and handling the parsed data still happen in order, so
+    /// the stored result matches a serial run. Readings are written in batches at each
+    /// `HistoryEnd` either way.
+    pub async fn rerun_parallel(
+        &mut self,
+        since_id: i32,
+        workers: usize,
+    ) -> Result<PacketStats, OpenWhoopError> {
+        let mut stats = PacketStats::default();
+        let mut id = since_id.saturating_sub(1);
+        loop {
+            let packets = self.database.get_packets(id).await?;
+            let Some(last) = packets.last() else {
+                break;
+            };
+            id = last.id;
+
+            let mut assembled = Vec::with_capacity(packets.len());
+            let mut uuids = Vec::with_capacity(packets.len());
+            let mut crc_failed = Vec::with_capacity(packets.len());
+            for packet in packets {
+                let uuid = packet.uuid;
+                let raw = self.capture_unknown.then(|| packet.bytes.clone());
+                match self.assemble_packet(packet) {
+                    Ok(packet) => {
+                        assembled.push(packet);
+                        uuids.push(uuid);
+                        crc_failed.push(self.crc_failed);
+                    }
+                    Err(outcome) => {
+                        if let (Some(raw), PacketOutcome::ParseError(error)) = (raw, &outcome) {
+                            self.capture_unknown_packet(uuid, raw, error).await?;
+                        }
+                        stats.record(&outcome);
+                    }
+                }
+            }
+
+            let assembled: Arc<[WhoopPacket]> = assembled.into();
+            let mut next = 0;
+            while next < assembled.len() {
+                let range = next..assembled.len();
+                let parsed =
+                    parse_packets(assembled.clone(), range, &self.parse_context, workers).await?;
+
+                for data in parsed {
+                    let index = next;
+                    next += 1;
+                    let data = match data {
+                        Ok(data) => data,
+                        Err(error) => {
+                            if self.capture_unknown {
+                                let framed = assembled[index].framed_packet();
+                                self.capture_unknown_packet(uuids[index], framed, &error)
+                                    .await?;
+                            }
+                            stats.record(&PacketOutcome::from_error(error));
+                            continue;
+                        }
+                    };
+
+                    let failed = crc_failed[index];
+                    if let (true, WhoopData::HistoryReading(reading)) = (failed, &data) {
+                        self.low_confidence.push(reading.unix);
+                    }
+
+                    // Firmware version changes how the packets after it are parsed
+                    let version = matches!(data, WhoopData::VersionInfo { .. });
+                    let outcome = PacketOutcome::Handled(self.handle_data(data).await?);
+                    stats.record(&outcome);
+                    if version {
+                        break;
+                    }
+                }
+            }
+
+            info!("re-ran packets up to {}", id);
+        }
+
+        Ok(stats)
+    }
+
+    /// First packet id worth re-running to cover everything the strap recorded since `since`.
+    ///
+    /// Packets don't store when they were received, so this binary searches the ids on the
+    /// timestamps of packets that carry one, relying on packets being stored in order.
+    pub async fn packet_id_since(&self, since: NaiveDateTime) -> Result<i32, OpenWhoopError> {
+        let since = Local
+            .from_local_datetime(&since)
+            .earliest()
+            .ok_or_else(|| {
+                OpenWhoopError::Invalid(format!("{} doesn't exist in the local timezone", since))
+            })?
+            .timestamp_millis();
+
+        let Some(last) = self.database.last_packet_id().await? else {
+            return Ok(0);
+        };
+
+        // Ids of stored packets start at 1
+        let (mut low, mut high) = (1, last + 1);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.first_packet_timestamp(mid).await? {
+                Some(unix) if unix < since => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+
+        Ok(low)
+    }
+
+    /// Timestamp in ms of the first packet with id `id` or later that has one
+    async fn first_packet_timestamp(&self, id: i32) -> Result<Option<i64>, OpenWhoopError> {
+        let mut id = id.saturating_sub(1);
+        loop {
+            let packets = self.database.get_packets(id).await?;
+            let Some(last) = packets.last() else {
+                return Ok(None);
+            };
+            id = last.id;
+
+            if let Some(unix) = packets.into_iter().find_map(|p| self.packet_timestamp(p)) {
+                return Ok(Some(unix));
+            }
+        }
+    }
+
+    fn packet_timestamp(&self, packet: packets::Model) -> Option<i64> {
+        let packet = WhoopPacket::from_data(packet.bytes).ok()?;
+        if packet.partial {
+            return None;
+        }
+
+        let unix = match WhoopData::from_packet_with(packet, &self.parse_context).ok()? {
+            WhoopData::HistoryReading(hr) => return i64::try_from(hr.unix).ok(),
+            WhoopData::HistoryMetadata { unix, .. }
+            | WhoopData::ConsoleLog { unix, .. }
+            | WhoopData::RunAlarm { unix }
+            | WhoopData::Event { unix, .. }
+            | WhoopData::UnknownEvent { unix, .. }
+            | WhoopData::HighFreqSync { unix, .. }
+            | WhoopData::DoubleTap { unix }
+            | WhoopData::Charging { unix, .. }
+            | WhoopData::ImuStream { unix, .. } => unix,
+            _ => return None,
+        };
+
+        Some(i64::from(unix) * 1000)
+    }
+
+    /// Packets that start a history download, re-acknowledging the pointer stored for
+    /// [`OpenWhoop::device`] first so an interrupted download resumes instead of starting over.
+    pub async fn history_start_packets(&self) -> Result<Vec<WhoopPacket>, OpenWhoopError> {
+        let pointer = match &self.device {
+            Some(device) => self.database.get_sync_pointer(device).await?,
+            None => None,
+        };
+
+        let mut packets = Vec::new();
+        if let Some(pointer) = pointer {
+            packets.push(WhoopPacket::history_end(pointer));
+        }
+
+        packets.push(WhoopPacket::history_start());
+        Ok(packets)
+    }
+
+    /// Drops the state of an interrupted download and returns the packets that resume it.
+    ///
+    /// Readings received since the last `HistoryEnd` weren't acknowledged yet, the strap sends
+    /// them again from the stored pointer, so they are discarded here instead of stored twice.
+    pub async fn resume_history_packets(&mut self) -> Result<Vec<WhoopPacket>, OpenWhoopError> {
+        self.packet = None;
+        self.last_history_packet = None;
+        self.history_packets.clear();
+        self.low_confidence.clear();
+        self.crc_failed = false;
+        self.history_start_packets().await
+    }
+
+    /// Packet that moves the strap's read pointer back to the oldest reading in its data
+    /// range, for downloading all of its history again. The stored pointer is forgotten so
+    /// the download that follows doesn't resume from it.
+    pub async fn rewind_history_packet(
+        &self,
+        start: u32,
+        end: u32,
+    ) -> Result<WhoopPacket, OpenWhoopError> {
+        if end < start {
+            return Err(OpenWhoopError::Invalid(format!(
+                "invalid data range from {} to {}",
+                start, end
+            )));
+        }
+
+        if let Some(device) = &self.device {
+            self.database.clear_sync_pointer(device).await?;
+        }
+        Ok(WhoopPacket::set_read_pointer(start))
+    }
+
+    pub async fn get_latest_sleep(&self) -> Result<Option<SleepCycle>, OpenWhoopError> {
         Ok(self.database.get_latest_sleep().await?.map(map_sleep_cycle))
     }
 
-    pub async fn detect_events(&self) -> anyhow::Result<()> {
+    /// Detects activities in the gaps between sleeps.
+    ///
+    /// Runs incrementally: only readings newer than the latest stored activity are
+    /// scanned, and nothing is scanned when no sleep was added since that activity.
+    pub async fn detect_events(&self) -> Result<(), OpenWhoopError> {
         let latest_activity = self.database.get_latest_activity().await?;
-        let start_from = latest_activity.map(|a| a.from);
+        let processed_until = latest_activity.map(|a| a.to);
+
+        let Some(latest_sleep) = self.get_latest_sleep().await? else {
+            return Ok(());
+        };
+
+        if processed_until.is_some_and(|until| until >= latest_sleep.start) {
+            return Ok(());
+        }
 
         let sleeps = self
             .database
-            .get_sleep_cycles(start_from)
+            .get_sleep_cycles(latest_activity.map(|a| a.from.date()), None)
             .await?
             .windows(2)
             .map(|sleep| (sleep[0].id, sleep[0].end, sleep[1].start))
             .collect::<Vec<_>>();
 
         for (cycle_id, start, end) in sleeps {
+            let start = processed_until.map_or(start, |until| until.max(start));
+            if start >= end {
+                continue;
+            }
+
             let options = SearchHistory {
                 from: Some(start),
                 to: Some(end),
@@ -168,12 +938,31 @@ impl OpenWhoop {
             };
 
             let mut history = self.database.search_history(options).await?;
-            let events = ActivityPeriod::detect(history.as_mut_slice());
+            let mut events =
+                ActivityPeriod::detect_with_config(history.as_mut_slice(), &self.activity_config);
+
+            let tolerance = self.activity_config.marker_tolerance;
+            let markers = self
+                .database
+                .get_markers(Some(start - tolerance))
+                .await?
+                .into_iter()
+                .take_while(|marker| *marker <= end + tolerance)
+                .collect::<Vec<_>>();
+            ActivityPeriod::bound_by_markers(&mut events, &markers, tolerance, start..=end);
 
             for event in events {
-                let activity = match event.activity {
-                    Activity::Active => activities::ActivityType::Activity,
-                    Activity::Sleep => activities::ActivityType::Nap,
+                let (activity, confidence) = match event.activity {
+                    Activity::Active => {
+                        let from = history.partition_point(|r| r.time < event.start);
+                        let to = history.partition_point(|r| r.time <= event.end);
+                        // Low confidence guesses stay generic
+                        match ActivityClassifier::classify_activity(&history[from..to]) {
+                            Some(guess) => (guess.activity, Some(guess.confidence)),
+                            None => (activities::ActivityType::Activity, None),
+                        }
+                    }
+                    Activity::Sleep => (activities::ActivityType::Nap, None),
                     _ => continue,
                 };
 
@@ -182,6 +971,7 @@ impl OpenWhoop {
                     from: event.start,
                     to: event.end,
                     activity,
+                    confidence,
                 };
 
                 let duration = activity.to - activity.from;
@@ -199,7 +989,7 @@ impl OpenWhoop {
     }
 
     /// TODO: add handling for data splits
-    pub async fn detect_sleeps(&self) -> anyhow::Result<()> {
+    pub async fn detect_sleeps(&self) -> Result<(), OpenWhoopError> {
         'a: loop {
             let last_sleep = self.get_latest_sleep().await?;
 
@@ -239,6 +1029,7 @@ impl OpenWhoop {
                                     from: sleep.start,
                                     to: sleep.end,
                                     activity: activities::ActivityType::Nap,
+                                    confidence: None,
                                 };
                                 self.database.create_activity(nap).await?;
                                 continue;
@@ -248,6 +1039,7 @@ impl OpenWhoop {
                                     from: last_sleep.start,
                                     to: last_sleep.end,
                                     activity: activities::ActivityType::Nap,
+                                    confidence: None,
                                 };
                                 self.database.create_activity(nap).await?;
                             }
@@ -255,7 +1047,13 @@ impl OpenWhoop {
                     }
                 }
 
-                let sleep_cycle = SleepCycle::from_event(sleep, &history);
+                smooth_bpm(&mut history, BPM_SMOOTHING_WINDOW);
+                let sleep_cycle = SleepCycle::from_event_with_config(
+                    sleep,
+                    &history,
+                    &self.hrv_config,
+                    &self.sleep_score_config,
+                );
 
                 info!(
                     "Detected sleep from {} to {}, duration: {}",
@@ -273,12 +1071,11 @@ impl OpenWhoop {
         Ok(())
     }
 
-    pub async fn calculate_spo2(&self) -> anyhow::Result<()> {
+    pub async fn calculate_spo2(&self) -> Result<(), OpenWhoopError> {
         loop {
             let last = self.database.last_spo2_time().await?;
             let options = SearchHistory {
-                from: last
-                    .map(|t| t - TimeDelta::seconds(SpO2Calculator::WINDOW_SIZE as i64)),
+                from: last.map(|t| t - TimeDelta::seconds(SpO2Calculator::WINDOW_SIZE as i64)),
                 to: None,
                 limit: Some(86400),
             };
@@ -300,7 +1097,7 @@ impl OpenWhoop {
         Ok(())
     }
 
-    pub async fn calculate_skin_temp(&self) -> anyhow::Result<()> {
+    pub async fn calculate_skin_temp(&self) -> Result<(), OpenWhoopError> {
         loop {
             let readings = self
                 .database
@@ -326,7 +1123,12 @@ impl OpenWhoop {
         Ok(())
     }
 
-    pub async fn calculate_stress(&self) -> anyhow::Result<()> {
+    pub async fn calculate_stress(&self) -> Result<(), OpenWhoopError> {
+        let mut smoothing = match self.stress_smoothing {
+            Some(span) => Some(Ema::new(span, self.database.last_smoothed_stress().await?)),
+            None => None,
+        };
+
         loop {
             let last_stress = self.database.last_stress_time().await?;
             let options = SearchHistory {
@@ -341,20 +1143,220 @@ impl OpenWhoop {
                 break;
             }
 
+            // The first window ends on the last scored reading, which must not be averaged twice
             let stress_scores = history
                 .windows(StressCalculator::MIN_READING_PERIOD)
-                .filter_map(StressCalculator::calculate_stress);
+                .filter_map(StressCalculator::calculate_stress)
+                .filter(|stress| last_stress.is_none_or(|last| stress.time > last));
 
             for stress in stress_scores {
-                self.database.update_stress_on_reading(stress).await?;
+                let smoothed = smoothing.as_mut().map(|ema| ema.update(stress.score));
+                self.database
+                    .update_stress_on_reading(stress, smoothed)
+                    .await?;
             }
         }
 
         Ok(())
     }
-}
 
-fn map_sleep_cycle(sleep: openwhoop_entities::sleep_cycles::Model) -> SleepCycle {
+    /// Sums exercise strain per day, skipping exercises too short to score.
+    pub async fn daily_strain(
+        &self,
+        exercises: &[activities::ActivityPeriod],
+        calculator: &StrainCalculator,
+    ) -> Result<Vec<(NaiveDate, f64)>, OpenWhoopError> {
+        let mut daily = BTreeMap::new();
+
+        for exercise in exercises {
+            let mut history = self
+                .database
+                .search_history(SearchHistory {
+                    from: Some(exercise.from),
+                    to: Some(exercise.to),
+                    ..Default::default()
+                })
+                .await?;
+            smooth_bpm(&mut history, BPM_SMOOTHING_WINDOW);
+
+            if let Some(strain) = calculator.calculate(&history) {
+                *daily.entry(exercise.from.date()).or_insert(0.0) += strain.0;
+            }
+        }
+
+        Ok(daily.into_iter().collect())
+    }
+
+    /// Total calories burned over `exercises`, `None` if `config` is missing a user param
+    pub async fn exercise_calories(
+        &self,
+        exercises: &[activities::ActivityPeriod],
+        config: &CalorieConfig,
+    ) -> Result<Option<f64>, OpenWhoopError> {
+        let mut total = 0.0;
+
+        for exercise in exercises {
+            let mut history = self
+                .database
+                .search_history(SearchHistory {
+                    from: Some(exercise.from),
+                    to: Some(exercise.to),
+                    ..Default::default()
+                })
+                .await?;
+            smooth_bpm(&mut history, BPM_SMOOTHING_WINDOW);
+
+            let Some(calories) = config.calories(&history) else {
+                return Ok(None);
+            };
+            total += calories;
+        }
+
+        Ok(Some(total))
+    }
+
+    /// Strain zones from the heart rate of the last [`PERSONAL_ZONE_DAYS`] of exercise, see
+    /// [`ZoneModel::personal`]. `None` without exercise in that time.
+    pub async fn personal_zones(&self) -> Result<Option<ZoneModel>, OpenWhoopError> {
+        let since = Local::now().naive_local() - TimeDelta::days(PERSONAL_ZONE_DAYS);
+        let exercises = self
+            .database
+            .search_activities(activities::SearchActivityPeriods {
+                from: Some(since),
+                ..Default::default()
+            })
+            .await?
+            .into_iter()
+            .filter(|a| !matches!(a.activity, activities::ActivityType::Nap));
+
+        let mut readings = Vec::new();
+        for exercise in exercises {
+            let history = self
+                .database
+                .search_history(SearchHistory {
+                    from: Some(exercise.from),
+                    to: Some(exercise.to),
+                    ..Default::default()
+                })
+                .await?;
+            readings.extend(history);
+        }
+
+        Ok(ZoneModel::personal(&readings))
+    }
+
+    /// Composes the night ending on `date`, that day's exercise strain, mean stress and wear
+    /// time into one report.
+    pub async fn daily_summary(
+        &self,
+        date: NaiveDate,
+        calculator: &StrainCalculator,
+    ) -> Result<DailySummary, OpenWhoopError> {
+        let start = date.and_time(NaiveTime::MIN);
+        let end = start + TimeDelta::days(1);
+
+        let recovery_calculator = RecoveryCalculator::default();
+        let baseline_start = date - TimeDelta::days(i64::from(recovery_calculator.baseline_days));
+        let sleeps = self
+            .database
+            .get_sleep_cycles(Some(baseline_start), Some(date))
+            .await?;
+        let sleep = sleeps.iter().find(|sleep| sleep.id == date).copied();
+        let recovery = match sleep {
+            Some(sleep) => self
+                .database
+                .imported_recovery(sleep.start, sleep.end)
+                .await?
+                .or_else(|| recovery_calculator.score(&sleep, &sleeps)),
+            None => None,
+        };
+        let skin_temp = match sleep {
+            Some(sleep) => self.database.mean_skin_temp(sleep.start, sleep.end).await?,
+            None => None,
+        };
+
+        let exercises = self
+            .database
+            .search_activities(activities::SearchActivityPeriods {
+                from: Some(start - TimeDelta::seconds(1)),
+                to: Some(end + TimeDelta::days(1)),
+                ..Default::default()
+            })
+            .await?
+            .into_iter()
+            .filter(|a| a.from.date() == date)
+            .filter(|a| !matches!(a.activity, activities::ActivityType::Nap))
+            .collect::<Vec<_>>();
+        let strain = self
+            .daily_strain(&exercises, calculator)
+            .await?
+            .into_iter()
+            .map(|(_, strain)| strain)
+            .sum();
+
+        let stress = self
+            .database
+            .stress_buckets(start, end, TimeDelta::days(1))
+            .await?
+            .first()
+            .map(|(_, stress)| *stress);
+
+        Ok(DailySummary {
+            date,
+            sleep,
+            skin_temp,
+            recovery,
+            strain,
+            exercises: exercises.len(),
+            stress,
+            wear: self.database.on_wrist_fraction(date).await?,
+            units: UnitSystem::default(),
+        })
+    }
+}
+
+/// Parses `packets[range]` split over `workers` blocking tasks, keeping their order
+async fn parse_packets(
+    packets: Arc<[WhoopPacket]>,
+    range: Range<usize>,
+    context: &ParseContext,
+    workers: usize,
+) -> Result<Vec<Result<WhoopData, WhoopError>>, OpenWhoopError> {
+    let chunk = range.len().div_ceil(workers.max(1)).max(1);
+    let tasks = range
+        .clone()
+        .step_by(chunk)
+        .map(|start| {
+            let end = (start + chunk).min(range.end);
+            let packets = packets.clone();
+            let context = context.clone();
+            tokio::task::spawn_blocking(move || {
+                packets[start..end]
+                    .iter()
+                    .map(|packet| WhoopData::from_packet_with(packet.clone(), &context))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut parsed = Vec::with_capacity(range.len());
+    for task in tasks {
+        parsed.extend(task.await?);
+    }
+
+    Ok(parsed)
+}
+
+/// Appends one console log entry without the trailing NUL, prefixed with its local timestamp
+/// like in the `console_logs` table, with the offset so it stays unambiguous.
+fn write_console_log(file: &mut File, unix: u32, log: &str) -> std::io::Result<()> {
+    let time = DateTime::from_timestamp(i64::from(unix), 0)
+        .map(|t| t.with_timezone(&Local).to_rfc3339())
+        .unwrap_or_else(|| unix.to_string());
+    writeln!(file, "{} {}", time, log.trim_end_matches(['\0', '\n']))
+}
+
+fn map_sleep_cycle(sleep: openwhoop_entities::sleep_cycles::Model) -> SleepCycle {
     SleepCycle {
         id: sleep.end.date(),
         start: sleep.start,
@@ -368,5 +1370,1147 @@ fn map_sleep_cycle(sleep: openwhoop_entities::sleep_cycles::Model) -> SleepCycle
         score: sleep
             .score
             .unwrap_or_else(|| SleepCycle::sleep_score(sleep.start, sleep.end)),
+        efficiency: sleep.efficiency,
+        sdnn: sleep.sdnn,
+        pnn50: sleep.pnn50,
+        deep_hrv: sleep.deep_hrv.map(|hrv| hrv.try_into().unwrap()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openwhoop_codec::{
+        SensorData, Wrist,
+        constants::{CommandNumber, MetadataType, PacketType},
+    };
+    use openwhoop_entities::{activities as activity_entity, recoveries as recovery_entity};
+    use sea_orm::{
+        ActiveModelTrait, ActiveValue::NotSet, ColumnTrait, EntityTrait, QueryFilter, Set,
+        sea_query::Expr,
+    };
+
+    use super::*;
+
+    const MINUTE_MS: u64 = 60_000;
+
+    fn local_time(unix: u64) -> chrono::NaiveDateTime {
+        DateTime::from_timestamp_millis(unix as i64)
+            .unwrap()
+            .with_timezone(&Local)
+            .naive_local()
+    }
+
+    #[tokio::test]
+    async fn history_download_resumes_from_sync_pointer() {
+        let whoop =
+            OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await).with_device("band");
+
+        let packets = whoop.history_start_packets().await.unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].cmd, CommandNumber::SendHistoricalData.as_u8());
+
+        whoop
+            .database
+            .set_sync_pointer("band", 0x12345678)
+            .await
+            .unwrap();
+        let packets = whoop.history_start_packets().await.unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].cmd, CommandNumber::HistoricalDataResult.as_u8());
+        assert_eq!(&packets[0].data[1..5], &0x12345678_u32.to_le_bytes());
+        assert_eq!(packets[1].cmd, CommandNumber::SendHistoricalData.as_u8());
+
+        // Another band doesn't resume from this one's pointer
+        let other = OpenWhoop::new(whoop.database.clone()).with_device("other");
+        let packets = other.history_start_packets().await.unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].cmd, CommandNumber::SendHistoricalData.as_u8());
+    }
+
+    #[tokio::test]
+    async fn rewind_history_starts_from_oldest_pointer() {
+        let whoop =
+            OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await).with_device("band");
+        whoop
+            .database
+            .set_sync_pointer("band", 50_000)
+            .await
+            .unwrap();
+
+        let packet = whoop.rewind_history_packet(1200, 98_000).await.unwrap();
+        assert_eq!(packet.cmd, CommandNumber::SetReadPointer.as_u8());
+        assert_eq!(&packet.data[1..5], &1200_u32.to_le_bytes());
+
+        // The old pointer must not be re-acknowledged, that would skip back ahead
+        let packets = whoop.history_start_packets().await.unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].cmd, CommandNumber::SendHistoricalData.as_u8());
+
+        assert!(whoop.rewind_history_packet(98_000, 1200).await.is_err());
+    }
+
+    /// Two nights around 16 hours of awake readings with one hour of activity after two hours
+    async fn whoop_with_workout() -> OpenWhoop {
+        let whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        let first = 1735718400000; // 2025-01-01 08:00:00 UTC
+        let readings = (0..16 * 60)
+            .map(|i| HistoryReading {
+                unix: first + i * MINUTE_MS,
+                bpm: 80,
+                rr: vec![750],
+                activity: if (120..180).contains(&i) {
+                    600_000_000
+                } else {
+                    100_000_000
+                },
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect::<Vec<_>>();
+        let last = readings.last().unwrap().unix;
+        whoop.database.create_readings(readings).await.unwrap();
+
+        let wake = local_time(first);
+        let bed = local_time(last);
+        whoop
+            .database
+            .create_sleep(SleepCycle::fixture(wake - TimeDelta::hours(8), wake))
+            .await
+            .unwrap();
+        whoop
+            .database
+            .create_sleep(SleepCycle::fixture(bed, bed + TimeDelta::hours(8)))
+            .await
+            .unwrap();
+
+        whoop
+    }
+
+    #[tokio::test]
+    async fn detect_events_skips_processed_readings() {
+        let whoop = whoop_with_workout().await;
+
+        whoop.detect_events().await.unwrap();
+        let stored = activity_entity::Entity::find()
+            .all(whoop.database.connection())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].activity, "Activity");
+
+        // Any write would overwrite this marker
+        activity_entity::Entity::update_many()
+            .col_expr(activity_entity::Column::Activity, Expr::value("Rowing"))
+            .filter(activity_entity::Column::Id.eq(stored[0].id))
+            .exec(whoop.database.connection())
+            .await
+            .unwrap();
+
+        whoop.detect_events().await.unwrap();
+        let rerun = activity_entity::Entity::find()
+            .all(whoop.database.connection())
+            .await
+            .unwrap();
+        assert_eq!(rerun.len(), 1);
+        assert_eq!(rerun[0].activity, "Rowing");
+        assert_eq!(rerun[0].end, stored[0].end);
+    }
+
+    #[tokio::test]
+    async fn detect_events_continues_after_stored_activity() {
+        let whoop = whoop_with_workout().await;
+        whoop.detect_events().await.unwrap();
+
+        // Another day with a workout, ended by a third night
+        let first = 1735718400000 + 24 * 60 * MINUTE_MS;
+        let readings = (0..16 * 60)
+            .map(|i| HistoryReading {
+                unix: first + i * MINUTE_MS,
+                bpm: 80,
+                rr: vec![750],
+                activity: if (120..180).contains(&i) {
+                    600_000_000
+                } else {
+                    100_000_000
+                },
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect::<Vec<_>>();
+        let bed = local_time(readings.last().unwrap().unix);
+        whoop.database.create_readings(readings).await.unwrap();
+        whoop
+            .database
+            .create_sleep(SleepCycle::fixture(bed, bed + TimeDelta::hours(8)))
+            .await
+            .unwrap();
+
+        whoop.detect_events().await.unwrap();
+        let stored = activity_entity::Entity::find()
+            .all(whoop.database.connection())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().any(|a| a.start >= local_time(first)));
+    }
+
+    #[tokio::test]
+    async fn markers_set_activity_boundaries() {
+        let whoop = whoop_with_workout().await;
+
+        // Tapped two minutes before the workout and two minutes after its last reading
+        let first = 1735718400_u32;
+        let (start, end) = (first + 118 * 60, first + 181 * 60);
+        whoop.database.create_marker(start).await.unwrap();
+        whoop.database.create_marker(end).await.unwrap();
+
+        whoop.detect_events().await.unwrap();
+        let stored = activity_entity::Entity::find()
+            .all(whoop.database.connection())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].start, local_time(u64::from(start) * 1000));
+        assert_eq!(stored[0].end, local_time(u64::from(end) * 1000));
+    }
+
+    #[tokio::test]
+    async fn daily_summary_covers_every_domain() {
+        let whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        let date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let at = |hour| date.and_hms_opt(hour, 0, 0).unwrap();
+
+        // An hour of rest from 08:00 followed by an hour of exercise, a reading a second
+        let first = Local
+            .from_local_datetime(&at(8))
+            .unwrap()
+            .timestamp_millis() as u64;
+        let readings = (0..2 * 3600)
+            .map(|i| {
+                let active = i >= 3600;
+                HistoryReading {
+                    unix: first + i * 1000,
+                    bpm: if active { 150 } else { 70 },
+                    rr: vec![if active { 400 } else { 850 }],
+                    activity: if active { 600_000_000 } else { 100_000_000 },
+                    imu_data: vec![],
+                    sensor_data: None,
+                }
+            })
+            .collect::<Vec<_>>();
+        whoop.database.create_readings(readings).await.unwrap();
+        whoop
+            .database
+            .create_sleep(SleepCycle::fixture(at(0) - TimeDelta::hours(1), at(7)))
+            .await
+            .unwrap();
+        // Nights like it before, the baseline recovery is scored against
+        for days in 1..=4 {
+            let before = TimeDelta::days(days);
+            whoop
+                .database
+                .create_sleep(SleepCycle::fixture(
+                    at(0) - TimeDelta::hours(1) - before,
+                    at(7) - before,
+                ))
+                .await
+                .unwrap();
+        }
+        whoop
+            .database
+            .create_sleep(SleepCycle::fixture(at(22), at(22) + TimeDelta::hours(8)))
+            .await
+            .unwrap();
+        whoop.detect_events().await.unwrap();
+        whoop.calculate_stress().await.unwrap();
+
+        let summary = whoop
+            .daily_summary(date, &StrainCalculator::new(190, 50))
+            .await
+            .unwrap();
+        assert_eq!(summary.sleep.map(|s| s.id), Some(date));
+        assert_eq!(summary.exercises, 1);
+        assert!(summary.strain > 0.0);
+        assert!(summary.stress.is_some());
+        assert_eq!(summary.recovery, Some(50.0));
+        assert!(
+            (summary.wear - 120.0 / 1440.0).abs() < 0.01,
+            "{}",
+            summary.wear
+        );
+
+        let report = summary.to_string();
+        assert!(report.contains("Recovery: 50\n"));
+        assert!(report.contains("(low coverage)"));
+
+        // The score of a cycle imported from the WHOOP app takes precedence
+        recovery_entity::ActiveModel {
+            id: NotSet,
+            cycle_start: Set(at(0) - TimeDelta::hours(1)),
+            cycle_end: Set(None),
+            score: Set(Some(71.0)),
+            resting_hr: Set(None),
+            hrv: Set(None),
+            skin_temp: Set(None),
+            spo2: Set(None),
+            day_strain: Set(None),
+        }
+        .insert(whoop.database.connection())
+        .await
+        .unwrap();
+        let imported = whoop
+            .daily_summary(date, &StrainCalculator::new(190, 50))
+            .await
+            .unwrap();
+        assert_eq!(imported.recovery, Some(71.0));
+
+        let summary = DailySummary {
+            skin_temp: Some(34.0),
+            ..summary
+        };
+        assert!(summary.to_string().contains("Skin temp: 34.0 °C"));
+        let report = summary.with_units(UnitSystem::Imperial).to_string();
+        assert!(report.contains("Skin temp: 93.2 °F"), "{report}");
+    }
+
+    #[tokio::test]
+    async fn smoothed_stress_lags_raw_stress() {
+        let db = DatabaseHandler::new("sqlite::memory:").await;
+        let whoop = OpenWhoop::new(db).with_stress_smoothing(60);
+
+        // Ten minutes of varied RR intervals scoring low, then flat ones scoring maximal
+        let reading = |i: u64| {
+            let rr = if i < 600 {
+                700 + 300 * (i as u16 % 2)
+            } else {
+                850
+            };
+            HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 70,
+                rr: vec![rr],
+                activity: 100_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            }
+        };
+        // Scored in two runs split mid-step, the second continues the first's average
+        for range in [0..650, 650..1200] {
+            let readings = range.map(reading).collect();
+            whoop.database.create_readings(readings).await.unwrap();
+            whoop.calculate_stress().await.unwrap();
+        }
+
+        let rows = whoop
+            .database
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        let scored = rows.iter().filter(|row| row.stress.is_some()).count();
+        let (raw, smoothed): (Vec<f64>, Vec<f64>) = rows
+            .iter()
+            .filter_map(|row| Some((row.stress?, row.stress_smoothed?)))
+            .unzip();
+        assert_eq!(raw.len(), scored);
+
+        // Windows straddling the change score a little under the low plateau
+        let low = raw.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = 10.0;
+        assert!(raw[0] < 1.0, "{}", raw[0]);
+        assert!(smoothed.iter().all(|&s| (low..=high).contains(&s)));
+        // Rises steadily and well after the raw series saturates, then settles on it
+        let step = raw.iter().position(|&s| s >= high).unwrap();
+        assert!(smoothed[step] < high - 1.0, "{}", smoothed[step]);
+        assert!(smoothed[step..].windows(2).all(|w| w[0] <= w[1] + 1e-9));
+        assert!((high - smoothed.last().unwrap()).abs() < 0.1);
+    }
+
+    #[tokio::test]
+    async fn labeled_activity_survives_detection() {
+        let whoop = whoop_with_workout().await;
+        whoop.detect_events().await.unwrap();
+
+        let detected = whoop.database.get_latest_activity().await.unwrap().unwrap();
+        whoop
+            .database
+            .relabel_activity(detected.from, activities::ActivityType::Running)
+            .await
+            .unwrap();
+
+        // Detecting the same period again, as a fresh detection run would
+        whoop.database.create_activity(detected).await.unwrap();
+        whoop.detect_events().await.unwrap();
+
+        let stored = activity_entity::Entity::find()
+            .all(whoop.database.connection())
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].activity, "Running");
+        assert!(stored[0].manual);
+
+        let missing = detected.from - TimeDelta::minutes(1);
+        assert!(
+            whoop
+                .database
+                .relabel_activity(missing, activities::ActivityType::Cycling)
+                .await
+                .is_err()
+        );
+    }
+
+    fn cmd_packet(packet: WhoopPacket) -> packets::Model {
+        packets::Model {
+            id: 0,
+            uuid: CMD_FROM_STRAP,
+            bytes: packet.framed_packet(),
+            hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn handle_packet_tallies_outcomes() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        let body_location = || {
+            cmd_packet(WhoopPacket::new(
+                PacketType::CommandResponse,
+                0,
+                CommandNumber::GetBodyLocationAndStatus.as_u8(),
+                vec![0x54, 0x01, 0x01, Wrist::Left.as_u8(), 0x01],
+            ))
+        };
+        let unimplemented = || {
+            cmd_packet(WhoopPacket::new(
+                PacketType::CommandResponse,
+                0,
+                CommandNumber::GetClock.as_u8(),
+                vec![0x00],
+            ))
+        };
+        let garbage = || packets::Model {
+            id: 0,
+            uuid: CMD_FROM_STRAP,
+            bytes: vec![0x00, 0x01, 0x02],
+            hash: None,
+        };
+
+        let mut stats = PacketStats::default();
+        for packet in [
+            body_location(),
+            unimplemented(),
+            garbage(),
+            unimplemented(),
+            body_location(),
+            garbage(),
+            cmd_packet(WhoopPacket::version()),
+        ] {
+            let outcome = whoop.handle_packet(packet).await.unwrap();
+            stats.record(&outcome);
+        }
+
+        assert_eq!(
+            stats,
+            PacketStats {
+                handled: 2,
+                partial: 0,
+                unimplemented: 3,
+                parse_errors: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn rtc_lost_event_sets_clock() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        let event = |event: u8| packets::Model {
+            id: 0,
+            uuid: EVENTS_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::Event, 0, event, vec![0x00, 0, 0, 0, 0])
+                .framed_packet(),
+            hash: None,
+        };
+
+        let outcome = whoop.handle_packet(event(EventNumber::WristOn as u8)).await;
+        assert!(matches!(outcome.unwrap(), PacketOutcome::Handled(None)));
+
+        match whoop.handle_packet(event(EventNumber::RtcLost as u8)).await {
+            Ok(PacketOutcome::Handled(Some(packet))) => {
+                assert_eq!(packet.cmd, CommandNumber::SetClock.as_u8());
+            }
+            _ => panic!("RtcLost should answer with a set clock packet"),
+        }
+    }
+
+    #[tokio::test]
+    async fn v12_readings_get_activity_from_wrist_movement() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        // Lying still for a reading, then turning the wrist over
+        let gravity = [[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]];
+        for (i, accel_gravity) in (0..).zip(gravity) {
+            let reading = HistoryReading {
+                unix: 1735689600000 + i * 1000,
+                bpm: 60,
+                rr: vec![],
+                activity: 0,
+                imu_data: vec![],
+                sensor_data: Some(SensorData {
+                    skin_contact: 1,
+                    accel_gravity,
+                    ..Default::default()
+                }),
+            };
+            whoop
+                .handle_data(WhoopData::HistoryReading(reading))
+                .await
+                .unwrap();
+        }
+
+        let activities = whoop
+            .history_packets
+            .iter()
+            .map(|reading| Activity::from(i64::from(reading.activity)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            activities,
+            [Activity::Inactive, Activity::Sleep, Activity::Active]
+        );
+    }
+
+    #[tokio::test]
+    async fn double_tap_is_stored_as_marker() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        let unix = 1735689600_u32;
+        let mut data = vec![0x00];
+        data.extend_from_slice(&unix.to_le_bytes());
+        let packet = packets::Model {
+            id: 0,
+            uuid: EVENTS_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::Event, 0, EventNumber::DoubleTap as u8, data)
+                .framed_packet(),
+            hash: None,
+        };
+
+        // Downloading again resends the event, it is only stored once
+        for _ in 0..2 {
+            let outcome = whoop.handle_packet(packet.clone()).await.unwrap();
+            assert!(matches!(outcome, PacketOutcome::Handled(None)));
+        }
+
+        let markers = whoop.database.get_markers(None).await.unwrap();
+        let expected = DateTime::from_timestamp(i64::from(unix), 0)
+            .unwrap()
+            .with_timezone(&Local)
+            .naive_local();
+        assert_eq!(markers, vec![expected]);
+    }
+
+    #[tokio::test]
+    async fn imu_stream_is_stored() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        let unix = 1735689600_u32;
+        let mut data = Vec::new();
+        data.extend_from_slice(&7_u32.to_le_bytes());
+        data.extend_from_slice(&unix.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        // A single sample at rest, 1g on z
+        for raw in [0_i16, 0, 1875, 0, 0, 0] {
+            data.extend_from_slice(&raw.to_be_bytes());
+        }
+        let packet = packets::Model {
+            id: 0,
+            uuid: DATA_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::RealtimeImuDataStream, 0, 0, data).framed_packet(),
+            hash: None,
+        };
+
+        for _ in 0..2 {
+            let outcome = whoop.handle_packet(packet.clone()).await.unwrap();
+            assert!(matches!(outcome, PacketOutcome::Handled(None)));
+        }
+
+        let time = DateTime::from_timestamp(i64::from(unix), 0)
+            .unwrap()
+            .with_timezone(&Local)
+            .naive_local();
+        let stream = whoop
+            .database
+            .get_imu_stream(time, time + TimeDelta::seconds(1))
+            .await
+            .unwrap();
+        assert_eq!(stream.len(), 1);
+        assert_eq!(stream[0].1.len(), 1);
+        assert_eq!(stream[0].1[0].acc_z_g, 1.0);
+    }
+
+    #[tokio::test]
+    async fn charging_readings_are_excluded_from_resting_hr() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        // Three hours asleep, except for 20 minutes on the charger reading nonsense
+        let first = 1735689600000; // 2025-01-01 00:00:00 UTC
+        let readings = (0..180)
+            .map(|i| HistoryReading {
+                unix: first + i * MINUTE_MS,
+                bpm: if (60..80).contains(&i) { 30 } else { 55 },
+                rr: vec![1000; 5],
+                activity: 1_200_000_000,
+                imu_data: vec![],
+                sensor_data: None,
+            })
+            .collect();
+        whoop.database.create_readings(readings).await.unwrap();
+
+        let event = |event: EventNumber, minute: u64| {
+            let unix = u32::try_from((first + minute * MINUTE_MS) / 1000).unwrap();
+            let mut data = vec![0x00];
+            data.extend_from_slice(&unix.to_le_bytes());
+            packets::Model {
+                id: 0,
+                uuid: EVENTS_FROM_STRAP,
+                bytes: WhoopPacket::new(PacketType::Event, 0, event as u8, data).framed_packet(),
+                hash: None,
+            }
+        };
+        for packet in [
+            event(EventNumber::ChargingOn, 60),
+            event(EventNumber::ChargingOff, 80),
+        ] {
+            let outcome = whoop.handle_packet(packet).await.unwrap();
+            assert!(matches!(outcome, PacketOutcome::Handled(None)));
+        }
+
+        whoop.detect_sleeps().await.unwrap();
+        let sleeps = whoop.database.get_sleep_cycles(None, None).await.unwrap();
+        assert!(!sleeps.is_empty());
+        assert!(sleeps.iter().all(|sleep| sleep.min_bpm == 55), "{sleeps:?}");
+
+        // They are still stored
+        let rows = whoop
+            .database
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 180);
+    }
+
+    #[tokio::test]
+    async fn high_freq_sync_events_drive_sync_state() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        whoop.high_freq_sync = HighFreqSync::Requested;
+
+        let event = |event: EventNumber| packets::Model {
+            id: 0,
+            uuid: EVENTS_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::Event, 0, event as u8, vec![0x00, 0, 0, 0, 0])
+                .framed_packet(),
+            hash: None,
+        };
+
+        // Unrelated events don't enable it
+        whoop
+            .handle_packet(event(EventNumber::WristOn))
+            .await
+            .unwrap();
+        assert!(!whoop.high_freq_sync.is_enabled());
+
+        match whoop
+            .handle_packet(event(EventNumber::HighFreqSyncPrompt))
+            .await
+        {
+            Ok(PacketOutcome::Handled(Some(packet))) => {
+                assert_eq!(packet.cmd, CommandNumber::EnterHighFreqSync.as_u8());
+            }
+            _ => panic!("A prompt should be answered by entering high frequency sync"),
+        }
+        assert_eq!(whoop.high_freq_sync, HighFreqSync::Prompted);
+
+        let outcome = whoop
+            .handle_packet(event(EventNumber::HighFreqSyncEnabled))
+            .await;
+        assert!(matches!(outcome.unwrap(), PacketOutcome::Handled(None)));
+        assert!(whoop.high_freq_sync.is_enabled());
+
+        // Already enabled, nothing to answer
+        let outcome = whoop
+            .handle_packet(event(EventNumber::HighFreqSyncPrompt))
+            .await;
+        assert!(matches!(outcome.unwrap(), PacketOutcome::Handled(None)));
+        assert!(whoop.high_freq_sync.is_enabled());
+
+        whoop
+            .handle_packet(event(EventNumber::HighFreqSyncDisabled))
+            .await
+            .unwrap();
+        assert_eq!(whoop.high_freq_sync, HighFreqSync::Disabled);
+    }
+
+    #[tokio::test]
+    async fn unknown_command_is_captured() {
+        // No `CommandNumber` uses 200
+        let unknown = || {
+            cmd_packet(WhoopPacket::new(
+                PacketType::CommandResponse,
+                0,
+                200,
+                vec![0x00],
+            ))
+        };
+
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        let outcome = whoop.handle_packet(unknown()).await.unwrap();
+        assert!(matches!(
+            outcome,
+            PacketOutcome::ParseError(WhoopError::InvalidCommandType(200))
+        ));
+        let captured = whoop.database.get_unknown_packets().await.unwrap();
+        assert!(captured.is_empty());
+
+        let mut whoop = whoop.with_capture_unknown(true);
+        whoop.handle_packet(unknown()).await.unwrap();
+        let captured = whoop.database.get_unknown_packets().await.unwrap();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].uuid, CMD_FROM_STRAP);
+        assert_eq!(captured[0].bytes, unknown().bytes);
+        assert_eq!(captured[0].error, "InvalidCommandType(200)");
+        let received_at = captured[0].received_at;
+        assert!(received_at.is_some());
+
+        // Receiving or re-running it again keeps the first capture
+        whoop.handle_packet(unknown()).await.unwrap();
+        let mut whoop = whoop.with_rerunning(true);
+        whoop.handle_packet(unknown()).await.unwrap();
+        let captured = whoop.database.get_unknown_packets().await.unwrap();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].received_at, received_at);
+
+        // First seen while re-running, when it arrived isn't known
+        let other = cmd_packet(WhoopPacket::new(
+            PacketType::CommandResponse,
+            0,
+            201,
+            vec![0x00],
+        ));
+        whoop.handle_packet(other).await.unwrap();
+        let captured = whoop.database.get_unknown_packets().await.unwrap();
+        assert_eq!(captured[1].received_at, None);
+    }
+
+    fn console_log(unix: u32, log: &str) -> packets::Model {
+        let mut data = vec![0x00];
+        data.extend_from_slice(&unix.to_le_bytes());
+        data.extend_from_slice(&[0x00, 0x00]);
+        data.extend_from_slice(log.as_bytes());
+        data.push(0x00);
+        packets::Model {
+            id: 0,
+            uuid: DATA_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::ConsoleLogs, 0, 2, data).framed_packet(),
+            hash: None,
+        }
+    }
+
+    async fn store_console_logs(whoop: &OpenWhoop, times: &[u32]) -> Vec<i32> {
+        let mut ids = Vec::new();
+        for &unix in times {
+            let packet = console_log(unix, &format!("log {}", unix));
+            let stored = whoop
+                .database
+                .create_packet(packet.uuid, packet.bytes)
+                .await
+                .unwrap();
+            ids.push(stored.id);
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn rerun_since_id_skips_earlier_packets() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        let ids = store_console_logs(&whoop, &[1735689600, 1735689660, 1735689720]).await;
+
+        let stats = whoop.rerun(ids[1]).await.unwrap();
+        assert_eq!(stats.handled, 2);
+
+        let logs = whoop.database.get_console_logs(None).await.unwrap();
+        let logs = logs.iter().map(|l| l.log.as_str()).collect::<Vec<_>>();
+        assert_eq!(logs, vec!["log 1735689660", "log 1735689720"]);
+    }
+
+    #[tokio::test]
+    async fn packet_id_since_finds_first_packet_of_day() {
+        let whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        let day = |d: u32| {
+            let time = NaiveDate::from_ymd_opt(2025, 1, d)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap();
+            Local.from_local_datetime(&time).unwrap().timestamp() as u32
+        };
+        let ids = store_console_logs(&whoop, &[day(1), day(2), day(3), day(4), day(5)]).await;
+
+        let since = |d: u32| {
+            NaiveDate::from_ymd_opt(2025, 1, d)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        assert_eq!(whoop.packet_id_since(since(1)).await.unwrap(), ids[0]);
+        assert_eq!(whoop.packet_id_since(since(4)).await.unwrap(), ids[3]);
+        assert_eq!(whoop.packet_id_since(since(9)).await.unwrap(), ids[4] + 1);
+    }
+
+    #[tokio::test]
+    async fn console_logs_are_appended_to_file() {
+        let path =
+            std::env::temp_dir().join(format!("openwhoop-console-{}.log", uuid::Uuid::new_v4()));
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        let mut whoop =
+            OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await).with_console_log(file);
+
+        for packet in [
+            console_log(1735689600, "Trim: 0x00000000"),
+            console_log(1735689660, "battery 87%\n"),
+        ] {
+            let outcome = whoop.handle_packet(packet).await.unwrap();
+            assert!(matches!(outcome, PacketOutcome::Handled(None)));
+        }
+
+        let stored = whoop.database.get_console_logs(None).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[1].log, "battery 87%\n");
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let local = |unix| Local.timestamp_opt(unix, 0).unwrap().to_rfc3339();
+        assert_eq!(
+            contents,
+            format!(
+                "{} Trim: 0x00000000\n{} battery 87%\n",
+                local(1735689600),
+                local(1735689660)
+            )
+        );
+        assert_eq!(
+            &local(1735689600)[..19],
+            stored[0].time.format("%FT%T").to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn console_log_write_errors_dont_stop_the_download() {
+        let path =
+            std::env::temp_dir().join(format!("openwhoop-console-{}.log", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "").unwrap();
+        // Opened without write access, every write fails
+        let file = File::open(&path).unwrap();
+        let mut whoop =
+            OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await).with_console_log(file);
+
+        let outcome = whoop
+            .handle_packet(console_log(1735689600, "Trim: 0x00000000"))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, PacketOutcome::Handled(None)));
+        std::fs::remove_file(&path).unwrap();
+
+        let stored = whoop.database.get_console_logs(None).await.unwrap();
+        assert_eq!(stored.len(), 1);
+    }
+
+    fn metadata_packet(cmd: MetadataType, unix: u32, data: u32) -> packets::Model {
+        let mut bytes = unix.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 6]);
+        bytes.extend_from_slice(&data.to_le_bytes());
+        packets::Model {
+            id: 0,
+            uuid: DATA_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::Metadata, 0, cmd as u8, bytes).framed_packet(),
+            hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_outcome_requires_complete_marker() {
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        // Stream stops after the first chunk, before the strap reports it is done
+        for packet in [
+            metadata_packet(MetadataType::HistoryStart, 1735689600, 0),
+            metadata_packet(MetadataType::HistoryEnd, 1735689660, 42),
+        ] {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+
+        assert_eq!(
+            whoop.sync_outcome,
+            SyncOutcome {
+                complete: false,
+                last_unix: Some(1735689660),
+            }
+        );
+
+        let packet = metadata_packet(MetadataType::HistoryComplete, 1735689720, 0);
+        whoop.handle_packet(packet).await.unwrap();
+        assert!(whoop.sync_outcome.complete);
+        assert_eq!(whoop.sync_outcome.last_unix, Some(1735689720));
+    }
+
+    fn reading_packet(unix: u32) -> packets::Model {
+        let mut data = Vec::new();
+        data.extend_from_slice(&unix.to_le_bytes()); // sequence
+        data.extend_from_slice(&unix.to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        data.extend_from_slice(&[60, 1]);
+        data.extend_from_slice(&[0xe8, 0x03, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        packets::Model {
+            id: 0,
+            uuid: DATA_FROM_STRAP,
+            bytes: WhoopPacket::new(PacketType::HistoricalData, 7, 0, data).framed_packet(),
+            hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupted_reading_is_kept_low_confidence_when_lenient() {
+        let first = 1735689600;
+        let mut corrupted = reading_packet(first);
+        corrupted.bytes[21] ^= 0x01; // BPM 60 to 61
+        let end = metadata_packet(MetadataType::HistoryEnd, first + 1, 100);
+
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        let outcome = whoop.handle_packet(corrupted.clone()).await.unwrap();
+        assert!(matches!(
+            outcome,
+            PacketOutcome::ParseError(WhoopError::InvalidDataCrc32)
+        ));
+
+        let mut whoop =
+            OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await).with_lenient_crc(true);
+        for packet in [corrupted, end.clone()] {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+        let rows = whoop
+            .database
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].bpm, rows[0].low_confidence), (61, true));
+
+        // Downloading it again without the flipped bit replaces it
+        for packet in [reading_packet(first), end] {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+        let rows = whoop
+            .database
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!((rows[0].bpm, rows[0].low_confidence), (60, false));
+    }
+
+    #[tokio::test]
+    async fn resume_forgets_low_confidence_of_discarded_readings() {
+        let first = 1735689600;
+        let mut corrupted = reading_packet(first);
+        corrupted.bytes[21] ^= 0x01;
+
+        let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await)
+            .with_lenient_crc(true)
+            .with_device("band");
+        whoop.handle_packet(corrupted).await.unwrap();
+        assert!(whoop.crc_failed);
+        whoop.resume_history_packets().await.unwrap();
+        assert!(!whoop.crc_failed && whoop.low_confidence.is_empty());
+
+        // The strap resends the reading intact from the acknowledged pointer
+        let end = metadata_packet(MetadataType::HistoryEnd, first + 1, 100);
+        for packet in [reading_packet(first), end] {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+        let rows = whoop
+            .database
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!((rows[0].bpm, rows[0].low_confidence), (60, false));
+    }
+
+    #[tokio::test]
+    async fn resume_after_disconnect_keeps_every_reading_once() {
+        let mut whoop =
+            OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await).with_device("band");
+        let first = 1735689600;
+        let chunk = |from: u32, to: u32, pointer: u32| {
+            let mut packets = vec![metadata_packet(MetadataType::HistoryStart, first + from, 0)];
+            packets.extend((from..to).map(|i| reading_packet(first + i)));
+            let end = metadata_packet(MetadataType::HistoryEnd, first + to, pointer);
+            packets.push(end);
+            packets
+        };
+
+        for packet in chunk(0, 5, 100) {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+
+        // Link drops after two readings of the second chunk and half of the third
+        let mut partial = reading_packet(first + 7);
+        partial.bytes.truncate(12);
+        let received = [
+            reading_packet(first + 5),
+            reading_packet(first + 6),
+            partial,
+        ];
+        for packet in received {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+
+        let packets = whoop.resume_history_packets().await.unwrap();
+        assert_eq!(packets[0].cmd, CommandNumber::HistoricalDataResult.as_u8());
+        assert_eq!(&packets[0].data[1..5], &100_u32.to_le_bytes());
+
+        // The strap resends the second chunk from the acknowledged pointer
+        for packet in chunk(5, 10, 200) {
+            whoop.handle_packet(packet).await.unwrap();
+        }
+
+        let readings = whoop
+            .database
+            .search_history(SearchHistory::default())
+            .await
+            .unwrap();
+        let times = readings.iter().map(|r| r.time).collect::<Vec<_>>();
+        let expected = (0..10)
+            .map(|i| {
+                DateTime::from_timestamp(i64::from(first + i), 0)
+                    .unwrap()
+                    .with_timezone(&Local)
+                    .naive_local()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(times, expected);
+        assert_eq!(
+            whoop.database.get_sync_pointer("band").await.unwrap(),
+            Some(200)
+        );
+    }
+
+    #[tokio::test]
+    async fn parallel_rerun_matches_serial() {
+        let first = 1735689600;
+        let mut stored = vec![metadata_packet(MetadataType::HistoryStart, first, 0)];
+        stored.extend((0..40).map(|i| reading_packet(first + i)));
+        // A reading split over two notifications
+        let split = reading_packet(first + 40);
+        let (head, tail) = split.bytes.split_at(12);
+        stored.push(packets::Model {
+            bytes: head.to_vec(),
+            ..split.clone()
+        });
+        stored.push(packets::Model {
+            bytes: tail.to_vec(),
+            ..split
+        });
+        stored.push(console_log(first + 41, "battery 87%"));
+        stored.push(packets::Model {
+            id: 0,
+            uuid: CMD_FROM_STRAP,
+            bytes: vec![0x00, 0x01, 0x02],
+            hash: None,
+        });
+        stored.extend((42..60).map(|i| reading_packet(first + i)));
+        stored.push(metadata_packet(MetadataType::HistoryEnd, first + 60, 100));
+        let complete = metadata_packet(MetadataType::HistoryComplete, first + 61, 0);
+        stored.push(complete);
+
+        let mut results = Vec::new();
+        for workers in [None, Some(3)] {
+            let mut whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+            for packet in stored.clone() {
+                whoop
+                    .database
+                    .create_packet(packet.uuid, packet.bytes)
+                    .await
+                    .unwrap();
+            }
+
+            let stats = match workers {
+                None => whoop.rerun(0).await.unwrap(),
+                Some(workers) => whoop.rerun_parallel(0, workers).await.unwrap(),
+            };
+            let rows = whoop
+                .database
+                .search_history_rows(SearchHistory::default())
+                .await
+                .unwrap();
+            let logs = whoop.database.get_console_logs(None).await.unwrap();
+            results.push((stats, rows, logs, whoop.sync_outcome));
+        }
+
+        let (stats, rows, ..) = &results[0];
+        // Every second but the one taken by the console log
+        assert_eq!(rows.len(), 59);
+        assert_eq!(stats.parse_errors, 1);
+        assert_eq!(stats.partial, 1);
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[tokio::test]
+    async fn process_reading_stores_derived_fields() {
+        let whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+
+        let sensor_data = SensorData {
+            ppg_green: 100,
+            ppg_red_ir: 200,
+            spo2_red: 3000,
+            spo2_ir: 4000,
+            skin_temp_raw: 850,
+            ambient_light: 50,
+            led_drive_1: 10,
+            led_drive_2: 20,
+            resp_rate_raw: 0,
+            signal_quality: 0,
+            skin_contact: 1,
+            accel_gravity: [0.0, 0.0, 1.0],
+        };
+        let reading = HistoryReading {
+            unix: 1735689600000,
+            bpm: 64,
+            rr: vec![937],
+            activity: 500_000_000,
+            imu_data: vec![],
+            sensor_data: Some(sensor_data.clone()),
+        };
+        whoop.process_reading(reading.clone()).await.unwrap();
+
+        // Invalid readings are skipped
+        let empty = HistoryReading {
+            unix: 1735689660000,
+            bpm: 0,
+            ..reading
+        };
+        whoop.process_reading(empty).await.unwrap();
+
+        let rows = whoop
+            .database
+            .search_history_rows(SearchHistory::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].bpm, 64);
+        assert_eq!(rows[0].rr, vec![937]);
+        assert_eq!(rows[0].skin_temp, Some(34.0));
+        assert_eq!(rows[0].sensor_data, Some(sensor_data));
     }
 }
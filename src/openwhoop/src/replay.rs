@@ -0,0 +1,148 @@
+use std::fmt;
+
+use openwhoop_codec::{WhoopData, WhoopError};
+use openwhoop_db::capture::parse_line;
+use openwhoop_entities::packets;
+use serde_json::{Value, json};
+
+use crate::{OpenWhoop, OpenWhoopError, PacketOutcome};
+
+/// A parsed packet that doesn't match its expected JSON, `None` where either side ran out
+#[derive(Debug, PartialEq)]
+pub struct ReplayMismatch {
+    pub index: usize,
+    pub expected: Option<Value>,
+    pub parsed: Option<Value>,
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<Value>| {
+            value
+                .as_ref()
+                .map_or("nothing".to_owned(), Value::to_string)
+        };
+        write!(
+            f,
+            "packet {}: expected {}, parsed {}",
+            self.index,
+            show(&self.expected),
+            show(&self.parsed)
+        )
+    }
+}
+
+impl OpenWhoop {
+    /// Parses a capture file written by `dump-packets` into one JSON value per packet,
+    /// without storing anything. Packets that fail to parse become `{"error": "..."}`, so
+    /// fixtures can pin down failures as well.
+    pub fn replay(&mut self, capture: &str) -> Result<Vec<Value>, OpenWhoopError> {
+        let mut parsed = Vec::new();
+        for line in capture.lines().filter(|line| !line.trim().is_empty()) {
+            let (uuid, bytes) = parse_line(line)?;
+            let stored = packets::Model {
+                id: 0,
+                uuid,
+                bytes,
+                hash: None,
+            };
+
+            let result = match self.assemble_packet(stored) {
+                Ok(packet) => WhoopData::from_packet_with(packet, &self.parse_context),
+                Err(PacketOutcome::ParseError(error)) => Err(error),
+                Err(PacketOutcome::Unimplemented) => Err(WhoopError::Unimplemented),
+                Err(PacketOutcome::Partial | PacketOutcome::Handled(_)) => continue,
+            };
+
+            let value = match result {
+                Ok(data) => {
+                    // Later packets are parsed for the firmware that reported, like on sync
+                    if let WhoopData::VersionInfo { harvard, .. } = &data {
+                        match harvard.parse() {
+                            Ok(firmware) => self.parse_context.firmware = Some(firmware),
+                            Err(_) => warn!("Unrecognized firmware version: {}", harvard),
+                        }
+                    }
+                    serde_json::to_value(&data)
+                        .map_err(|error| OpenWhoopError::Invalid(error.to_string()))?
+                }
+                Err(error) => json!({ "error": format!("{:?}", error) }),
+            };
+            parsed.push(value);
+        }
+
+        Ok(parsed)
+    }
+
+    /// Replays `capture` and compares every parsed packet to `expected`, returning the
+    /// packets that differ. An empty result means the parser still produces the fixture.
+    pub fn validate_replay(
+        &mut self,
+        capture: &str,
+        expected: &[Value],
+    ) -> Result<Vec<ReplayMismatch>, OpenWhoopError> {
+        let parsed = self.replay(capture)?;
+        let count = parsed.len().max(expected.len());
+
+        Ok((0..count)
+            .filter_map(|index| {
+                let expected = expected.get(index).cloned();
+                let parsed = parsed.get(index).cloned();
+                (expected != parsed).then_some(ReplayMismatch {
+                    index,
+                    expected,
+                    parsed,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openwhoop_codec::{
+        WhoopPacket,
+        constants::{CMD_FROM_STRAP, EVENTS_FROM_STRAP, EventNumber, PacketType},
+    };
+    use openwhoop_db::{DatabaseHandler, capture::format_line};
+
+    use super::*;
+
+    async fn corpus() -> (OpenWhoop, String) {
+        let whoop = OpenWhoop::new(DatabaseHandler::new("sqlite::memory:").await);
+        let mut data = vec![0x00];
+        data.extend_from_slice(&1735732800u32.to_le_bytes());
+        let double_tap = WhoopPacket::new(PacketType::Event, 0, EventNumber::DoubleTap as u8, data);
+        let capture = [
+            format_line(EVENTS_FROM_STRAP, &double_tap.framed_packet()),
+            // Truncated by the adapter
+            format_line(CMD_FROM_STRAP, &[0xAA, 0x01]),
+        ]
+        .join("\n");
+        (whoop, capture)
+    }
+
+    #[tokio::test]
+    async fn corpus_matches_expected_output() {
+        let (mut whoop, capture) = corpus().await;
+        let expected = [
+            json!({ "DoubleTap": { "unix": 1735732800 } }),
+            json!({ "error": "PacketTooShort" }),
+        ];
+
+        let mismatches = whoop.validate_replay(&capture, &expected).unwrap();
+        assert_eq!(mismatches, []);
+    }
+
+    #[tokio::test]
+    async fn wrong_expectation_fails() {
+        let (mut whoop, capture) = corpus().await;
+        let expected = [json!({ "DoubleTap": { "unix": 1735732801 } })];
+
+        let mismatches = whoop.validate_replay(&capture, &expected).unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].index, 0);
+        assert_eq!(mismatches[1].expected, None);
+        assert!(mismatches[1].parsed.is_some());
+    }
+}
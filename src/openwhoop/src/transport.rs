@@ -0,0 +1,112 @@
+use std::{collections::BTreeSet, future::Future, pin::Pin};
+
+use btleplug::{
+    api::{Central, CharPropFlags, Characteristic, Peripheral as _, ValueNotification, WriteType},
+    platform::{Adapter, Peripheral},
+};
+use futures::Stream;
+use openwhoop_codec::constants::WHOOP_SERVICE;
+use uuid::Uuid;
+
+use crate::error::OpenWhoopError;
+
+/// Notifications from every subscribed characteristic, in the order they arrived
+pub type NotificationStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+/// Link to a strap that [`crate::WhoopDevice`] talks through.
+///
+/// [`BleTransport`] is the Bluetooth link used by the CLI, other links (a serial dongle,
+/// packets replayed from a file, a mock in tests) only have to move bytes to and from
+/// the strap's characteristics, and report their own errors as
+/// [`OpenWhoopError::Transport`].
+pub trait Transport {
+    /// Opens the link, also called to reconnect after it dropped
+    fn connect(&mut self) -> impl Future<Output = Result<(), OpenWhoopError>> + Send;
+
+    fn is_connected(&self) -> impl Future<Output = Result<bool, OpenWhoopError>> + Send;
+
+    /// Asks for notifications from `characteristic`
+    fn subscribe(
+        &self,
+        characteristic: Uuid,
+    ) -> impl Future<Output = Result<(), OpenWhoopError>> + Send;
+
+    /// Writes `data` to `characteristic` without waiting for a response
+    fn write(
+        &self,
+        characteristic: Uuid,
+        data: &[u8],
+    ) -> impl Future<Output = Result<(), OpenWhoopError>> + Send;
+
+    /// Reads what the strap sends, see [`NotificationStream`]
+    fn notifications(
+        &self,
+    ) -> impl Future<Output = Result<NotificationStream, OpenWhoopError>> + Send;
+
+    /// Signal strength in dBm, `None` if the link doesn't report one
+    fn rssi(&self) -> impl Future<Output = Result<Option<i16>, OpenWhoopError>> + Send;
+}
+
+/// Talks to a strap over Bluetooth LE through btleplug
+pub struct BleTransport {
+    peripheral: Peripheral,
+    adapter: Adapter,
+}
+
+impl BleTransport {
+    pub fn new(peripheral: Peripheral, adapter: Adapter) -> Self {
+        Self {
+            peripheral,
+            adapter,
+        }
+    }
+
+    fn create_char(characteristic: Uuid) -> Characteristic {
+        Characteristic {
+            uuid: characteristic,
+            service_uuid: WHOOP_SERVICE,
+            properties: CharPropFlags::empty(),
+            descriptors: BTreeSet::new(),
+        }
+    }
+}
+
+impl Transport for BleTransport {
+    async fn connect(&mut self) -> Result<(), OpenWhoopError> {
+        self.peripheral.connect().await?;
+        let _ = self.adapter.stop_scan().await;
+        self.peripheral.discover_services().await?;
+        Ok(())
+    }
+
+    async fn is_connected(&self) -> Result<bool, OpenWhoopError> {
+        Ok(self.peripheral.is_connected().await?)
+    }
+
+    async fn subscribe(&self, characteristic: Uuid) -> Result<(), OpenWhoopError> {
+        self.peripheral
+            .subscribe(&Self::create_char(characteristic))
+            .await?;
+        Ok(())
+    }
+
+    async fn write(&self, characteristic: Uuid, data: &[u8]) -> Result<(), OpenWhoopError> {
+        self.peripheral
+            .write(
+                &Self::create_char(characteristic),
+                data,
+                WriteType::WithoutResponse,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<NotificationStream, OpenWhoopError> {
+        Ok(self.peripheral.notifications().await?)
+    }
+
+    async fn rssi(&self) -> Result<Option<i16>, OpenWhoopError> {
+        let properties = self.peripheral.properties().await?;
+        Ok(properties.and_then(|p| p.rssi))
+    }
+}